@@ -0,0 +1,75 @@
+use crate::db::SubscriptionHandle;
+use crate::{ChangeEvent, Database, DatabaseConfig, Result};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task;
+
+/// `.await`-able wrapper around `Database` for async runtimes
+///
+/// Every call runs the blocking database work on tokio's blocking thread
+/// pool, so the async executor is never stalled by disk IO or lock waits.
+/// The wrapper is cheap to clone; all clones share one database.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    inner: Arc<Mutex<Database>>,
+}
+
+impl AsyncDatabase {
+    pub async fn open(config: DatabaseConfig) -> Result<Self> {
+        let db = task::spawn_blocking(move || Database::open(config)).await.map_err(std::io::Error::from)??;
+        Ok(Self::from_database(db))
+    }
+    
+    /// Wrap an already opened database
+    pub fn from_database(db: Database) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(db)),
+        }
+    }
+    
+    pub async fn set(&self, key: String, value: Vec<u8>) -> Result<()> {
+        self.run(move |db| db.set(key, value)).await
+    }
+    
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = key.to_string();
+        self.run(move |db| db.get(&key)).await
+    }
+    
+    pub async fn delete(&self, key: &str) -> Result<bool> {
+        let key = key.to_string();
+        self.run(move |db| db.delete(&key)).await
+    }
+    
+    pub async fn list_keys(&self) -> Result<Vec<String>> {
+        self.run(|db| db.list_keys()).await
+    }
+    
+    pub async fn flush(&self) -> Result<()> {
+        self.run(|db| db.flush()).await
+    }
+    
+    /// Stream change events into an async channel
+    ///
+    /// Events stop flowing once the returned handle is dropped.
+    pub async fn subscribe(&self) -> Result<(SubscriptionHandle, mpsc::UnboundedReceiver<ChangeEvent>)> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = self
+            .run(move |db| {
+                db.subscribe(move |event| {
+                    let _ = tx.send(event);
+                })
+            })
+            .await?;
+        Ok((handle, rx))
+    }
+    
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Database) -> Result<T> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        task::spawn_blocking(move || f(&mut inner.lock().unwrap())).await.map_err(std::io::Error::from)?
+    }
+}
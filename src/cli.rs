@@ -1,9 +1,197 @@
-use crate::{Database, Result};
-use std::io::{self, Write};
+use crate::db::recovery::DEFAULT_RECOVERY_PROGRESS_EVERY;
+use crate::db::{Operation, WriteAheadLog};
+use crate::{ChangeEvent, Database, LohError, RecoveryProgress, Result, ValueMeta, VerifyReport};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crossbeam::channel::Receiver;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Writes library log records to stderr
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}: {}", record.level().as_str().to_lowercase(), record.args());
+        }
+    }
+    
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Print library log messages on stderr, at the level named by the
+/// `LOHDB_LOG` environment variable (`error` to `trace`; default `warn`)
+///
+/// Does nothing if the process already installed a logger.
+pub fn init_logging() {
+    let level = std::env::var("LOHDB_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(log::LevelFilter::Warn);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// How a value is written on the command line and printed by `get`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    /// UTF-8 text, taken as typed
+    Text,
+    /// Two hex digits per byte, either case
+    Hex,
+    /// Standard, padded base64
+    Base64,
+}
+
+impl ValueFormat {
+    /// Take a leading `--hex` or `--base64` flag off `args`
+    pub fn from_args<'a, 'b>(args: &'a [&'b str]) -> (Self, &'a [&'b str]) {
+        match args.first() {
+            Some(&"--hex") => (ValueFormat::Hex, &args[1..]),
+            Some(&"--base64") => (ValueFormat::Base64, &args[1..]),
+            _ => (ValueFormat::Text, args),
+        }
+    }
+    
+    /// Turn the value as typed into the bytes to store
+    pub fn decode(self, input: &str) -> Result<Vec<u8>> {
+        match self {
+            ValueFormat::Text => Ok(input.as_bytes().to_vec()),
+            ValueFormat::Hex => decode_hex(input),
+            ValueFormat::Base64 => BASE64
+                .decode(input)
+                .map_err(|e| LohError::InvalidArgument(format!("invalid base64 value: {}", e))),
+        }
+    }
+    
+    /// Render stored bytes for display, or `None` if they are not valid text
+    pub fn encode(self, value: &[u8]) -> Option<String> {
+        match self {
+            ValueFormat::Text => std::str::from_utf8(value).ok().map(str::to_string),
+            ValueFormat::Hex => Some(value.iter().map(|b| format!("{:02x}", b)).collect()),
+            ValueFormat::Base64 => Some(BASE64.encode(value)),
+        }
+    }
+}
+
+fn decode_hex(input: &str) -> Result<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        return Err(LohError::InvalidArgument(format!(
+            "invalid hex value: expected an even number of digits, got {}",
+            input.len()
+        )));
+    }
+    input
+        .as_bytes()
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| LohError::InvalidArgument(format!(
+                    "invalid hex value: '{}' at position {} is not a hex byte",
+                    String::from_utf8_lossy(pair), i * 2
+                )))
+        })
+        .collect()
+}
+
+/// Split a command line into words
+///
+/// Single or double quotes group words containing whitespace. Inside double
+/// quotes, `\"`, `\\`, `\n` and `\t` are unescaped.
+pub fn split_args(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(words);
+        }
+        
+        let mut word = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            match c {
+                '\'' => loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(LohError::InvalidArgument("unterminated ' quote".to_string())),
+                    }
+                },
+                '"' => loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some(c) => word.push(c),
+                            None => return Err(LohError::InvalidArgument("unterminated \" quote".to_string())),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(LohError::InvalidArgument("unterminated \" quote".to_string())),
+                    }
+                },
+                c => word.push(c),
+            }
+        }
+        words.push(word);
+    }
+}
+
+/// Whether the session continues after a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+/// How command results and errors are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Decorated text for people (the default)
+    #[default]
+    Human,
+    /// One JSON object per command on stdout, errors included
+    Json,
+}
+
+impl OutputFormat {
+    /// Report a failed command, with the script line it came from if any
+    ///
+    /// Human errors go to stderr; JSON errors go to stdout as
+    /// `{"error": "..."}` so they interleave with the results.
+    pub fn print_error(self, error: &LohError, line: Option<usize>) {
+        match (self, line) {
+            (OutputFormat::Human, Some(line)) => eprintln!("❌ Error on line {}: {}", line, error),
+            (OutputFormat::Human, None) => eprintln!("❌ Error: {}", error),
+            (OutputFormat::Json, Some(line)) => print_json(json!({ "error": error.to_string(), "line": line })),
+            (OutputFormat::Json, None) => print_json(json!({ "error": error.to_string() })),
+        }
+    }
+}
+
+fn print_json(value: serde_json::Value) {
+    println!("{}", value);
+}
 
 pub fn run_cli(mut db: Database) -> Result<()> {
     println!("LohDB Interactive CLI");
-    println!("Commands: set <key> <value>, get <key>, delete <key>, list, quit");
+    println!("Commands: set [--hex|--base64] <key> <value>, get [--hex|--base64] <key>, info <key>, delete <key>, delif [--hex|--base64] <key> <value>, take [--hex|--base64] <key>, rename [--overwrite] <from> <to>, delprefix <prefix>, list [--limit <n>] [--after <key>], stats, profile, ttl <key>, persist <key>, expire, compact, export <path>, import <path>, export-csv <path>, import-csv <path>, quit");
+    println!("Quote values containing spaces: set greeting \"hello world\"");
     
     // Subscribe to changes for demo
     let _subscription = db.subscribe(|event| {
@@ -15,69 +203,743 @@ pub fn run_cli(mut db: Database) -> Result<()> {
         io::stdout().flush()?;
         
         let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+        if io::stdin().read_line(&mut input)? == 0 {
+            break;
+        }
         
-        let parts: Vec<&str> = input.trim().split_whitespace().collect();
-        if parts.is_empty() {
+        match run_command(&mut db, &input, OutputFormat::Human) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => break,
+            Err(e) => println!("❌ Error: {}", e),
+        }
+    }
+    
+    db.close()
+}
+
+/// Run commands read line by line from `input`, stopping at the first failure
+///
+/// Blank lines and lines starting with `#` are skipped. A failing command is
+/// reported with its line number and its error is returned.
+pub fn run_script(db: &mut Database, input: impl BufRead, output: OutputFormat) -> Result<()> {
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim_start().starts_with('#') {
             continue;
         }
-        
-        match parts[0].to_lowercase().as_str() {
-            "set" if parts.len() == 3 => {
-                let key = parts[1].to_string();
-                let value = parts[2].as_bytes().to_vec();
-                match db.set(key.clone(), value) {
-                    Ok(_) => println!("✅ Set '{}' successfully", key),
-                    Err(e) => println!("❌ Error: {}", e),
-                }
-            }
-            "get" if parts.len() == 2 => {
-                let key = parts[1];
-                match db.get(key) {
-                    Ok(Some(value)) => {
-                        match String::from_utf8(value) {
-                            Ok(s) => println!("📄 '{}' = '{}'", key, s),
-                            Err(_) => println!("📄 '{}' = <binary data>", key),
-                        }
-                    }
-                    Ok(None) => println!("🔍 Key '{}' not found", key),
-                    Err(e) => println!("❌ Error: {}", e),
-                }
-            }
-            "delete" if parts.len() == 2 => {
-                let key = parts[1];
-                match db.delete(key) {
-                    Ok(existed) => {
-                        if existed {
-                            println!("🗑️  Deleted '{}'", key);
-                        } else {
-                            println!("🔍 Key '{}' not found", key);
-                        }
-                    }
-                    Err(e) => println!("❌ Error: {}", e),
+        match run_command(db, &line, output) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => break,
+            Err(e) => {
+                output.print_error(&e, Some(index + 1));
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One parsed command line, ready to run against a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `set [--hex|--base64] <key> <value>`, with the value already decoded
+    Set { key: String, value: Vec<u8> },
+    Get { key: String, format: ValueFormat },
+    Info { key: String },
+    Delete { key: String },
+    /// `delif [--hex|--base64] <key> <value>`, with the value already decoded
+    DeleteIf { key: String, expected: Vec<u8> },
+    /// `take [--hex|--base64] <key>`: delete the key and print its value
+    Take { key: String, format: ValueFormat },
+    Rename { from: String, to: String, overwrite: bool },
+    DeletePrefix { prefix: String },
+    /// `list` with no options: every key at once
+    List,
+    /// `list [--limit <n>] [--after <key>]`: one page of keys
+    ListPage { after: Option<String>, limit: usize },
+    Stats,
+    Profile,
+    Ttl { key: String },
+    Persist { key: String },
+    Compact,
+    Expire,
+    Export { path: String },
+    Import { path: String },
+    ExportCsv { path: String },
+    ImportCsv { path: String },
+    Quit,
+}
+
+/// Parse one command line, or `None` for a blank line
+///
+/// Everything after the key of a `set` or `delif` is its value: quote it to keep
+/// whitespace as typed, or leave it unquoted to have the words joined with
+/// single spaces. Unknown commands and bad arguments are errors.
+pub fn parse_command(line: &str) -> Result<Option<Command>> {
+    let words = split_args(line)?;
+    let parts: Vec<&str> = words.iter().map(String::as_str).collect();
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    
+    let command = match parts[0].to_lowercase().as_str() {
+        "set" if parts.len() >= 3 => {
+            let (format, args) = ValueFormat::from_args(&parts[1..]);
+            if args.len() < 2 {
+                return Err(usage("set [--hex|--base64] <key> <value>"));
+            }
+            // Unquoted words of a text value are joined with single spaces
+            let value = format.decode(&args[1..].join(" "))?;
+            Command::Set { key: args[0].to_string(), value }
+        }
+        "get" if parts.len() == 2 || parts.len() == 3 => {
+            let (format, args) = ValueFormat::from_args(&parts[1..]);
+            if args.len() != 1 {
+                return Err(usage("get [--hex|--base64] <key>"));
+            }
+            Command::Get { key: args[0].to_string(), format }
+        }
+        "delif" if parts.len() >= 3 => {
+            let (format, args) = ValueFormat::from_args(&parts[1..]);
+            if args.len() < 2 {
+                return Err(usage("delif [--hex|--base64] <key> <value>"));
+            }
+            let expected = format.decode(&args[1..].join(" "))?;
+            Command::DeleteIf { key: args[0].to_string(), expected }
+        }
+        "take" if parts.len() == 2 || parts.len() == 3 => {
+            let (format, args) = ValueFormat::from_args(&parts[1..]);
+            if args.len() != 1 {
+                return Err(usage("take [--hex|--base64] <key>"));
+            }
+            Command::Take { key: args[0].to_string(), format }
+        }
+        "info" if parts.len() == 2 => Command::Info { key: parts[1].to_string() },
+        "delete" if parts.len() == 2 => Command::Delete { key: parts[1].to_string() },
+        "rename" if parts.len() == 3 || (parts.len() == 4 && parts[1] == "--overwrite") => Command::Rename {
+            from: parts[parts.len() - 2].to_string(),
+            to: parts[parts.len() - 1].to_string(),
+            overwrite: parts.len() == 4,
+        },
+        "delprefix" if parts.len() == 2 => {
+            // An empty prefix would silently wipe the whole namespace
+            if parts[1].is_empty() {
+                return Err(usage("delprefix <prefix> (the prefix must not be empty)"));
+            }
+            Command::DeletePrefix { prefix: parts[1].to_string() }
+        }
+        "list" if parts.len() == 1 => Command::List,
+        "list" => {
+            let (after, limit) = parse_list_options(&parts[1..])?;
+            Command::ListPage { after: after.map(str::to_string), limit }
+        }
+        "stats" => Command::Stats,
+        "profile" if parts.len() == 1 => Command::Profile,
+        "ttl" if parts.len() == 2 => Command::Ttl { key: parts[1].to_string() },
+        "persist" if parts.len() == 2 => Command::Persist { key: parts[1].to_string() },
+        "compact" if parts.len() == 1 => Command::Compact,
+        "expire" if parts.len() == 1 => Command::Expire,
+        "export" if parts.len() == 2 => Command::Export { path: parts[1].to_string() },
+        "import" if parts.len() == 2 => Command::Import { path: parts[1].to_string() },
+        "export-csv" if parts.len() == 2 => Command::ExportCsv { path: parts[1].to_string() },
+        "import-csv" if parts.len() == 2 => Command::ImportCsv { path: parts[1].to_string() },
+        "quit" | "exit" => Command::Quit,
+        _ => {
+            return Err(LohError::InvalidArgument(format!(
+                "unknown command '{}'. Available: set, get, delete, delif, take, rename, delprefix, list, stats, profile, ttl, persist, expire, compact, export, import, export-csv, import-csv, quit",
+                line.trim()
+            )));
+        }
+    };
+    Ok(Some(command))
+}
+
+/// Parse and run one command line, printing its result in `output` format
+///
+/// Failed commands, including unknown commands and bad arguments, return
+/// an error rather than printing it. A blank line does nothing.
+pub fn run_command(db: &mut Database, line: &str, output: OutputFormat) -> Result<Flow> {
+    let Some(command) = parse_command(line)? else {
+        return Ok(Flow::Continue);
+    };
+    let json = output == OutputFormat::Json;
+    
+    match command {
+        Command::Set { key, value } => {
+            db.set(key.clone(), value)?;
+            if json {
+                print_json(json!({ "key": key, "ok": true }));
+            } else {
+                println!("✅ Set '{}' successfully", key);
+            }
+        }
+        Command::Get { key, format } => {
+            // A missing key fails the command, so scripts stop on it
+            let value = db.get_required(&key)?;
+            if json {
+                let (value, encoding) = json_value(format, &value);
+                print_json(json!({ "key": key, "found": true, "value": value, "encoding": encoding }));
+            } else {
+                match format.encode(&value) {
+                    Some(s) => println!("📄 '{}' = '{}'", key, s),
+                    None => println!("📄 '{}' = <binary data, {} bytes; use get --hex or --base64>", key, value.len()),
                 }
             }
-            "list" => {
-                match db.list_keys() {
-                    Ok(keys) => {
-                        if keys.is_empty() {
-                            println!("📭 Database is empty");
-                        } else {
-                            println!("📋 Keys ({}): {}", keys.len(), keys.join(", "));
-                        }
+        }
+        Command::Info { key } => {
+            let info = db.get_with_metadata(&key)?;
+            let modified_ms = |info: &ValueMeta| {
+                info.last_modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+            };
+            match (info, json) {
+                (Some(info), true) => print_json(json!({
+                    "key": key,
+                    "found": true,
+                    "size": info.size,
+                    "version": info.version,
+                    "modified_ms": modified_ms(&info),
+                })),
+                (Some(info), false) => println!(
+                    "ℹ️  '{}': {} bytes, version {}, last modified {}",
+                    key, info.size, info.version, format_timestamp(modified_ms(&info))
+                ),
+                (None, true) => print_json(json!({ "key": key, "found": false })),
+                (None, false) => println!("🔍 Key '{}' not found", key),
+            }
+        }
+        Command::Delete { key } => {
+            let deleted = db.delete(&key)?;
+            if json {
+                print_json(json!({ "key": key, "deleted": deleted }));
+            } else if deleted {
+                println!("🗑️  Deleted '{}'", key);
+            } else {
+                println!("🔍 Key '{}' not found", key);
+            }
+        }
+        Command::DeleteIf { key, expected } => {
+            let deleted = db.delete_if(&key, &expected)?;
+            if json {
+                print_json(json!({ "key": key, "deleted": deleted }));
+            } else if deleted {
+                println!("🗑️  Deleted '{}'", key);
+            } else {
+                println!("⏭️  Kept '{}': it is missing or holds another value", key);
+            }
+        }
+        Command::Take { key, format } => {
+            let value = db.take(&key)?;
+            match (value, json) {
+                (Some(value), true) => {
+                    let (value, encoding) = json_value(format, &value);
+                    print_json(json!({ "key": key, "taken": true, "value": value, "encoding": encoding }));
+                }
+                (None, true) => print_json(json!({ "key": key, "taken": false })),
+                (Some(value), false) => match format.encode(&value) {
+                    Some(s) => println!("📤 Took '{}' = '{}'", key, s),
+                    None => println!("📤 Took '{}' = <binary data, {} bytes; use take --hex or --base64>", key, value.len()),
+                },
+                (None, false) => println!("🔍 Key '{}' not found", key),
+            }
+        }
+        Command::Rename { from, to, overwrite } => {
+            let renamed = db.rename(&from, &to, overwrite)?;
+            if json {
+                print_json(json!({ "from": from, "to": to, "renamed": renamed }));
+            } else if renamed {
+                println!("✏️  Renamed '{}' to '{}'", from, to);
+            } else {
+                println!("🔍 Key '{}' not found", from);
+            }
+        }
+        Command::DeletePrefix { prefix } => {
+            let deleted = db.delete_prefix(&prefix)?;
+            if json {
+                print_json(json!({ "prefix": prefix, "deleted": deleted }));
+            } else {
+                println!("🗑️  Deleted {} key(s) starting with '{}'", deleted, prefix);
+            }
+        }
+        Command::List => {
+            let keys = db.list_keys()?;
+            if json {
+                print_json(json!({ "keys": keys }));
+            } else if keys.is_empty() {
+                println!("📭 Database is empty");
+            } else {
+                println!("📋 Keys ({}): {}", keys.len(), keys.join(", "));
+            }
+        }
+        Command::ListPage { after, limit } => {
+            let (keys, next) = db.list_keys_paged(after.as_deref(), limit)?;
+            if json {
+                print_json(json!({ "keys": keys, "next": next }));
+            } else {
+                if keys.is_empty() {
+                    println!("📭 No more keys");
+                } else {
+                    println!("📋 Keys ({}): {}", keys.len(), keys.join(", "));
+                }
+                if let Some(next) = next {
+                    println!("➡️  More keys: list --limit {} --after {}", limit, next);
+                }
+            }
+        }
+        Command::Stats => {
+            let stats = db.stats()?;
+            if json {
+                print_json(serde_json::to_value(&stats)?);
+            } else {
+                println!("📊 Keys: {}", stats.num_keys);
+                println!("   Data file: {} bytes", stats.data_file_bytes);
+                if let Some(dedup) = &stats.dedup {
+                    println!("   Deduplicated: {} distinct values for {} keys", dedup.blobs, dedup.references);
+                }
+                println!("   WAL: {} bytes, {} records", stats.wal_file_bytes, stats.wal_entries);
+                println!("   Ops since open: {} sets, {} gets, {} deletes", stats.sets, stats.gets, stats.deletes);
+                println!("   Expired since open: {} keys", stats.expired_keys);
+                if let Some(error) = &stats.last_flush_error {
+                    println!("⚠️  Last background flush failed: {}", error);
+                }
+                if let Some(error) = &stats.replay.first_error {
+                    println!("⚠️  WAL replay at open skipped {} record(s): {}", stats.replay.skipped, error);
+                }
+            }
+        }
+        Command::Profile => {
+            let profile = db.size_profile()?;
+            if json {
+                print_json(serde_json::to_value(&profile)?);
+            } else {
+                println!("📏 Keys: {}", profile.keys);
+                for (name, sizes) in [("Key", &profile.key_bytes), ("Value", &profile.value_bytes)] {
+                    println!(
+                        "   {} bytes: min {}, p50 {}, mean {:.1}, p99 {}, max {}, total {}",
+                        name, sizes.min, sizes.p50, sizes.mean, sizes.p99, sizes.max, sizes.total
+                    );
+                }
+                if let Some(largest) = &profile.largest_value {
+                    match &largest.namespace {
+                        Some(ns) => println!("   Largest value: {} in namespace {} ({} bytes)", largest.key, ns, largest.bytes),
+                        None => println!("   Largest value: {} ({} bytes)", largest.key, largest.bytes),
                     }
-                    Err(e) => println!("❌ Error: {}", e),
                 }
             }
-            "quit" | "exit" => {
+        }
+        Command::Ttl { key } => {
+            // A missing key is an answer here, like `get`, not a failure
+            let ttl_ms = match db.ttl(&key) {
+                Ok(ttl) => Some(ttl.map(|ttl| ttl.as_millis() as u64)),
+                Err(LohError::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            match (ttl_ms, json) {
+                (Some(ttl_ms), true) => print_json(json!({ "key": key, "found": true, "ttl_ms": ttl_ms })),
+                (Some(Some(ttl_ms)), false) => println!("⏳ '{}' expires in {}.{:03}s", key, ttl_ms / 1000, ttl_ms % 1000),
+                (Some(None), false) => println!("♾️  '{}' does not expire", key),
+                (None, true) => print_json(json!({ "key": key, "found": false })),
+                (None, false) => println!("🔍 Key '{}' not found", key),
+            }
+        }
+        Command::Persist { key } => {
+            let persisted = db.persist(&key)?;
+            if json {
+                print_json(json!({ "key": key, "persisted": persisted }));
+            } else if persisted {
+                println!("📌 '{}' no longer expires", key);
+            } else {
+                println!("🔍 '{}' has no expiry to remove", key);
+            }
+        }
+        Command::Compact => {
+            let stats = db.compact()?;
+            if json {
+                print_json(json!({
+                    "bytes_before": stats.bytes_before,
+                    "bytes_after": stats.bytes_after,
+                    "bytes_reclaimed": stats.bytes_reclaimed(),
+                }));
+            } else {
+                println!("🗜️  Compacted: reclaimed {} bytes ({} -> {})", stats.bytes_reclaimed(), stats.bytes_before, stats.bytes_after);
+            }
+        }
+        Command::Expire => {
+            let removed = db.expire_now()?;
+            if json {
+                print_json(json!({ "expired": removed }));
+            } else {
+                println!("⏰ Removed {} expired key(s)", removed);
+            }
+        }
+        Command::Export { path } => {
+            let file = std::fs::File::create(&path)?;
+            db.export_json(io::BufWriter::new(file))?;
+            if json {
+                print_json(json!({ "exported": path }));
+            } else {
+                println!("💾 Exported to '{}'", path);
+            }
+        }
+        Command::Import { path } => {
+            let file = std::fs::File::open(&path)?;
+            db.import_json(io::BufReader::new(file))?;
+            if json {
+                print_json(json!({ "imported": path }));
+            } else {
+                println!("📥 Imported from '{}'", path);
+            }
+        }
+        Command::ExportCsv { path } => {
+            let file = std::fs::File::create(&path)?;
+            db.export_csv(io::BufWriter::new(file))?;
+            if json {
+                print_json(json!({ "exported": path }));
+            } else {
+                println!("💾 Exported to '{}'", path);
+            }
+        }
+        Command::ImportCsv { path } => {
+            let file = std::fs::File::open(&path)?;
+            db.import_csv(file)?;
+            if json {
+                print_json(json!({ "imported": path }));
+            } else {
+                println!("📥 Imported from '{}'", path);
+            }
+        }
+        Command::Quit => {
+            if !json {
                 println!("👋 Goodbye!");
+            }
+            return Ok(Flow::Quit);
+        }
+    }
+    
+    Ok(Flow::Continue)
+}
+
+/// Print the outcome of `Database::verify` or `Database::repair`
+pub fn print_verify_report(report: &VerifyReport, output: OutputFormat, mut out: impl Write) -> Result<()> {
+    if output == OutputFormat::Json {
+        writeln!(out, "{}", serde_json::to_string(report)?)?;
+        return Ok(());
+    }
+    
+    writeln!(
+        out,
+        "WAL: {} record(s) in {} segment(s), sequences {} to {}",
+        report.wal_records, report.wal_segments, report.wal_first_seq, report.wal_last_seq
+    )?;
+    if let Some(corruption) = &report.wal_corruption {
+        writeln!(out, "⚠️  WAL unreadable at {}@{}: {}", corruption.segment, corruption.offset, corruption.error)?;
+        if corruption.at_tail {
+            writeln!(out, "   The damage is in the last segment; `verify --repair` can truncate it")?;
+        }
+    }
+    match (&report.data_file_error, report.data_file_present) {
+        (Some(error), _) => writeln!(out, "⚠️  Data file: {}", error)?,
+        (None, true) => writeln!(out, "Data file: {} key(s), checksum ok", report.data_file_keys)?,
+        (None, false) => writeln!(out, "Data file: none")?,
+    }
+    if report.consistent {
+        writeln!(out, "✅ Consistent")?;
+    } else {
+        writeln!(out, "❌ Inconsistent")?;
+    }
+    Ok(())
+}
+
+/// Show WAL replay progress on one stderr line that rewrites itself
+///
+/// Meant for `DatabaseConfig::on_recovery_progress` at the default interval;
+/// a replay that finishes before the first report prints nothing.
+pub fn print_recovery_progress(progress: RecoveryProgress) {
+    if progress.done && progress.entries < DEFAULT_RECOVERY_PROGRESS_EVERY {
+        return;
+    }
+    let percent = match progress.total_bytes {
+        0 => 100,
+        total => progress.bytes_read * 100 / total,
+    };
+    eprint!("\rReplaying WAL: {} record(s), {}% of {} bytes", progress.entries, percent, progress.total_bytes);
+    if progress.done {
+        eprintln!();
+    }
+}
+
+/// Most events printed between flushes while watching a busy database
+const WATCH_BATCH: usize = 256;
+
+/// Print change events as they arrive until `stop` fires or `events` closes
+///
+/// Events already queued are written in batches with one flush each, so a
+/// burst of writes does not turn into a syscall per event. Returns how many
+/// events were printed; a closed `out` (e.g. a pipe into `head`) ends the
+/// watch quietly.
+pub fn watch(
+    events: &Receiver<ChangeEvent>,
+    stop: &Receiver<()>,
+    output: OutputFormat,
+    out: impl Write,
+) -> Result<u64> {
+    let mut out = io::BufWriter::new(out);
+    let mut printed = 0;
+    
+    loop {
+        let first = crossbeam::select! {
+            recv(events) -> event => match event {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+            recv(stop) -> _ => break,
+        };
+        
+        let mut batch = std::iter::once(first).chain(events.try_iter().take(WATCH_BATCH - 1));
+        let result = batch
+            .try_for_each(|event| {
+                printed += 1;
+                write_event(&mut out, &event, output)
+            })
+            .and_then(|_| out.flush());
+        match result {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    
+    Ok(printed)
+}
+
+fn write_event(out: &mut impl Write, event: &ChangeEvent, output: OutputFormat) -> io::Result<()> {
+    let (kind, value) = match event {
+        ChangeEvent::Set { value, .. } => ("set", Some(value)),
+        ChangeEvent::Delete { .. } => ("delete", None),
+        ChangeEvent::Append { chunk, .. } => ("append", Some(chunk)),
+        ChangeEvent::Evicted { .. } => ("evicted", None),
+        ChangeEvent::Expired { .. } => ("expired", None),
+    };
+    
+    if output == OutputFormat::Json {
+        let mut line = json!({ "event": kind, "namespace": event.namespace(), "key": event.key() });
+        if let Some(value) = value {
+            let (value, encoding) = json_value(ValueFormat::Text, value);
+            line["value"] = value.into();
+            line["encoding"] = encoding.into();
+        }
+        return writeln!(out, "{}", line);
+    }
+    
+    let scope = event.namespace().map(|ns| format!("[{}] ", ns)).unwrap_or_default();
+    match value {
+        Some(value) => match ValueFormat::Text.encode(value) {
+            Some(text) => writeln!(out, "📡 {}{} '{}' = '{}'", scope, kind, event.key(), text),
+            None => writeln!(out, "📡 {}{} '{}' = <binary data, {} bytes>", scope, kind, event.key(), value.len()),
+        },
+        None => writeln!(out, "📡 {}{} '{}'", scope, kind, event.key()),
+    }
+}
+
+/// Print every record in the WAL under `dir`, oldest first
+///
+/// The log is opened read-only, so this works while the database is closed
+/// and changes nothing. Values longer than `max_value_bytes` are cut short.
+/// Reading stops with a warning at the first record that cannot be read,
+/// such as a torn tail left by a crash. Returns how many records were printed.
+pub fn dump_wal(dir: &Path, max_value_bytes: usize, output: OutputFormat, mut out: impl Write) -> Result<u64> {
+    let wal = WriteAheadLog::open_read_only(dir)?;
+    let mut printed = 0;
+    
+    for located in wal.iter_entries() {
+        let located = match located {
+            Ok(located) => located,
+            Err(e) => {
+                match output {
+                    OutputFormat::Human => eprintln!("⚠️  Warning: stopped reading the WAL: {}", e),
+                    OutputFormat::Json => writeln!(out, "{}", json!({ "warning": e.to_string() }))?,
+                }
                 break;
             }
-            _ => {
-                println!("❓ Unknown command. Available: set, get, delete, list, quit");
+        };
+        
+        let segment = located.segment.file_name().unwrap_or_default().to_string_lossy();
+        let entry = &located.entry;
+        // A batch prints as one line per write, all with the batch's sequence number
+        for operation in flatten_batches(&entry.operation) {
+            let (op, namespace, key, value) = match operation {
+                Operation::Set { namespace, key, value } => ("set", namespace, key, Some(value)),
+                Operation::Delete { namespace, key } => ("delete", namespace, key, None),
+                Operation::SetWithExpiry { namespace, key, value, .. } => ("set", namespace, key, Some(value)),
+                Operation::SetWithMeta { namespace, key, value, .. } => ("set", namespace, key, Some(value)),
+                Operation::DeletePrefix { namespace, prefix, .. } => ("delete_prefix", namespace, prefix, None),
+                Operation::Append { namespace, key, chunk, .. } => ("append", namespace, key, Some(chunk)),
+                Operation::Persist { namespace, key } => ("persist", namespace, key, None),
+                Operation::Expire { namespace, key } => ("expire", namespace, key, None),
+                Operation::Rename { namespace, from, value, .. } => ("rename", namespace, from, Some(value)),
+                Operation::StreamChunk { namespace, key, chunk, .. } => ("stream_chunk", namespace, key, Some(chunk)),
+                Operation::StreamCommit { namespace, key, .. } => ("stream_commit", namespace, key, None),
+                Operation::Batch { .. } => unreachable!("batches are flattened"),
+            };
+            let expires_ms = match operation {
+                Operation::SetWithExpiry { expires_ms, .. } => Some(*expires_ms),
+                _ => None,
+            };
+            let meta = match operation {
+                Operation::SetWithMeta { meta, .. } => Some(meta.iter().collect::<BTreeMap<_, _>>()),
+                _ => None,
+            };
+            let renamed_to = match operation {
+                Operation::Rename { to, .. } => Some(to),
+                _ => None,
+            };
+            
+            if output == OutputFormat::Json {
+                let mut line = json!({
+                    "seq": entry.seq,
+                    "segment": segment,
+                    "offset": located.offset,
+                    "timestamp_ms": entry.timestamp_ms,
+                    "op": op,
+                    "namespace": namespace,
+                    "key": key,
+                });
+                if let Some(to) = renamed_to {
+                    line["to"] = to.as_str().into();
+                }
+                if let Some(expires_ms) = expires_ms {
+                    line["expires_ms"] = expires_ms.into();
+                }
+                if let Some(meta) = &meta {
+                    line["meta"] = json!(meta);
+                }
+                if let Some(value) = value {
+                    let shown = &value[..value.len().min(max_value_bytes)];
+                    let (shown, encoding) = json_value(ValueFormat::Text, shown);
+                    line["value"] = shown.into();
+                    line["encoding"] = encoding.into();
+                    line["value_bytes"] = value.len().into();
+                    line["truncated"] = (value.len() > max_value_bytes).into();
+                }
+                writeln!(out, "{}", line)?;
+            } else {
+                let scope = namespace.as_ref().map(|ns| format!("[{}] ", ns)).unwrap_or_default();
+                write!(
+                    out,
+                    "#{} {}@{} {} {} {}'{}'",
+                    entry.seq, segment, located.offset, format_timestamp(entry.timestamp_ms), op.to_uppercase(), scope, key
+                )?;
+                if let Some(to) = renamed_to {
+                    write!(out, " -> '{}'", to)?;
+                }
+                if let Some(value) = value {
+                    write!(out, " = {}", preview_value(value, max_value_bytes))?;
+                }
+                if let Some(meta) = &meta {
+                    let pairs: Vec<String> = meta.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                    write!(out, " {{{}}}", pairs.join(", "))?;
+                }
+                match expires_ms {
+                    Some(expires_ms) => writeln!(out, " (expires {})", format_timestamp(expires_ms))?,
+                    None => writeln!(out)?,
+                }
             }
         }
+        printed += 1;
     }
     
-    Ok(())
+    out.flush()?;
+    Ok(printed)
+}
+
+/// The writes in `operation`, with any batches expanded
+fn flatten_batches(operation: &Operation) -> Vec<&Operation> {
+    match operation {
+        Operation::Batch { ops } => ops.iter().flat_map(flatten_batches).collect(),
+        operation => vec![operation],
+    }
+}
+
+/// Show a value as text (or hex if it is binary), cut to `max_bytes`
+fn preview_value(value: &[u8], max_bytes: usize) -> String {
+    let shown = &value[..value.len().min(max_bytes)];
+    let text = match std::str::from_utf8(shown) {
+        Ok(text) => format!("'{}'", text),
+        // A cut through a multi-byte character leaves valid text before it
+        Err(e) if e.error_len().is_none() && e.valid_up_to() > 0 => {
+            format!("'{}'", String::from_utf8_lossy(&shown[..e.valid_up_to()]))
+        }
+        Err(_) => format!("0x{}", ValueFormat::Hex.encode(shown).unwrap_or_default()),
+    };
+    if value.len() > max_bytes {
+        format!("{}… ({} bytes)", text, value.len())
+    } else {
+        text
+    }
+}
+
+/// Render milliseconds since the Unix epoch as an ISO 8601 UTC time
+///
+/// Records upgraded from a pre-versioning log carry no time and show as `-`.
+fn format_timestamp(timestamp_ms: u64) -> String {
+    if timestamp_ms == 0 {
+        return "-".to_string();
+    }
+    let secs = timestamp_ms / 1000;
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60, timestamp_ms % 1000
+    )
+}
+
+/// Encode a value for JSON output, returning it with the encoding's name
+///
+/// Text stays readable when it is valid UTF-8 and falls back to base64.
+fn json_value(format: ValueFormat, value: &[u8]) -> (String, &'static str) {
+    match format {
+        ValueFormat::Hex => (ValueFormat::Hex.encode(value).unwrap_or_default(), "hex"),
+        ValueFormat::Text => match ValueFormat::Text.encode(value) {
+            Some(text) => (text, "utf8"),
+            None => (BASE64.encode(value), "base64"),
+        },
+        ValueFormat::Base64 => (BASE64.encode(value), "base64"),
+    }
+}
+
+fn usage(synopsis: &str) -> LohError {
+    LohError::InvalidArgument(format!("usage: {}", synopsis))
+}
+
+/// Parse `--limit <n>` and `--after <key>` for a paged `list`
+fn parse_list_options<'a>(args: &[&'a str]) -> Result<(Option<&'a str>, usize)> {
+    const DEFAULT_PAGE_SIZE: usize = 100;
+    
+    let mut after = None;
+    let mut limit = DEFAULT_PAGE_SIZE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--after" => {
+                after = Some(*args.next().ok_or_else(|| LohError::InvalidArgument("--after needs a key".to_string()))?);
+            }
+            "--limit" => {
+                let value = args.next().ok_or_else(|| LohError::InvalidArgument("--limit needs a number".to_string()))?;
+                limit = value.parse().map_err(|_| LohError::InvalidArgument(format!("invalid --limit '{}'", value)))?;
+            }
+            other => return Err(LohError::InvalidArgument(format!("unknown list option '{}'", other))),
+        }
+    }
+    Ok((after, limit))
 }
\ No newline at end of file
@@ -0,0 +1,66 @@
+use crate::db::manifest::{Manifest, MANIFEST_FILE};
+use crate::db::{dedup, shard, wal, Database};
+use crate::{LohError, Result};
+use std::fs;
+use std::path::Path;
+
+/// Data file copied alongside the WAL segments
+const DATA_FILE: &str = "data.db";
+
+impl Database {
+    /// Copy the database into `dest_dir` so it can be opened standalone
+    ///
+    /// Writers are paused while the storage is flushed and the manifest, data
+    /// file and WAL segments are copied, so the backup holds exactly the
+    /// state at the time of the call. Everything lands in `dest_dir`, even
+    /// for a database configured with `wal_dir` or `data_file`. `dest_dir` is created if needed and
+    /// must not already contain a database. In-memory databases have nothing to copy and fail.
+    pub fn backup_to(&self, dest_dir: &str) -> Result<()> {
+        if self.is_in_memory() {
+            return Err(LohError::InvalidArgument(
+                "an in-memory database has no files to back up; use export_json".to_string(),
+            ));
+        }
+        
+        let dest = Path::new(dest_dir);
+        if dest.join(DATA_FILE).exists() || dest.join(MANIFEST_FILE).exists() || wal::log_exists(dest)? {
+            return Err(LohError::InvalidArgument(format!(
+                "backup destination '{}' already contains a database",
+                dest_dir
+            )));
+        }
+        fs::create_dir_all(dest)?;
+        
+        let read_only = self.is_read_only();
+        let source = Path::new(self.data_dir());
+        let data_files = self.data_files();
+        let blob_file = self.blob_file();
+        self.with_quiesced(|wal, storage| {
+            if !read_only {
+                storage.flush()?;
+                wal.flush()?;
+            }
+            
+            // The copy gets the default layout, wherever the source keeps
+            // its WAL and data file
+            let dest_files = shard::shard_files(&dest.join(DATA_FILE), data_files.len());
+            let blob_files = blob_file.map(|blob_file| (blob_file, dedup::blob_file(&dest.join(DATA_FILE))));
+            for (data_file, dest_file) in data_files.iter().map(|path| path.as_path()).zip(dest_files).chain(blob_files) {
+                if data_file.exists() {
+                    fs::copy(data_file, dest_file)?;
+                }
+            }
+            if let Some(mut manifest) = Manifest::read(source)? {
+                manifest.wal_dir = None;
+                manifest.data_file = None;
+                manifest.write(dest, None)?;
+            }
+            for segment in wal.segment_paths() {
+                if let Some(name) = segment.file_name() {
+                    fs::copy(&segment, dest.join(name))?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
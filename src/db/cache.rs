@@ -0,0 +1,219 @@
+use crate::db::engine::Recency;
+use crate::db::meta::KeyMeta;
+use crate::db::{CompactStats, SnapshotEntries, StorageEngine};
+use crate::Result;
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Bounded LRU read cache in front of any storage engine
+///
+/// `retrieve` answers from the cache when it can and otherwise reads
+/// through to the wrapped engine, keeping what it read. Everything that can
+/// change a value (`store`, `store_at`, `remove`, `initialize`, and keys
+/// the wrapped engine evicts on its own) drops it from the cache first, so
+/// a hit is always the engine's current value. Only values that exist are
+/// cached; everything else is passed straight through.
+///
+/// Worth it in front of engines whose reads are not plain memory lookups,
+/// such as `MmapStorageEngine`; install one with `Database::migrate_to`.
+pub struct CachingStorageEngine<E: StorageEngine> {
+    inner: E,
+    cache: Mutex<ReadCache>,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Keys the wrapped engine evicted, not yet drained by `take_evicted`
+    evicted: Vec<String>,
+}
+
+#[derive(Default)]
+struct ReadCache {
+    values: HashMap<String, Arc<[u8]>>,
+    recency: Recency,
+}
+
+impl ReadCache {
+    fn invalidate(&mut self, key: &str) {
+        if self.values.remove(key).is_some() {
+            self.recency.forget(key);
+        }
+    }
+    
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl<E: StorageEngine> CachingStorageEngine<E> {
+    /// Cache up to `max_entries` values read from `inner`; 0 caches nothing
+    pub fn new(inner: E, max_entries: usize) -> Self {
+        Self {
+            inner,
+            cache: Mutex::default(),
+            max_entries,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evicted: Vec::new(),
+        }
+    }
+    
+    /// The wrapped engine
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+    
+    /// Reads answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+    
+    /// Reads passed through to the wrapped engine
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+    
+    /// Values currently cached
+    pub fn cached(&self) -> usize {
+        self.cache.lock().unwrap().values.len()
+    }
+    
+    /// Drop `key` from the cache, along with anything the wrapped engine
+    /// just evicted
+    fn invalidate(&mut self, key: &str) {
+        let evicted = self.inner.take_evicted();
+        let cache = self.cache.get_mut().unwrap();
+        cache.invalidate(key);
+        for key in &evicted {
+            cache.invalidate(key);
+        }
+        self.evicted.extend(evicted);
+    }
+}
+
+impl<E: StorageEngine> StorageEngine for CachingStorageEngine<E> {
+    fn initialize(&mut self) -> Result<()> {
+        self.cache.get_mut().unwrap().clear();
+        self.inner.initialize()
+    }
+    
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let result = self.inner.store(key, value);
+        self.invalidate(key);
+        result
+    }
+    
+    fn store_at(&mut self, key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+        let result = self.inner.store_at(key, value, modified_ms);
+        self.invalidate(key);
+        result
+    }
+    
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        if self.max_entries == 0 {
+            return self.inner.retrieve(key);
+        }
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(value) = cache.values.get(key).cloned() {
+                cache.recency.touch(key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(value.to_vec()));
+            }
+        }
+        
+        // Writes take `&mut self`, so none can land between this read and
+        // caching what it returned
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let Some(value) = self.inner.retrieve(key)? else {
+            return Ok(None);
+        };
+        let mut cache = self.cache.lock().unwrap();
+        cache.values.insert(key.to_string(), Arc::from(value.as_slice()));
+        cache.recency.touch(key);
+        while cache.values.len() > self.max_entries {
+            let Some(oldest) = cache.recency.oldest().cloned() else {
+                break;
+            };
+            cache.invalidate(&oldest);
+        }
+        Ok(Some(value))
+    }
+    
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        let result = self.inner.remove(key);
+        self.invalidate(key);
+        result
+    }
+    
+    fn metadata(&self, key: &str) -> Result<Option<KeyMeta>> {
+        self.inner.metadata(key)
+    }
+    
+    fn supports_expiry(&self) -> bool {
+        self.inner.supports_expiry()
+    }
+    
+    fn set_expiry(&mut self, key: &str, expires_ms: Option<u64>) -> Result<bool> {
+        self.inner.set_expiry(key, expires_ms)
+    }
+    
+    fn expired_keys(&self, now_ms: u64) -> Result<Vec<String>> {
+        self.inner.expired_keys(now_ms)
+    }
+    
+    fn supports_user_meta(&self) -> bool {
+        self.inner.supports_user_meta()
+    }
+    
+    fn set_user_meta(&mut self, key: &str, meta: Option<HashMap<String, String>>) -> Result<bool> {
+        self.inner.set_user_meta(key, meta)
+    }
+    
+    fn user_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        self.inner.user_meta(key)
+    }
+    
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.inner.list_keys()
+    }
+    
+    fn key_count(&self) -> Result<usize> {
+        self.inner.key_count()
+    }
+    
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        self.inner.scan_keys(start, limit)
+    }
+    
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        self.inner.scan_keys_rev(end, limit)
+    }
+    
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+    
+    fn disk_usage(&self) -> Result<u64> {
+        self.inner.disk_usage()
+    }
+    
+    fn compact(&mut self) -> Result<CompactStats> {
+        self.inner.compact()
+    }
+    
+    fn snapshot(&self) -> Result<SnapshotEntries> {
+        self.inner.snapshot()
+    }
+    
+    fn take_evicted(&mut self) -> Vec<String> {
+        let evicted = self.inner.take_evicted();
+        let cache = self.cache.get_mut().unwrap();
+        for key in &evicted {
+            cache.invalidate(key);
+        }
+        self.evicted.extend(evicted);
+        std::mem::take(&mut self.evicted)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{LohError, Result};
+
+/// Magic prefix of a checksummed data file
+const CHECKSUM_MAGIC: &[u8; 4] = b"LOHD";
+const CHECKSUM_VERSION: u8 = 1;
+/// Magic + version + CRC32 of the payload + payload length
+const HEADER_LEN: usize = CHECKSUM_MAGIC.len() + 1 + 4 + 8;
+
+/// Wrap the finished data file contents in a header holding their CRC32
+///
+/// This is the outermost layer, applied after compression and encryption,
+/// so any damage to the bytes on disk is caught before decoding starts.
+pub(crate) fn seal(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(CHECKSUM_MAGIC);
+    out.push(CHECKSUM_VERSION);
+    out.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Reverse `seal`, failing with `LohError::DataCorrupt` if the payload
+/// is truncated or does not match its checksum
+///
+/// Data files written before checksums were added have no header and are
+/// passed through unchecked.
+pub(crate) fn verify(mut data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(CHECKSUM_MAGIC) {
+        return Ok(data);
+    }
+    if data.len() < HEADER_LEN {
+        return Err(LohError::DataCorrupt(format!(
+            "header is truncated: {} of {} bytes present",
+            data.len(), HEADER_LEN
+        )));
+    }
+    
+    let version = data[CHECKSUM_MAGIC.len()];
+    if version != CHECKSUM_VERSION {
+        return Err(LohError::DataCorrupt(format!(
+            "uses checksum version {}, but this build only supports version {}",
+            version, CHECKSUM_VERSION
+        )));
+    }
+    
+    let crc_at = CHECKSUM_MAGIC.len() + 1;
+    let expected_crc = u32::from_le_bytes(data[crc_at..crc_at + 4].try_into().unwrap());
+    let expected_len = u64::from_le_bytes(data[crc_at + 4..HEADER_LEN].try_into().unwrap());
+    let payload = &data[HEADER_LEN..];
+    if payload.len() as u64 != expected_len {
+        return Err(LohError::DataCorrupt(format!(
+            "expected {} bytes of data but found {}",
+            expected_len, payload.len()
+        )));
+    }
+    
+    let actual_crc = crc32fast::hash(payload);
+    if actual_crc != expected_crc {
+        return Err(LohError::DataCorrupt(format!(
+            "checksum mismatch (stored {:08x}, computed {:08x})",
+            expected_crc, actual_crc
+        )));
+    }
+    data.drain(..HEADER_LEN);
+    Ok(data)
+}
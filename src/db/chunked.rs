@@ -0,0 +1,66 @@
+use crate::db::{namespace, Database, StorageEngine};
+use crate::{LohError, Result};
+
+/// Bytes of the little-endian `u32` length in front of each chunk
+const CHUNK_HEADER_LEN: usize = 4;
+
+/// Split a chunked value into its chunks
+pub(crate) fn split_chunks(value: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut chunks = Vec::new();
+    let mut rest = value;
+    while !rest.is_empty() {
+        let header = rest.get(..CHUNK_HEADER_LEN).ok_or_else(not_chunked)?;
+        let len = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+        let chunk = rest.get(CHUNK_HEADER_LEN..CHUNK_HEADER_LEN + len).ok_or_else(not_chunked)?;
+        chunks.push(chunk);
+        rest = &rest[CHUNK_HEADER_LEN + len..];
+    }
+    Ok(chunks)
+}
+
+fn not_chunked() -> LohError {
+    LohError::InvalidArgument("value is not a sequence of appended chunks".to_string())
+}
+
+/// The value of `storage_key` with `chunk` appended, starting from nothing
+/// if `fresh`
+pub(crate) fn appended(storage: &dyn StorageEngine, storage_key: &str, chunk: &[u8], fresh: bool) -> Result<Vec<u8>> {
+    let mut value = if fresh { Vec::new() } else { storage.retrieve(storage_key)?.unwrap_or_default() };
+    value.reserve(CHUNK_HEADER_LEN + chunk.len());
+    value.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    value.extend_from_slice(chunk);
+    Ok(value)
+}
+
+impl Database {
+    /// Append `chunk` to the chunked value at `key`, creating it if absent,
+    /// and return the value's new length in bytes
+    ///
+    /// Only the chunk is written to the WAL, so an append costs the same
+    /// however long the value has grown. A chunked value is stored as a
+    /// plain value made of one frame per chunk, oldest first:
+    ///
+    /// ```text
+    /// len: u32 LE | chunk bytes
+    /// ```
+    ///
+    /// so `get` returns the framed bytes and the length includes 4 bytes of
+    /// framing per chunk; `read_chunks` splits them again. Appending to a
+    /// value that was not built by `append` fails. Subscribers receive
+    /// `ChangeEvent::Append` with just the chunk. Like `set`, an append
+    /// bumps the version and clears any expiry; an expired value is
+    /// replaced, not extended.
+    pub fn append(&mut self, key: &str, chunk: &[u8]) -> Result<u64> {
+        namespace::validate_key(key)?;
+        self.apply_append(None, key, chunk)
+    }
+    
+    /// The chunks appended to `key`, oldest first, or an empty list if the
+    /// key is absent
+    pub fn read_chunks(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        let Some(value) = self.get(key)? else {
+            return Ok(Vec::new());
+        };
+        Ok(split_chunks(&value)?.into_iter().map(<[u8]>::to_vec).collect())
+    }
+}
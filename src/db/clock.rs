@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of the wall-clock time a database reads: WAL timestamps, and with
+/// them each key's last-modified time, and key expiry
+///
+/// Set on `DatabaseConfig::clock`. `SystemClock` is the default; tests can
+/// substitute a `MockClock` to move time forward by hand instead of
+/// sleeping. Intervals of background work (checkpoints, the expiry sweep's
+/// schedule) are still timed by the system: a mock clock decides when a
+/// key has expired, not how often the sweep looks.
+pub trait Clock: Send + Sync {
+    /// The current time
+    fn now(&self) -> SystemTime;
+    
+    /// The current time in milliseconds since the Unix epoch, or 0 before it
+    fn now_ms(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// The operating system's clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that stands still until told to move, for deterministic tests
+///
+/// Keeps millisecond precision, like every timestamp the database stores.
+/// Share it with the database through an `Arc` and keep a clone to
+/// `advance` it.
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now_ms: AtomicU64,
+}
+
+impl MockClock {
+    /// A clock reading `start`
+    pub fn new(start: SystemTime) -> Self {
+        let clock = Self::default();
+        clock.set(start);
+        clock
+    }
+    
+    /// A clock reading `now_ms` milliseconds since the Unix epoch
+    pub fn at_ms(now_ms: u64) -> Self {
+        Self { now_ms: AtomicU64::new(now_ms) }
+    }
+    
+    /// Move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        self.now_ms.fetch_add(by.as_millis().min(u64::MAX as u128) as u64, Ordering::SeqCst);
+    }
+    
+    /// Make the clock read `now`, which may be earlier than it does
+    pub fn set(&self, now: SystemTime) {
+        let now_ms = now.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.now_ms())
+    }
+    
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{LohError, Result};
+
+/// Magic prefix marking a compressed data file
+///
+/// An uncompressed data file starts with its format marker instead (or, if
+/// it predates format markers, the bincode length of the key map, which can
+/// never spell out these bytes in practice).
+const COMPRESSED_MAGIC: &[u8; 4] = b"LOHZ";
+
+/// Pluggable codec applied to the on-disk data snapshot
+pub trait Compressor: Send + Sync {
+    /// Stable identifier written into compressed files to detect codec mismatches
+    fn name(&self) -> &str;
+    
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>>;
+    
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// LZ4 block compression
+#[cfg(feature = "lz4")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lz4Compressor;
+
+#[cfg(feature = "lz4")]
+impl Compressor for Lz4Compressor {
+    fn name(&self) -> &str {
+        "lz4"
+    }
+    
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(lz4_flex::compress_prepend_size(data))
+    }
+    
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        lz4_flex::decompress_size_prepended(data).map_err(|e| LohError::Compression(format!("lz4: {}", e)))
+    }
+}
+
+/// Compress `data` and prefix it with the magic marker and codec name
+pub(crate) fn encode(compressor: &dyn Compressor, data: &[u8]) -> Result<Vec<u8>> {
+    let name = compressor.name().as_bytes();
+    if name.len() > u8::MAX as usize {
+        return Err(LohError::InvalidArgument(format!("compressor name '{}' is too long", compressor.name())));
+    }
+    
+    let compressed = compressor.compress(data)?;
+    let mut out = Vec::with_capacity(COMPRESSED_MAGIC.len() + 1 + name.len() + compressed.len());
+    out.extend_from_slice(COMPRESSED_MAGIC);
+    out.push(name.len() as u8);
+    out.extend_from_slice(name);
+    out.extend_from_slice(&compressed);
+    Ok(out)
+}
+
+/// Reverse `encode`, passing uncompressed data through unchanged
+///
+/// Errors if the data was written by a different codec than `compressor`,
+/// or is compressed while no compressor is configured.
+pub(crate) fn decode(compressor: Option<&dyn Compressor>, data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(rest) = data.strip_prefix(COMPRESSED_MAGIC) else {
+        return Ok(data);
+    };
+    
+    let (&name_len, rest) = rest
+        .split_first()
+        .ok_or_else(|| LohError::Compression("compressed data file is truncated".to_string()))?;
+    if rest.len() < name_len as usize {
+        return Err(LohError::Compression("compressed data file is truncated".to_string()));
+    }
+    let (name, payload) = rest.split_at(name_len as usize);
+    let name = String::from_utf8_lossy(name);
+    
+    match compressor {
+        Some(c) if c.name() == name => c.decompress(payload),
+        Some(c) => Err(LohError::Compression(format!(
+            "data file was compressed with '{}' but the database is configured with '{}'",
+            name,
+            c.name()
+        ))),
+        None => Err(LohError::Compression(format!(
+            "data file was compressed with '{}' but no compressor is configured",
+            name
+        ))),
+    }
+}
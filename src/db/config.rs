@@ -0,0 +1,535 @@
+use crate::db::recovery::DEFAULT_RECOVERY_PROGRESS_EVERY;
+use crate::db::subscriber::DEFAULT_SUBSCRIBER_BUFFER;
+use crate::db::wal::{DEFAULT_WAL_RETRY_BACKOFF, DEFAULT_WAL_SEGMENT_BYTES, DEFAULT_WAL_WRITE_RETRIES};
+use crate::db::{dedup, perms, shard, Clock, Compressor, FlushScheduler, OverflowPolicy, RecoveryObserver, RecoveryProgress, SerializationFormat, SystemClock};
+use crate::{LohError, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct DatabaseConfig {
+    pub data_dir: String,
+    /// Directory for the WAL segments instead of `data_dir`, such as one on
+    /// a faster device than the data file (default: none). The lock and
+    /// manifest stay in `data_dir`, so this directory must not be shared
+    /// with another database.
+    pub wal_dir: Option<String>,
+    /// Path of the data file instead of `data.db` in `data_dir`, such as one
+    /// on bulk storage (default: none)
+    ///
+    /// Both locations are recorded in the manifest, and reopening with a
+    /// different layout fails rather than silently starting from empty
+    /// files.
+    pub data_file: Option<String>,
+    /// Split the data file into this many shards by a hash of each key
+    /// (default 1: a single file), each flushed only when written to
+    ///
+    /// A checkpoint then rewrites just the shards changed since the last
+    /// one, instead of every key, so it holds up readers and writers for
+    /// less time when writes cluster on few keys between checkpoints. Each
+    /// shard also has its own lock, so `set`, `get` and `delete` of keys in
+    /// different shards apply to storage concurrently (unless `dedup` is
+    /// set, which shares values across shards); the WAL stays single, so
+    /// appending to it is still serialized, and each key's writes stay in
+    /// order. Shards are `data.0.db`, `data.1.db`, ... in place of the data
+    /// file; the count is recorded in the manifest and cannot change
+    /// afterwards. See `ShardedStorageEngine`.
+    pub shards: usize,
+    /// Store each distinct value once, however many keys hold it (default
+    /// false): keys point at their value by a hash of its bytes, and a value
+    /// no key refers to any more is dropped at the next checkpoint (at once
+    /// in memory)
+    ///
+    /// Values live in `data.blobs.db` beside the data file (or its shards),
+    /// which then holds only keys and their metadata. Pays off when many
+    /// keys hold the same large values; every set costs a hash and a read
+    /// of any stored value with the same hash. Recorded in the manifest and
+    /// cannot change afterwards. See `DedupStorageEngine`.
+    pub dedup: bool,
+    /// How often the database is checkpointed in the background: the storage
+    /// engine is flushed and the WAL segments it covers deleted. Zero
+    /// disables background checkpoints entirely: the WAL alone provides
+    /// durability and keeps growing until an explicit `Database::checkpoint`.
+    pub wal_sync_interval_ms: u64,
+    /// Also checkpoint in the background as soon as a write leaves the WAL
+    /// larger than this many bytes (default: none). Works alongside
+    /// `wal_sync_interval_ms` or, with that at zero, on its own, bounding
+    /// the WAL, and so recovery time, under bursts of writes.
+    pub checkpoint_wal_bytes: Option<u64>,
+    /// Also checkpoint in the background once the WAL holds more than this
+    /// many records per live key (default: none). Overwriting the same keys
+    /// over and over grows the log, and the work of replaying it, without
+    /// growing the data; the ratio is checked a few times a second.
+    pub checkpoint_ratio: Option<f64>,
+    /// Checkpoint after every this many writes (default: none), on the
+    /// writing thread: the write that completes the count returns once the
+    /// data file covers it and every write before it. Bounds what only the
+    /// WAL holds, and so recovery time, by write count rather than time;
+    /// works alongside the other triggers. Each call that logs a WAL record
+    /// (a set, a delete, a whole batch) counts once.
+    pub flush_every_n_writes: Option<u64>,
+    /// Thread that runs those checkpoints, shared with other databases;
+    /// `None` (the default) uses `FlushScheduler::global`
+    pub flush_scheduler: Option<FlushScheduler>,
+    /// How often expired keys are swept: deleted, logged, and published.
+    /// Expired keys read as absent in between. Zero disables the background
+    /// sweep, leaving it to `Database::expire_now` (default 1000ms).
+    pub ttl_sweep_interval_ms: u64,
+    /// Size at which the active WAL segment is rolled over to a new file
+    /// (default 64 MiB)
+    pub wal_segment_bytes: u64,
+    /// Bytes of WAL records to collect in memory before writing them out
+    /// together (default 0: every write goes straight to the OS)
+    ///
+    /// Saves a system call per small write, but records still buffered when
+    /// the process crashes are lost even though the write returned. They are
+    /// written out by `Database::sync`, every checkpoint, group commit, and
+    /// on close or drop. Only for workloads that can lose the last few
+    /// writes.
+    pub wal_buffer_bytes: usize,
+    /// Fsync the WAL after this many writes accumulate unsynced (default 0:
+    /// only `Database::sync`, segment rollovers and checkpoints fsync it)
+    ///
+    /// Bounds what a power loss or OS crash can take: at most
+    /// `wal_commit_batch - 1` writes that had already returned, as counted
+    /// by `Database::unsynced_count`. A crash of the process alone loses
+    /// nothing beyond the write buffer. The write that completes a batch
+    /// waits for the fsync. Not needed with `group_commit`, which fsyncs
+    /// every write.
+    pub wal_commit_batch: usize,
+    /// Retry a WAL write that fails with a transient IO error (an
+    /// interrupted call, `WouldBlock`, or a timeout) this many times before
+    /// the write fails (default 3). Permanent errors, a full disk among
+    /// them, fail right away, and failed fsyncs are never retried.
+    pub wal_write_retries: u32,
+    /// Wait before the first of those retries, doubled for each one after
+    /// (default 10ms)
+    pub wal_retry_backoff_ms: u64,
+    /// Make every write durable (fsynced) before it returns, batching the
+    /// fsyncs of concurrent writers. Each write then waits for up to
+    /// `group_commit_delay_us` plus one fsync; off by default, in which case
+    /// writes reach the OS but are not fsynced individually.
+    pub group_commit: bool,
+    /// How long the commit thread waits for more writers to join a batch
+    /// (default 1000µs)
+    pub group_commit_delay_us: u64,
+    /// Sync immediately once this many writers are waiting (default 64)
+    pub group_commit_max_batch: usize,
+    /// Reject keys longer than this many bytes (default: no limit)
+    pub max_key_bytes: Option<usize>,
+    /// Reject values longer than this many bytes (default: no limit beyond
+    /// the WAL's 4 GiB record size)
+    pub max_value_bytes: Option<usize>,
+    /// Size of each WAL record a `ValueWriter` logs while a value streams
+    /// in (default 1 MiB)
+    pub stream_chunk_bytes: usize,
+    /// Keep everything in memory: no directory, lock, data file, or WAL is
+    /// created and `data_dir` is ignored. `flush` and `checkpoint` are
+    /// no-ops, and the data is gone once the database is dropped.
+    pub in_memory: bool,
+    /// Turn an in-memory database into an LRU cache holding at most this
+    /// many keys. Evicted keys are published as `ChangeEvent::Evicted`.
+    /// Requires `in_memory`.
+    pub cache_max_entries: Option<usize>,
+    /// Like `cache_max_entries`, but bounds the total bytes of keys and values
+    pub cache_max_bytes: Option<usize>,
+    /// Open without any possibility of mutation: writes are rejected and no
+    /// background checkpoints are scheduled. A read-only open takes no directory
+    /// lock, since it only reads the files once while opening.
+    pub read_only: bool,
+    /// Let a writable open go ahead when WAL replay stops at a record it
+    /// cannot decode. The recovered state is written to the data file and
+    /// the WAL segments are moved aside as `*.corrupt` files, so the next
+    /// open neither replays the skipped records nor hides new writes behind
+    /// them. Off by default, where such an open fails with
+    /// `LohError::WalCorrupt`; a read-only open reports the skipped records
+    /// in `DbStats::replay` either way.
+    pub salvage_wal: bool,
+    /// How long `Database::open` keeps trying for the directory lock while
+    /// another process holds it, backing off between attempts, before it
+    /// fails with `LohError::Locked`; `None` (the default) fails at once
+    pub open_timeout: Option<Duration>,
+    /// Codec applied to the data file on flush; `None` (the default) stores
+    /// it uncompressed
+    pub compressor: Option<Arc<dyn Compressor>>,
+    /// Codec applied to each WAL record, independently of `compressor`;
+    /// `None` (the default) logs records as they are. The log must be
+    /// reopened with the same codec while it holds compressed records.
+    pub wal_compressor: Option<Arc<dyn Compressor>>,
+    /// Key for encrypting the WAL and data file at rest. Requires the
+    /// `crypto` feature; opening with the wrong key fails with a decryption
+    /// error.
+    pub encryption_key: Option<[u8; 32]>,
+    /// Unix permission bits for the files LohDB creates, such as `0o600`;
+    /// directories it creates get the matching mode with search permission
+    /// added (`0o700`). Defaults to `0o600` when `encryption_key` is set and
+    /// to the process defaults otherwise. The umask still applies, existing
+    /// directories keep their permissions, and it is ignored on other
+    /// platforms.
+    pub file_mode: Option<u32>,
+    /// Encoding of WAL records and the data file (default bincode). Every
+    /// file records its format, and opening files written in another one
+    /// fails rather than misreading them.
+    pub serialization_format: SerializationFormat,
+    /// Events buffered per subscriber before `overflow_policy` kicks in
+    /// (default 1024)
+    pub subscriber_buffer: usize,
+    /// What to do when a subscriber's buffer is full (default: drop the new
+    /// event and count it in `DbStats::dropped_events`). Individual channel
+    /// subscriptions can override it with `subscribe_channel_with_policy`.
+    pub overflow_policy: OverflowPolicy,
+    /// Called from the opening thread while `Database::open` replays the
+    /// WAL, so a long recovery can show progress (default: none)
+    pub on_recovery_progress: Option<RecoveryObserver>,
+    /// Records replayed between calls to `on_recovery_progress` (default
+    /// 10,000)
+    pub recovery_progress_every: u64,
+    /// Where the database reads the time, for WAL timestamps (and so each
+    /// key's last-modified time) and key expiry (default `SystemClock`);
+    /// tests can pass a `MockClock` to expire keys without sleeping
+    pub clock: Arc<dyn Clock>,
+}
+
+impl DatabaseConfig {
+    pub fn builder() -> DatabaseConfigBuilder {
+        DatabaseConfigBuilder::default()
+    }
+    
+    /// Directory holding the WAL segments: `wal_dir`, or else `data_dir`
+    pub(crate) fn effective_wal_dir(&self) -> &str {
+        self.wal_dir.as_deref().unwrap_or(&self.data_dir)
+    }
+    
+    /// Path of the data file: `data_file`, or else `data.db` in `data_dir`
+    pub(crate) fn effective_data_file(&self) -> PathBuf {
+        match &self.data_file {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(&self.data_dir).join("data.db"),
+        }
+    }
+    
+    /// Paths of the data file's shards, or just the data file when unsharded
+    pub(crate) fn effective_data_files(&self) -> Vec<PathBuf> {
+        shard::shard_files(&self.effective_data_file(), self.shards)
+    }
+    
+    /// Path of the blob file of a `dedup` database, beside the data file
+    pub(crate) fn effective_blob_file(&self) -> Option<PathBuf> {
+        self.dedup.then(|| dedup::blob_file(&self.effective_data_file()))
+    }
+    
+    /// `file_mode`, or the restrictive default for encrypted databases
+    pub(crate) fn effective_file_mode(&self) -> Option<u32> {
+        self.file_mode.or(self.encryption_key.map(|_| perms::ENCRYPTED_FILE_MODE))
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: "./lohdb_data".to_string(),
+            wal_dir: None,
+            data_file: None,
+            shards: 1,
+            dedup: false,
+            wal_sync_interval_ms: 1000,
+            checkpoint_wal_bytes: None,
+            checkpoint_ratio: None,
+            flush_every_n_writes: None,
+            flush_scheduler: None,
+            ttl_sweep_interval_ms: 1000,
+            wal_segment_bytes: DEFAULT_WAL_SEGMENT_BYTES,
+            wal_buffer_bytes: 0,
+            wal_commit_batch: 0,
+            wal_write_retries: DEFAULT_WAL_WRITE_RETRIES,
+            wal_retry_backoff_ms: DEFAULT_WAL_RETRY_BACKOFF.as_millis() as u64,
+            group_commit: false,
+            group_commit_delay_us: 1000,
+            group_commit_max_batch: 64,
+            max_key_bytes: None,
+            max_value_bytes: None,
+            stream_chunk_bytes: 1024 * 1024,
+            in_memory: false,
+            cache_max_entries: None,
+            cache_max_bytes: None,
+            read_only: false,
+            salvage_wal: false,
+            open_timeout: None,
+            compressor: None,
+            wal_compressor: None,
+            encryption_key: None,
+            file_mode: None,
+            serialization_format: SerializationFormat::default(),
+            subscriber_buffer: DEFAULT_SUBSCRIBER_BUFFER,
+            overflow_policy: OverflowPolicy::default(),
+            on_recovery_progress: None,
+            recovery_progress_every: DEFAULT_RECOVERY_PROGRESS_EVERY,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// Chainable construction of a `DatabaseConfig`, starting from the defaults
+#[derive(Default)]
+pub struct DatabaseConfigBuilder {
+    config: DatabaseConfig,
+}
+
+impl DatabaseConfigBuilder {
+    pub fn data_dir(mut self, data_dir: impl Into<String>) -> Self {
+        self.config.data_dir = data_dir.into();
+        self
+    }
+    
+    pub fn wal_dir(mut self, wal_dir: impl Into<String>) -> Self {
+        self.config.wal_dir = Some(wal_dir.into());
+        self
+    }
+    
+    pub fn data_file(mut self, data_file: impl Into<String>) -> Self {
+        self.config.data_file = Some(data_file.into());
+        self
+    }
+    
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.config.shards = shards;
+        self
+    }
+    
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.config.dedup = dedup;
+        self
+    }
+    
+    pub fn wal_sync_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.config.wal_sync_interval_ms = interval_ms;
+        self
+    }
+    
+    pub fn checkpoint_wal_bytes(mut self, wal_bytes: u64) -> Self {
+        self.config.checkpoint_wal_bytes = Some(wal_bytes);
+        self
+    }
+    
+    pub fn checkpoint_ratio(mut self, ratio: f64) -> Self {
+        self.config.checkpoint_ratio = Some(ratio);
+        self
+    }
+    
+    pub fn flush_every_n_writes(mut self, writes: u64) -> Self {
+        self.config.flush_every_n_writes = Some(writes);
+        self
+    }
+    
+    pub fn flush_scheduler(mut self, scheduler: FlushScheduler) -> Self {
+        self.config.flush_scheduler = Some(scheduler);
+        self
+    }
+    
+    pub fn ttl_sweep_interval_ms(mut self, interval_ms: u64) -> Self {
+        self.config.ttl_sweep_interval_ms = interval_ms;
+        self
+    }
+    
+    pub fn wal_segment_bytes(mut self, segment_bytes: u64) -> Self {
+        self.config.wal_segment_bytes = segment_bytes;
+        self
+    }
+    
+    pub fn wal_buffer_bytes(mut self, buffer_bytes: usize) -> Self {
+        self.config.wal_buffer_bytes = buffer_bytes;
+        self
+    }
+    
+    pub fn wal_commit_batch(mut self, commit_batch: usize) -> Self {
+        self.config.wal_commit_batch = commit_batch;
+        self
+    }
+    
+    pub fn wal_write_retries(mut self, retries: u32) -> Self {
+        self.config.wal_write_retries = retries;
+        self
+    }
+    
+    pub fn wal_retry_backoff_ms(mut self, backoff_ms: u64) -> Self {
+        self.config.wal_retry_backoff_ms = backoff_ms;
+        self
+    }
+    
+    pub fn group_commit(mut self, enabled: bool) -> Self {
+        self.config.group_commit = enabled;
+        self
+    }
+    
+    pub fn group_commit_delay_us(mut self, delay_us: u64) -> Self {
+        self.config.group_commit_delay_us = delay_us;
+        self
+    }
+    
+    pub fn group_commit_max_batch(mut self, max_batch: usize) -> Self {
+        self.config.group_commit_max_batch = max_batch;
+        self
+    }
+    
+    pub fn max_key_bytes(mut self, max_bytes: usize) -> Self {
+        self.config.max_key_bytes = Some(max_bytes);
+        self
+    }
+    
+    pub fn max_value_bytes(mut self, max_bytes: usize) -> Self {
+        self.config.max_value_bytes = Some(max_bytes);
+        self
+    }
+    
+    pub fn stream_chunk_bytes(mut self, chunk_bytes: usize) -> Self {
+        self.config.stream_chunk_bytes = chunk_bytes;
+        self
+    }
+    
+    pub fn in_memory(mut self, in_memory: bool) -> Self {
+        self.config.in_memory = in_memory;
+        self
+    }
+    
+    pub fn cache_max_entries(mut self, max_entries: usize) -> Self {
+        self.config.cache_max_entries = Some(max_entries);
+        self
+    }
+    
+    pub fn cache_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.config.cache_max_bytes = Some(max_bytes);
+        self
+    }
+    
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.config.read_only = read_only;
+        self
+    }
+    
+    pub fn salvage_wal(mut self, salvage_wal: bool) -> Self {
+        self.config.salvage_wal = salvage_wal;
+        self
+    }
+    
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.config.open_timeout = Some(timeout);
+        self
+    }
+    
+    pub fn compressor<C: Compressor + 'static>(mut self, compressor: C) -> Self {
+        self.config.compressor = Some(Arc::new(compressor));
+        self
+    }
+    
+    pub fn wal_compressor<C: Compressor + 'static>(mut self, compressor: C) -> Self {
+        self.config.wal_compressor = Some(Arc::new(compressor));
+        self
+    }
+    
+    pub fn encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.config.encryption_key = Some(key);
+        self
+    }
+    
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.config.file_mode = Some(mode);
+        self
+    }
+    
+    pub fn serialization_format(mut self, format: SerializationFormat) -> Self {
+        self.config.serialization_format = format;
+        self
+    }
+    
+    pub fn subscriber_buffer(mut self, buffer: usize) -> Self {
+        self.config.subscriber_buffer = buffer;
+        self
+    }
+    
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.config.overflow_policy = policy;
+        self
+    }
+    
+    pub fn on_recovery_progress<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(RecoveryProgress) + Send + Sync + 'static,
+    {
+        self.config.on_recovery_progress = Some(Arc::new(observer));
+        self
+    }
+    
+    pub fn recovery_progress_every(mut self, entries: u64) -> Self {
+        self.config.recovery_progress_every = entries;
+        self
+    }
+    
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = clock;
+        self
+    }
+    
+    /// Validate the settings and produce the config
+    pub fn build(self) -> Result<DatabaseConfig> {
+        if self.config.data_dir.is_empty() {
+            return Err(LohError::InvalidArgument("data_dir must not be empty".to_string()));
+        }
+        if self.config.wal_dir.as_deref() == Some("") || self.config.data_file.as_deref() == Some("") {
+            return Err(LohError::InvalidArgument("wal_dir and data_file must not be empty".to_string()));
+        }
+        if self.config.in_memory && self.config.read_only {
+            return Err(LohError::InvalidArgument("in_memory and read_only cannot both be set".to_string()));
+        }
+        let is_cache = self.config.cache_max_entries.is_some() || self.config.cache_max_bytes.is_some();
+        if is_cache && !self.config.in_memory {
+            // Evictions are not logged, so replaying the WAL would bring them back
+            return Err(LohError::InvalidArgument("cache limits require in_memory".to_string()));
+        }
+        if self.config.shards == 0 {
+            return Err(LohError::InvalidArgument("shards must be greater than zero".to_string()));
+        }
+        if self.config.dedup && is_cache {
+            // An evicted key would take its blob's reference with it unseen
+            return Err(LohError::InvalidArgument("dedup cannot be combined with cache limits".to_string()));
+        }
+        if self.config.shards > 1 && self.config.in_memory {
+            return Err(LohError::InvalidArgument("shards split the data file; an in-memory database has none".to_string()));
+        }
+        if self.config.stream_chunk_bytes == 0 {
+            return Err(LohError::InvalidArgument("stream_chunk_bytes must be greater than zero".to_string()));
+        }
+        self.config.serialization_format.check_available()?;
+        if let Some(mode) = self.config.file_mode {
+            if mode & !0o7777 != 0 {
+                return Err(LohError::InvalidArgument(format!("file_mode {:o} is not a permission mode", mode)));
+            }
+            if mode & 0o600 != 0o600 {
+                // The database could not reopen its own files
+                return Err(LohError::InvalidArgument("file_mode must give the owner read and write permission".to_string()));
+            }
+        }
+        if self.config.wal_segment_bytes == 0 {
+            return Err(LohError::InvalidArgument("wal_segment_bytes must be greater than zero".to_string()));
+        }
+        if self.config.group_commit_max_batch == 0 {
+            return Err(LohError::InvalidArgument("group_commit_max_batch must be greater than zero".to_string()));
+        }
+        if self.config.subscriber_buffer == 0 {
+            return Err(LohError::InvalidArgument("subscriber_buffer must be greater than zero".to_string()));
+        }
+        if self.config.checkpoint_wal_bytes == Some(0) {
+            return Err(LohError::InvalidArgument("checkpoint_wal_bytes must be greater than zero".to_string()));
+        }
+        if self.config.checkpoint_ratio.is_some_and(|ratio| !(ratio.is_finite() && ratio > 0.0)) {
+            return Err(LohError::InvalidArgument("checkpoint_ratio must be a positive number".to_string()));
+        }
+        if self.config.flush_every_n_writes == Some(0) {
+            return Err(LohError::InvalidArgument("flush_every_n_writes must be greater than zero".to_string()));
+        }
+        if self.config.recovery_progress_every == 0 {
+            return Err(LohError::InvalidArgument("recovery_progress_every must be greater than zero".to_string()));
+        }
+        Ok(self.config)
+    }
+}
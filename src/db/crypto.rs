@@ -0,0 +1,100 @@
+use crate::{LohError, Result};
+use std::sync::Arc;
+
+/// Magic prefix marking an encrypted data file
+const ENCRYPTED_MAGIC: &[u8; 4] = b"LOHE";
+
+/// Authenticated encryption applied to WAL records and the data file
+///
+/// Each call to `encrypt` must produce a self-contained blob carrying its own
+/// nonce, and `decrypt` must fail (rather than return garbage) when the key
+/// is wrong or the data was tampered with.
+pub trait Encryptor: Send + Sync {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+    
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// ChaCha20-Poly1305 with a random 96-bit nonce prepended to each blob
+#[cfg(feature = "crypto")]
+pub struct ChaCha20Poly1305Encryptor {
+    cipher: chacha20poly1305::ChaCha20Poly1305,
+}
+
+#[cfg(feature = "crypto")]
+impl ChaCha20Poly1305Encryptor {
+    const NONCE_LEN: usize = 12;
+    
+    pub fn new(key: [u8; 32]) -> Self {
+        use chacha20poly1305::KeyInit;
+        
+        Self {
+            cipher: chacha20poly1305::ChaCha20Poly1305::new(&key.into()),
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl Encryptor for ChaCha20Poly1305Encryptor {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        use chacha20poly1305::aead::{Aead, AeadCore, OsRng};
+        
+        let nonce = chacha20poly1305::ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| LohError::Encryption("encryption failed".to_string()))?;
+            
+        let mut out = Vec::with_capacity(Self::NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+    
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        use chacha20poly1305::aead::Aead;
+        
+        if ciphertext.len() < Self::NONCE_LEN {
+            return Err(LohError::Encryption("decryption failed: ciphertext is truncated".to_string()));
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(Self::NONCE_LEN);
+        self.cipher
+            .decrypt(nonce.into(), ciphertext)
+            .map_err(|_| LohError::Encryption("decryption failed: wrong encryption key or corrupted data".to_string()))
+    }
+}
+
+/// Build the encryptor for `DatabaseConfig::encryption_key`
+#[cfg(feature = "crypto")]
+pub(crate) fn encryptor_for_key(key: [u8; 32]) -> Result<Arc<dyn Encryptor>> {
+    Ok(Arc::new(ChaCha20Poly1305Encryptor::new(key)))
+}
+
+/// Build the encryptor for `DatabaseConfig::encryption_key`
+#[cfg(not(feature = "crypto"))]
+pub(crate) fn encryptor_for_key(_key: [u8; 32]) -> Result<Arc<dyn Encryptor>> {
+    Err(LohError::Encryption(
+        "an encryption key was configured but lohdb was built without the `crypto` feature".to_string(),
+    ))
+}
+
+/// Encrypt a whole file body and prefix it with the magic marker
+pub(crate) fn seal_file(encryptor: &dyn Encryptor, data: &[u8]) -> Result<Vec<u8>> {
+    let sealed = encryptor.encrypt(data)?;
+    let mut out = Vec::with_capacity(ENCRYPTED_MAGIC.len() + sealed.len());
+    out.extend_from_slice(ENCRYPTED_MAGIC);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Reverse `seal_file`, passing unencrypted files through unchanged
+pub(crate) fn open_file(encryptor: Option<&dyn Encryptor>, data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(sealed) = data.strip_prefix(ENCRYPTED_MAGIC) else {
+        return Ok(data);
+    };
+    
+    match encryptor {
+        Some(encryptor) => encryptor.decrypt(sealed),
+        None => Err(LohError::Encryption("data file is encrypted but no encryption key is configured".to_string())),
+    }
+}
@@ -0,0 +1,329 @@
+use crate::db::meta::KeyMeta;
+use crate::db::{shard, CompactStats, SnapshotEntries, StorageEngine};
+use crate::{LohError, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Storage that keeps each distinct value once, however many keys hold it
+///
+/// Values live in a blob engine under a content id, the 64-bit FNV-1a hash
+/// of their bytes in hex, and the key engine maps each key to the id of
+/// its value, along with the key's version, expiry and attributes. Setting
+/// a key to bytes already stored adds a reference to the existing blob
+/// instead of a copy; a blob no key refers to any more is dropped.
+///
+/// The hash only narrows the search: a set compares the bytes of the blobs
+/// sharing its hash before reusing one, and a different value with the
+/// same hash gets an id of its own (`{hash}.1`, `{hash}.2`, ...), so a
+/// collision never hands a key someone else's value. That comparison reads
+/// the matching blob back on every set.
+///
+/// Reference counts are not stored: `initialize` recounts them from the
+/// key engine, so they always agree with what was loaded, and drops any
+/// blob left unreferenced by a crash. `flush` writes the blobs before the
+/// keys, and blobs orphaned since the last flush are removed only after
+/// the keys no longer referring to them are written, so the blob file
+/// always holds every value the key file points to. Blobs kept in memory
+/// only (see `without_blob_file`) go as soon as their last reference does,
+/// since nothing may ever flush them. `Database::open` builds one of these
+/// over file engines when `DatabaseConfig::dedup` is set.
+pub struct DedupStorageEngine {
+    /// Key -> content id of its value
+    keys: Box<dyn StorageEngine>,
+    /// Content id -> value
+    blobs: Box<dyn StorageEngine>,
+    /// Keys referring to each stored blob
+    refcounts: HashMap<String, u64>,
+    /// Blobs whose last reference went since the last flush
+    orphans: HashSet<String>,
+    /// Whether the blobs are written to a file, so an orphan has to stay
+    /// until the keys no longer referring to it are written too
+    blob_file: bool,
+}
+
+/// Live blobs of a `DedupStorageEngine`, from `StorageEngine::dedup_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct DedupStats {
+    /// Distinct values stored, counting any no key refers to any more that
+    /// wait for the next flush to remove them
+    pub blobs: usize,
+    /// Keys referring to a blob; each beyond the first to the same blob is
+    /// a value shared rather than copied
+    pub references: u64,
+}
+
+impl DedupStorageEngine {
+    /// Keep keys in `keys` and each distinct value once in `blobs`
+    pub fn new(keys: Box<dyn StorageEngine>, blobs: Box<dyn StorageEngine>) -> Self {
+        Self { keys, blobs, refcounts: HashMap::new(), orphans: HashSet::new(), blob_file: true }
+    }
+    
+    /// Drop each blob as soon as no key refers to it, instead of at the
+    /// next flush, for blobs that are never written to a file, such as
+    /// those of an in-memory database, which never flushes
+    pub fn without_blob_file(mut self) -> Self {
+        self.blob_file = false;
+        self
+    }
+    
+    /// Number of keys referring to the blob holding `value`, 0 if it is
+    /// not stored
+    pub fn refcount(&self, value: &[u8]) -> Result<u64> {
+        Ok(match self.find_blob(value)? {
+            Some(id) => self.refcounts.get(&id).copied().unwrap_or(0),
+            None => 0,
+        })
+    }
+    
+    /// Content id `key` refers to
+    fn blob_of(&self, key: &str) -> Result<Option<String>> {
+        self.keys.retrieve(key)?.map(blob_id).transpose()
+    }
+    
+    /// Ids of the stored blobs whose bytes hash like `value`'s
+    fn candidates(&self, value: &[u8]) -> Result<Vec<String>> {
+        const BATCH: usize = 16;
+        let prefix = format!("{:016x}", shard::fnv1a(value));
+        let mut ids: Vec<String> = Vec::new();
+        loop {
+            let start = match ids.last() {
+                Some(last) => Bound::Excluded(last.as_str()),
+                None => Bound::Included(prefix.as_str()),
+            };
+            let batch = self.blobs.scan_keys(start, BATCH)?;
+            let scanned = batch.len();
+            let matching: Vec<String> = batch.into_iter().take_while(|id| id.starts_with(&prefix)).collect();
+            let done = scanned < BATCH || matching.len() < scanned;
+            ids.extend(matching);
+            if done {
+                return Ok(ids);
+            }
+        }
+    }
+    
+    /// Id of the stored blob holding exactly `value`
+    fn find_blob(&self, value: &[u8]) -> Result<Option<String>> {
+        for id in self.candidates(value)? {
+            if self.blobs.retrieve(&id)?.as_deref() == Some(value) {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+    
+    /// Add a reference to the blob holding `value`, storing it first if it
+    /// is new, and return its id
+    fn acquire(&mut self, value: &[u8]) -> Result<String> {
+        let id = match self.find_blob(value)? {
+            Some(id) => id,
+            None => {
+                let taken = self.candidates(value)?;
+                let hash = format!("{:016x}", shard::fnv1a(value));
+                let id = (0..)
+                    .map(|n| if n == 0 { hash.clone() } else { format!("{}.{}", hash, n) })
+                    .find(|id| !taken.contains(id))
+                    .expect("some suffix is free");
+                self.blobs.store(&id, value)?;
+                id
+            }
+        };
+        *self.refcounts.entry(id.clone()).or_default() += 1;
+        Ok(id)
+    }
+    
+    /// Drop a reference to blob `id`, orphaning it if it was the last, or
+    /// removing it right away if there is no blob file
+    fn release(&mut self, id: &str) -> Result<()> {
+        if let Some(count) = self.refcounts.get_mut(id) {
+            *count -= 1;
+            if *count == 0 {
+                self.refcounts.remove(id);
+                if self.blob_file {
+                    self.orphans.insert(id.to_string());
+                } else {
+                    self.blobs.remove(id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Point `key` at `value`, through `store` or `store_at`
+    fn store_with(
+        &mut self,
+        key: &str,
+        value: &[u8],
+        store: impl FnOnce(&mut dyn StorageEngine, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let previous = self.blob_of(key)?;
+        let id = self.acquire(value)?;
+        if let Err(e) = store(self.keys.as_mut(), id.as_bytes()) {
+            self.release(&id)?;
+            return Err(e);
+        }
+        if let Some(previous) = previous {
+            self.release(&previous)?;
+        }
+        Ok(())
+    }
+}
+
+/// The data file of a deduplicating database's blobs, beside `data_file`:
+/// `data.blobs.db` for `data.db`
+pub(crate) fn blob_file(data_file: &Path) -> PathBuf {
+    let stem = data_file.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = data_file.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    data_file.with_file_name(format!("{}.blobs{}", stem, extension))
+}
+
+/// A content id as stored in the key engine
+fn blob_id(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|_| LohError::DataCorrupt("a deduplicated key holds no valid blob id".to_string()))
+}
+
+impl StorageEngine for DedupStorageEngine {
+    fn initialize(&mut self) -> Result<()> {
+        self.keys.initialize()?;
+        self.blobs.initialize()?;
+        
+        let stored: HashSet<String> = self.blobs.list_keys()?.into_iter().collect();
+        self.refcounts.clear();
+        for key in self.keys.list_keys()? {
+            let Some(id) = self.blob_of(&key)? else { continue };
+            if !stored.contains(&id) {
+                return Err(LohError::DataCorrupt(format!("key '{}' refers to blob {}, which is missing", key, id)));
+            }
+            *self.refcounts.entry(id).or_default() += 1;
+        }
+        // Left behind by a crash between writing the blobs and the keys
+        self.orphans = stored.into_iter().filter(|id| !self.refcounts.contains_key(id)).collect();
+        Ok(())
+    }
+    
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.store_with(key, value, |keys, id| keys.store(key, id))
+    }
+    
+    fn store_at(&mut self, key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+        self.store_with(key, value, |keys, id| keys.store_at(key, id, modified_ms))
+    }
+    
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(id) = self.blob_of(key)? else {
+            return Ok(None);
+        };
+        match self.blobs.retrieve(&id)? {
+            Some(value) => Ok(Some(value)),
+            None => Err(LohError::DataCorrupt(format!("key '{}' refers to blob {}, which is missing", key, id))),
+        }
+    }
+    
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        let Some(id) = self.blob_of(key)? else {
+            return Ok(false);
+        };
+        self.keys.remove(key)?;
+        self.release(&id)?;
+        Ok(true)
+    }
+    
+    fn metadata(&self, key: &str) -> Result<Option<KeyMeta>> {
+        self.keys.metadata(key)
+    }
+    
+    fn supports_expiry(&self) -> bool {
+        self.keys.supports_expiry()
+    }
+    
+    fn set_expiry(&mut self, key: &str, expires_ms: Option<u64>) -> Result<bool> {
+        self.keys.set_expiry(key, expires_ms)
+    }
+    
+    fn expired_keys(&self, now_ms: u64) -> Result<Vec<String>> {
+        self.keys.expired_keys(now_ms)
+    }
+    
+    fn supports_user_meta(&self) -> bool {
+        self.keys.supports_user_meta()
+    }
+    
+    fn set_user_meta(&mut self, key: &str, meta: Option<HashMap<String, String>>) -> Result<bool> {
+        self.keys.set_user_meta(key, meta)
+    }
+    
+    fn user_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        self.keys.user_meta(key)
+    }
+    
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.keys.list_keys()
+    }
+    
+    fn key_count(&self) -> Result<usize> {
+        self.keys.key_count()
+    }
+    
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        self.keys.scan_keys(start, limit)
+    }
+    
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        self.keys.scan_keys_rev(end, limit)
+    }
+    
+    fn flush(&mut self) -> Result<()> {
+        self.blobs.flush()?;
+        self.keys.flush()?;
+        // Nothing on disk refers to them now; they leave the blob file on
+        // its next flush
+        for id in std::mem::take(&mut self.orphans) {
+            if !self.refcounts.contains_key(&id) {
+                self.blobs.remove(&id)?;
+            }
+        }
+        Ok(())
+    }
+    
+    fn disk_usage(&self) -> Result<u64> {
+        Ok(self.keys.disk_usage()? + self.blobs.disk_usage()?)
+    }
+    
+    fn compact(&mut self) -> Result<CompactStats> {
+        let bytes_before = self.disk_usage()?;
+        self.flush()?;
+        // Writes the blob file again, without the orphans just removed
+        self.blobs.compact()?;
+        self.keys.compact()?;
+        Ok(CompactStats { bytes_before, bytes_after: self.disk_usage()? })
+    }
+    
+    fn snapshot(&self) -> Result<SnapshotEntries> {
+        let mut values: HashMap<String, Arc<[u8]>> = HashMap::new();
+        let mut entries = BTreeMap::new();
+        for key in self.keys.list_keys()? {
+            let Some(id) = self.blob_of(&key)? else { continue };
+            let value = match values.get(&id) {
+                Some(value) => value.clone(),
+                None => {
+                    let value: Arc<[u8]> = Arc::from(self.retrieve(&key)?.unwrap_or_default());
+                    values.insert(id, value.clone());
+                    value
+                }
+            };
+            entries.insert(key, value);
+        }
+        Ok(Arc::new(entries))
+    }
+    
+    fn take_evicted(&mut self) -> Vec<String> {
+        self.keys.take_evicted()
+    }
+    
+    fn dedup_stats(&self) -> Option<DedupStats> {
+        // An orphan that was set again since is referred to, and counted, already
+        let orphans = self.orphans.iter().filter(|id| !self.refcounts.contains_key(*id)).count();
+        Some(DedupStats { blobs: self.refcounts.len() + orphans, references: self.refcounts.values().sum() })
+    }
+}
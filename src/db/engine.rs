@@ -1,5 +1,18 @@
-use crate::Result;
-use std::collections::HashMap;
+use crate::db::checksum;
+use crate::db::compression::{self, Compressor};
+use crate::db::crypto::{self, Encryptor};
+use crate::db::dedup::DedupStats;
+use crate::db::format::{self, SerializationFormat};
+use crate::db::meta::{self, ExpiryQueue, KeyMeta};
+use crate::db::perms;
+use crate::db::shard::Shards;
+use crate::{LohError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 /// Trait for pluggable storage backends
 pub trait StorageEngine: Send + Sync {
@@ -9,139 +22,833 @@ pub trait StorageEngine: Send + Sync {
     /// Store a key-value pair
     fn store(&mut self, key: &str, value: &[u8]) -> Result<()>;
     
+    /// Store a key-value pair written at `modified_ms`, bumping the key's version
+    ///
+    /// Recovery calls this with each record's WAL timestamp. Engines that
+    /// track `metadata` override it; the default just calls `store`.
+    fn store_at(&mut self, key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+        let _ = modified_ms;
+        self.store(key, value)
+    }
+    
     /// Retrieve a value by key
     fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>>;
     
+    /// `store_at`, handing over `value` instead of lending it
+    ///
+    /// Engines that keep values as `Arc<[u8]>` hold on to this one rather
+    /// than copying it, which spares a second copy of a large value; the
+    /// default copies it through `store_at`.
+    fn store_shared(&mut self, key: &str, value: Arc<[u8]>, modified_ms: u64) -> Result<()> {
+        self.store_at(key, &value, modified_ms)
+    }
+    
+    /// `retrieve`, sharing the stored value instead of copying it where
+    /// the engine keeps values as `Arc<[u8]>`; the default copies it
+    fn retrieve_shared(&self, key: &str) -> Result<Option<Arc<[u8]>>> {
+        Ok(self.retrieve(key)?.map(Arc::from))
+    }
+    
     /// Remove a key-value pair
     fn remove(&mut self, key: &str) -> Result<bool>;
     
+    /// Version and last-modified time of a key
+    ///
+    /// Removing a key forgets them, so a key set again starts over at
+    /// version 1. The default tracks nothing and always returns `None`.
+    fn metadata(&self, key: &str) -> Result<Option<KeyMeta>> {
+        let _ = key;
+        Ok(None)
+    }
+    
+    /// Whether `set_expiry` is implemented
+    fn supports_expiry(&self) -> bool {
+        false
+    }
+    
+    /// Make `key` expire at `expires_ms` (milliseconds since the Unix
+    /// epoch), or never with `None`, returning whether the key exists
+    ///
+    /// The expiry is kept in the key's `metadata`. Engines that support it
+    /// override this and `supports_expiry`; the default fails.
+    fn set_expiry(&mut self, key: &str, expires_ms: Option<u64>) -> Result<bool> {
+        let _ = (key, expires_ms);
+        Err(LohError::InvalidArgument("this storage engine does not support key expiry".to_string()))
+    }
+    
+    /// Keys whose expiry time has passed at `now_ms`
+    ///
+    /// Called by every expiry sweep, so engines implementing `set_expiry`
+    /// should answer without visiting every key. The default reports none.
+    fn expired_keys(&self, now_ms: u64) -> Result<Vec<String>> {
+        let _ = now_ms;
+        Ok(Vec::new())
+    }
+    
+    /// Whether `set_user_meta` is implemented
+    fn supports_user_meta(&self) -> bool {
+        false
+    }
+    
+    /// Attach `meta`, string attributes kept beside the value, to `key`, or
+    /// drop them with `None`, returning whether the key exists
+    ///
+    /// Like an expiry, they belong to the value they were set with: storing
+    /// the key again or removing it drops them. Engines that support them
+    /// override this, `user_meta` and `supports_user_meta`; the default
+    /// fails.
+    fn set_user_meta(&mut self, key: &str, meta: Option<HashMap<String, String>>) -> Result<bool> {
+        let _ = (key, meta);
+        Err(LohError::InvalidArgument("this storage engine does not support key metadata".to_string()))
+    }
+    
+    /// Attributes attached to `key` by `set_user_meta`, if any
+    fn user_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        let _ = key;
+        Ok(None)
+    }
+    
     /// List all keys
     fn list_keys(&self) -> Result<Vec<String>>;
     
+    /// Number of keys held
+    ///
+    /// The default counts the output of `list_keys`, allocating every key;
+    /// engines that know their size should override it.
+    fn key_count(&self) -> Result<usize> {
+        Ok(self.list_keys()?.len())
+    }
+    
+    /// List up to `limit` keys at or after `start`, in ascending order
+    ///
+    /// The default sorts the output of `list_keys` on every call; sorted
+    /// engines should override it with a range scan.
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        let mut keys = self.list_keys()?;
+        keys.sort();
+        Ok(keys
+            .into_iter()
+            .filter(|k| match start {
+                Bound::Included(s) => k.as_str() >= s,
+                Bound::Excluded(s) => k.as_str() > s,
+                Bound::Unbounded => true,
+            })
+            .take(limit)
+            .collect())
+    }
+    
+    /// List up to `limit` keys at or before `end`, in descending order
+    ///
+    /// The mirror image of `scan_keys`, for reverse iteration. The default
+    /// sorts the output of `list_keys` on every call; sorted engines should
+    /// override it with a reversed range scan.
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        let mut keys = self.list_keys()?;
+        keys.sort();
+        Ok(keys
+            .into_iter()
+            .rev()
+            .filter(|k| match end {
+                Bound::Included(e) => k.as_str() <= e,
+                Bound::Excluded(e) => k.as_str() < e,
+                Bound::Unbounded => true,
+            })
+            .take(limit)
+            .collect())
+    }
+    
     /// Flush any pending writes
     fn flush(&mut self) -> Result<()>;
+    
+    /// Bytes currently used on disk by this engine
+    fn disk_usage(&self) -> Result<u64> {
+        Ok(0)
+    }
+    
+    /// Rewrite the engine's files with only live entries, dropping space
+    /// held by overwritten and deleted values
+    ///
+    /// Append-only engines override this to garbage-collect their log. The
+    /// default flushes and reports `disk_usage` before and after, which is
+    /// all an engine that rewrites its whole file on every flush needs.
+    fn compact(&mut self) -> Result<CompactStats> {
+        let bytes_before = self.disk_usage()?;
+        self.flush()?;
+        Ok(CompactStats { bytes_before, bytes_after: self.disk_usage()? })
+    }
+    
+    /// A point-in-time copy of every entry, for `Database::snapshot`
+    ///
+    /// Engines that keep their entries in a `SnapshotEntries` map return a
+    /// clone of the `Arc` and copy the map only when next written to. The
+    /// default copies every key and value up front.
+    fn snapshot(&self) -> Result<SnapshotEntries> {
+        let mut entries = BTreeMap::new();
+        for key in self.list_keys()? {
+            if let Some(value) = self.retrieve(&key)? {
+                entries.insert(key, Arc::from(value));
+            }
+        }
+        Ok(Arc::new(entries))
+    }
+    
+    /// Drain the keys this engine evicted on its own since the last call
+    ///
+    /// Only caching engines evict; the default reports nothing.
+    fn take_evicted(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+    
+    /// Blobs and references of a deduplicating engine
+    ///
+    /// Only `DedupStorageEngine` shares values between keys; the default
+    /// reports `None`.
+    fn dedup_stats(&self) -> Option<DedupStats> {
+        None
+    }
+    
+    /// The separately locked shards of a sharding engine
+    ///
+    /// Given these, `Database` locks only the shard of the key for
+    /// single-key reads and writes. Only `ShardedStorageEngine` has shards;
+    /// the default reports `None`, and the whole engine is locked instead.
+    fn shards(&self) -> Option<Arc<Shards>> {
+        None
+    }
+}
+
+/// Shared, copy-on-write map of storage key -> value
+///
+/// Cloning it is cheap; the engine copies the map (but not the values) the
+/// first time it is written to while a clone is still alive.
+pub type SnapshotEntries = Arc<BTreeMap<String, Arc<[u8]>>>;
+
+/// Outcome of `StorageEngine::compact`, in `disk_usage` bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactStats {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl CompactStats {
+    /// Bytes freed by the compaction
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
 }
 
 /// In-memory storage engine for testing and caching
+///
+/// With `with_max_entries` or `with_max_bytes` it becomes an LRU cache:
+/// every `store` and `retrieve` marks the key as most recently used, and a
+/// `store` that pushes the engine over a limit evicts least recently used
+/// keys until it fits again. Evicted keys are reported by `take_evicted`.
 pub struct InMemoryStorageEngine {
-    data: HashMap<String, Vec<u8>>,
+    data: SnapshotEntries,
+    meta: HashMap<String, KeyMeta>,
+    expiring: ExpiryQueue,
+    user_meta: HashMap<String, HashMap<String, String>>,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    /// Key and value bytes currently held
+    bytes: usize,
+    /// Access order, only tracked when a limit is set
+    recency: Option<Mutex<Recency>>,
+    evicted: Vec<String>,
+}
+
+/// Least-recently-used bookkeeping for `InMemoryStorageEngine` and
+/// `CachingStorageEngine`
+#[derive(Default)]
+pub(crate) struct Recency {
+    /// Key -> tick of its last access
+    ticks: HashMap<String, u64>,
+    /// Tick -> key, oldest first
+    order: BTreeMap<u64, String>,
+    next_tick: u64,
+}
+
+impl Recency {
+    pub(crate) fn touch(&mut self, key: &str) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        match self.ticks.get_mut(key) {
+            Some(old) => {
+                self.order.remove(old);
+                *old = tick;
+            }
+            None => {
+                self.ticks.insert(key.to_string(), tick);
+            }
+        }
+        self.order.insert(tick, key.to_string());
+    }
+    
+    pub(crate) fn forget(&mut self, key: &str) {
+        if let Some(tick) = self.ticks.remove(key) {
+            self.order.remove(&tick);
+        }
+    }
+    
+    pub(crate) fn oldest(&self) -> Option<&String> {
+        self.order.values().next()
+    }
 }
 
 impl InMemoryStorageEngine {
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
+            data: Arc::default(),
+            meta: HashMap::new(),
+            expiring: ExpiryQueue::default(),
+            user_meta: HashMap::new(),
+            max_entries: None,
+            max_bytes: None,
+            bytes: 0,
+            recency: None,
+            evicted: Vec::new(),
+        }
+    }
+    
+    /// Evict least recently used keys once more than `max_entries` are held
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self.recency.get_or_insert_with(Default::default);
+        self
+    }
+    
+    /// Evict least recently used keys once keys and values exceed `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self.recency.get_or_insert_with(Default::default);
+        self
+    }
+    
+    fn over_limit(&self) -> bool {
+        self.max_entries.is_some_and(|max| self.data.len() > max)
+            || self.max_bytes.is_some_and(|max| self.bytes > max)
+    }
+    
+    /// Evict until within the limits, never evicting `keep`
+    fn evict(&mut self, keep: &str) {
+        while self.over_limit() {
+            let Some(recency) = self.recency.as_mut().map(|r| r.get_mut().unwrap()) else {
+                return;
+            };
+            let Some(oldest) = recency.oldest().filter(|k| k.as_str() != keep).cloned() else {
+                return;
+            };
+            recency.forget(&oldest);
+            if let Some(meta) = self.meta.remove(&oldest) {
+                self.expiring.update(&oldest, meta.expires_ms, None);
+            }
+            self.user_meta.remove(&oldest);
+            if let Some(value) = Arc::make_mut(&mut self.data).remove(&oldest) {
+                self.bytes -= oldest.len() + value.len();
+            }
+            self.evicted.push(oldest);
         }
     }
 }
 
+impl Default for InMemoryStorageEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StorageEngine for InMemoryStorageEngine {
     fn initialize(&mut self) -> Result<()> {
         Ok(())
     }
     
     fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
-        self.data.insert(key.to_string(), value.to_vec());
+        self.store_at(key, value, meta::now_ms())
+    }
+    
+    fn store_at(&mut self, key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+        self.store_shared(key, Arc::from(value), modified_ms)
+    }
+    
+    fn store_shared(&mut self, key: &str, value: Arc<[u8]>, modified_ms: u64) -> Result<()> {
+        let len = value.len();
+        match Arc::make_mut(&mut self.data).insert(key.to_string(), value) {
+            Some(old) => self.bytes = self.bytes - old.len() + len,
+            None => self.bytes += key.len() + len,
+        }
+        let previous = self.meta.get(key).copied();
+        self.meta.insert(key.to_string(), KeyMeta::next(previous, modified_ms));
+        self.expiring.update(key, previous.and_then(|m| m.expires_ms), None);
+        self.user_meta.remove(key);
+        if let Some(recency) = &mut self.recency {
+            recency.get_mut().unwrap().touch(key);
+            self.evict(key);
+        }
         Ok(())
     }
     
     fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        Ok(self.data.get(key).cloned())
+        Ok(self.retrieve_shared(key)?.map(|v| v.to_vec()))
+    }
+    
+    fn retrieve_shared(&self, key: &str) -> Result<Option<Arc<[u8]>>> {
+        let value = self.data.get(key).cloned();
+        if let (Some(_), Some(recency)) = (&value, &self.recency) {
+            recency.lock().unwrap().touch(key);
+        }
+        Ok(value)
     }
     
     fn remove(&mut self, key: &str) -> Result<bool> {
-        Ok(self.data.remove(key).is_some())
+        let Some(old) = Arc::make_mut(&mut self.data).remove(key) else {
+            return Ok(false);
+        };
+        self.bytes -= key.len() + old.len();
+        if let Some(meta) = self.meta.remove(key) {
+            self.expiring.update(key, meta.expires_ms, None);
+        }
+        self.user_meta.remove(key);
+        if let Some(recency) = &mut self.recency {
+            recency.get_mut().unwrap().forget(key);
+        }
+        Ok(true)
+    }
+    
+    fn metadata(&self, key: &str) -> Result<Option<KeyMeta>> {
+        Ok(self.meta.get(key).copied())
+    }
+    
+    fn supports_expiry(&self) -> bool {
+        true
+    }
+    
+    fn set_expiry(&mut self, key: &str, expires_ms: Option<u64>) -> Result<bool> {
+        match self.meta.get_mut(key) {
+            Some(meta) => {
+                self.expiring.update(key, meta.expires_ms, expires_ms);
+                meta.expires_ms = expires_ms;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    
+    fn expired_keys(&self, now_ms: u64) -> Result<Vec<String>> {
+        Ok(self.expiring.due(now_ms))
+    }
+    
+    fn supports_user_meta(&self) -> bool {
+        true
+    }
+    
+    fn set_user_meta(&mut self, key: &str, meta: Option<HashMap<String, String>>) -> Result<bool> {
+        if !self.data.contains_key(key) {
+            return Ok(false);
+        }
+        match meta {
+            Some(meta) => self.user_meta.insert(key.to_string(), meta),
+            None => self.user_meta.remove(key),
+        };
+        Ok(true)
+    }
+    
+    fn user_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        Ok(self.user_meta.get(key).cloned())
     }
     
     fn list_keys(&self) -> Result<Vec<String>> {
         Ok(self.data.keys().cloned().collect())
     }
     
+    /// O(1): the length of the key map
+    fn key_count(&self) -> Result<usize> {
+        Ok(self.data.len())
+    }
+    
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        Ok(self.data
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+    
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        Ok(self.data
+            .range::<str, _>((Bound::Unbounded, end))
+            .rev()
+            .take(limit)
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+    
     fn flush(&mut self) -> Result<()> {
         Ok(())
     }
+    
+    fn snapshot(&self) -> Result<SnapshotEntries> {
+        Ok(self.data.clone())
+    }
+    
+    fn take_evicted(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.evicted)
+    }
 }
 
 /// File-based storage engine with durability
+///
+/// The data file carries a CRC32 of its contents; loading a damaged file
+/// fails with `LohError::DataCorrupt`.
 pub struct FileStorageEngine {
-    data: HashMap<String, Vec<u8>>,
+    data: SnapshotEntries,
+    meta: BTreeMap<String, KeyMeta>,
+    expiring: ExpiryQueue,
+    user_meta: BTreeMap<String, HashMap<String, String>>,
     data_dir: String,
+    /// Overrides `data.db` in `data_dir`
+    data_file: Option<PathBuf>,
     dirty: bool,
+    compressor: Option<Arc<dyn Compressor>>,
+    encryptor: Option<Arc<dyn Encryptor>>,
+    format: SerializationFormat,
+    file_mode: Option<u32>,
 }
 
 impl FileStorageEngine {
     pub fn new(data_dir: String) -> Self {
         Self {
-            data: HashMap::new(),
+            data: Arc::default(),
+            meta: BTreeMap::new(),
+            expiring: ExpiryQueue::default(),
+            user_meta: BTreeMap::new(),
             data_dir,
+            data_file: None,
             dirty: false,
+            compressor: None,
+            encryptor: None,
+            format: SerializationFormat::default(),
+            file_mode: None,
         }
     }
     
-    fn data_file_path(&self) -> String {
-        format!("{}/data.db", self.data_dir)
+    /// Create the data directory and data file with `file_mode` (Unix
+    /// permission bits) instead of the process defaults
+    ///
+    /// Each flush writes a new data file, so this also applies to a data
+    /// file that already exists once it is next flushed.
+    pub fn with_file_mode(mut self, file_mode: u32) -> Self {
+        self.file_mode = Some(file_mode);
+        self
+    }
+    
+    /// Keep the data file at `path` instead of `data.db` in the data directory
+    ///
+    /// Its directory is created when the file is first written, and
+    /// replacement files are staged beside it.
+    pub fn with_data_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.data_file = Some(path.into());
+        self
+    }
+    
+    /// Compress the data file with `compressor`
+    ///
+    /// Uncompressed data files written earlier still load and are compressed
+    /// on the next flush.
+    pub fn with_compressor(mut self, compressor: Arc<dyn Compressor>) -> Self {
+        self.compressor = Some(compressor);
+        self
+    }
+    
+    /// Encrypt the data file with `encryptor`
+    ///
+    /// Data is compressed (if configured) before it is encrypted.
+    pub fn with_encryptor(mut self, encryptor: Arc<dyn Encryptor>) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+    
+    /// Serialize the data file with `format` instead of bincode
+    ///
+    /// Loading a data file written in another format fails.
+    pub fn with_format(mut self, format: SerializationFormat) -> Self {
+        self.format = format;
+        self
+    }
+    
+    fn data_file_path(&self) -> PathBuf {
+        self.data_file.clone().unwrap_or_else(|| Path::new(&self.data_dir).join("data.db"))
+    }
+    
+    /// Directory holding the data file
+    fn data_file_dir(&self) -> PathBuf {
+        match self.data_file_path().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
     }
     
     fn load_from_disk(&mut self) -> Result<()> {
         use std::fs;
         
         let data_path = self.data_file_path();
-        if !std::path::Path::new(&data_path).exists() {
+        if !data_path.exists() {
             return Ok(());
         }
         
-        let data = fs::read(&data_path)?;
+        let data = checksum::verify(fs::read(&data_path)?)?;
+        let data = crypto::open_file(self.encryptor.as_deref(), data)?;
+        let data = compression::decode(self.compressor.as_deref(), data)?;
         if !data.is_empty() {
-            self.data = bincode::deserialize(&data)?;
+            let snapshot = decode_data_file(self.format, &data)?;
+            self.data = Arc::new(snapshot.entries.into_iter().map(|(k, v)| (k, Arc::from(v))).collect());
+            self.meta = snapshot.meta;
+            for (key, expires_ms) in snapshot.expiries {
+                if let Some(meta) = self.meta.get_mut(&key) {
+                    meta.expires_ms = Some(expires_ms);
+                    self.expiring.update(&key, None, Some(expires_ms));
+                }
+            }
+            self.user_meta = snapshot.user_meta;
         }
         
         Ok(())
     }
     
     fn save_to_disk(&mut self) -> Result<()> {
-        if !self.dirty {
+        // A missing file is written even when empty, so the layout is complete
+        if !self.dirty && self.data_file_path().exists() {
             return Ok(());
         }
         
         use std::fs;
         
-        fs::create_dir_all(&self.data_dir)?;
-        let data = bincode::serialize(&self.data)?;
-        fs::write(self.data_file_path(), data)?;
+        let dir = self.data_file_dir();
+        perms::create_dir_all(&dir, self.file_mode)?;
+        let expiries = self.meta
+            .iter()
+            .filter_map(|(key, meta)| Some((key.as_str(), meta.expires_ms?)))
+            .collect();
+        let snapshot = DataFileRef { entries: &self.data, meta: &self.meta, expiries, user_meta: &self.user_meta };
+        let mut data = format::encode_snapshot(self.format, &snapshot)?;
+        if let Some(compressor) = &self.compressor {
+            data = compression::encode(compressor.as_ref(), &data)?;
+        }
+        if let Some(encryptor) = &self.encryptor {
+            data = crypto::seal_file(encryptor.as_ref(), &data)?;
+        }
+        
+        // Replace the old snapshot only once the new one is on stable
+        // storage: a crash mid-write keeps the old file, and a checkpoint
+        // may delete the WAL records this one covers as soon as we return
+        let path = self.data_file_path();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut file = perms::open_options(self.file_mode).write(true).create(true).truncate(true).open(&tmp_path)?;
+        file.write_all(&checksum::seal(&data))?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        #[cfg(unix)]
+        fs::File::open(&dir)?.sync_all()?;
         self.dirty = false;
         
         Ok(())
     }
 }
 
+/// Contents of the data file
+#[derive(Deserialize)]
+struct DataFile {
+    entries: BTreeMap<String, Vec<u8>>,
+    meta: BTreeMap<String, KeyMeta>,
+    /// Key -> `KeyMeta::expires_ms`, for keys that expire
+    #[serde(default)]
+    expiries: BTreeMap<String, u64>,
+    /// Key -> attributes set by `set_user_meta`, for keys that have them
+    #[serde(default)]
+    user_meta: BTreeMap<String, HashMap<String, String>>,
+}
+
+/// Data file written before key metadata was tracked
+#[derive(Deserialize)]
+struct DataFileV2 {
+    entries: BTreeMap<String, Vec<u8>>,
+    meta: BTreeMap<String, KeyMeta>,
+    expiries: BTreeMap<String, u64>,
+}
+
+/// Data file written before expiries were tracked
+#[derive(Deserialize)]
+struct DataFileV1 {
+    entries: BTreeMap<String, Vec<u8>>,
+    meta: BTreeMap<String, KeyMeta>,
+}
+
+/// `DataFile` borrowed from the engine for writing
+#[derive(Serialize)]
+struct DataFileRef<'a> {
+    entries: &'a BTreeMap<String, Arc<[u8]>>,
+    meta: &'a BTreeMap<String, KeyMeta>,
+    expiries: BTreeMap<&'a str, u64>,
+    user_meta: &'a BTreeMap<String, HashMap<String, String>>,
+}
+
+/// Decode a data file written by this or an earlier version
+///
+/// Each older layout is a prefix of the newer one, so a file that ends too
+/// early for one layout is retried as the one before it. The oldest files
+/// hold only the values.
+fn decode_data_file(format: SerializationFormat, data: &[u8]) -> Result<DataFile> {
+    if let Ok(file) = format::decode_snapshot::<DataFile>(format, data) {
+        return Ok(file);
+    }
+    if let Ok(file) = format::decode_snapshot::<DataFileV2>(format, data) {
+        return Ok(DataFile { entries: file.entries, meta: file.meta, expiries: file.expiries, user_meta: BTreeMap::new() });
+    }
+    if let Ok(file) = format::decode_snapshot::<DataFileV1>(format, data) {
+        return Ok(DataFile { entries: file.entries, meta: file.meta, expiries: BTreeMap::new(), user_meta: BTreeMap::new() });
+    }
+    Ok(DataFile {
+        entries: format::decode_snapshot(format, data)?,
+        meta: BTreeMap::new(),
+        expiries: BTreeMap::new(),
+        user_meta: BTreeMap::new(),
+    })
+}
+
 impl StorageEngine for FileStorageEngine {
     fn initialize(&mut self) -> Result<()> {
         self.load_from_disk()
     }
     
     fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
-        self.data.insert(key.to_string(), value.to_vec());
+        self.store_at(key, value, meta::now_ms())
+    }
+    
+    fn store_at(&mut self, key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+        self.store_shared(key, Arc::from(value), modified_ms)
+    }
+    
+    fn store_shared(&mut self, key: &str, value: Arc<[u8]>, modified_ms: u64) -> Result<()> {
+        Arc::make_mut(&mut self.data).insert(key.to_string(), value);
+        let previous = self.meta.get(key).copied();
+        self.meta.insert(key.to_string(), KeyMeta::next(previous, modified_ms));
+        self.expiring.update(key, previous.and_then(|m| m.expires_ms), None);
+        self.user_meta.remove(key);
         self.dirty = true;
         Ok(())
     }
     
     fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.get(key).map(|v| v.to_vec()))
+    }
+    
+    fn retrieve_shared(&self, key: &str) -> Result<Option<Arc<[u8]>>> {
         Ok(self.data.get(key).cloned())
     }
     
     fn remove(&mut self, key: &str) -> Result<bool> {
-        let existed = self.data.remove(key).is_some();
+        let existed = Arc::make_mut(&mut self.data).remove(key).is_some();
         if existed {
+            if let Some(meta) = self.meta.remove(key) {
+                self.expiring.update(key, meta.expires_ms, None);
+            }
+            self.user_meta.remove(key);
             self.dirty = true;
         }
         Ok(existed)
     }
     
+    fn metadata(&self, key: &str) -> Result<Option<KeyMeta>> {
+        Ok(self.meta.get(key).copied())
+    }
+    
+    fn supports_expiry(&self) -> bool {
+        true
+    }
+    
+    fn set_expiry(&mut self, key: &str, expires_ms: Option<u64>) -> Result<bool> {
+        match self.meta.get_mut(key) {
+            Some(meta) => {
+                self.expiring.update(key, meta.expires_ms, expires_ms);
+                meta.expires_ms = expires_ms;
+                self.dirty = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    
+    fn expired_keys(&self, now_ms: u64) -> Result<Vec<String>> {
+        Ok(self.expiring.due(now_ms))
+    }
+    
+    fn supports_user_meta(&self) -> bool {
+        true
+    }
+    
+    fn set_user_meta(&mut self, key: &str, meta: Option<HashMap<String, String>>) -> Result<bool> {
+        if !self.data.contains_key(key) {
+            return Ok(false);
+        }
+        match meta {
+            Some(meta) => self.user_meta.insert(key.to_string(), meta),
+            None => self.user_meta.remove(key),
+        };
+        self.dirty = true;
+        Ok(true)
+    }
+    
+    fn user_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        Ok(self.user_meta.get(key).cloned())
+    }
+    
     fn list_keys(&self) -> Result<Vec<String>> {
         Ok(self.data.keys().cloned().collect())
     }
     
+    /// O(1): the length of the key map
+    fn key_count(&self) -> Result<usize> {
+        Ok(self.data.len())
+    }
+    
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        Ok(self.data
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+    
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        Ok(self.data
+            .range::<str, _>((Bound::Unbounded, end))
+            .rev()
+            .take(limit)
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+    
+    fn snapshot(&self) -> Result<SnapshotEntries> {
+        Ok(self.data.clone())
+    }
+    
     fn flush(&mut self) -> Result<()> {
         self.save_to_disk()
     }
+    
+    /// Write the data file out even if nothing changed since the last flush
+    fn compact(&mut self) -> Result<CompactStats> {
+        let bytes_before = self.disk_usage()?;
+        self.dirty = true;
+        self.save_to_disk()?;
+        Ok(CompactStats { bytes_before, bytes_after: self.disk_usage()? })
+    }
+    
+    fn disk_usage(&self) -> Result<u64> {
+        match std::fs::metadata(self.data_file_path()) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
 }
\ No newline at end of file
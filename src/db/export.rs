@@ -0,0 +1,184 @@
+use crate::db::{namespace, Database};
+use crate::{LohError, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+
+impl Database {
+    /// Write every key as a JSON object of `{ "key": "<base64 value>" }`
+    ///
+    /// The export is a consistent snapshot taken under one storage lock.
+    /// Namespaced keys are included in their encoded form so `import_json`
+    /// restores them into the same namespace.
+    pub fn export_json<W: Write>(&self, mut writer: W) -> Result<()> {
+        let entries: BTreeMap<String, String> = self
+            .snapshot_entries()?
+            .into_iter()
+            .map(|(key, value)| (key, BASE64.encode(value)))
+            .collect();
+            
+        serde_json::to_writer_pretty(&mut writer, &entries)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+    
+    /// Load a document produced by `export_json`, overwriting existing keys
+    ///
+    /// Every pair goes through the WAL, so importing the same file twice
+    /// leaves the database in the same state.
+    pub fn import_json<R: Read>(&mut self, reader: R) -> Result<()> {
+        let entries: BTreeMap<String, String> = serde_json::from_reader(reader)?;
+        
+        // Decode everything first so a bad entry doesn't leave a partial import
+        let mut decoded = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let value = BASE64
+                .decode(&value)
+                .map_err(|e| LohError::InvalidArgument(format!("invalid base64 value for key '{}': {}", key, e)))?;
+            decoded.push((key, value));
+        }
+        
+        for (storage_key, value) in decoded {
+            let (ns, key) = namespace::split_storage_key(&storage_key);
+            self.apply_set(ns, key.to_string(), value)?;
+        }
+        Ok(())
+    }
+    
+    /// Write every key as a CSV row of `key,value_base64`, after that header
+    ///
+    /// Rows are written as they are read, holding only the key list in
+    /// memory. As with `iter`, each value is read under its own short lock,
+    /// so writers are not blocked but the export is not a point-in-time
+    /// snapshot. Keys are quoted when they contain a comma, quote, or line
+    /// break; namespaced keys are written in their encoded form.
+    pub fn export_csv<W: Write>(&self, mut writer: W) -> Result<()> {
+        let storage = self.storage_handle();
+        let keys = storage.read().unwrap().list_keys()?;
+        
+        writer.write_all(CSV_HEADER.as_bytes())?;
+        writer.write_all(b"\r\n")?;
+        for key in keys {
+            let Some(value) = storage.read().unwrap().retrieve(&key)? else {
+                continue;
+            };
+            write_csv_field(&mut writer, &key)?;
+            writer.write_all(b",")?;
+            writer.write_all(BASE64.encode(value).as_bytes())?;
+            writer.write_all(b"\r\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+    
+    /// Load rows produced by `export_csv`, overwriting existing keys
+    ///
+    /// Rows are applied through the WAL one at a time as they are parsed, so
+    /// a malformed row fails the import after the rows before it were
+    /// written. Re-importing the corrected file is safe.
+    pub fn import_csv<R: Read>(&mut self, reader: R) -> Result<()> {
+        let mut reader = BufReader::new(reader);
+        let mut fields = Vec::new();
+        let mut line = 1;
+        
+        let header = read_csv_record(&mut reader, &mut fields, &mut line)?;
+        if header.is_none() || fields != [CSV_HEADER_KEY, CSV_HEADER_VALUE] {
+            return Err(LohError::InvalidArgument(format!("CSV header must be '{}'", CSV_HEADER)));
+        }
+        
+        while let Some(row) = read_csv_record(&mut reader, &mut fields, &mut line)? {
+            let [storage_key, value] = fields.as_slice() else {
+                return Err(LohError::InvalidArgument(format!(
+                    "CSV line {}: expected 2 fields, found {}",
+                    row, fields.len()
+                )));
+            };
+            let value = BASE64.decode(value).map_err(|e| {
+                LohError::InvalidArgument(format!("CSV line {}: invalid base64 value for key '{}': {}", row, storage_key, e))
+            })?;
+            let (ns, key) = namespace::split_storage_key(storage_key);
+            self.apply_set(ns, key.to_string(), value)?;
+        }
+        Ok(())
+    }
+}
+
+const CSV_HEADER_KEY: &str = "key";
+const CSV_HEADER_VALUE: &str = "value_base64";
+const CSV_HEADER: &str = "key,value_base64";
+
+/// Write `field`, quoting it (and doubling its quotes) only when needed
+fn write_csv_field<W: Write>(writer: &mut W, field: &str) -> Result<()> {
+    if field.contains([',', '"', '\r', '\n']) {
+        writer.write_all(b"\"")?;
+        writer.write_all(field.replace('"', "\"\"").as_bytes())?;
+        writer.write_all(b"\"")?;
+    } else {
+        writer.write_all(field.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read one RFC 4180 record into `fields`, returning the line it starts on,
+/// or `None` at end of input
+///
+/// Quoted fields may span lines; `line` is the number of the next physical
+/// line and is advanced past the record. Blank lines are skipped.
+fn read_csv_record<R: BufRead>(reader: &mut R, fields: &mut Vec<String>, line: &mut usize) -> Result<Option<usize>> {
+    fields.clear();
+    let mut buf = Vec::new();
+    
+    // Skip blank lines
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(None);
+        }
+        *line += 1;
+        if !buf.iter().all(|b| matches!(b, b'\r' | b'\n')) {
+            break;
+        }
+    }
+    let start = *line - 1;
+    
+    let mut field = Vec::new();
+    let mut in_quotes = false;
+    let mut pos = 0;
+    loop {
+        if pos == buf.len() {
+            if !in_quotes {
+                break;
+            }
+            // The quoted field continues on the next line
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                return Err(LohError::InvalidArgument(format!("CSV line {}: unterminated quoted field", start)));
+            }
+            *line += 1;
+            continue;
+        }
+        
+        let byte = buf[pos];
+        pos += 1;
+        match (in_quotes, byte) {
+            (true, b'"') if buf.get(pos) == Some(&b'"') => {
+                field.push(b'"');
+                pos += 1;
+            }
+            (true, b'"') => in_quotes = false,
+            (true, byte) => field.push(byte),
+            (false, b'"') if field.is_empty() => in_quotes = true,
+            (false, b',') => fields.push(csv_field(std::mem::take(&mut field), start)?),
+            (false, b'\r') if buf.get(pos) == Some(&b'\n') => {}
+            (false, b'\n') => break,
+            (false, byte) => field.push(byte),
+        }
+    }
+    fields.push(csv_field(field, start)?);
+    Ok(Some(start))
+}
+
+fn csv_field(bytes: Vec<u8>, line: usize) -> Result<String> {
+    String::from_utf8(bytes).map_err(|_| LohError::InvalidArgument(format!("CSV line {}: field is not valid UTF-8", line)))
+}
@@ -0,0 +1,132 @@
+use crate::{LohError, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// Magic prefix marking a data file that records its serialization format
+///
+/// Data files written before formats were configurable have no marker and
+/// are always bincode.
+const FORMAT_MAGIC: &[u8; 4] = b"LOHS";
+
+/// Encoding of WAL records and the data snapshot
+///
+/// The format is recorded in every file when it is created, and opening a
+/// database whose files were written in another format fails with
+/// `LohError::Format` instead of misreading them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SerializationFormat {
+    /// Compact and fast, but tied to the Rust type layout (the default)
+    #[default]
+    Bincode,
+    /// Human-readable and portable, at the cost of size and speed
+    Json,
+    /// Portable and compact; requires the `msgpack` feature
+    MessagePack,
+}
+
+impl SerializationFormat {
+    /// Byte identifying the format in file headers
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            SerializationFormat::Bincode => 0,
+            SerializationFormat::Json => 1,
+            SerializationFormat::MessagePack => 2,
+        }
+    }
+    
+    pub(crate) fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(SerializationFormat::Bincode),
+            1 => Ok(SerializationFormat::Json),
+            2 => Ok(SerializationFormat::MessagePack),
+            other => Err(LohError::Format(format!("unknown serialization format id {}", other))),
+        }
+    }
+    
+    /// Fail if this build cannot encode the format
+    pub(crate) fn check_available(self) -> Result<()> {
+        #[cfg(not(feature = "msgpack"))]
+        if self == SerializationFormat::MessagePack {
+            return Err(msgpack_unavailable());
+        }
+        Ok(())
+    }
+    
+    pub(crate) fn serialize<T: Serialize>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            SerializationFormat::Bincode => Ok(bincode::serialize(value)?),
+            SerializationFormat::Json => Ok(serde_json::to_vec(value)?),
+            #[cfg(feature = "msgpack")]
+            SerializationFormat::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|e| LohError::Format(format!("messagepack: {}", e)))
+            }
+            #[cfg(not(feature = "msgpack"))]
+            SerializationFormat::MessagePack => Err(msgpack_unavailable()),
+        }
+    }
+    
+    pub(crate) fn deserialize<T: DeserializeOwned>(self, data: &[u8]) -> Result<T> {
+        match self {
+            SerializationFormat::Bincode => Ok(bincode::deserialize(data)?),
+            SerializationFormat::Json => Ok(serde_json::from_slice(data)?),
+            #[cfg(feature = "msgpack")]
+            SerializationFormat::MessagePack => {
+                rmp_serde::from_slice(data).map_err(|e| LohError::Format(format!("messagepack: {}", e)))
+            }
+            #[cfg(not(feature = "msgpack"))]
+            SerializationFormat::MessagePack => Err(msgpack_unavailable()),
+        }
+    }
+    
+    /// Fail unless data found in `what` was written in this format
+    pub(crate) fn expect(self, found: SerializationFormat, what: &str) -> Result<()> {
+        if found != self {
+            return Err(LohError::Format(format!(
+                "{} was written as {} but the database is configured for {}",
+                what, found, self
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SerializationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SerializationFormat::Bincode => "bincode",
+            SerializationFormat::Json => "json",
+            SerializationFormat::MessagePack => "messagepack",
+        })
+    }
+}
+
+#[cfg(not(feature = "msgpack"))]
+fn msgpack_unavailable() -> LohError {
+    LohError::Format("MessagePack was configured but lohdb was built without the `msgpack` feature".to_string())
+}
+
+/// Serialize a data snapshot behind the marker naming its format
+pub(crate) fn encode_snapshot<T: Serialize>(format: SerializationFormat, value: &T) -> Result<Vec<u8>> {
+    let mut out = FORMAT_MAGIC.to_vec();
+    out.push(format.id());
+    out.extend_from_slice(&format.serialize(value)?);
+    Ok(out)
+}
+
+/// Reverse `encode_snapshot`, reading unmarked (older) snapshots as bincode
+///
+/// Fails if the snapshot was written in a format other than `format`.
+pub(crate) fn decode_snapshot<T: DeserializeOwned>(format: SerializationFormat, data: &[u8]) -> Result<T> {
+    let (found, payload) = match data.strip_prefix(FORMAT_MAGIC) {
+        Some(rest) => {
+            let (&id, payload) = rest
+                .split_first()
+                .ok_or_else(|| LohError::Format("data file is truncated".to_string()))?;
+            (SerializationFormat::from_id(id)?, payload)
+        }
+        None => (SerializationFormat::Bincode, data),
+    };
+    format.expect(found, "data file")?;
+    format.deserialize(payload)
+}
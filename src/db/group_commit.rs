@@ -0,0 +1,141 @@
+use crate::db::WriteAheadLog;
+use crate::{LohError, Result};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Background thread that makes WAL appends durable in batches
+///
+/// Writers append to the log as usual and then block in `wait_durable`. The
+/// thread waits up to `delay` for more writers to join (or until `max_batch`
+/// are waiting), fsyncs the active segment once, and wakes every writer the
+/// sync covered.
+pub(crate) struct GroupCommitter {
+    shared: Arc<Shared>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<CommitState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct CommitState {
+    /// Highest sequence number a writer is waiting on
+    requested_seq: u64,
+    /// Highest sequence number known to be on stable storage
+    durable_seq: u64,
+    /// Writers currently blocked in `wait_durable`
+    waiting: usize,
+    syncs: u64,
+    /// Set once an fsync fails; every later wait fails with it
+    failure: Option<String>,
+    shutdown: bool,
+}
+
+impl GroupCommitter {
+    pub fn spawn(wal: Arc<Mutex<WriteAheadLog>>, delay: Duration, max_batch: usize) -> Self {
+        let durable_seq = wal.lock().unwrap().last_seq();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(CommitState { durable_seq, requested_seq: durable_seq, ..Default::default() }),
+            changed: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || {
+            let shared = thread_shared;
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                // Sleep until some writer needs a sync
+                state = shared.changed
+                    .wait_while(state, |s| !s.shutdown && s.requested_seq <= s.durable_seq)
+                    .unwrap();
+                if state.shutdown {
+                    break;
+                }
+
+                // Give concurrent writers a chance to join this batch
+                let deadline = Instant::now() + delay;
+                while state.waiting < max_batch && !state.shutdown {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = shared.changed.wait_timeout(state, deadline - now).unwrap().0;
+                }
+                drop(state);
+
+                let result = sync_active_segment(&wal);
+
+                state = shared.state.lock().unwrap();
+                match result {
+                    Ok(seq) => {
+                        state.durable_seq = state.durable_seq.max(seq);
+                        state.syncs += 1;
+                    }
+                    Err(e) => state.failure = Some(e.to_string()),
+                }
+                shared.changed.notify_all();
+                if state.failure.is_some() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Block until the record with sequence number `seq` is on stable storage
+    pub fn wait_durable(&self, seq: u64) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.requested_seq = state.requested_seq.max(seq);
+        state.waiting += 1;
+        self.shared.changed.notify_all();
+
+        let mut state = self.shared.changed
+            .wait_while(state, |s| s.durable_seq < seq && s.failure.is_none())
+            .unwrap();
+        state.waiting -= 1;
+
+        if state.durable_seq < seq {
+            let failure = state.failure.as_deref().unwrap_or("group commit stopped");
+            return Err(LohError::Io(std::io::Error::other(format!("failed to sync WAL: {}", failure))));
+        }
+        Ok(())
+    }
+
+    /// Number of fsyncs issued so far
+    pub fn syncs(&self) -> u64 {
+        self.shared.state.lock().unwrap().syncs
+    }
+}
+
+/// Fsync the active segment, returning the last sequence number it covers
+///
+/// The WAL is locked only long enough to write out its buffer and duplicate
+/// the file handle, so writers keep appending while the sync runs. Older
+/// segments were synced when they were rolled over.
+fn sync_active_segment(wal: &Mutex<WriteAheadLog>) -> Result<u64> {
+    let (file, seq) = {
+        let mut wal = wal.lock().unwrap();
+        wal.flush()?;
+        (wal.active_file()?, wal.last_seq())
+    };
+    file.sync_data()?;
+    wal.lock().unwrap().mark_synced(seq);
+    Ok(seq)
+}
+
+impl Drop for GroupCommitter {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.changed.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::db::{ChangeEvent, Database, Operation, OverflowPolicy, SubscriptionHandle, WriteAheadLog};
+use crate::db::stream::StagedStreams;
+use crate::{LohError, Result};
+use crossbeam::channel::Receiver;
+use serde::{Deserialize, Serialize};
+
+/// A change read back from the WAL by `Database::change_history`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedChange {
+    /// Sequence number of the WAL record the change came from; one record
+    /// may yield several changes
+    pub seq: u64,
+    /// Time of the write, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub event: ChangeEvent,
+}
+
+/// Every change in `wal`, oldest first, including buffered records
+fn logged_changes(wal: &mut WriteAheadLog) -> Result<Vec<LoggedChange>> {
+    wal.flush()?;
+    let mut changes = Vec::new();
+    let mut streams = StagedStreams::default();
+    for located in wal.iter_entries() {
+        let entry = located?.entry;
+        let (seq, timestamp_ms) = (entry.seq, entry.timestamp_ms);
+        if let Some(operation) = streams.resolve(entry.operation) {
+            push_events(operation, &mut |event| changes.push(LoggedChange { seq, timestamp_ms, event }));
+        }
+    }
+    Ok(changes)
+}
+
+/// Pass `push` the events published for `operation`
+fn push_events(operation: Operation, push: &mut impl FnMut(ChangeEvent)) {
+    match operation {
+        Operation::Set { namespace, key, value }
+        | Operation::SetWithExpiry { namespace, key, value, .. }
+        | Operation::SetWithMeta { namespace, key, value, .. } => {
+            push(ChangeEvent::Set { namespace, key, value, version: 0 });
+        }
+        Operation::Delete { namespace, key } => push(ChangeEvent::Delete { namespace, key }),
+        Operation::Expire { namespace, key } => push(ChangeEvent::Expired { namespace, key }),
+        Operation::DeletePrefix { namespace, keys, .. } => {
+            for key in keys {
+                push(ChangeEvent::Delete { namespace: namespace.clone(), key });
+            }
+        }
+        Operation::Append { namespace, key, chunk, .. } => {
+            push(ChangeEvent::Append { namespace, key, chunk, version: 0 });
+        }
+        Operation::Batch { ops } => {
+            for operation in ops {
+                push_events(operation, push);
+            }
+        }
+        // Leaves the value alone, so subscribers are not told
+        Operation::Persist { .. } => {}
+        Operation::Rename { namespace, from, to, value } => {
+            push(ChangeEvent::Delete { namespace: namespace.clone(), key: from });
+            push(ChangeEvent::Set { namespace, key: to, value, version: 0 });
+        }
+        // Resolved by `StagedStreams` into the `Set` they amount to
+        Operation::StreamChunk { .. } | Operation::StreamCommit { .. } => {}
+    }
+}
+
+impl Database {
+    /// Every change still in the WAL, oldest first, as the events
+    /// subscribers received for it
+    ///
+    /// History only goes back to the last checkpoint: once a checkpoint
+    /// (background or explicit) moves records into the data file, their
+    /// WAL segments are deleted and those changes are gone from history.
+    /// Use a long `wal_sync_interval_ms`, or zero to checkpoint only on
+    /// demand, to keep more of it. In-memory databases keep no history.
+    ///
+    /// The log does not record versions, so `version` is 0 in every event.
+    /// Evictions are not logged and never appear.
+    pub fn change_history(&self) -> Result<Vec<LoggedChange>> {
+        let wal = self.wal_handle();
+        let mut wal = wal.lock().unwrap();
+        logged_changes(&mut wal)
+    }
+    
+    /// Changes logged after WAL sequence number `after_seq`, oldest first,
+    /// for filling a gap a sequenced subscriber noticed
+    ///
+    /// Fails with `InvalidArgument` once a checkpoint has released any of
+    /// them: the WAL no longer reaches back that far, and the subscriber
+    /// has to start over from a snapshot.
+    pub fn changes_after(&self, after_seq: u64) -> Result<Vec<LoggedChange>> {
+        let wal = self.wal_handle();
+        let mut wal = wal.lock().unwrap();
+        if wal.first_seq() > after_seq + 1 {
+            return Err(LohError::InvalidArgument(format!(
+                "changes after sequence {} are gone: the WAL now starts at {}",
+                after_seq, wal.first_seq()
+            )));
+        }
+        let mut changes = logged_changes(&mut wal)?;
+        changes.retain(|change| change.seq > after_seq);
+        Ok(changes)
+    }
+    
+    /// Channel subscription whose events each carry the sequence number
+    /// (LSN) and time of the WAL record that logged them
+    ///
+    /// Every write publishes its events before it releases the WAL lock, so
+    /// events arrive in the order their writes committed, however many
+    /// threads write: `seq` never decreases, the events of one record (a
+    /// transaction, a `delete_prefix`, a rename) share its `seq` and arrive
+    /// together, and cache evictions a write caused follow its own events.
+    /// This holds for every subscription; this one just shows the `seq`.
+    ///
+    /// A jump in `seq` is not necessarily a loss: some records publish
+    /// nothing (`persist`, the chunks of a streamed value) and changes to
+    /// keys outside `prefix` are filtered out. Only the overflow policy
+    /// loses events, and counts them in `DbStats::subscriber_stats`. Pass
+    /// `OverflowPolicy::Block` to lose none, or on a jump from `a` to `b`
+    /// call `changes_after(a)` and take the changes before `b` that match
+    /// `prefix`. Backfilled events carry a `version` of 0.
+    ///
+    /// Events are sent once their record is logged, before the write
+    /// returns: with `group_commit`, before the fsync that makes it durable,
+    /// so a crash can lose a write a subscriber has already seen.
+    pub fn subscribe_sequenced(
+        &mut self,
+        prefix: String,
+        policy: OverflowPolicy,
+    ) -> Result<(SubscriptionHandle, Receiver<LoggedChange>)> {
+        self.event_bus_handle().lock().unwrap().subscribe_sequenced(prefix, policy, 0)
+    }
+    
+    /// `change_history` together with a channel subscription to every
+    /// change after it, for bootstrapping a replica and then tailing it
+    ///
+    /// The history is read and the subscription registered while writers
+    /// are paused, and the subscription skips any write in flight at that
+    /// moment that the history already holds, so every change is seen
+    /// exactly once. Only keys starting with `prefix` are included in both.
+    pub fn subscribe_channel_with_history(
+        &mut self,
+        prefix: String,
+        policy: OverflowPolicy,
+    ) -> Result<(Vec<LoggedChange>, SubscriptionHandle, Receiver<ChangeEvent>)> {
+        let wal = self.wal_handle();
+        let mut wal = wal.lock().unwrap();
+        let mut history = logged_changes(&mut wal)?;
+        history.retain(|change| change.event.key().starts_with(&prefix));
+        let event_bus = self.event_bus_handle();
+        let (handle, events) = event_bus.lock().unwrap().subscribe_channel_after(prefix, policy, wal.last_seq())?;
+        Ok((history, handle, events))
+    }
+}
@@ -0,0 +1,129 @@
+use crate::db::{namespace, Database, StorageEngine};
+use crate::{LohError, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+/// Derives an index key from a key and its value, or `None` to leave it unindexed
+pub type IndexExtractor = Arc<dyn Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// In-memory secondary index over the default namespace
+struct SecondaryIndex {
+    extractor: IndexExtractor,
+    /// Index key -> primary keys carrying it
+    entries: BTreeMap<Vec<u8>, BTreeSet<String>>,
+    /// Primary key -> its current index key, so a stale entry can be
+    /// removed without the old value
+    reverse: HashMap<String, Vec<u8>>,
+}
+
+impl SecondaryIndex {
+    fn insert(&mut self, key: &str, value: &[u8]) {
+        self.remove(key);
+        if let Some(index_key) = (self.extractor)(key, value) {
+            self.entries.entry(index_key.clone()).or_default().insert(key.to_string());
+            self.reverse.insert(key.to_string(), index_key);
+        }
+    }
+    
+    fn remove(&mut self, key: &str) {
+        let Some(index_key) = self.reverse.remove(key) else {
+            return;
+        };
+        if let Some(keys) = self.entries.get_mut(&index_key) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.entries.remove(&index_key);
+            }
+        }
+    }
+}
+
+/// Every secondary index of a database, by name
+#[derive(Default)]
+pub(crate) struct Indexes {
+    by_name: HashMap<String, SecondaryIndex>,
+}
+
+impl Indexes {
+    /// Update every index for a set of `key` in namespace `ns`
+    pub fn on_set(&mut self, ns: Option<&str>, key: &str, value: &[u8]) {
+        if ns.is_none() {
+            self.by_name.values_mut().for_each(|index| index.insert(key, value));
+        }
+    }
+    
+    /// Drop `key` in namespace `ns` from every index
+    pub fn on_delete(&mut self, ns: Option<&str>, key: &str) {
+        if ns.is_none() {
+            self.by_name.values_mut().for_each(|index| index.remove(key));
+        }
+    }
+    
+    /// `on_delete` for a storage-encoded key, as reported by evictions
+    pub fn on_delete_storage_key(&mut self, storage_key: &str) {
+        let (ns, key) = namespace::split_storage_key(storage_key);
+        self.on_delete(ns, key);
+    }
+}
+
+impl Database {
+    /// Index the default namespace by a value derived from each entry
+    ///
+    /// `extractor` receives every key and value and returns the index key to
+    /// file it under, or `None` to leave the entry out. It must be
+    /// deterministic and cheap: it runs on every `set`, with writers paused.
+    /// Existing entries are indexed right away, and from then on every
+    /// `set` and `delete` keeps the index current. Indexes live in memory
+    /// only, so create them again after each open.
+    pub fn create_index<F>(&mut self, name: &str, extractor: F) -> Result<()>
+    where
+        F: Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        let indexes = self.indexes_handle();
+        self.with_quiesced(|_wal, storage| {
+            let mut indexes = indexes.write().unwrap();
+            if indexes.by_name.contains_key(name) {
+                return Err(LohError::InvalidArgument(format!("index '{}' already exists", name)));
+            }
+            
+            let mut index = SecondaryIndex {
+                extractor: Arc::new(extractor),
+                entries: BTreeMap::new(),
+                reverse: HashMap::new(),
+            };
+            build(&mut index, storage)?;
+            indexes.by_name.insert(name.to_string(), index);
+            Ok(())
+        })
+    }
+    
+    /// Remove the index `name`, returning whether it existed
+    pub fn drop_index(&mut self, name: &str) -> bool {
+        self.indexes_handle().write().unwrap().by_name.remove(name).is_some()
+    }
+    
+    /// Keys of the default namespace whose entry `name` filed under `index_key`,
+    /// in sorted order
+    pub fn index_lookup(&self, name: &str, index_key: &[u8]) -> Result<Vec<String>> {
+        let indexes = self.indexes_handle();
+        let indexes = indexes.read().unwrap();
+        let index = indexes
+            .by_name
+            .get(name)
+            .ok_or_else(|| LohError::NotFound(format!("index '{}'", name)))?;
+        Ok(index.entries.get(index_key).map_or_else(Vec::new, |keys| keys.iter().cloned().collect()))
+    }
+}
+
+/// Index every entry of the default namespace currently in `storage`
+fn build(index: &mut SecondaryIndex, storage: &dyn StorageEngine) -> Result<()> {
+    for key in storage.list_keys()? {
+        if namespace::is_namespaced(&key) {
+            continue;
+        }
+        if let Some(value) = storage.retrieve(&key)? {
+            index.insert(&key, &value);
+        }
+    }
+    Ok(())
+}
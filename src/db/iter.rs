@@ -0,0 +1,133 @@
+use crate::db::{namespace, Database, StorageEngine};
+use crate::Result;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+
+/// Lazy iterator over the key/value pairs of the default namespace
+///
+/// The key set is captured when the iterator is created; each value is read
+/// from the live storage as the iterator advances, taking the read lock only
+/// for that lookup. Keys deleted after creation are skipped, keys added after
+/// creation are not visited, and a value overwritten mid-scan is returned in
+/// its newest form. Only the keys are held in memory, never all the values.
+///
+/// Keys come in ascending order (descending from a reverse `iter_range`),
+/// and `rev()` walks them the other way.
+pub struct DbIterator {
+    storage: Arc<RwLock<Box<dyn StorageEngine>>>,
+    keys: std::vec::IntoIter<String>,
+}
+
+impl DbIterator {
+    /// Read the value of `key`, or `None` if it has been deleted since
+    fn read(&self, key: String) -> Option<Result<(String, Vec<u8>)>> {
+        match self.storage.read().unwrap().retrieve(&key) {
+            Ok(Some(value)) => Some(Ok((key, value))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl Iterator for DbIterator {
+    type Item = Result<(String, Vec<u8>)>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(key) = self.keys.next() {
+            if let Some(item) = self.read(key) {
+                return Some(item);
+            }
+        }
+        None
+    }
+    
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.keys.len()))
+    }
+}
+
+impl DoubleEndedIterator for DbIterator {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while let Some(key) = self.keys.next_back() {
+            if let Some(item) = self.read(key) {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+impl Database {
+    /// Iterate lazily over the default namespace; see `DbIterator` for the
+    /// consistency guarantees
+    pub fn iter(&self) -> Result<DbIterator> {
+        Ok(DbIterator {
+            storage: self.storage_handle(),
+            keys: self.list_keys()?.into_iter(),
+        })
+    }
+    
+    /// Iterate lazily over the keys of the default namespace in
+    /// `start..end`, ascending or (with `reverse`) descending
+    ///
+    /// `start` is inclusive and `end` exclusive; `None` leaves that side
+    /// open, so `iter_range(None, None, true)` walks the whole namespace
+    /// from the last key. With time-ordered keys a reverse scan yields the
+    /// newest entries first; stop after the first N with `take`. Only the
+    /// keys in the range are visited, using the engine's range scans.
+    /// Consistency is as for `iter`.
+    pub fn iter_range(&self, start: Option<&str>, end: Option<&str>, reverse: bool) -> Result<DbIterator> {
+        let storage = self.storage_handle();
+        let keys = {
+            let storage = storage.read().unwrap();
+            keys_in_range(storage.as_ref(), start, end, reverse)?
+        };
+        Ok(DbIterator {
+            storage,
+            keys: keys.into_iter(),
+        })
+    }
+    
+    /// Visit every entry of the default namespace without copying values
+    ///
+    /// Unlike `iter`, this holds the storage read lock for the whole scan, so
+    /// it sees a consistent point-in-time view but blocks writers until `f`
+    /// has seen every entry.
+    pub fn for_each<F: FnMut(&str, &[u8])>(&self, mut f: F) -> Result<()> {
+        let storage = self.storage_handle();
+        let storage = storage.read().unwrap();
+        for storage_key in storage.list_keys()? {
+            if let (None, key) = namespace::split_storage_key(&storage_key) {
+                if let Some(value) = storage.retrieve(&storage_key)? {
+                    f(key, &value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keys of the default namespace in `start..end`, scanned in batches in the
+/// requested direction
+fn keys_in_range(storage: &dyn StorageEngine, start: Option<&str>, end: Option<&str>, reverse: bool) -> Result<Vec<String>> {
+    const BATCH: usize = 1024;
+    // Namespaced keys sort before every key of the default namespace
+    let start = start.filter(|s| *s > namespace::FIRST_KEY_AFTER_NAMESPACES).unwrap_or(namespace::FIRST_KEY_AFTER_NAMESPACES);
+    let in_range = |key: &str| key >= start && end.is_none_or(|end| key < end);
+    
+    let mut keys: Vec<String> = Vec::new();
+    loop {
+        let batch = match (reverse, keys.last()) {
+            (false, None) => storage.scan_keys(Bound::Included(start), BATCH)?,
+            (false, Some(last)) => storage.scan_keys(Bound::Excluded(last), BATCH)?,
+            (true, None) => storage.scan_keys_rev(end.map_or(Bound::Unbounded, Bound::Excluded), BATCH)?,
+            (true, Some(last)) => storage.scan_keys_rev(Bound::Excluded(last), BATCH)?,
+        };
+        let exhausted = batch.len() < BATCH;
+        let before = keys.len();
+        keys.extend(batch.into_iter().take_while(|k| in_range(k)));
+        if exhausted || keys.len() - before < BATCH {
+            return Ok(keys);
+        }
+    }
+}
@@ -1,126 +1,1525 @@
 use crate::db::{
-    StorageEngine, FileStorageEngine, WriteAheadLog, Operation,
-    EventBus, ChangeEvent, SubscriptionHandle, Subscriber
+    StorageEngine, CompactStats, FileStorageEngine, InMemoryStorageEngine, WriteAheadLog, Operation, ReplayReport,
+    EventBus, ChangeEvent, EventFilter, SubscriptionHandle, NamespaceHandle, DirLock, DbStats, SyncHealth, DatabaseConfig, ValueMeta, VersionedPut, LifecycleBus, LifecycleEvent, OverflowPolicy, Encryptor, Manifest, SerializationFormat, ShardedStorageEngine, Shards, DedupStorageEngine, Clock
 };
-use crate::Result;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
-use std::thread;
+use crate::db::{chunked, crypto, manifest, meta, namespace, perms, wal};
+use crate::db::recovery::RecoverTo;
+use crate::db::stats::OpCounters;
+use crate::db::sync::{self, CheckpointTriggers, FlushHealth, FlushMode, FlushRegistration, FlushScheduler};
+use crate::db::group_commit::GroupCommitter;
+use crate::db::index::Indexes;
+use crate::db::shard::{KeyStorage, KeyStorageRead, KeyStorageWrite};
+use crate::db::stream::{self, StagedStreams};
+use crate::db::ttl::{self, ExpirySweeper, ExpiryTargets};
+#[cfg(feature = "metrics")]
+use crate::db::metrics::{Metrics, MetricsSnapshot};
+use crate::{LohError, Result};
+use crossbeam::channel::Receiver;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-pub struct DatabaseConfig {
-    pub data_dir: String,
-    pub wal_sync_interval_ms: u64,
+/// Evaluate `$body`, recording how long it took in `$self.metrics.$histogram`
+/// when the `metrics` feature is enabled; without it this is just `$body`
+macro_rules! timed {
+    ($self:ident . $histogram:ident, $body:expr) => {{
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = $body;
+        #[cfg(feature = "metrics")]
+        $self.metrics.$histogram.record(started.elapsed());
+        result
+    }};
 }
 
 pub struct Database {
-    storage: Arc<Mutex<Box<dyn StorageEngine>>>,
+    storage: Arc<RwLock<Box<dyn StorageEngine>>>,
+    /// The shards of a sharded engine, which single-key reads and writes
+    /// lock one at a time under a read lock on `storage`
+    shards: Option<Arc<Shards>>,
     wal: Arc<Mutex<WriteAheadLog>>,
+    data_dir: String,
+    /// Where the data file, or each of its shards, lives, which may be
+    /// outside `data_dir`
+    data_files: Vec<PathBuf>,
+    /// Where the values of a `dedup` database live
+    blob_file: Option<PathBuf>,
+    counters: OpCounters,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
     event_bus: Arc<Mutex<EventBus>>,
-    _sync_handle: thread::JoinHandle<()>,
+    lifecycle: Arc<Mutex<LifecycleBus>>,
+    indexes: Arc<RwLock<Indexes>>,
+    read_only: bool,
+    in_memory: bool,
+    max_key_bytes: Option<usize>,
+    max_value_bytes: Option<usize>,
+    stream_chunk_bytes: usize,
+    /// Mode of the files the database creates
+    file_mode: Option<u32>,
+    /// Encrypts the WAL and the data file, and what a `ValueWriter` stages
+    encryptor: Option<Arc<dyn Encryptor>>,
+    /// Encoding of the WAL, the data file and typed values
+    format: SerializationFormat,
+    /// Keys removed by expiry since open, shared with the sweeper
+    expired: Arc<AtomicU64>,
+    /// Time source for expiry; the WAL holds its own handle for timestamps
+    clock: Arc<dyn Clock>,
+    committer: Option<GroupCommitter>,
+    flush_registration: Option<FlushRegistration>,
+    /// Outcome of the latest checkpoint and the background thread's heartbeat
+    flush_health: Arc<FlushHealth>,
+    /// Checkpoint after every this many writes
+    flush_every_n_writes: Option<u64>,
+    /// Writes since open, counted towards `flush_every_n_writes`
+    writes: AtomicU64,
+    expiry_sweeper: Option<ExpirySweeper>,
+    /// What the WAL replay at open recovered
+    replay: ReplayReport,
+    _lock: Option<DirLock>,
 }
 
 impl Database {
     pub fn open(config: DatabaseConfig) -> Result<Self> {
-        let mut storage: Box<dyn StorageEngine> = Box::new(FileStorageEngine::new(config.data_dir.clone()));
-        storage.initialize()?;
+        Self::open_with(config, None)
+    }
+    
+    /// `open`, failing with `LohError::Locked` straight away if another
+    /// process holds the directory, whatever `open_timeout` says
+    pub fn try_open(config: DatabaseConfig) -> Result<Self> {
+        Self::open(DatabaseConfig { open_timeout: None, ..config })
+    }
+    
+    /// Open the database, replaying the WAL only up to `target` if given
+    pub(crate) fn open_with(config: DatabaseConfig, target: Option<RecoverTo>) -> Result<Self> {
+        if config.in_memory {
+            if config.read_only {
+                return Err(LohError::InvalidArgument(
+                    "an in-memory database cannot be opened read-only".to_string(),
+                ));
+            }
+            let mut engine = InMemoryStorageEngine::new();
+            if let Some(max_entries) = config.cache_max_entries {
+                engine = engine.with_max_entries(max_entries);
+            }
+            if let Some(max_bytes) = config.cache_max_bytes {
+                engine = engine.with_max_bytes(max_bytes);
+            }
+            let engine: Box<dyn StorageEngine> = if config.dedup {
+                Box::new(DedupStorageEngine::new(Box::new(engine), Box::new(InMemoryStorageEngine::new())).without_blob_file())
+            } else {
+                Box::new(engine)
+            };
+            return Ok(Self::assemble(
+                &config,
+                engine,
+                WriteAheadLog::in_memory().with_clock(config.clock.clone()),
+                None,
+            ));
+        }
         
-        let wal_path = format!("{}/wal.log", config.data_dir);
-        let mut wal = WriteAheadLog::new(&wal_path)?;
+        log::debug!("opening database in '{}'", config.data_dir);
+        if config.read_only && !std::path::Path::new(&config.data_dir).is_dir() {
+            return Err(LohError::NotFound(format!("data directory '{}'", config.data_dir)));
+        }
         
-        // Replay WAL to restore state
-        let storage_for_replay = Arc::new(Mutex::new(storage));
-        {
-            let storage_clone = storage_for_replay.clone();
-            wal.replay(|operation| {
-                let mut storage = storage_clone.lock().unwrap();
-                match operation {
-                    Operation::Set { key, value } => {
-                        storage.store(&key, &value)?;
-                    }
-                    Operation::Delete { key } => {
-                        storage.remove(&key)?;
-                    }
-                }
-                Ok(())
-            })?;
+        // Hold an exclusive lock on the directory for the lifetime of the database
+        let lock = if config.read_only {
+            None
+        } else {
+            perms::check_writable(&config.data_dir, "data directory", config.effective_file_mode())?;
+            let file_mode = config.effective_file_mode();
+            Some(match config.open_timeout {
+                Some(timeout) => DirLock::acquire_within(&config.data_dir, file_mode, timeout)?,
+                None => DirLock::acquire_with_mode(&config.data_dir, file_mode)?,
+            })
+        };
+        
+        let manifest = Manifest::read(&config.data_dir)?;
+        if let Some(manifest) = &manifest {
+            manifest.check(&config)?;
         }
         
-        let wal = Arc::new(Mutex::new(wal));
-        let event_bus = Arc::new(Mutex::new(EventBus::new()));
+        // Only once the manifest agrees, so a mistyped path is not created
+        if !config.read_only {
+            let file_mode = config.effective_file_mode();
+            perms::check_writable(config.effective_wal_dir(), "WAL directory", file_mode)?;
+            if let Some(dir) = config.effective_data_file().parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                perms::check_writable(dir, "data file directory", file_mode)?;
+            }
+            stream::remove_staged(&config.data_dir)?;
+        }
         
-        // Start background sync thread
-        let storage_for_sync = storage_for_replay.clone();
-        let sync_handle = thread::spawn(move || {
-            let interval = Duration::from_millis(config.wal_sync_interval_ms);
-            let mut last_sync = Instant::now();
-            
-            loop {
-                thread::sleep(Duration::from_millis(100));
-                
-                if last_sync.elapsed() >= interval {
-                    if let Ok(mut storage) = storage_for_sync.lock() {
-                        let _ = storage.flush();
+        let encryptor = config.encryption_key.map(crypto::encryptor_for_key).transpose()?;
+        
+        let mut wal = if config.read_only {
+            WriteAheadLog::open_read_only(config.effective_wal_dir())?
+        } else {
+            WriteAheadLog::open_with_mode(config.effective_wal_dir(), config.effective_file_mode())?
+                .with_segment_bytes(config.wal_segment_bytes)
+                .with_buffer_bytes(config.wal_buffer_bytes)
+                .with_commit_batch(config.wal_commit_batch)
+                .with_clock(config.clock.clone())
+                .with_write_retries(config.wal_write_retries, Duration::from_millis(config.wal_retry_backoff_ms))
+        };
+        wal.check_format(config.serialization_format)?;
+        wal = wal.with_format(config.serialization_format);
+        if let Some(encryptor) = &encryptor {
+            wal = wal.with_encryptor(encryptor.clone());
+        }
+        if let Some(compressor) = &config.wal_compressor {
+            wal = wal.with_compressor(compressor.clone());
+        }
+        
+        // Without a data file, a fresh directory and one whose data file was
+        // lost look alike; only the WAL can tell them apart
+        let data_file_present = config.effective_data_files().iter().any(|path| path.exists());
+        if !data_file_present && wal.first_seq() > 1 {
+            return Err(LohError::DataCorrupt(format!(
+                "'{}' has no data file, but WAL records up to sequence {} were checkpointed into one; \
+                 restore it from a backup",
+                config.data_dir, wal.first_seq() - 1
+            )));
+        }
+        if manifest.is_none() && !data_file_present && wal.last_seq() == 0 {
+            log::debug!("initializing a new database in '{}'", config.data_dir);
+        }
+        
+        // While the WAL still starts at the first record, recovering to an
+        // earlier point rebuilds from scratch instead of the newer data file
+        let file_engine = || configured_storage(&config, encryptor.as_ref());
+        let mut storage: Box<dyn StorageEngine> = if target.is_some() && wal.first_seq() == 1 {
+            Box::new(InMemoryStorageEngine::new())
+        } else {
+            file_engine()
+        };
+        match storage.initialize() {
+            // The WAL still holds every write, so it can rebuild the data alone
+            Err(LohError::DataCorrupt(reason)) if wal.first_seq() == 1 => {
+                log::warn!("corrupt data file in '{}' ({}); rebuilding it from the WAL", config.data_dir, reason);
+                storage = if config.read_only {
+                    Box::new(InMemoryStorageEngine::new())
+                } else {
+                    // Every shard is rebuilt, so they all make way
+                    let data_paths = config.effective_data_files().into_iter().chain(config.effective_blob_file());
+                    for data_path in data_paths.filter(|path| path.exists()) {
+                        let mut corrupt_path = data_path.clone().into_os_string();
+                        corrupt_path.push(".corrupt");
+                        std::fs::rename(&data_path, corrupt_path)?;
                     }
-                    last_sync = Instant::now();
-                }
+                    file_engine()
+                };
+                storage.initialize()?;
+            }
+            Err(LohError::DataCorrupt(reason)) => {
+                return Err(LohError::DataCorrupt(format!(
+                    "{}; records up to sequence {} exist only in the data file, so the WAL cannot rebuild it",
+                    reason, wal.first_seq() - 1
+                )));
+            }
+            result => result?,
+        }
+        
+        // Replay WAL to restore state
+        let mut past_target = false;
+        let on_progress = |progress| {
+            if let Some(observer) = &config.on_recovery_progress {
+                observer(progress);
+            }
+        };
+        let mut streams = StagedStreams::default();
+        let replay = wal.replay_with_progress(|entry| {
+            if past_target || target.is_some_and(|t| !t.includes(&entry)) {
+                past_target = true;
+                return Ok(());
+            }
+            match streams.resolve(entry.operation) {
+                Some(operation) => replay_operation(storage.as_mut(), operation, entry.timestamp_ms),
+                None => Ok(()),
+            }
+        }, config.recovery_progress_every, on_progress)?;
+        if let Some(target) = target {
+            // The data file may hold every logged write, so an earlier point is
+            // only reachable while the WAL still starts at the first record
+            let has_records = wal.last_seq() >= wal.first_seq();
+            let covers_log = !past_target
+                && (has_records || matches!(target, RecoverTo::Sequence(n) if n >= wal.last_seq()));
+            if wal.first_seq() > 1 && !covers_log {
+                return Err(LohError::InvalidArgument(format!(
+                    "cannot recover to {}: records up to sequence {} were checkpointed into the data \
+                     file, which is newer than the requested point",
+                    target, wal.first_seq() - 1
+                )));
+            }
+        }
+        
+        // Later writes would land behind the unreadable records, and the
+        // next replay would skip them too
+        if replay.skipped > 0 && !config.read_only {
+            let error = replay.first_error.as_deref().unwrap_or("unknown error");
+            if !config.salvage_wal {
+                return Err(LohError::WalCorrupt(format!(
+                    "{} WAL record(s) in '{}' cannot be replayed ({}); open read-only to inspect them, or \
+                     set salvage_wal to keep the {} recovered record(s) and set the WAL aside",
+                    replay.skipped, config.data_dir, error, replay.applied
+                )));
+            }
+            log::warn!(
+                "salvaging '{}': keeping {} recovered WAL record(s) and setting aside {} unreadable one(s)",
+                config.data_dir, replay.applied, replay.skipped
+            );
+            storage.flush()?;
+            for segment in wal.segment_paths() {
+                let mut corrupt_path = segment.clone().into_os_string();
+                corrupt_path.push(".corrupt");
+                std::fs::copy(&segment, corrupt_path)?;
             }
+            wal.truncate()?;
+        }
+        
+        if !config.read_only {
+            manifest::record(&config.data_dir, manifest, &config)?;
+        }
+        
+        log::debug!(
+            "opened database in '{}': {} keys, WAL at sequence {}",
+            config.data_dir, storage.key_count()?, wal.last_seq()
+        );
+        let mut db = Self::assemble(&config, storage, wal, lock);
+        db.replay = replay;
+        db.encryptor = encryptor;
+        Ok(db)
+    }
+    
+    /// Open a database that lives entirely in memory
+    ///
+    /// Nothing touches the filesystem: no directory, lock, data file, or WAL
+    /// is created, and everything is lost when the database is dropped.
+    /// `flush` and `checkpoint` succeed without doing anything.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::open(DatabaseConfig { in_memory: true, ..DatabaseConfig::default() })
+    }
+    
+    /// Wrap recovered storage and WAL, starting the background threads `config` asks for
+    fn assemble(
+        config: &DatabaseConfig,
+        storage: Box<dyn StorageEngine>,
+        wal: WriteAheadLog,
+        lock: Option<DirLock>,
+    ) -> Self {
+        let shards = storage.shards();
+        let storage = Arc::new(RwLock::new(storage));
+        let wal = Arc::new(Mutex::new(wal));
+        let event_bus = Arc::new(Mutex::new(EventBus::with_capacity(
+            config.subscriber_buffer,
+            config.overflow_policy,
+        )));
+        let lifecycle = Arc::new(Mutex::new(LifecycleBus::with_capacity(config.subscriber_buffer)));
+        
+        // Neither background thread has any disk to work on in memory
+        let on_disk_writer = !config.read_only && !config.in_memory;
+        
+        let committer = (on_disk_writer && config.group_commit).then(|| {
+            GroupCommitter::spawn(
+                wal.clone(),
+                Duration::from_micros(config.group_commit_delay_us),
+                config.group_commit_max_batch,
+            )
+        });
+        
+        // Register for background checkpoints (nothing to sync when
+        // read-only, and they are off without an interval, a WAL size or
+        // a ratio)
+        let triggers = CheckpointTriggers {
+            interval: (config.wal_sync_interval_ms > 0).then(|| Duration::from_millis(config.wal_sync_interval_ms)),
+            wal_bytes: config.checkpoint_wal_bytes,
+            ratio: config.checkpoint_ratio,
+        };
+        let scheduled = triggers.interval.is_some() || triggers.wal_bytes.is_some() || triggers.ratio.is_some();
+        let flush_health = Arc::new(FlushHealth::new(&triggers));
+        let flush_registration = (on_disk_writer && scheduled).then(|| {
+            let scheduler = config.flush_scheduler.as_ref().unwrap_or_else(|| FlushScheduler::global());
+            scheduler.register(
+                triggers,
+                storage.clone(),
+                wal.clone(),
+                lifecycle.clone(),
+                flush_health.clone(),
+            )
         });
         
-        Ok(Self {
-            storage: storage_for_replay,
+        let indexes = Arc::default();
+        let expired = Arc::default();
+        
+        // Expired keys read as absent either way; the sweep reclaims them
+        let expiry_sweeper = (!config.read_only && config.ttl_sweep_interval_ms > 0).then(|| {
+            ExpirySweeper::spawn(
+                Duration::from_millis(config.ttl_sweep_interval_ms),
+                ExpiryTargets {
+                    wal: wal.clone(),
+                    storage: storage.clone(),
+                    indexes: Arc::clone(&indexes),
+                    event_bus: event_bus.clone(),
+                    expired: Arc::clone(&expired),
+                    clock: config.clock.clone(),
+                },
+            )
+        });
+        
+        Self {
+            storage,
+            shards,
             wal,
+            data_dir: config.data_dir.clone(),
+            data_files: config.effective_data_files(),
+            blob_file: config.effective_blob_file(),
+            counters: OpCounters::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new(),
             event_bus,
-            _sync_handle: sync_handle,
-        })
+            lifecycle,
+            indexes,
+            read_only: config.read_only,
+            in_memory: config.in_memory,
+            max_key_bytes: config.max_key_bytes,
+            max_value_bytes: config.max_value_bytes,
+            stream_chunk_bytes: config.stream_chunk_bytes,
+            file_mode: config.effective_file_mode(),
+            encryptor: None,
+            format: config.serialization_format,
+            expired,
+            clock: config.clock.clone(),
+            committer,
+            flush_registration,
+            flush_health,
+            flush_every_n_writes: config.flush_every_n_writes,
+            writes: AtomicU64::new(0),
+            expiry_sweeper,
+            replay: ReplayReport::default(),
+            _lock: lock,
+        }
     }
     
     pub fn set(&mut self, key: String, value: Vec<u8>) -> Result<()> {
-        let operation = Operation::Set {
-            key: key.clone(),
+        namespace::validate_key(&key)?;
+        self.apply_set(None, key, value)
+    }
+    
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.apply_get(None, key)
+    }
+    
+    /// Like `get`, but a missing (or expired) key is an error:
+    /// `LohError::NotFound` naming the key
+    pub fn get_required(&self, key: &str) -> Result<Vec<u8>> {
+        self.apply_get(None, key)?.ok_or_else(|| LohError::NotFound(format!("key '{}'", key)))
+    }
+    
+    /// Number of keys across all namespaces, without listing them
+    ///
+    /// O(1) for the built-in engines. Keys past their TTL count until swept.
+    pub fn key_count(&self) -> Result<usize> {
+        self.storage.read().unwrap().key_count()
+    }
+    
+    /// Set `key` only if its current version is `expected_version`
+    ///
+    /// Pass the version from `get_with_metadata` (or 0 to create a key that
+    /// must not exist yet). Returns the new version, or
+    /// `VersionedPut::Stale` with the current version if another write came
+    /// first; re-read and retry in that case. Storage engines that do not
+    /// track versions always report version 0.
+    pub fn put_if_version(&mut self, key: String, value: Vec<u8>, expected_version: u64) -> Result<VersionedPut> {
+        namespace::validate_key(&key)?;
+        self.apply_versioned_set(None, key, value, Some(expected_version), None)
+    }
+    
+    /// Get a value together with its size, version, and last-modified time
+    ///
+    /// The version counts sets since the key was (re)created, so it can
+    /// detect concurrent changes. Storage engines that do not track metadata
+    /// report version 0 and the Unix epoch.
+    pub fn get_with_metadata(&self, key: &str) -> Result<Option<ValueMeta>> {
+        self.apply_get_with_metadata(None, key)
+    }
+    
+    /// Set `key` together with `meta`, string attributes such as a content
+    /// type kept beside the value rather than inside it
+    ///
+    /// The attributes are logged with the value and survive recovery. They
+    /// belong to this value: any later write of the key without them (`set`,
+    /// `set_with_ttl`, `append`, ...) clears them, as does deleting it, so a
+    /// value never carries attributes that were meant for an older one. Fails
+    /// if the storage engine does not support key metadata.
+    pub fn set_with_meta(&mut self, key: String, value: Vec<u8>, meta: HashMap<String, String>) -> Result<()> {
+        namespace::validate_key(&key)?;
+        self.apply_set_with_meta(None, key, value, meta)
+    }
+    
+    /// The attributes stored with `key` by `set_with_meta`
+    ///
+    /// `None` if the key does not exist (or has expired); an empty map if it
+    /// was last written without attributes.
+    pub fn get_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        self.apply_get_meta(None, key)
+    }
+    
+    /// Get `key`, or if it is absent store and return the value `f` computes
+    ///
+    /// The check, the insert and the read happen under one hold of the
+    /// database's write locks, so concurrent callers never both insert and
+    /// `f` runs at most once per miss. An insert is logged and published
+    /// like `set`; a hit writes nothing. Expired keys count as absent.
+    ///
+    /// **`f` runs while the locks are held: it must not call back into this
+    /// database (or a namespace of it), which would deadlock.**
+    pub fn get_or_insert_with<F>(&mut self, key: &str, f: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        namespace::validate_key(key)?;
+        self.apply_get_or_insert_with(None, key, f)
+    }
+    
+    pub fn delete(&mut self, key: &str) -> Result<bool> {
+        self.apply_delete(None, key)
+    }
+    
+    /// Delete `key` and return the value it held, or `None` if it was
+    /// absent (or expired)
+    ///
+    /// The read and the delete happen under the same locks, so when several
+    /// callers take one key exactly one of them gets the value, which makes
+    /// this the pop of a simple work queue. Logged and published like
+    /// `delete`; taking a missing key writes nothing.
+    pub fn take(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.apply_take(None, key)
+    }
+    
+    /// Delete `key` only if its value equals `expected`, returning whether
+    /// it was deleted
+    ///
+    /// The comparison and the delete happen under the same locks, so a
+    /// lease holder can release its lock only while it is still theirs.
+    /// False means the key is absent (or expired) or holds another value;
+    /// `get` tells the two apart when it matters. Only a delete is logged
+    /// and published, like `delete`.
+    pub fn delete_if(&mut self, key: &str, expected: &[u8]) -> Result<bool> {
+        self.apply_delete_if(None, key, expected)
+    }
+    
+    /// Move the value at `from` to `to`, returning false if `from` does
+    /// not exist
+    ///
+    /// The expiry and `set_with_meta` attributes move with the value, and
+    /// the key starts over at version 1 under its new name. If `to` already
+    /// exists, it is replaced when `overwrite` is set, and otherwise the
+    /// rename fails with `LohError::InvalidArgument`, leaving both keys
+    /// alone. The move is logged as one WAL record, so recovery restores
+    /// both halves or neither, and subscribers see a `ChangeEvent::Delete`
+    /// of `from` followed by a `ChangeEvent::Set` of `to`. Renaming a key to
+    /// itself changes nothing.
+    pub fn rename(&mut self, from: &str, to: &str, overwrite: bool) -> Result<bool> {
+        namespace::validate_key(from)?;
+        namespace::validate_key(to)?;
+        self.apply_rename(None, from, to, overwrite)
+    }
+    
+    /// Number of keys in the default namespace starting with `prefix`
+    ///
+    /// Counted under one read lock by a range scan, so only the matching
+    /// keys are visited. An empty prefix counts the whole default namespace.
+    /// Keys past their TTL count until swept.
+    pub fn count_prefix(&self, prefix: &str) -> Result<usize> {
+        self.apply_count_prefix(None, prefix)
+    }
+    
+    /// Count the keys in the default namespace under each of `prefixes`
+    ///
+    /// One pass under one read lock, instead of a scan per prefix: the
+    /// range shared by all the prefixes is scanned once and each key is
+    /// checked against every prefix, O(n·p) for n keys in that range.
+    /// Overlapping prefixes count independently, so with `user:` and
+    /// `user:admin:` an admin key counts towards both. Every prefix gets an
+    /// entry, zero if nothing matched.
+    pub fn prefix_stats(&self, prefixes: &[String]) -> Result<HashMap<String, usize>> {
+        self.apply_prefix_stats(None, prefixes)
+    }
+    
+    /// Delete every key in the default namespace starting with `prefix`,
+    /// returning how many were removed
+    ///
+    /// **An empty prefix matches, and deletes, every key in the default
+    /// namespace.** Namespaced keys are never touched. The deletes are
+    /// logged as a single WAL record, so after a crash either all of them
+    /// or none are replayed, and one `ChangeEvent::Delete` is published per
+    /// removed key. Matching nothing is not an error and returns 0.
+    pub fn delete_prefix(&mut self, prefix: &str) -> Result<usize> {
+        self.apply_delete_prefix(None, prefix)
+    }
+    
+    /// List the keys in the default namespace
+    pub fn list_keys(&self) -> Result<Vec<String>> {
+        self.namespace_keys(None)
+    }
+    
+    /// List up to `limit` keys of the default namespace in sorted order,
+    /// starting after the cursor `after`
+    ///
+    /// Returns the page and the cursor for the next page, or `None` once the
+    /// end is reached. `after` does not need to exist: a cursor pointing at a
+    /// deleted key resumes at the next greater key.
+    pub fn list_keys_paged(&self, after: Option<&str>, limit: usize) -> Result<(Vec<String>, Option<String>)> {
+        if limit == 0 {
+            return Err(LohError::InvalidArgument("page limit must be greater than zero".to_string()));
+        }
+        
+        let storage = self.storage.read().unwrap();
+        let mut page = Vec::with_capacity(limit);
+        let mut start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        
+        // Fetch one extra key to learn whether another page exists
+        'scan: loop {
+            let wanted = limit + 1 - page.len();
+            let batch = storage.scan_keys(start, wanted)?;
+            let exhausted = batch.len() < wanted;
+            
+            for key in batch {
+                if namespace::is_namespaced(&key) {
+                    // Namespaced keys sort together; jump past all of them
+                    start = Bound::Included(namespace::FIRST_KEY_AFTER_NAMESPACES);
+                    continue 'scan;
+                }
+                page.push(key);
+            }
+            
+            if exhausted || page.len() > limit {
+                break;
+            }
+            start = Bound::Excluded(page.last().unwrap().as_str());
+        }
+        
+        let next = if page.len() > limit {
+            page.truncate(limit);
+            page.last().cloned()
+        } else {
+            None
+        };
+        Ok((page, next))
+    }
+    
+    /// Get a handle scoped to the namespace `name`
+    pub fn namespace(&self, name: &str) -> Result<NamespaceHandle<'_>> {
+        namespace::validate_namespace(name)?;
+        Ok(NamespaceHandle::new(self, name.to_string()))
+    }
+    
+    /// List the namespaces that currently hold at least one key
+    pub fn list_namespaces(&self) -> Result<Vec<String>> {
+        let keys = self.storage.read().unwrap().list_keys()?;
+        let mut namespaces: Vec<String> = keys
+            .iter()
+            .filter_map(|k| namespace::split_storage_key(k).0.map(str::to_string))
+            .collect();
+        namespaces.sort();
+        namespaces.dedup();
+        Ok(namespaces)
+    }
+    
+    /// Snapshot key count, on-disk sizes and operation counters
+    ///
+    /// Only takes a shared read lock on the storage.
+    pub fn stats(&self) -> Result<DbStats> {
+        let (num_keys, data_file_bytes, dedup) = {
+            let storage = self.storage.read().unwrap();
+            (storage.key_count()?, storage.disk_usage()?, storage.dedup_stats())
+        };
+        
+        let (wal_file_bytes, wal_sequence, wal_entries, unsynced_count) = {
+            let wal = self.wal.lock().unwrap();
+            (wal.disk_usage()?, wal.last_seq(), wal.entry_count(), wal.unsynced_count())
+        };
+        
+        let event_bus = self.event_bus.lock().unwrap();
+        Ok(DbStats {
+            num_keys,
+            data_file_bytes,
+            dedup,
+            wal_file_bytes,
+            wal_sequence,
+            wal_entries,
+            unsynced_count,
+            wal_syncs: self.committer.as_ref().map_or(0, GroupCommitter::syncs),
+            sets: OpCounters::load(&self.counters.sets),
+            gets: OpCounters::load(&self.counters.gets),
+            deletes: OpCounters::load(&self.counters.deletes),
+            subscribers: event_bus.subscriber_count(),
+            dropped_events: event_bus.dropped_events(),
+            subscriber_stats: event_bus.subscriber_stats(),
+            expired_keys: self.expired.load(Ordering::Relaxed),
+            last_flush_error: self.flush_health.last_error.lock().unwrap().clone(),
+            replay: self.replay.clone(),
+        })
+    }
+    
+    /// How background checkpoints of this database are going
+    ///
+    /// Never touches the storage lock, so it answers even while a
+    /// checkpoint holds it.
+    pub fn sync_health(&self) -> SyncHealth {
+        let Some(registration) = &self.flush_registration else {
+            return SyncHealth::default();
+        };
+        let health = &self.flush_health;
+        let last_heartbeat_ms = health.heartbeat_ms.load(Ordering::Relaxed);
+        SyncHealth {
+            scheduled: true,
+            thread_alive: registration.thread_alive(),
+            last_heartbeat_ms,
+            overdue: health.overdue(meta::now_ms()),
+            panics: health.panics.load(Ordering::Relaxed),
+            last_error: health.last_error.lock().unwrap().clone(),
+        }
+    }
+    
+    /// Shorthand for `sync_health().is_healthy()`
+    pub fn is_sync_healthy(&self) -> bool {
+        self.sync_health().is_healthy()
+    }
+    
+    /// Whether this database was opened with `DatabaseConfig::read_only`
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    
+    /// How values passed to `set_typed` are encoded
+    pub(crate) fn serialization_format(&self) -> SerializationFormat {
+        self.format
+    }
+    
+    /// Whether this database keeps everything in memory (`DatabaseConfig::in_memory`)
+    pub fn is_in_memory(&self) -> bool {
+        self.in_memory
+    }
+    
+    /// Lock the storage holding `storage_key` for reading: its shard, or
+    /// the whole engine if unsharded
+    fn key_storage(&self, storage_key: &str) -> KeyStorageRead<'_> {
+        KeyStorage::read(&self.storage, self.shards.as_deref(), storage_key)
+    }
+    
+    /// Lock the storage holding `storage_key` for writing: its shard, or
+    /// the whole engine if unsharded
+    fn key_storage_mut(&self, storage_key: &str) -> KeyStorageWrite<'_> {
+        KeyStorage::write(&self.storage, self.shards.as_deref(), storage_key)
+    }
+    
+    /// Release the WAL lock of a sharded write that has logged `seq`, as
+    /// soon as the key's shard is locked, reserving its place among the
+    /// change events; an unsharded write keeps it until it has published
+    fn release_wal<'a>(&self, wal: MutexGuard<'a, WriteAheadLog>, seq: u64) -> Option<MutexGuard<'a, WriteAheadLog>> {
+        if self.shards.is_none() {
+            return Some(wal);
+        }
+        self.event_bus.lock().unwrap().reserve(seq);
+        None
+    }
+    
+    /// Pass on the outcome of applying record `seq`, first publishing no
+    /// events for it if applying failed, so a reservation made by
+    /// `release_wal` does not hold back the events of later records
+    fn or_publish_nothing<T>(&self, applied: Result<T>, seq: u64, timestamp_ms: u64) -> Result<T> {
+        if applied.is_err() {
+            self.event_bus.lock().unwrap().publish_record(seq, timestamp_ms, Vec::new(), Vec::new())?;
+        }
+        applied
+    }
+    
+    /// Finish a write logged as WAL record `seq`, once the WAL is unlocked
+    ///
+    /// With group commit, blocks until the record has been fsynced. Every
+    /// `flush_every_n_writes`th write then checkpoints before returning; a
+    /// failed checkpoint does not fail the write, which is already logged,
+    /// but is reported like a failed background one.
+    pub(crate) fn finish_write(&self, seq: u64) -> Result<()> {
+        if let Some(committer) = &self.committer {
+            committer.wait_durable(seq)?;
+        }
+        let Some(every) = self.flush_every_n_writes else {
+            return Ok(());
+        };
+        // Exactly one writer sees each multiple, however many race here
+        let count = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        if !count.is_multiple_of(every) || self.in_memory {
+            return Ok(());
+        }
+        match self.checkpoint_now() {
+            Ok(()) => {
+                self.flush_health.last_error.lock().unwrap().take();
+            }
+            Err(e) => {
+                log::warn!("checkpoint after {} writes failed: {}", count, e);
+                let error = e.to_string();
+                *self.flush_health.last_error.lock().unwrap() = Some(error.clone());
+                self.lifecycle.lock().unwrap().publish(LifecycleEvent::FlushFailed { error });
+            }
+        }
+        Ok(())
+    }
+    
+    /// Enforce `max_key_bytes`/`max_value_bytes` and the WAL record size
+    pub(crate) fn check_sizes(&self, ns: Option<&str>, key: &str, value: &[u8]) -> Result<()> {
+        if let Some(limit) = self.max_key_bytes.filter(|&limit| key.len() > limit) {
+            return Err(LohError::TooLarge { what: "key", size: key.len() as u64, limit: limit as u64 });
+        }
+        self.check_value_limit(value.len())?;
+        wal::check_record_size(ns, key, value)
+    }
+    
+    /// Enforce `max_value_bytes` on a value of `len` bytes
+    pub(crate) fn check_value_limit(&self, len: usize) -> Result<()> {
+        if let Some(limit) = self.max_value_bytes.filter(|&limit| len > limit) {
+            return Err(LohError::TooLarge { what: "value", size: len as u64, limit: limit as u64 });
+        }
+        Ok(())
+    }
+    
+    pub(crate) fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(LohError::ReadOnly);
+        }
+        Ok(())
+    }
+    
+    pub(crate) fn apply_set(&self, ns: Option<&str>, key: String, value: Vec<u8>) -> Result<()> {
+        self.apply_versioned_set(ns, key, value, None, None).map(|_| ())
+    }
+    
+    /// Set `key`, first checking its version against `expected_version` if
+    /// given, and making it expire at `expires_ms` if given
+    ///
+    /// The check happens under the WAL lock, which every writer holds until
+    /// its write reaches storage, so no other write can slip in between.
+    pub(crate) fn apply_versioned_set(
+        &self,
+        ns: Option<&str>,
+        key: String,
+        value: Vec<u8>,
+        expected_version: Option<u64>,
+        expires_ms: Option<u64>,
+    ) -> Result<VersionedPut> {
+        timed!(self.set, self.versioned_set(ns, key, value, expected_version, expires_ms, None))
+    }
+    
+    pub(crate) fn apply_set_with_meta(
+        &self,
+        ns: Option<&str>,
+        key: String,
+        value: Vec<u8>,
+        meta: HashMap<String, String>,
+    ) -> Result<()> {
+        timed!(self.set, self.versioned_set(ns, key, value, None, None, Some(meta))).map(|_| ())
+    }
+    
+    /// `apply_versioned_set`, also attaching `meta` if given; a write carries
+    /// an expiry or attributes, never both
+    fn versioned_set(
+        &self,
+        ns: Option<&str>,
+        key: String,
+        value: Vec<u8>,
+        expected_version: Option<u64>,
+        expires_ms: Option<u64>,
+        meta: Option<HashMap<String, String>>,
+    ) -> Result<VersionedPut> {
+        self.ensure_writable()?;
+        self.check_sizes(ns, &key, &value)?;
+        if expires_ms.is_some() && !self.storage.read().unwrap().supports_expiry() {
+            return Err(LohError::InvalidArgument("this storage engine does not support key expiry".to_string()));
+        }
+        if meta.is_some() && !self.storage.read().unwrap().supports_user_meta() {
+            return Err(LohError::InvalidArgument("this storage engine does not support key metadata".to_string()));
+        }
+        
+        let namespace = ns.map(str::to_string);
+        let operation = match (expires_ms, &meta) {
+            (Some(expires_ms), None) => Operation::SetWithExpiry { namespace, key: key.clone(), value: value.clone(), expires_ms },
+            (None, Some(meta)) => Operation::SetWithMeta { namespace, key: key.clone(), value: value.clone(), meta: meta.clone() },
+            (None, None) => Operation::Set { namespace, key: key.clone(), value: value.clone() },
+            (Some(_), Some(_)) => unreachable!("no write sets both an expiry and metadata"),
+        };
+        
+        // Write to WAL first, keeping it locked until storage matches so both
+        // see writes in the same order and a checkpoint never splits them;
+        // or, sharded, until the key's shard is locked, which keeps writes
+        // of the key in order while writes to other shards go ahead
+        let mut wal = self.wal.lock().unwrap();
+        let storage_key = namespace::storage_key(ns, &key);
+        let mut storage = self.key_storage_mut(&storage_key);
+        if let Some(expected) = expected_version {
+            let current_version = storage.metadata(&storage_key)?.map_or(0, |m| m.version);
+            if current_version != expected {
+                return Ok(VersionedPut::Stale { current_version });
+            }
+        }
+        let seq = timed!(self.wal_append, wal.append(&operation))?;
+        let timestamp_ms = wal.last_timestamp_ms();
+        let wal = self.release_wal(wal, seq);
+        
+        // Then update storage and indexes
+        let stored = store_logged(&mut *storage, &storage_key, &value, timestamp_ms, expires_ms, meta);
+        let (version, evicted) = self.or_publish_nothing(stored, seq, timestamp_ms)?;
+        let storage = self.shards.is_some().then_some(storage);
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            indexes.on_set(ns, &key, &value);
+            evicted.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
+        
+        // Publish change event, before the next writer of the key can log anything
+        let event = ChangeEvent::Set { namespace: ns.map(str::to_string), key, value, version };
+        self.event_bus.lock().unwrap().publish_record(seq, timestamp_ms, vec![event], evicted)?;
+        drop((wal, storage));
+        self.finish_write(seq)?;
+        
+        OpCounters::incr(&self.counters.sets);
+        
+        Ok(VersionedPut::Written { version })
+    }
+    
+    pub(crate) fn apply_get(&self, ns: Option<&str>, key: &str) -> Result<Option<Vec<u8>>> {
+        OpCounters::incr(&self.counters.gets);
+        let storage_key = namespace::storage_key(ns, key);
+        timed!(self.get, {
+            let storage = self.key_storage(&storage_key);
+            if ttl::is_expired(&*storage, &storage_key, self.now_ms())? {
+                Ok(None)
+            } else {
+                storage.retrieve(&storage_key)
+            }
+        })
+    }
+    
+    /// `apply_get`, sharing the stored value rather than copying it where
+    /// the engine keeps values as `Arc<[u8]>`
+    pub(crate) fn apply_get_shared(&self, ns: Option<&str>, key: &str) -> Result<Option<Arc<[u8]>>> {
+        OpCounters::incr(&self.counters.gets);
+        let storage_key = namespace::storage_key(ns, key);
+        timed!(self.get, {
+            let storage = self.key_storage(&storage_key);
+            if ttl::is_expired(&*storage, &storage_key, self.now_ms())? {
+                Ok(None)
+            } else {
+                storage.retrieve_shared(&storage_key)
+            }
+        })
+    }
+    
+    pub(crate) fn apply_get_with_metadata(&self, ns: Option<&str>, key: &str) -> Result<Option<ValueMeta>> {
+        OpCounters::incr(&self.counters.gets);
+        let storage_key = namespace::storage_key(ns, key);
+        let storage = self.key_storage(&storage_key);
+        if ttl::is_expired(&*storage, &storage_key, self.now_ms())? {
+            return Ok(None);
+        }
+        let Some(value) = storage.retrieve(&storage_key)? else {
+            return Ok(None);
+        };
+        let meta = storage.metadata(&storage_key)?.unwrap_or_default();
+        Ok(Some(ValueMeta::new(value, meta)))
+    }
+    
+    pub(crate) fn apply_get_meta(&self, ns: Option<&str>, key: &str) -> Result<Option<HashMap<String, String>>> {
+        OpCounters::incr(&self.counters.gets);
+        let storage_key = namespace::storage_key(ns, key);
+        let storage = self.key_storage(&storage_key);
+        if ttl::is_expired(&*storage, &storage_key, self.now_ms())? || storage.retrieve(&storage_key)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(storage.user_meta(&storage_key)?.unwrap_or_default()))
+    }
+    
+    pub(crate) fn apply_get_or_insert_with<F>(&self, ns: Option<&str>, key: &str, f: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        // Hits only need the read lock
+        if let Some(value) = self.apply_get(ns, key)? {
+            return Ok(value);
+        }
+        timed!(self.set, self.insert_if_absent(ns, key, f))
+    }
+    
+    fn insert_if_absent<F>(&self, ns: Option<&str>, key: &str, f: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        self.ensure_writable()?;
+        let storage_key = namespace::storage_key(ns, key);
+        
+        // Hold the WAL lock until storage matches, and the storage lock from
+        // checking for the key until the new value is stored
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        if !ttl::is_expired(storage.as_ref(), &storage_key, self.now_ms())? {
+            if let Some(value) = storage.retrieve(&storage_key)? {
+                return Ok(value);
+            }
+        }
+        let value = f();
+        self.check_sizes(ns, key, &value)?;
+        
+        let seq = timed!(self.wal_append, wal.append(&Operation::Set {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
             value: value.clone(),
+        }))?;
+        storage.store_at(&storage_key, &value, wal.last_timestamp_ms())?;
+        let version = storage.metadata(&storage_key)?.map_or(0, |m| m.version);
+        let evicted = storage.take_evicted();
+        drop(storage);
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            indexes.on_set(ns, key, &value);
+            evicted.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
+        let event = ChangeEvent::Set {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+            value: value.clone(),
+            version,
         };
+        self.event_bus.lock().unwrap().publish_record(seq, wal.last_timestamp_ms(), vec![event], evicted)?;
+        drop(wal);
+        self.finish_write(seq)?;
+        OpCounters::incr(&self.counters.sets);
         
-        // Write to WAL first
-        self.wal.lock().unwrap().append(&operation)?;
+        Ok(value)
+    }
+    
+    pub(crate) fn apply_delete(&self, ns: Option<&str>, key: &str) -> Result<bool> {
+        timed!(self.delete, self.logged_delete(ns, key))
+    }
+    
+    fn logged_delete(&self, ns: Option<&str>, key: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        
+        let operation = Operation::Delete {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+        };
         
-        // Then update storage
-        self.storage.lock().unwrap().store(&key, &value)?;
+        // Write to WAL first, keeping it locked until storage matches, or
+        // until the key's shard is locked
+        let mut wal = self.wal.lock().unwrap();
+        let storage_key = namespace::storage_key(ns, key);
+        let mut storage = self.key_storage_mut(&storage_key);
+        let seq = timed!(self.wal_append, wal.append(&operation))?;
+        let timestamp_ms = wal.last_timestamp_ms();
+        let wal = self.release_wal(wal, seq);
+        
+        // Then update storage and indexes
+        let removed = storage.remove(&storage_key);
+        let existed = self.or_publish_nothing(removed, seq, timestamp_ms)?;
+        let storage = self.shards.is_some().then_some(storage);
+        let mut events = Vec::new();
+        if existed {
+            self.indexes.write().unwrap().on_delete(ns, key);
+            events.push(ChangeEvent::Delete {
+                namespace: ns.map(str::to_string),
+                key: key.to_string(),
+            });
+        }
         
         // Publish change event
-        let event = ChangeEvent::Set { key, value };
-        self.event_bus.lock().unwrap().publish(event)?;
+        self.event_bus.lock().unwrap().publish_record(seq, timestamp_ms, events, Vec::new())?;
+        drop((wal, storage));
+        self.finish_write(seq)?;
+        OpCounters::incr(&self.counters.deletes);
         
+        Ok(existed)
+    }
+    
+    pub(crate) fn apply_take(&self, ns: Option<&str>, key: &str) -> Result<Option<Vec<u8>>> {
+        timed!(self.delete, self.logged_take_if(ns, key, |_| true))
+    }
+    
+    pub(crate) fn apply_delete_if(&self, ns: Option<&str>, key: &str, expected: &[u8]) -> Result<bool> {
+        let taken = timed!(self.delete, self.logged_take_if(ns, key, |value| value == expected))?;
+        Ok(taken.is_some())
+    }
+    
+    /// Delete `key` and return its value if `wanted` accepts the value
+    fn logged_take_if<F>(&self, ns: Option<&str>, key: &str, wanted: F) -> Result<Option<Vec<u8>>>
+    where
+        F: FnOnce(&[u8]) -> bool,
+    {
+        self.ensure_writable()?;
+        let storage_key = namespace::storage_key(ns, key);
+        
+        // Hold the WAL lock until storage matches, and the storage lock from
+        // reading the value until it is removed
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        if ttl::is_expired(storage.as_ref(), &storage_key, self.now_ms())? {
+            return Ok(None);
+        }
+        let Some(value) = storage.retrieve(&storage_key)?.filter(|value| wanted(value)) else {
+            return Ok(None);
+        };
+        
+        let seq = timed!(self.wal_append, wal.append(&Operation::Delete {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+        }))?;
+        storage.remove(&storage_key)?;
+        drop(storage);
+        self.indexes.write().unwrap().on_delete(ns, key);
+        let event = ChangeEvent::Delete {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+        };
+        self.event_bus.lock().unwrap().publish_record(seq, wal.last_timestamp_ms(), vec![event], Vec::new())?;
+        drop(wal);
+        self.finish_write(seq)?;
+        OpCounters::incr(&self.counters.deletes);
+        
+        Ok(Some(value))
+    }
+    
+    /// Set or delete (`None`) each of `writes` in the default namespace as
+    /// one WAL record, once `check` has accepted the current contents
+    ///
+    /// `check` runs under the WAL and storage write locks, held until every
+    /// write is in storage, so nothing can change between the check and the
+    /// writes. If it fails, nothing is logged or stored.
+    pub(crate) fn apply_batch<C>(&self, mut writes: Vec<(String, Option<Vec<u8>>)>, check: C) -> Result<()>
+    where
+        C: FnOnce(&dyn StorageEngine) -> Result<()>,
+    {
+        self.ensure_writable()?;
+        for (key, value) in &writes {
+            namespace::validate_key(key)?;
+            if let Some(value) = value {
+                self.check_sizes(None, key, value)?;
+            }
+        }
+        
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        check(storage.as_ref())?;
+        // Deletes of absent keys change nothing, so they are not logged either
+        let mut kept = Vec::with_capacity(writes.len());
+        for (key, value) in writes {
+            if value.is_some() || storage.retrieve(&key)?.is_some() {
+                kept.push((key, value));
+            }
+        }
+        writes = kept;
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let ops = writes
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => Operation::Set { namespace: None, key: key.clone(), value: value.clone() },
+                None => Operation::Delete { namespace: None, key: key.clone() },
+            })
+            .collect();
+        let seq = timed!(self.wal_append, wal.append(&Operation::Batch { ops }))?;
+        
+        let mut events = Vec::with_capacity(writes.len());
+        for (key, value) in writes {
+            match value {
+                Some(value) => {
+                    storage.store_at(&key, &value, wal.last_timestamp_ms())?;
+                    let version = storage.metadata(&key)?.map_or(0, |m| m.version);
+                    events.push(ChangeEvent::Set { namespace: None, key, value, version });
+                }
+                None => {
+                    if storage.remove(&key)? {
+                        events.push(ChangeEvent::Delete { namespace: None, key });
+                    }
+                }
+            }
+        }
+        let evicted = storage.take_evicted();
+        drop(storage);
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            for event in &events {
+                match event {
+                    ChangeEvent::Set { key, value, .. } => indexes.on_set(None, key, value),
+                    ChangeEvent::Delete { key, .. } => indexes.on_delete(None, key),
+                    _ => {}
+                }
+            }
+            evicted.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
+        let sets = events.iter().filter(|e| matches!(e, ChangeEvent::Set { .. })).count() as u64;
+        let deletes = events.len() as u64 - sets;
+        self.event_bus.lock().unwrap().publish_record(seq, wal.last_timestamp_ms(), events, evicted)?;
+        drop(wal);
+        self.finish_write(seq)?;
+        
+        self.counters.sets.fetch_add(sets, Ordering::Relaxed);
+        self.counters.deletes.fetch_add(deletes, Ordering::Relaxed);
         Ok(())
     }
     
-    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        self.storage.lock().unwrap().retrieve(key)
+    pub(crate) fn apply_rename(&self, ns: Option<&str>, from: &str, to: &str, overwrite: bool) -> Result<bool> {
+        self.ensure_writable()?;
+        let from_key = namespace::storage_key(ns, from);
+        let to_key = namespace::storage_key(ns, to);
+        
+        // Hold the WAL lock until storage matches, and the storage lock from
+        // checking both keys until the value has moved
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        if ttl::is_expired(storage.as_ref(), &from_key, self.now_ms())? {
+            return Ok(false);
+        }
+        let Some(value) = storage.retrieve(&from_key)? else {
+            return Ok(false);
+        };
+        if from == to {
+            return Ok(true);
+        }
+        let to_exists = !ttl::is_expired(storage.as_ref(), &to_key, self.now_ms())? && storage.retrieve(&to_key)?.is_some();
+        if to_exists && !overwrite {
+            return Err(LohError::InvalidArgument(format!(
+                "cannot rename '{}' to '{}': the key already exists",
+                from, to
+            )));
+        }
+        self.check_sizes(ns, to, &value)?;
+        
+        let seq = timed!(self.wal_append, wal.append(&Operation::Rename {
+            namespace: ns.map(str::to_string),
+            from: from.to_string(),
+            to: to.to_string(),
+            value: value.clone(),
+        }))?;
+        move_key(storage.as_mut(), &from_key, &to_key, &value, wal.last_timestamp_ms())?;
+        let version = storage.metadata(&to_key)?.map_or(0, |m| m.version);
+        let evicted = storage.take_evicted();
+        drop(storage);
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            indexes.on_delete(ns, from);
+            indexes.on_set(ns, to, &value);
+            evicted.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
+        let events = vec![
+            ChangeEvent::Delete { namespace: ns.map(str::to_string), key: from.to_string() },
+            ChangeEvent::Set { namespace: ns.map(str::to_string), key: to.to_string(), value, version },
+        ];
+        self.event_bus.lock().unwrap().publish_record(seq, wal.last_timestamp_ms(), events, evicted)?;
+        drop(wal);
+        self.finish_write(seq)?;
+        
+        Ok(true)
     }
     
-    pub fn delete(&mut self, key: &str) -> Result<bool> {
-        let operation = Operation::Delete {
+    pub(crate) fn apply_count_prefix(&self, ns: Option<&str>, prefix: &str) -> Result<usize> {
+        let storage = self.storage.read().unwrap();
+        let mut count = 0;
+        for_each_with_prefix(storage.as_ref(), ns, prefix, |_| count += 1)?;
+        Ok(count)
+    }
+    
+    pub(crate) fn apply_prefix_stats(&self, ns: Option<&str>, prefixes: &[String]) -> Result<HashMap<String, usize>> {
+        let mut unique: Vec<&str> = prefixes.iter().map(String::as_str).collect();
+        unique.sort_unstable();
+        unique.dedup();
+        let mut counts = vec![0; unique.len()];
+        
+        // Anything matching one of the prefixes also starts with the shared part
+        let storage = self.storage.read().unwrap();
+        for_each_with_prefix(storage.as_ref(), ns, common_prefix(&unique), |storage_key| {
+            let key = namespace::split_storage_key(storage_key).1;
+            for (prefix, count) in unique.iter().zip(counts.iter_mut()) {
+                if key.starts_with(prefix) {
+                    *count += 1;
+                }
+            }
+        })?;
+        Ok(unique.into_iter().map(str::to_string).zip(counts).collect())
+    }
+    
+    pub(crate) fn apply_delete_prefix(&self, ns: Option<&str>, prefix: &str) -> Result<usize> {
+        self.ensure_writable()?;
+        
+        // Hold the WAL lock until storage matches, and the storage lock from
+        // collecting the matches until the last one is removed
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        let matches = keys_with_prefix(storage.as_ref(), ns, prefix)?;
+        if matches.is_empty() {
+            return Ok(0);
+        }
+        let seq = timed!(self.wal_append, wal.append(&Operation::DeletePrefix {
+            namespace: ns.map(str::to_string),
+            prefix: prefix.to_string(),
+            keys: matches.iter().map(|k| namespace::split_storage_key(k).1.to_string()).collect(),
+        }))?;
+        
+        let mut removed = Vec::with_capacity(matches.len());
+        for storage_key in matches {
+            if storage.remove(&storage_key)? {
+                removed.push(storage_key);
+            }
+        }
+        drop(storage);
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            removed.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
+        let events = removed
+            .iter()
+            .map(|storage_key| {
+                let (namespace, key) = namespace::split_storage_key(storage_key);
+                ChangeEvent::Delete { namespace: namespace.map(str::to_string), key: key.to_string() }
+            })
+            .collect();
+        self.event_bus.lock().unwrap().publish_record(seq, wal.last_timestamp_ms(), events, Vec::new())?;
+        drop(wal);
+        self.finish_write(seq)?;
+        self.counters.deletes.fetch_add(removed.len() as u64, Ordering::Relaxed);
+        
+        Ok(removed.len())
+    }
+    
+    pub(crate) fn apply_append(&self, ns: Option<&str>, key: &str, chunk: &[u8]) -> Result<u64> {
+        self.ensure_writable()?;
+        self.check_sizes(ns, key, chunk)?;
+        let storage_key = namespace::storage_key(ns, key);
+        
+        // Hold the WAL lock until storage matches, and the storage lock from
+        // reading the old value until the new one is stored
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        let fresh = ttl::is_expired(storage.as_ref(), &storage_key, self.now_ms())? || storage.retrieve(&storage_key)?.is_none();
+        let value = chunked::appended(storage.as_ref(), &storage_key, chunk, fresh)?;
+        chunked::split_chunks(&value)?;
+        self.check_value_limit(value.len())?;
+        
+        // Replay starts over exactly when this append did, whatever has expired by then
+        let seq = timed!(self.wal_append, wal.append(&Operation::Append {
+            namespace: ns.map(str::to_string),
             key: key.to_string(),
+            chunk: chunk.to_vec(),
+            fresh,
+        }))?;
+        storage.store_at(&storage_key, &value, wal.last_timestamp_ms())?;
+        let version = storage.metadata(&storage_key)?.map_or(0, |m| m.version);
+        let evicted = storage.take_evicted();
+        drop(storage);
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            indexes.on_set(ns, key, &value);
+            evicted.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
+        let event = ChangeEvent::Append {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+            chunk: chunk.to_vec(),
+            version,
         };
+        self.event_bus.lock().unwrap().publish_record(seq, wal.last_timestamp_ms(), vec![event], evicted)?;
+        drop(wal);
+        self.finish_write(seq)?;
+        OpCounters::incr(&self.counters.sets);
         
-        // Write to WAL first
-        self.wal.lock().unwrap().append(&operation)?;
+        Ok(value.len() as u64)
+    }
+    
+    /// Log `chunk` as the next piece of a value streaming to `key`, starting
+    /// a stream if `stream` is `None`; returns the stream's id
+    ///
+    /// Storage is left alone until `apply_stream_commit`.
+    pub(crate) fn log_stream_chunk(&self, ns: Option<&str>, key: &str, stream: Option<u64>, chunk: &[u8]) -> Result<u64> {
+        self.ensure_writable()?;
+        self.check_sizes(ns, key, chunk)?;
+        
+        let mut wal = self.wal.lock().unwrap();
+        let stream = stream.unwrap_or(wal.last_seq() + 1);
+        timed!(self.wal_append, wal.append(&Operation::StreamChunk {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+            stream,
+            chunk: chunk.to_vec(),
+        }))?;
+        Ok(stream)
+    }
+    
+    /// Commit a streamed value, `chunks` chunks logged as `stream`, storing
+    /// `value`, their concatenation, at `key` without copying it; with no
+    /// chunks `stream` is `None` and the value is empty
+    ///
+    /// A checkpoint while the value streamed in may have released its first
+    /// chunks, leaving a commit replay cannot rebuild the value from; the
+    /// commit then checkpoints too, so the data file holds the value before
+    /// this returns.
+    pub(crate) fn apply_stream_commit(
+        &self,
+        ns: Option<&str>,
+        key: &str,
+        stream: Option<u64>,
+        chunks: u64,
+        value: Arc<[u8]>,
+    ) -> Result<u64> {
+        self.ensure_writable()?;
+        self.check_value_limit(value.len())?;
+        let storage_key = namespace::storage_key(ns, key);
         
-        // Then update storage
-        let existed = self.storage.lock().unwrap().remove(key)?;
+        let mut wal = self.wal.lock().unwrap();
+        let stream = stream.unwrap_or(wal.last_seq() + 1);
+        let seq = timed!(self.wal_append, wal.append(&Operation::StreamCommit {
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+            stream,
+            chunks,
+        }))?;
+        let mut checkpointed = None;
+        let (version, evicted) = {
+            let mut storage = self.storage.write().unwrap();
+            storage.store_shared(&storage_key, value.clone(), wal.last_timestamp_ms())?;
+            let version = storage.metadata(&storage_key)?.map_or(0, |m| m.version);
+            let evicted = storage.take_evicted();
+            if chunks > 0 && wal.first_seq() > stream && !self.in_memory {
+                checkpointed = Some(timed!(self.flush, sync::checkpoint(&mut wal, storage.as_mut()))?);
+            }
+            (version, evicted)
+        };
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            indexes.on_set(ns, key, &value);
+            evicted.iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+        }
         
-        if existed {
-            // Publish change event
-            let event = ChangeEvent::Delete {
+        // The event carries a copy of the value, so only make one if
+        // someone is listening
+        let mut event_bus = self.event_bus.lock().unwrap();
+        let mut events = Vec::new();
+        if event_bus.has_subscribers() {
+            events.push(ChangeEvent::Set {
+                namespace: ns.map(str::to_string),
                 key: key.to_string(),
-            };
-            self.event_bus.lock().unwrap().publish(event)?;
+                value: value.to_vec(),
+                version,
+            });
+        }
+        event_bus.publish_record(seq, wal.last_timestamp_ms(), events, evicted)?;
+        drop((event_bus, wal));
+        if let Some((through_seq, segments_released)) = checkpointed {
+            log::debug!(
+                "checkpointed '{}' through sequence {} to keep a streamed value, released {} WAL segment(s)",
+                self.data_dir, through_seq, segments_released
+            );
+            self.lifecycle.lock().unwrap().publish(LifecycleEvent::Checkpointed { through_seq, segments_released });
         }
+        self.finish_write(seq)?;
+        OpCounters::incr(&self.counters.sets);
         
-        Ok(existed)
+        Ok(value.len() as u64)
     }
     
-    pub fn list_keys(&self) -> Result<Vec<String>> {
-        self.storage.lock().unwrap().list_keys()
+    /// Bytes per WAL record of a streamed value (`DatabaseConfig::stream_chunk_bytes`)
+    pub(crate) fn stream_chunk_bytes(&self) -> usize {
+        self.stream_chunk_bytes
+    }
+    
+    pub(crate) fn file_mode(&self) -> Option<u32> {
+        self.file_mode
+    }
+    
+    pub(crate) fn encryptor(&self) -> Option<&Arc<dyn Encryptor>> {
+        self.encryptor.as_ref()
+    }
+    
+    pub(crate) fn indexes_handle(&self) -> Arc<RwLock<Indexes>> {
+        self.indexes.clone()
+    }
+    
+    pub(crate) fn expiry_targets(&self) -> ExpiryTargets {
+        ExpiryTargets {
+            wal: self.wal.clone(),
+            storage: self.storage.clone(),
+            indexes: self.indexes.clone(),
+            event_bus: self.event_bus.clone(),
+            expired: self.expired.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+    
+    /// The current time by the configured clock, in ms since the Unix epoch
+    pub(crate) fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+    
+    pub(crate) fn wal_handle(&self) -> Arc<Mutex<WriteAheadLog>> {
+        self.wal.clone()
+    }
+    
+    pub(crate) fn event_bus_handle(&self) -> Arc<Mutex<EventBus>> {
+        self.event_bus.clone()
+    }
+    
+    pub(crate) fn storage_handle(&self) -> Arc<RwLock<Box<dyn StorageEngine>>> {
+        self.storage.clone()
+    }
+    
+    pub(crate) fn data_dir(&self) -> &str {
+        &self.data_dir
+    }
+    
+    pub(crate) fn data_files(&self) -> &[PathBuf] {
+        &self.data_files
+    }
+    
+    pub(crate) fn blob_file(&self) -> Option<&Path> {
+        self.blob_file.as_deref()
+    }
+    
+    /// Run `f` with both the WAL and the storage locked
+    ///
+    /// Writers hold the WAL lock until their entry is applied to storage, so
+    /// while `f` runs no write is in flight and storage reflects every
+    /// record in the WAL.
+    pub(crate) fn with_quiesced<R>(
+        &self,
+        f: impl FnOnce(&mut WriteAheadLog, &mut dyn StorageEngine) -> Result<R>,
+    ) -> Result<R> {
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        f(&mut wal, storage.as_mut())
+    }
+    
+    /// Copy every entry (with storage-encoded keys) under a single read lock
+    pub(crate) fn snapshot_entries(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let storage = self.storage.read().unwrap();
+        let mut entries = Vec::new();
+        for key in storage.list_keys()? {
+            if let Some(value) = storage.retrieve(&key)? {
+                entries.push((key, value));
+            }
+        }
+        Ok(entries)
+    }
+    
+    pub(crate) fn namespace_keys(&self, ns: Option<&str>) -> Result<Vec<String>> {
+        let keys = self.storage.read().unwrap().list_keys()?;
+        Ok(keys
+            .iter()
+            .filter_map(|k| match namespace::split_storage_key(k) {
+                (k_ns, key) if k_ns == ns => Some(key.to_string()),
+                _ => None,
+            })
+            .collect())
     }
     
     pub fn subscribe<F>(&mut self, callback: F) -> Result<SubscriptionHandle>
@@ -130,9 +1529,505 @@ impl Database {
         self.event_bus.lock().unwrap().subscribe(callback)
     }
     
+    /// Subscribe to changes of keys starting with `prefix` only
+    ///
+    /// The prefix is matched against the key within its namespace; an empty
+    /// prefix behaves like `subscribe`.
+    pub fn subscribe_prefix<F>(&mut self, prefix: String, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(ChangeEvent) + Send + Sync + 'static,
+    {
+        self.event_bus.lock().unwrap().subscribe_prefix(prefix, callback)
+    }
+    
+    /// Subscribe to changes of the kinds in `filter` only
+    ///
+    /// For consumers that care about one kind of change, such as a cache
+    /// invalidator taking `EventFilter::REMOVALS`: other events are never
+    /// sent to them, so they cost no wakeup and take no buffer space.
+    pub fn subscribe_filtered<F>(&mut self, filter: EventFilter, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(ChangeEvent) + Send + Sync + 'static,
+    {
+        self.subscribe_prefix_filtered(String::new(), filter, callback)
+    }
+    
+    /// `subscribe_filtered`, limited to keys starting with `prefix`
+    pub fn subscribe_prefix_filtered<F>(&mut self, prefix: String, filter: EventFilter, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(ChangeEvent) + Send + Sync + 'static,
+    {
+        self.event_bus.lock().unwrap().subscribe_filtered(prefix, filter, callback)
+    }
+    
+    /// Subscribe with a callback that receives changes in batches of up to
+    /// `max_batch`, each handed over at most `max_delay` after its first
+    /// change arrived
+    ///
+    /// Cuts thread wakeups under heavy write load for consumers that work
+    /// in bulk, such as one forwarding changes to another system. Changes
+    /// wait in the same bounded buffer as for `subscribe`, so the overflow
+    /// policy still applies while a batch is being handled. Fails if
+    /// `max_batch` is 0.
+    pub fn subscribe_batched<F>(&mut self, max_batch: usize, max_delay: Duration, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(Vec<ChangeEvent>) + Send + Sync + 'static,
+    {
+        self.event_bus.lock().unwrap().subscribe_batched(max_batch, max_delay, callback)
+    }
+    
+    /// Subscribe with a channel instead of a callback
+    ///
+    /// The caller owns the receiver and decides how and when to drain it.
+    /// Dropping the receiver or the handle unsubscribes.
+    pub fn subscribe_channel(&mut self) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        self.event_bus.lock().unwrap().subscribe_channel()
+    }
+    
+    /// `subscribe_channel`, limited to keys starting with `prefix`
+    pub fn subscribe_channel_prefix(&mut self, prefix: String) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        self.event_bus.lock().unwrap().subscribe_channel_prefix(prefix)
+    }
+    
+    /// `subscribe_channel_prefix` with its own overflow policy
+    ///
+    /// Pass `OverflowPolicy::Block` for a subscriber that must never miss an
+    /// event, e.g. one feeding a replica: writers then wait for it to catch
+    /// up instead of dropping events. One that stops reading for
+    /// `DEFAULT_BLOCK_TIMEOUT` is disconnected, ending its channel, so it
+    /// cannot hold writers up for good. Lag and losses of every subscription
+    /// show up in `DbStats::subscriber_stats`.
+    pub fn subscribe_channel_with_policy(
+        &mut self,
+        prefix: String,
+        policy: OverflowPolicy,
+    ) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        self.event_bus.lock().unwrap().subscribe_channel_with_policy(prefix, policy)
+    }
+    
+    /// Subscribe to `LifecycleEvent`s: background flushes and checkpoints
+    ///
+    /// These never appear on data subscriptions. Events are published from
+    /// the sync thread without blocking it, so a subscriber that falls more
+    /// than `subscriber_buffer` events behind misses some.
+    pub fn subscribe_lifecycle(&mut self) -> (SubscriptionHandle, Receiver<LifecycleEvent>) {
+        self.lifecycle.lock().unwrap().subscribe()
+    }
+    
+    /// Latency percentiles for sets, gets, deletes, WAL appends and flushes
+    /// since the database was opened
+    ///
+    /// Recording costs a few atomic adds per operation; build without the
+    /// `metrics` feature to leave it out entirely.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+    
+    /// Write storage out to the data file; a no-op for in-memory databases
+    ///
+    /// Shorthand for `flush_with(FlushMode::Snapshot)`. The data file is
+    /// rewritten and fsynced, but the WAL is left alone: records it still
+    /// buffers (see `wal_buffer_bytes`) stay buffered, the written ones are
+    /// not fsynced, and none are released, so `unsynced_count` is
+    /// unchanged and reopening replays the WAL as before. Use `sync` (or
+    /// `FlushMode::Both`) for a durability barrier, and `checkpoint` to
+    /// also release the WAL.
     pub fn flush(&mut self) -> Result<()> {
-        self.storage.lock().unwrap().flush()
+        self.flush_with(FlushMode::Snapshot)
+    }
+    
+    /// Make the data file, the WAL or both durable, as `mode` says
+    ///
+    /// Neither releases WAL segments; that is what `checkpoint` adds. A
+    /// no-op for in-memory databases.
+    pub fn flush_with(&mut self, mode: FlushMode) -> Result<()> {
+        self.ensure_writable()?;
+        match mode {
+            FlushMode::Snapshot => {
+                log::debug!("flushing storage in '{}'", self.data_dir);
+                timed!(self.flush, self.storage.write().unwrap().flush())
+            }
+            FlushMode::Wal => {
+                let mut wal = self.wal.lock().unwrap();
+                timed!(self.wal_sync, wal.sync())?;
+                log::debug!("synced the WAL of '{}' through sequence {}", self.data_dir, wal.last_seq());
+                Ok(())
+            }
+            FlushMode::Both => self.sync(),
+        }
+    }
+    
+    /// Writes whose WAL records have not been fsynced yet
+    ///
+    /// The durability window: a power loss or OS crash now could lose this
+    /// many writes that already returned. `sync` and `checkpoint` bring it
+    /// to 0, and `wal_commit_batch` keeps it below that batch size. Always 0
+    /// for in-memory databases.
+    pub fn unsynced_count(&self) -> u64 {
+        self.wal.lock().unwrap().unsynced_count()
+    }
+    
+    /// Durability barrier: returns once every write so far is on stable storage
+    ///
+    /// Flushes storage (fsyncing the data file) and fsyncs the WAL, holding
+    /// both locks so no write lands in between, and returns any I/O error.
+    /// Unlike the background flush, which is best-effort, this is the call
+    /// to make before acknowledging a write to the outside world. The WAL is
+    /// kept; `checkpoint` also releases it. Same as
+    /// `flush_with(FlushMode::Both)`. A no-op for in-memory databases.
+    pub fn sync(&mut self) -> Result<()> {
+        self.ensure_writable()?;
+        let mut wal = self.wal.lock().unwrap();
+        timed!(self.flush, {
+            self.storage.write().unwrap().flush()?;
+            wal.sync()
+        })?;
+        log::debug!("synced '{}' through sequence {}", self.data_dir, wal.last_seq());
+        Ok(())
+    }
+    
+    /// Flush storage and delete the WAL segments it now covers
+    ///
+    /// The active segment is rolled over first, so afterwards the log holds
+    /// only an empty segment and reopening replays nothing. A no-op for
+    /// in-memory databases. Publishes `LifecycleEvent::Checkpointed`.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.ensure_writable()?;
+        if self.in_memory {
+            return Ok(());
+        }
+        self.checkpoint_now()
+    }
+    
+    /// `checkpoint` for an open, writable, on-disk database
+    fn checkpoint_now(&self) -> Result<()> {
+        let event = self.with_quiesced(|wal, storage| {
+            let (through_seq, segments_released) = timed!(self.flush, sync::checkpoint(wal, storage))?;
+            log::debug!(
+                "checkpointed '{}' through sequence {}, released {} WAL segment(s)",
+                self.data_dir, through_seq, segments_released
+            );
+            Ok(LifecycleEvent::Checkpointed { through_seq, segments_released })
+        })?;
+        self.lifecycle.lock().unwrap().publish(event);
+        Ok(())
+    }
+    
+    /// Rewrite the data file with only live entries, returning how much
+    /// space that reclaimed
+    ///
+    /// For engines that rewrite their whole file on every flush this is a
+    /// forced flush, which still shrinks a data file last written before
+    /// many deletes; append-only engines such as `MmapStorageEngine` drop
+    /// their overwritten and deleted records. Writers are blocked until it
+    /// finishes. The WAL is left alone; `checkpoint` releases it.
+    pub fn compact(&mut self) -> Result<CompactStats> {
+        self.ensure_writable()?;
+        let stats = timed!(self.flush, self.storage.write().unwrap().compact())?;
+        log::debug!(
+            "compacted '{}' from {} to {} bytes",
+            self.data_dir, stats.bytes_before, stats.bytes_after
+        );
+        Ok(stats)
+    }
+    
+    /// Load many entries straight into storage, then checkpoint once
+    ///
+    /// Meant for seeding an empty database: nothing is logged to the WAL
+    /// per entry and no `ChangeEvent`s are published, so subscribers and
+    /// point-in-time recovery never see the loaded writes. Existing keys
+    /// are overwritten, not cleared. The entries become durable only when
+    /// the closing checkpoint completes; if a key is rejected, the entries
+    /// before it are still loaded and checkpointed before the error is
+    /// returned. Writers are blocked for the whole load.
+    pub fn bulk_load<I: Iterator<Item = (String, Vec<u8>)>>(&mut self, entries: I) -> Result<()> {
+        self.ensure_writable()?;
+        let (loaded, event) = self.with_quiesced(|wal, storage| {
+            let mut indexes = self.indexes.write().unwrap();
+            let mut loaded = Ok(());
+            for (key, value) in entries {
+                if let Err(e) = namespace::validate_key(&key).and_then(|()| self.check_sizes(None, &key, &value)) {
+                    loaded = Err(e);
+                    break;
+                }
+                storage.store_at(&key, &value, self.now_ms())?;
+                indexes.on_set(None, &key, &value);
+            }
+            // A cache-mode database may evict during the load; nobody is told
+            storage.take_evicted().iter().for_each(|storage_key| indexes.on_delete_storage_key(storage_key));
+            
+            let (through_seq, segments_released) = sync::checkpoint(wal, storage)?;
+            Ok((loaded, LifecycleEvent::Checkpointed { through_seq, segments_released }))
+        })?;
+        if !self.in_memory {
+            self.lifecycle.lock().unwrap().publish(event);
+        }
+        loaded
+    }
+    
+    /// Move every entry into `new_engine` and make it this database's storage
+    ///
+    /// Writers are held off from the first copy until the swap, so nothing
+    /// written meanwhile is lost. `new_engine` is initialized here and must
+    /// come up empty. Values keep their last-modified time, expiry and
+    /// `set_with_meta` attributes, but versions start over at 1. If initializing, copying or flushing the
+    /// new engine fails, the old one stays in place untouched.
+    ///
+    /// Afterwards the WAL is checkpointed against the new engine, as in
+    /// `checkpoint`, so reopening does not replay old records on top of it;
+    /// if only that step fails, the new engine is kept and the error
+    /// returned. Reopen with a configuration that builds the new engine
+    /// (for an in-memory database, point `data_dir` at the new engine's
+    /// directory). Publishes `LifecycleEvent::Checkpointed` unless in-memory.
+    pub fn migrate_to<E: StorageEngine + 'static>(&mut self, new_engine: E) -> Result<()> {
+        self.ensure_writable()?;
+        let mut new_engine: Box<dyn StorageEngine> = Box::new(new_engine);
+        new_engine.initialize()?;
+        if new_engine.key_count()? > 0 {
+            return Err(LohError::InvalidArgument("storage engine to migrate to is not empty".to_string()));
+        }
+        
+        let mut wal = self.wal.lock().unwrap();
+        let mut storage = self.storage.write().unwrap();
+        let mut copied = 0;
+        for storage_key in storage.list_keys()? {
+            let Some(value) = storage.retrieve(&storage_key)? else {
+                continue;
+            };
+            match storage.metadata(&storage_key)? {
+                Some(meta) => {
+                    new_engine.store_at(&storage_key, &value, meta.modified_ms)?;
+                    if meta.expires_ms.is_some() {
+                        new_engine.set_expiry(&storage_key, meta.expires_ms)?;
+                    }
+                }
+                None => new_engine.store(&storage_key, &value)?,
+            }
+            if let Some(user_meta) = storage.user_meta(&storage_key)? {
+                new_engine.set_user_meta(&storage_key, Some(user_meta))?;
+            }
+            copied += 1;
+        }
+        new_engine.flush()?;
+        
+        *storage = new_engine;
+        self.shards = storage.shards();
+        log::debug!("migrated {} key(s) in '{}' to a new storage engine", copied, self.data_dir);
+        let (through_seq, segments_released) = sync::checkpoint(&mut wal, storage.as_mut())?;
+        drop((wal, storage));
+        
+        if !self.in_memory {
+            self.lifecycle.lock().unwrap().publish(LifecycleEvent::Checkpointed { through_seq, segments_released });
+        }
+        Ok(())
+    }
+    
+    /// Shut down, reporting any error instead of leaving it to `Drop`
+    ///
+    /// Stops background checkpoints, writes a final checkpoint (so the
+    /// next open has nothing to replay), stops the group commit thread, and
+    /// ends every subscription: callback threads exit and channel receivers
+    /// disconnect once drained. The directory lock is released on return,
+    /// even on error. Dropping a database instead skips the checkpoint and
+    /// leaves recovery to the WAL.
+    pub fn close(mut self) -> Result<()> {
+        // Joined first so they cannot write alongside the final checkpoint
+        self.flush_registration.take();
+        self.expiry_sweeper.take();
+        let result = if self.read_only { Ok(()) } else { self.checkpoint() };
+        self.committer.take();
+        self.event_bus.lock().unwrap().close();
+        self.lifecycle.lock().unwrap().close();
+        result
+    }
+}
+
+/// The storage engine `config` describes: a file engine or one per shard,
+/// under a `DedupStorageEngine` over the blob file if `dedup` is set
+pub(crate) fn configured_storage(config: &DatabaseConfig, encryptor: Option<&Arc<dyn Encryptor>>) -> Box<dyn StorageEngine> {
+    let keys = configured_key_storage(config, encryptor);
+    match config.effective_blob_file() {
+        Some(blob_file) => {
+            let blobs = configured_file_engine(config, encryptor).with_data_file(blob_file);
+            Box::new(DedupStorageEngine::new(keys, Box::new(blobs)))
+        }
+        None => keys,
+    }
+}
+
+/// The engine for the data file, or its shards, alone
+fn configured_key_storage(config: &DatabaseConfig, encryptor: Option<&Arc<dyn Encryptor>>) -> Box<dyn StorageEngine> {
+    if config.shards <= 1 {
+        let mut engine = configured_file_engine(config, encryptor);
+        if let Some(data_file) = &config.data_file {
+            engine = engine.with_data_file(data_file);
+        }
+        return Box::new(engine);
+    }
+    let shards = config
+        .effective_data_files()
+        .into_iter()
+        .map(|data_file| Box::new(configured_file_engine(config, encryptor).with_data_file(data_file)) as Box<dyn StorageEngine>)
+        .collect();
+    Box::new(ShardedStorageEngine::new(shards))
+}
+
+/// A file engine with the codecs, format and permissions of `config`, for
+/// the default data file until pointed elsewhere
+pub(crate) fn configured_file_engine(config: &DatabaseConfig, encryptor: Option<&Arc<dyn Encryptor>>) -> FileStorageEngine {
+    let mut engine = FileStorageEngine::new(config.data_dir.clone()).with_format(config.serialization_format);
+    if let Some(compressor) = &config.compressor {
+        engine = engine.with_compressor(compressor.clone());
+    }
+    if let Some(encryptor) = encryptor {
+        engine = engine.with_encryptor(encryptor.clone());
+    }
+    if let Some(file_mode) = config.effective_file_mode() {
+        engine = engine.with_file_mode(file_mode);
+    }
+    engine
+}
+
+/// Store a value just logged at `timestamp_ms`, with its expiry or
+/// attributes, returning the key's new version and the keys it evicted
+fn store_logged(
+    storage: &mut dyn StorageEngine,
+    storage_key: &str,
+    value: &[u8],
+    timestamp_ms: u64,
+    expires_ms: Option<u64>,
+    meta: Option<HashMap<String, String>>,
+) -> Result<(u64, Vec<String>)> {
+    storage.store_at(storage_key, value, timestamp_ms)?;
+    if expires_ms.is_some() {
+        storage.set_expiry(storage_key, expires_ms)?;
+    }
+    if meta.is_some() {
+        storage.set_user_meta(storage_key, meta)?;
+    }
+    let version = storage.metadata(storage_key)?.map_or(0, |m| m.version);
+    Ok((version, storage.take_evicted()))
+}
+
+/// Apply one logged operation to `storage`, as recovery does
+fn replay_operation(storage: &mut dyn StorageEngine, operation: Operation, timestamp_ms: u64) -> Result<()> {
+    match operation {
+        Operation::Set { namespace, key, value } => {
+            let storage_key = namespace::storage_key(namespace.as_deref(), &key);
+            storage.store_at(&storage_key, &value, timestamp_ms)?;
+        }
+        Operation::Delete { namespace, key } | Operation::Expire { namespace, key } => {
+            storage.remove(&namespace::storage_key(namespace.as_deref(), &key))?;
+        }
+        Operation::SetWithExpiry { namespace, key, value, expires_ms } => {
+            let storage_key = namespace::storage_key(namespace.as_deref(), &key);
+            storage.store_at(&storage_key, &value, timestamp_ms)?;
+            storage.set_expiry(&storage_key, Some(expires_ms))?;
+        }
+        Operation::SetWithMeta { namespace, key, value, meta } => {
+            let storage_key = namespace::storage_key(namespace.as_deref(), &key);
+            storage.store_at(&storage_key, &value, timestamp_ms)?;
+            storage.set_user_meta(&storage_key, Some(meta))?;
+        }
+        Operation::Append { namespace, key, chunk, fresh } => {
+            let storage_key = namespace::storage_key(namespace.as_deref(), &key);
+            let value = chunked::appended(storage, &storage_key, &chunk, fresh)?;
+            storage.store_at(&storage_key, &value, timestamp_ms)?;
+        }
+        Operation::DeletePrefix { namespace, keys, .. } => {
+            for key in keys {
+                storage.remove(&namespace::storage_key(namespace.as_deref(), &key))?;
+            }
+        }
+        Operation::Batch { ops } => {
+            for operation in ops {
+                replay_operation(storage, operation, timestamp_ms)?;
+            }
+        }
+        Operation::Persist { namespace, key } => {
+            storage.set_expiry(&namespace::storage_key(namespace.as_deref(), &key), None)?;
+        }
+        Operation::Rename { namespace, from, to, value } => {
+            let ns = namespace.as_deref();
+            move_key(storage, &namespace::storage_key(ns, &from), &namespace::storage_key(ns, &to), &value, timestamp_ms)?;
+        }
+        // Turned into a `Set` by `StagedStreams` once the whole value is in
+        Operation::StreamChunk { .. } | Operation::StreamCommit { .. } => {}
+    }
+    Ok(())
+}
+
+/// Store `value` at `to_key` in place of `from_key`, carrying over its
+/// expiry and attributes, as `Database::rename` does
+fn move_key(storage: &mut dyn StorageEngine, from_key: &str, to_key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+    let expires_ms = storage.metadata(from_key)?.and_then(|meta| meta.expires_ms);
+    let user_meta = storage.user_meta(from_key)?;
+    storage.remove(from_key)?;
+    // Removed first so the key starts over at version 1
+    storage.remove(to_key)?;
+    storage.store_at(to_key, value, modified_ms)?;
+    if expires_ms.is_some() {
+        storage.set_expiry(to_key, expires_ms)?;
+    }
+    if user_meta.is_some() {
+        storage.set_user_meta(to_key, user_meta)?;
+    }
+    Ok(())
+}
+
+/// Storage keys of every key in `ns` starting with `prefix`, in sorted order
+pub(crate) fn keys_with_prefix(storage: &dyn StorageEngine, ns: Option<&str>, prefix: &str) -> Result<Vec<String>> {
+    let mut matches = Vec::new();
+    for_each_with_prefix(storage, ns, prefix, |k| matches.push(k.to_string()))?;
+    Ok(matches)
+}
+
+/// Call `f` with the storage key of every key in `ns` starting with
+/// `prefix`, in sorted order, scanning in batches without collecting them
+fn for_each_with_prefix(
+    storage: &dyn StorageEngine,
+    ns: Option<&str>,
+    prefix: &str,
+    mut f: impl FnMut(&str),
+) -> Result<()> {
+    const BATCH: usize = 1024;
+    let start_key = namespace::storage_key(ns, prefix);
+    let mut last: Option<String> = None;
+    loop {
+        let start = last.as_deref().map_or(Bound::Included(start_key.as_str()), Bound::Excluded);
+        let mut batch = storage.scan_keys(start, BATCH)?;
+        let exhausted = batch.len() < BATCH;
+        for key in &batch {
+            if !key.starts_with(&start_key) {
+                return Ok(());
+            }
+            // With an empty prefix the default namespace's range also covers namespaced keys
+            if ns.is_some() || !namespace::is_namespaced(key) {
+                f(key);
+            }
+        }
+        if exhausted {
+            return Ok(());
+        }
+        last = batch.pop();
+    }
+}
+
+/// Longest prefix shared by every string in `prefixes`
+fn common_prefix<'a>(prefixes: &[&'a str]) -> &'a str {
+    let Some((first, rest)) = prefixes.split_first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for other in rest {
+        len = first.bytes().zip(other.bytes()).take(len).take_while(|(a, b)| a == b).count();
+    }
+    // Stay on a character boundary
+    while !first.is_char_boundary(len) {
+        len -= 1;
     }
+    &first[..len]
 }
 
 // Implement Send and Sync manually since we know our implementation is thread-safe
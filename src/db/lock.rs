@@ -0,0 +1,83 @@
+use crate::db::perms;
+use crate::{LohError, Result};
+use fs2::FileExt;
+use std::fs::File;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait between attempts of `DirLock::acquire_within`
+const MAX_RETRY_WAIT: Duration = Duration::from_millis(100);
+
+/// Exclusive advisory lock on the `LOCK` file inside a data directory
+///
+/// The lock is taken with `flock` (or `LockFileEx` on Windows), so it is
+/// held per open file handle: a second `Database::open` on the same
+/// `data_dir` fails whether it comes from another process or from the same
+/// one. The lock is released when this value is dropped.
+pub struct DirLock {
+    file: File,
+    path: String,
+}
+
+impl DirLock {
+    pub fn acquire<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        Self::acquire_with_mode(data_dir, None)
+    }
+    
+    /// `acquire`, creating the directory and lock file with `file_mode` (Unix
+    /// permission bits) if they do not exist yet
+    pub fn acquire_with_mode<P: AsRef<Path>>(data_dir: P, file_mode: Option<u32>) -> Result<Self> {
+        perms::create_dir_all(&data_dir, file_mode)?;
+        
+        let path = data_dir.as_ref().join("LOCK");
+        let path_str = path.to_string_lossy().to_string();
+        
+        let file = perms::open_options(file_mode)
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        
+        if file.try_lock_exclusive().is_err() {
+            return Err(LohError::Locked(data_dir.as_ref().display().to_string()));
+        }
+        
+        Ok(Self {
+            file,
+            path: path_str,
+        })
+    }
+    
+    /// `acquire_with_mode`, trying again while the lock is held elsewhere
+    /// until `timeout` has passed, then failing with `LohError::Locked`
+    ///
+    /// Waits 1ms after the first failed attempt and twice as long after
+    /// each one after that, up to 100ms, so a lock released soon is picked
+    /// up soon without polling a long-held one in a tight loop.
+    pub fn acquire_within<P: AsRef<Path>>(data_dir: P, file_mode: Option<u32>, timeout: Duration) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let mut wait = Duration::from_millis(1);
+        loop {
+            match Self::acquire_with_mode(&data_dir, file_mode) {
+                Err(LohError::Locked(_)) if Instant::now() < deadline => {
+                    thread::sleep(wait.min(deadline.saturating_duration_since(Instant::now())));
+                    wait = (wait * 2).min(MAX_RETRY_WAIT);
+                }
+                result => return result,
+            }
+        }
+    }
+    
+    /// Path of the lock file
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
@@ -0,0 +1,72 @@
+use crate::db::engine::SnapshotEntries;
+use crate::db::{Database, StorageEngine, WriteAheadLog};
+use crate::Result;
+use std::sync::{MutexGuard, RwLockReadGuard};
+
+/// Write barrier held for the length of `Database::with_maintenance`
+///
+/// Holds the WAL lock, which every write takes before touching storage, and
+/// a read lock on storage, taken once writes to shards that had already
+/// released the WAL are applied, so no write is in flight and none can
+/// start: storage reflects every record in the WAL and stays that way until
+/// the guard goes. Reads from other threads carry on as usual.
+pub struct MaintenanceGuard<'a> {
+    wal: MutexGuard<'a, WriteAheadLog>,
+    storage: RwLockReadGuard<'a, Box<dyn StorageEngine>>,
+}
+
+impl MaintenanceGuard<'_> {
+    /// The storage engine, frozen at `seq`
+    ///
+    /// Keys are storage-encoded: those in a namespace carry its prefix, see
+    /// `namespace::split_storage_key`.
+    pub fn storage(&self) -> &dyn StorageEngine {
+        self.storage.as_ref()
+    }
+    
+    /// WAL sequence number of the last write storage reflects
+    pub fn seq(&self) -> u64 {
+        self.wal.last_seq()
+    }
+    
+    /// Every entry, with storage-encoded keys, as it stands at `seq`
+    ///
+    /// As cheap as `Database::snapshot` for the engines that share values
+    /// copy-on-write, and still valid once the guard is released.
+    pub fn entries(&self) -> Result<SnapshotEntries> {
+        self.storage.snapshot()
+    }
+}
+
+impl Database {
+    /// Run `f` with writers paused, for work that must not interleave with
+    /// writes, such as a consistent backup or a full rewrite elsewhere
+    ///
+    /// Every write, from any thread or namespace handle, blocks until `f`
+    /// returns; reads go on meanwhile. Locks are taken in the same order as
+    /// writers take them, so the barrier itself cannot deadlock with them.
+    ///
+    /// `f` must not write through this database: a namespace handle's
+    /// writes and `Transaction::commit` wait for the barrier `f` is holding,
+    /// and never return (`set` and the other `&mut self` writes cannot be
+    /// called while it is borrowed). Neither do `stats`, `unsynced_count`
+    /// and `change_history`, which read the WAL; use `seq` and `entries` on
+    /// the guard instead. Reads of storage such as `get` and `scan` are
+    /// fine, and so is waiting on another thread that only reads.
+    pub fn with_maintenance<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&MaintenanceGuard) -> Result<R>,
+    {
+        let wal = self.wal_handle();
+        let storage = self.storage_handle();
+        let wal = wal.lock().unwrap();
+        // A sharded write holds a read lock on storage, not the WAL, while
+        // it applies, so wait for any in flight before looking
+        drop(storage.write().unwrap());
+        let guard = MaintenanceGuard {
+            wal,
+            storage: storage.read().unwrap(),
+        };
+        f(&guard)
+    }
+}
@@ -0,0 +1,190 @@
+use crate::db::{perms, DatabaseConfig};
+use crate::{LohError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the manifest inside a data directory
+pub(crate) const MANIFEST_FILE: &str = "MANIFEST";
+
+/// Version of the on-disk layout as a whole; bump it when a change would
+/// make older builds misread a data directory
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Settings a data directory was created with, stored as JSON in
+/// `{data_dir}/MANIFEST`
+///
+/// Written when a database is first opened for writing, and checked on
+/// every later open so that a directory from a newer, incompatible build,
+/// or a config that cannot read its files, fails up front with a clear
+/// error instead of partway through loading them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// On-disk format version; see `FORMAT_VERSION`
+    pub format_version: u32,
+    /// Crate version that created the directory
+    pub created_by: String,
+    /// Encoding of WAL records and the data file
+    pub serialization_format: String,
+    /// Name of the data file codec, if any
+    pub compression: Option<String>,
+    pub encrypted: bool,
+    /// `DatabaseConfig::wal_dir`, if the WAL lives outside the directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wal_dir: Option<String>,
+    /// `DatabaseConfig::data_file`, if the data file lives elsewhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_file: Option<String>,
+    /// `DatabaseConfig::shards`, the number of files the data is split into
+    #[serde(default = "one_shard", skip_serializing_if = "is_one_shard")]
+    pub shards: usize,
+    /// `DatabaseConfig::dedup`, whether values live apart from their keys
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dedup: bool,
+}
+
+fn one_shard() -> usize {
+    1
+}
+
+fn is_one_shard(shards: &usize) -> bool {
+    *shards == 1
+}
+
+impl Manifest {
+    /// The manifest a directory created with `config` by this build gets
+    pub(crate) fn for_config(config: &DatabaseConfig) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            created_by: env!("CARGO_PKG_VERSION").to_string(),
+            serialization_format: config.serialization_format.to_string(),
+            compression: config.compressor.as_ref().map(|c| c.name().to_string()),
+            encrypted: config.encryption_key.is_some(),
+            wal_dir: config.wal_dir.clone(),
+            data_file: config.data_file.clone(),
+            shards: config.shards,
+            dedup: config.dedup,
+        }
+    }
+    
+    /// Read the manifest in `dir`, or `None` if there is none yet
+    pub fn read(dir: impl AsRef<Path>) -> Result<Option<Self>> {
+        let path = dir.as_ref().join(MANIFEST_FILE);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| LohError::Format(format!("'{}' is not a valid manifest: {}", path.display(), e)))
+    }
+    
+    /// Fail if this build cannot read the directory or `config` contradicts it
+    ///
+    /// Adding compression to an uncompressed database is allowed, since
+    /// uncompressed data files still load; every other difference is not,
+    /// including a WAL directory or data file path other than the recorded
+    /// one, another number of shards, or turning `dedup` on or off.
+    pub(crate) fn check(&self, config: &DatabaseConfig) -> Result<()> {
+        if self.format_version > FORMAT_VERSION {
+            return Err(LohError::Format(format!(
+                "'{}' was written by lohdb {} in on-disk format version {}, but this build only supports up to version {}",
+                config.data_dir, self.created_by, self.format_version, FORMAT_VERSION
+            )));
+        }
+        
+        let format = config.serialization_format.to_string();
+        if self.serialization_format != format {
+            return Err(LohError::Format(format!(
+                "'{}' was written as {} but the database is configured for {}",
+                config.data_dir, self.serialization_format, format
+            )));
+        }
+        
+        match (self.encrypted, config.encryption_key.is_some()) {
+            (true, false) => {
+                return Err(LohError::Encryption(format!(
+                    "'{}' is encrypted; open it with its encryption_key", config.data_dir
+                )));
+            }
+            (false, true) => {
+                return Err(LohError::Encryption(format!(
+                    "'{}' is not encrypted, but an encryption_key was given", config.data_dir
+                )));
+            }
+            _ => {}
+        }
+        
+        // Compared as resolved paths, so spelling out the default is no change
+        let wal_dir = self.wal_dir.as_deref().unwrap_or(&config.data_dir);
+        if wal_dir != config.effective_wal_dir() {
+            return Err(LohError::InvalidArgument(format!(
+                "'{}' keeps its WAL in '{}', but the database is configured for '{}'; move the WAL segments first",
+                config.data_dir, wal_dir, config.effective_wal_dir()
+            )));
+        }
+        let data_file = self.data_file.as_ref().map_or_else(|| Path::new(&config.data_dir).join("data.db"), PathBuf::from);
+        if data_file != config.effective_data_file() {
+            return Err(LohError::InvalidArgument(format!(
+                "'{}' keeps its data file at '{}', but the database is configured for '{}'; move the data file first",
+                config.data_dir, data_file.display(), config.effective_data_file().display()
+            )));
+        }
+        
+        if self.shards != config.shards {
+            return Err(LohError::InvalidArgument(format!(
+                "'{}' is split into {} shard(s), but the database is configured for {}; keys cannot move between shards",
+                config.data_dir, self.shards, config.shards
+            )));
+        }
+        
+        if self.dedup != config.dedup {
+            return Err(LohError::InvalidArgument(if self.dedup {
+                format!("'{}' stores its values deduplicated; open it with dedup", config.data_dir)
+            } else {
+                format!("'{}' does not store its values deduplicated, but dedup was configured", config.data_dir)
+            }));
+        }
+        
+        let configured = config.compressor.as_ref().map(|c| c.name());
+        match (self.compression.as_deref(), configured) {
+            (Some(found), None) => Err(LohError::Compression(format!(
+                "'{}' is compressed with '{}', but no compressor is configured", config.data_dir, found
+            ))),
+            (Some(found), Some(configured)) if found != configured => Err(LohError::Compression(format!(
+                "'{}' is compressed with '{}', but the configured compressor is '{}'",
+                config.data_dir, found, configured
+            ))),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Write the manifest into `dir`, replacing any existing one atomically
+    pub(crate) fn write(&self, dir: impl AsRef<Path>, file_mode: Option<u32>) -> Result<()> {
+        let path = dir.as_ref().join(MANIFEST_FILE);
+        let tmp_path = path.with_extension("tmp");
+        let mut file = perms::open_options(file_mode).write(true).create(true).truncate(true).open(&tmp_path)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
+
+/// Record `config` in the manifest of `dir` if it is missing or out of date
+///
+/// Called once a writable open has loaded everything, so a directory from
+/// before manifests only gets one after its files proved readable with
+/// `config`. The original `created_by` is kept.
+pub(crate) fn record(dir: &str, existing: Option<Manifest>, config: &DatabaseConfig) -> Result<()> {
+    let mut wanted = Manifest::for_config(config);
+    if let Some(existing) = &existing {
+        wanted.created_by = existing.created_by.clone();
+    }
+    if existing.as_ref() != Some(&wanted) {
+        wanted.write(dir, config.effective_file_mode())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Per-key bookkeeping a storage engine keeps alongside the value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMeta {
+    /// Number of sets since the key was created; the first set is version 1
+    pub version: u64,
+    /// Time of the last set, in milliseconds since the Unix epoch
+    pub modified_ms: u64,
+    /// When the key expires, in milliseconds since the Unix epoch; `None`
+    /// keeps it forever. A plain set clears it.
+    ///
+    /// Not part of the serialized form: the data file stores expiries in a
+    /// map of their own, so files written before expiry existed still load.
+    #[serde(skip)]
+    pub expires_ms: Option<u64>,
+}
+
+impl KeyMeta {
+    /// Metadata after one more set at `modified_ms`
+    pub fn next(previous: Option<KeyMeta>, modified_ms: u64) -> Self {
+        Self {
+            version: previous.map_or(0, |m| m.version) + 1,
+            modified_ms,
+            expires_ms: None,
+        }
+    }
+    
+    /// Whether the key's expiry time has passed at `now_ms`
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        self.expires_ms.is_some_and(|expires_ms| expires_ms <= now_ms)
+    }
+}
+
+/// Keys with an expiry, ordered by when they expire
+///
+/// Lets an engine find expired keys without looking at every key.
+#[derive(Default)]
+pub(crate) struct ExpiryQueue {
+    by_deadline: BTreeSet<(u64, String)>,
+}
+
+impl ExpiryQueue {
+    /// Move `key` from its old expiry to its new one (`None` for neither)
+    pub fn update(&mut self, key: &str, old: Option<u64>, new: Option<u64>) {
+        if old == new {
+            return;
+        }
+        if let Some(old) = old {
+            self.by_deadline.remove(&(old, key.to_string()));
+        }
+        if let Some(new) = new {
+            self.by_deadline.insert((new, key.to_string()));
+        }
+    }
+    
+    /// Keys expiring at or before `now_ms`, soonest first
+    pub fn due(&self, now_ms: u64) -> Vec<String> {
+        self.by_deadline
+            .iter()
+            .take_while(|(deadline, _)| *deadline <= now_ms)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+}
+
+/// A value together with its metadata, as returned by `Database::get_with_metadata`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueMeta {
+    pub value: Vec<u8>,
+    /// Length of `value` in bytes
+    pub size: usize,
+    /// Number of sets since the key was created (0 if the storage engine
+    /// does not track versions)
+    pub version: u64,
+    /// Time of the last set (the Unix epoch if the engine does not track it)
+    pub last_modified: SystemTime,
+}
+
+impl ValueMeta {
+    pub(crate) fn new(value: Vec<u8>, meta: KeyMeta) -> Self {
+        Self {
+            size: value.len(),
+            value,
+            version: meta.version,
+            last_modified: UNIX_EPOCH + Duration::from_millis(meta.modified_ms),
+        }
+    }
+}
+
+/// Outcome of `Database::put_if_version`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionedPut {
+    /// The value was written and the key is now at `version`
+    Written { version: u64 },
+    /// Another write got there first; the key is at `current_version`
+    /// (0 if it does not exist) and nothing was written
+    Stale { current_version: u64 },
+}
+
+impl VersionedPut {
+    /// Whether the value was written
+    pub fn is_written(&self) -> bool {
+        matches!(self, VersionedPut::Written { .. })
+    }
+}
+
+/// Current time in milliseconds since the Unix epoch
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
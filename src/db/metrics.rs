@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Linear sub-buckets per power of two; each bucket spans at most 25% of its
+/// lower bound, which bounds the error of a reported percentile
+const SUB_BUCKET_BITS: u32 = 2;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Lock-free latency histogram over nanoseconds
+///
+/// Values below 4ns get a bucket each; above that, every power of two is
+/// split into four equal buckets. Recording is a couple of relaxed atomic
+/// adds, so it can sit on the hot path of every operation.
+pub(crate) struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            total_nanos: AtomicU64::new(0),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+    
+    pub fn record(&self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+    
+    pub fn summary(&self) -> LatencySummary {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let count: u64 = counts.iter().sum();
+        if count == 0 {
+            return LatencySummary::default();
+        }
+        let max = self.max_nanos.load(Ordering::Relaxed);
+        
+        // Report a bucket's upper bound, capped at the largest value seen
+        let percentile = |q: f64| {
+            let rank = ((count as f64 * q).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (index, &n) in counts.iter().enumerate() {
+                seen += n;
+                if seen >= rank {
+                    return Duration::from_nanos(bucket_upper_bound(index).min(max));
+                }
+            }
+            Duration::from_nanos(max)
+        };
+        
+        LatencySummary {
+            count,
+            mean: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed) / count),
+            p50: percentile(0.50),
+            p99: percentile(0.99),
+            max: Duration::from_nanos(max),
+        }
+    }
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let exp = 63 - nanos.leading_zeros();
+    let shift = exp - SUB_BUCKET_BITS;
+    // The top SUB_BUCKET_BITS + 1 bits, in SUB_BUCKETS..2 * SUB_BUCKETS
+    let mantissa = (nanos >> shift) as usize;
+    shift as usize * SUB_BUCKETS + mantissa
+}
+
+/// Largest value that falls in bucket `index`
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let mantissa = (index % SUB_BUCKETS + SUB_BUCKETS) as u64;
+    (mantissa << shift) + ((1 << shift) - 1)
+}
+
+/// Latency histograms for a `Database`, one per kind of operation
+pub(crate) struct Metrics {
+    pub set: LatencyHistogram,
+    pub get: LatencyHistogram,
+    pub delete: LatencyHistogram,
+    pub wal_append: LatencyHistogram,
+    pub wal_sync: LatencyHistogram,
+    pub flush: LatencyHistogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            set: LatencyHistogram::new(),
+            get: LatencyHistogram::new(),
+            delete: LatencyHistogram::new(),
+            wal_append: LatencyHistogram::new(),
+            wal_sync: LatencyHistogram::new(),
+            flush: LatencyHistogram::new(),
+        }
+    }
+    
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            set: self.set.summary(),
+            get: self.get.summary(),
+            delete: self.delete.summary(),
+            wal_append: self.wal_append.summary(),
+            wal_sync: self.wal_sync.summary(),
+            flush: self.flush.summary(),
+        }
+    }
+}
+
+/// Latency distribution of one kind of operation since the database opened
+///
+/// Percentiles are read from a bucketed histogram and may overstate the
+/// true value by up to 25%; `max` is exact. All zero until something is
+/// recorded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Point-in-time latency metrics for a `Database` (`metrics` feature)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Every set, including versioned, expiring and compute-if-absent inserts
+    pub set: LatencySummary,
+    pub get: LatencySummary,
+    pub delete: LatencySummary,
+    /// Appending one record to the WAL, a part of every write
+    pub wal_append: LatencySummary,
+    /// Fsyncing the WAL on its own, through `flush_with(FlushMode::Wal)`
+    pub wal_sync: LatencySummary,
+    /// Foreground storage flushes: `flush`, `sync` and `checkpoint`;
+    /// background flushes report theirs in `LifecycleEvent::Flushed`
+    pub flush: LatencySummary,
+}
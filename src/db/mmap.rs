@@ -0,0 +1,308 @@
+use crate::db::{CompactStats, StorageEngine};
+use crate::{LohError, Result};
+use memmap2::MmapMut;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::ops::Bound;
+use std::path::PathBuf;
+
+const MAGIC: &[u8; 4] = b"LOHM";
+const FORMAT_VERSION: u8 = 1;
+/// Magic, version, and three reserved bytes
+const FILE_HEADER_LEN: usize = 8;
+/// Kind byte, key length, value length
+const RECORD_HEADER_LEN: usize = 1 + 4 + 4;
+
+/// Zero kind byte: the unused, zero-filled tail of the file
+const KIND_END: u8 = 0;
+const KIND_PUT: u8 = 1;
+const KIND_DELETE: u8 = 2;
+
+/// Size of a new data file; it at least doubles whenever it fills up
+const INITIAL_CAPACITY: usize = 1024 * 1024;
+/// Dead bytes tolerated before `flush` compacts, however high the dead ratio
+const COMPACT_MIN_DEAD_BYTES: usize = 1024 * 1024;
+
+/// Storage engine backed by a memory-mapped, append-only data file
+///
+/// Only an index of key -> value offset is kept in RAM; values are read out
+/// of the mapping, so only the pages actually touched are faulted in.
+/// Writes append to the file instead of rewriting it.
+///
+/// On-disk layout of `{data_dir}/data.mmap`:
+///
+/// ```text
+/// header:  "LOHM" | version: u8 | 3 reserved bytes
+/// record:  kind: u8 | key_len: u32 LE | value_len: u32 LE | key | value
+/// ```
+///
+/// `kind` is 1 for a put and 2 for a delete (with an empty value). The file
+/// is preallocated and zero-filled, so a zero kind byte marks the end of the
+/// records. On load, scanning stops at the first record with an unknown
+/// kind, a non-UTF-8 key, or a length running past the file, and everything
+/// from there on is cleared.
+///
+/// Overwritten and deleted records become dead space. `flush` compacts the
+/// file (rewriting only live entries) once dead bytes reach 1 MiB and
+/// outnumber live bytes; `compact` forces it.
+///
+/// The mapping assumes exclusive access to the file, which `Database`
+/// guarantees through its directory lock.
+pub struct MmapStorageEngine {
+    data_dir: String,
+    map: Option<MmapMut>,
+    file: Option<File>,
+    /// Key -> (value offset, value length)
+    index: BTreeMap<String, (usize, usize)>,
+    /// Bytes in use, including the file header
+    len: usize,
+    dead_bytes: usize,
+    dirty: bool,
+}
+
+impl MmapStorageEngine {
+    pub fn new(data_dir: String) -> Self {
+        Self {
+            data_dir,
+            map: None,
+            file: None,
+            index: BTreeMap::new(),
+            len: FILE_HEADER_LEN,
+            dead_bytes: 0,
+            dirty: false,
+        }
+    }
+
+    fn data_file_path(&self) -> PathBuf {
+        PathBuf::from(&self.data_dir).join("data.mmap")
+    }
+
+    /// Bytes held by overwritten or deleted records
+    pub fn dead_bytes(&self) -> usize {
+        self.dead_bytes
+    }
+
+    fn mapped(&self) -> Result<&MmapMut> {
+        self.map
+            .as_ref()
+            .ok_or_else(|| LohError::InvalidArgument("storage engine is not initialized".to_string()))
+    }
+
+    /// Grow the file and mapping so `additional` more bytes fit
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        let capacity = self.mapped()?.len();
+        // Keep one spare byte so the end marker after the last record stays zero
+        let needed = self.len + additional + 1;
+        if needed <= capacity {
+            return Ok(());
+        }
+
+        let new_capacity = needed.max(capacity * 2);
+        self.mapped()?.flush()?;
+        self.map = None;
+        let file = self.file.as_ref().expect("initialized engine has a file");
+        file.set_len(new_capacity as u64)?;
+        // SAFETY: the file is only accessed through this engine
+        self.map = Some(unsafe { MmapMut::map_mut(file)? });
+        Ok(())
+    }
+
+    fn append(&mut self, kind: u8, key: &str, value: &[u8]) -> Result<usize> {
+        self.reserve(RECORD_HEADER_LEN + key.len() + value.len())?;
+        let map = self.map.as_mut().expect("reserve maps the file");
+        let value_offset = write_record(map, self.len, kind, key, value);
+        self.len = value_offset + value.len();
+        self.dirty = true;
+        Ok(value_offset)
+    }
+
+    /// Rebuild the index by scanning the records in the mapping
+    fn load(&mut self) -> Result<()> {
+        let map = self.map.as_mut().expect("load runs after mapping");
+        if &map[..MAGIC.len()] != MAGIC {
+            return Err(LohError::InvalidArgument("data.mmap is not a LohDB mmap data file".to_string()));
+        }
+        if map[MAGIC.len()] != FORMAT_VERSION {
+            return Err(LohError::InvalidArgument(format!(
+                "data.mmap uses format version {}, but this build only supports version {}",
+                map[MAGIC.len()], FORMAT_VERSION
+            )));
+        }
+
+        let mut pos = FILE_HEADER_LEN;
+        while pos + RECORD_HEADER_LEN <= map.len() {
+            let kind = map[pos];
+            let key_len = u32::from_le_bytes(map[pos + 1..pos + 5].try_into().unwrap()) as usize;
+            let value_len = u32::from_le_bytes(map[pos + 5..pos + 9].try_into().unwrap()) as usize;
+            let key_start = pos + RECORD_HEADER_LEN;
+            let value_offset = key_start + key_len;
+            let end = value_offset + value_len;
+
+            let valid = matches!(kind, KIND_PUT | KIND_DELETE) && end < map.len();
+            let key = valid.then(|| std::str::from_utf8(&map[key_start..value_offset]).ok()).flatten();
+            let Some(key) = key else {
+                break;
+            };
+
+            if let Some((_, old_len)) = self.index.remove(key) {
+                self.dead_bytes += RECORD_HEADER_LEN + key.len() + old_len;
+            }
+            if kind == KIND_PUT {
+                self.index.insert(key.to_string(), (value_offset, value_len));
+            } else {
+                self.dead_bytes += end - pos;
+            }
+            pos = end;
+        }
+        self.len = pos;
+
+        // Clear a torn record so later appends are not misread after it
+        if map[pos..].iter().any(|&b| b != KIND_END) {
+            map[pos..].fill(KIND_END);
+            map.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Create a zero-filled data file of `capacity` bytes with its header written
+fn create_data_file(path: &PathBuf, capacity: usize) -> Result<(File, MmapMut)> {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+    file.set_len(capacity as u64)?;
+    // SAFETY: the file is only accessed through this engine
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    map[..MAGIC.len()].copy_from_slice(MAGIC);
+    map[MAGIC.len()] = FORMAT_VERSION;
+    Ok((file, map))
+}
+
+/// Write one record at `pos`, returning the offset of its value
+fn write_record(map: &mut MmapMut, pos: usize, kind: u8, key: &str, value: &[u8]) -> usize {
+    let key_start = pos + RECORD_HEADER_LEN;
+    let value_offset = key_start + key.len();
+    map[pos + 1..pos + 5].copy_from_slice(&(key.len() as u32).to_le_bytes());
+    map[pos + 5..pos + 9].copy_from_slice(&(value.len() as u32).to_le_bytes());
+    map[key_start..value_offset].copy_from_slice(key.as_bytes());
+    map[value_offset..value_offset + value.len()].copy_from_slice(value);
+    // The kind byte goes last: until it is set the record reads as the end marker
+    map[pos] = kind;
+    value_offset
+}
+
+impl StorageEngine for MmapStorageEngine {
+    fn initialize(&mut self) -> Result<()> {
+        fs::create_dir_all(&self.data_dir)?;
+        let path = self.data_file_path();
+
+        if !path.exists() || fs::metadata(&path)?.len() == 0 {
+            let (file, map) = create_data_file(&path, INITIAL_CAPACITY)?;
+            map.flush()?;
+            self.file = Some(file);
+            self.map = Some(map);
+            return Ok(());
+        }
+
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        // SAFETY: the file is only accessed through this engine
+        self.map = Some(unsafe { MmapMut::map_mut(&file)? });
+        self.file = Some(file);
+        self.load()
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let value_offset = self.append(KIND_PUT, key, value)?;
+        if let Some((_, old_len)) = self.index.insert(key.to_string(), (value_offset, value.len())) {
+            self.dead_bytes += RECORD_HEADER_LEN + key.len() + old_len;
+        }
+        Ok(())
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.index.get(key) {
+            Some(&(offset, len)) => Ok(Some(self.mapped()?[offset..offset + len].to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        let Some((_, old_len)) = self.index.remove(key) else {
+            return Ok(false);
+        };
+        self.append(KIND_DELETE, key, &[])?;
+        self.dead_bytes += 2 * (RECORD_HEADER_LEN + key.len()) + old_len;
+        Ok(true)
+    }
+
+    fn list_keys(&self) -> Result<Vec<String>> {
+        Ok(self.index.keys().cloned().collect())
+    }
+    
+    fn key_count(&self) -> Result<usize> {
+        Ok(self.index.len())
+    }
+
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        Ok(self.index
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        Ok(self.index
+            .range::<str, _>((Bound::Unbounded, end))
+            .rev()
+            .take(limit)
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+
+    /// Sync dirty pages to disk, compacting first if enough space is dead
+    fn flush(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let live_bytes = self.len - FILE_HEADER_LEN - self.dead_bytes;
+        if self.dead_bytes >= COMPACT_MIN_DEAD_BYTES && self.dead_bytes > live_bytes {
+            self.compact()?;
+        } else {
+            self.mapped()?.flush()?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Bytes of the data file holding records, live or dead
+    fn disk_usage(&self) -> Result<u64> {
+        Ok(self.len as u64)
+    }
+
+    /// Rewrite the data file with only live entries, reclaiming dead space
+    fn compact(&mut self) -> Result<CompactStats> {
+        let bytes_before = self.len as u64;
+        let live_bytes = self.len - FILE_HEADER_LEN - self.dead_bytes;
+        let tmp_path = self.data_file_path().with_extension("compact");
+        let (file, mut map) = create_data_file(&tmp_path, INITIAL_CAPACITY.max(live_bytes * 2))?;
+
+        let old_map = self.mapped()?;
+        let mut index = BTreeMap::new();
+        let mut len = FILE_HEADER_LEN;
+        for (key, &(offset, value_len)) in &self.index {
+            let value = &old_map[offset..offset + value_len];
+            let value_offset = write_record(&mut map, len, KIND_PUT, key, value);
+            index.insert(key.clone(), (value_offset, value_len));
+            len = value_offset + value_len;
+        }
+        map.flush()?;
+
+        fs::rename(&tmp_path, self.data_file_path())?;
+        self.map = Some(map);
+        self.file = Some(file);
+        self.index = index;
+        self.len = len;
+        self.dead_bytes = 0;
+        Ok(CompactStats { bytes_before, bytes_after: self.len as u64 })
+    }
+}
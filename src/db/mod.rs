@@ -1,9 +1,80 @@
+pub mod compression;
+mod backup;
+pub mod cache;
+mod checksum;
+pub mod clock;
+mod chunked;
+pub mod config;
+pub mod crypto;
+pub mod dedup;
 pub mod engine;
+mod export;
+pub mod format;
+mod group_commit;
+pub mod history;
+pub mod index;
+pub mod iter;
 pub mod kv;
+pub mod maintenance;
+pub mod manifest;
+pub mod meta;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mmap")]
+pub mod mmap;
 pub mod wal;
+pub mod wal_backend;
+mod perms;
 pub mod subscriber;
+pub mod namespace;
+pub mod profile;
+pub mod recovery;
+pub mod shard;
+pub mod snapshot;
+pub mod lock;
+pub mod stats;
+pub mod stream;
+mod sync;
+pub mod transaction;
+mod ttl;
+mod typed;
+mod validate;
+pub mod verify;
 
-pub use engine::{StorageEngine, FileStorageEngine, InMemoryStorageEngine};
-pub use kv::{Database, DatabaseConfig};
-pub use wal::{WriteAheadLog, Operation};
-pub use subscriber::{ChangeEvent, Subscriber, SubscriptionHandle, EventBus};
\ No newline at end of file
+pub use cache::CachingStorageEngine;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use engine::{StorageEngine, CompactStats, FileStorageEngine, InMemoryStorageEngine, SnapshotEntries};
+#[cfg(feature = "mmap")]
+pub use mmap::MmapStorageEngine;
+pub use compression::Compressor;
+pub use format::SerializationFormat;
+#[cfg(feature = "lz4")]
+pub use compression::Lz4Compressor;
+pub use crypto::Encryptor;
+pub use dedup::{DedupStats, DedupStorageEngine};
+#[cfg(feature = "crypto")]
+pub use crypto::ChaCha20Poly1305Encryptor;
+pub use config::{DatabaseConfig, DatabaseConfigBuilder};
+pub use history::LoggedChange;
+pub use index::IndexExtractor;
+pub use iter::DbIterator;
+pub use kv::Database;
+pub use maintenance::MaintenanceGuard;
+pub use manifest::Manifest;
+pub use meta::{KeyMeta, ValueMeta, VersionedPut};
+#[cfg(feature = "metrics")]
+pub use metrics::{LatencySummary, MetricsSnapshot};
+pub use wal::{WriteAheadLog, Operation, WalEntry, WalEntries, LocatedEntry, ReplayReport};
+pub use wal_backend::{FileBackend, MemoryBackend, SegmentFile, SegmentReader, WalBackend};
+pub use subscriber::{ChangeEvent, EventFilter, LifecycleEvent, Subscriber, SubscriptionHandle, EventBus, LifecycleBus, OverflowPolicy};
+pub use namespace::NamespaceHandle;
+pub use profile::{LargestValue, SizeProfile, SizeSummary};
+pub use recovery::{RecoverTo, RecoveryObserver, RecoveryProgress};
+pub use shard::{ShardedStorageEngine, Shards};
+pub use snapshot::Snapshot;
+pub use lock::DirLock;
+pub use stats::{DbStats, SubscriberStats, SyncHealth};
+pub use stream::{ValueReader, ValueWriter};
+pub use sync::{FlushMode, FlushScheduler};
+pub use transaction::Transaction;
+pub use verify::{VerifyReport, WalCorruption};
\ No newline at end of file
@@ -0,0 +1,213 @@
+use crate::db::{chunked, typed, Database, ValueMeta, ValueReader, ValueWriter, VersionedPut};
+use crate::{LohError, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Separator used to encode namespaced keys in storage and the WAL.
+///
+/// Namespaced keys are stored as `\0<namespace>\0<key>`, so keys in the
+/// default namespace must not start with a NUL byte.
+const NAMESPACE_SEPARATOR: char = '\0';
+
+/// Build the key used by the storage engine for `key` in `namespace`
+pub(crate) fn storage_key(namespace: Option<&str>, key: &str) -> String {
+    match namespace {
+        Some(ns) => format!("{}{}{}{}", NAMESPACE_SEPARATOR, ns, NAMESPACE_SEPARATOR, key),
+        None => key.to_string(),
+    }
+}
+
+/// Split a storage key back into its namespace and user key
+pub(crate) fn split_storage_key(storage_key: &str) -> (Option<&str>, &str) {
+    match storage_key.strip_prefix(NAMESPACE_SEPARATOR) {
+        Some(rest) => match rest.split_once(NAMESPACE_SEPARATOR) {
+            Some((ns, key)) => (Some(ns), key),
+            None => (None, storage_key),
+        },
+        None => (None, storage_key),
+    }
+}
+
+/// Smallest storage key that sorts after every namespaced key
+pub(crate) const FIRST_KEY_AFTER_NAMESPACES: &str = "\u{1}";
+
+/// Whether `storage_key` belongs to a namespace rather than the default one
+pub(crate) fn is_namespaced(storage_key: &str) -> bool {
+    storage_key.starts_with(NAMESPACE_SEPARATOR)
+}
+
+/// Check that a user-supplied key is valid for the default namespace
+pub(crate) fn validate_key(key: &str) -> Result<()> {
+    if key.starts_with(NAMESPACE_SEPARATOR) {
+        return Err(LohError::InvalidArgument("keys may not start with a NUL byte (reserved for namespaces)".to_string()));
+    }
+    Ok(())
+}
+
+/// Check that a namespace name can be encoded unambiguously
+pub(crate) fn validate_namespace(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(LohError::InvalidArgument("namespace name must not be empty".to_string()));
+    }
+    if name.contains(NAMESPACE_SEPARATOR) {
+        return Err(LohError::InvalidArgument("namespace name must not contain a NUL byte".to_string()));
+    }
+    Ok(())
+}
+
+/// A logical partition of keys within one `Database`
+///
+/// Keys in different namespaces never collide, and each namespace can be
+/// listed and cleared independently. Namespaces are created implicitly by
+/// the first write and disappear from `Database::list_namespaces` once empty.
+pub struct NamespaceHandle<'a> {
+    db: &'a Database,
+    name: String,
+}
+
+impl<'a> NamespaceHandle<'a> {
+    pub(crate) fn new(db: &'a Database, name: String) -> Self {
+        Self { db, name }
+    }
+
+    /// Name of this namespace
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set(&self, key: String, value: Vec<u8>) -> Result<()> {
+        self.db.apply_set(Some(&self.name), key, value)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.db.apply_get(Some(&self.name), key)
+    }
+
+    /// Delete `key` and return its value; see `Database::take`
+    pub fn take(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.db.apply_take(Some(&self.name), key)
+    }
+
+    /// Delete `key` only if it holds `expected`; see `Database::delete_if`
+    pub fn delete_if(&self, key: &str, expected: &[u8]) -> Result<bool> {
+        self.db.apply_delete_if(Some(&self.name), key, expected)
+    }
+
+    /// Get a value that must exist; see `Database::get_required`
+    pub fn get_required(&self, key: &str) -> Result<Vec<u8>> {
+        self.get(key)?
+            .ok_or_else(|| LohError::NotFound(format!("key '{}' in namespace '{}'", key, self.name)))
+    }
+
+    /// Store `value` at `key` in the configured format; see `Database::set_typed`
+    pub fn set_typed<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let bytes = self.db.serialization_format().serialize(value)?;
+        self.db.apply_set(Some(&self.name), key.to_string(), bytes)
+    }
+
+    /// The value of `key` decoded as a `T`; see `Database::get_typed`
+    pub fn get_typed<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.db.apply_get(Some(&self.name), key)?
+            .map(|bytes| typed::decode(self.db.serialization_format(), key, &bytes))
+            .transpose()
+    }
+
+    /// Set `key` only if its current version is `expected_version`
+    pub fn put_if_version(&self, key: String, value: Vec<u8>, expected_version: u64) -> Result<VersionedPut> {
+        self.db.apply_versioned_set(Some(&self.name), key, value, Some(expected_version), None)
+    }
+
+    /// Get a value together with its size, version, and last-modified time
+    pub fn get_with_metadata(&self, key: &str) -> Result<Option<ValueMeta>> {
+        self.db.apply_get_with_metadata(Some(&self.name), key)
+    }
+
+    /// Set `key` together with string attributes; see `Database::set_with_meta`
+    pub fn set_with_meta(&self, key: String, value: Vec<u8>, meta: HashMap<String, String>) -> Result<()> {
+        self.db.apply_set_with_meta(Some(&self.name), key, value, meta)
+    }
+
+    /// The attributes stored with `key`; see `Database::get_meta`
+    pub fn get_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        self.db.apply_get_meta(Some(&self.name), key)
+    }
+
+    /// Get `key`, or store and return what `f` computes if it is absent;
+    /// see `Database::get_or_insert_with`
+    pub fn get_or_insert_with<F>(&self, key: &str, f: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        self.db.apply_get_or_insert_with(Some(&self.name), key, f)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<bool> {
+        self.db.apply_delete(Some(&self.name), key)
+    }
+
+    /// Move the value at `from` to `to` within this namespace; see
+    /// `Database::rename`
+    pub fn rename(&self, from: &str, to: &str, overwrite: bool) -> Result<bool> {
+        self.db.apply_rename(Some(&self.name), from, to, overwrite)
+    }
+
+    /// Write the value at `key` through `io::Write`; see
+    /// `Database::open_value_writer`
+    pub fn open_value_writer(&self, key: &str) -> Result<ValueWriter<'a>> {
+        ValueWriter::new(self.db, Some(&self.name), key)
+    }
+
+    /// Read the value at `key` through `io::Read`; see
+    /// `Database::open_value_reader`
+    pub fn open_value_reader(&self, key: &str) -> Result<ValueReader> {
+        self.db.apply_open_value_reader(Some(&self.name), key)
+    }
+
+    /// Append `chunk` to the chunked value at `key`; see `Database::append`
+    pub fn append(&self, key: &str, chunk: &[u8]) -> Result<u64> {
+        self.db.apply_append(Some(&self.name), key, chunk)
+    }
+
+    /// The chunks appended to `key`, oldest first
+    pub fn read_chunks(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        let Some(value) = self.get(key)? else {
+            return Ok(Vec::new());
+        };
+        Ok(chunked::split_chunks(&value)?.into_iter().map(<[u8]>::to_vec).collect())
+    }
+
+    /// Delete every key in this namespace starting with `prefix`; an empty
+    /// prefix clears the namespace. See `Database::delete_prefix`.
+    pub fn delete_prefix(&self, prefix: &str) -> Result<usize> {
+        self.db.apply_delete_prefix(Some(&self.name), prefix)
+    }
+
+    /// Number of keys in this namespace starting with `prefix`
+    pub fn count_prefix(&self, prefix: &str) -> Result<usize> {
+        self.db.apply_count_prefix(Some(&self.name), prefix)
+    }
+
+    /// Count the keys in this namespace under each of `prefixes` in one
+    /// pass; see `Database::prefix_stats`
+    pub fn prefix_stats(&self, prefixes: &[String]) -> Result<HashMap<String, usize>> {
+        self.db.apply_prefix_stats(Some(&self.name), prefixes)
+    }
+
+    /// List the keys in this namespace
+    pub fn list_keys(&self) -> Result<Vec<String>> {
+        self.db.namespace_keys(Some(&self.name))
+    }
+
+    /// Delete every key in this namespace, returning how many were removed
+    pub fn clear(&self) -> Result<usize> {
+        let keys = self.list_keys()?;
+        let mut removed = 0;
+        for key in keys {
+            if self.delete(&key)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{LohError, Result};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Mode for new files when encryption is on and no `file_mode` is configured
+pub(crate) const ENCRYPTED_FILE_MODE: u32 = 0o600;
+
+/// Directory mode matching `file_mode`: searchable by whoever may read
+pub(crate) fn dir_mode(file_mode: u32) -> u32 {
+    file_mode | ((file_mode & 0o444) >> 2)
+}
+
+/// `create_dir_all`, giving directories it creates the mode matching
+/// `file_mode` on Unix
+///
+/// Existing directories keep their permissions. Ignored elsewhere.
+pub(crate) fn create_dir_all(path: impl AsRef<Path>, file_mode: Option<u32>) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    if let Some(mode) = file_mode {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(dir_mode(mode));
+    }
+    #[cfg(not(unix))]
+    let _ = file_mode;
+    builder.create(path)
+}
+
+/// `OpenOptions` that create files with `file_mode` on Unix
+///
+/// Like any creation mode, it is narrowed by the process umask and only
+/// applies to files that do not exist yet. Ignored elsewhere.
+pub(crate) fn open_options(file_mode: Option<u32>) -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    if let Some(mode) = file_mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = file_mode;
+    options
+}
+
+/// Create `dir` if needed and make sure files can be written in it
+///
+/// A database that cannot write one of its directories would otherwise fail
+/// later with a bare I/O error from whichever file it touched first; `role`
+/// names the directory in the error. The check writes and removes a small
+/// probe file.
+pub(crate) fn check_writable(dir: impl AsRef<Path>, role: &str, file_mode: Option<u32>) -> Result<()> {
+    let dir = dir.as_ref();
+    let context = |what: &str, e: io::Error| {
+        LohError::Io(io::Error::new(e.kind(), format!("{} {} '{}': {}", what, role, dir.display(), e)))
+    };
+    create_dir_all(dir, file_mode).map_err(|e| context("cannot create", e))?;
+    
+    let probe = dir.join(".write-check");
+    let written = open_options(file_mode)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .and_then(|mut file| file.write_all(b"lohdb"));
+    let removed = fs::remove_file(&probe);
+    written.and(removed).map_err(|e| context("cannot write to", e))
+}
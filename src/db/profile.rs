@@ -0,0 +1,108 @@
+use crate::db::{namespace, ttl, Database};
+use crate::Result;
+use serde::Serialize;
+
+/// Distribution of one size across every key, in bytes
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SizeSummary {
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p99: u64,
+    pub total: u64,
+}
+
+impl SizeSummary {
+    /// Summarise `sizes`, sorting them in place
+    fn of(sizes: &mut [u64]) -> Self {
+        if sizes.is_empty() {
+            return Self::default();
+        }
+        sizes.sort_unstable();
+        let total: u64 = sizes.iter().sum();
+        // Nearest rank: the smallest size at least `q` of the keys are within
+        let percentile = |q: f64| {
+            let rank = ((sizes.len() as f64 * q).ceil() as usize).max(1);
+            sizes[rank - 1]
+        };
+        Self {
+            min: sizes[0],
+            max: sizes[sizes.len() - 1],
+            mean: total as f64 / sizes.len() as f64,
+            p50: percentile(0.50),
+            p99: percentile(0.99),
+            total,
+        }
+    }
+}
+
+/// The key holding the largest value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LargestValue {
+    /// `None` for the default namespace
+    pub namespace: Option<String>,
+    pub key: String,
+    pub bytes: u64,
+}
+
+/// Shape of the data in a database, from `Database::size_profile`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SizeProfile {
+    /// Live keys across all namespaces
+    pub keys: usize,
+    /// Length of each key as written, without its namespace
+    pub key_bytes: SizeSummary,
+    /// Length of each value as `get` returns it, before compression or
+    /// encryption on disk
+    pub value_bytes: SizeSummary,
+    pub largest_value: Option<LargestValue>,
+}
+
+impl Database {
+    /// Min, max, mean, median and p99 of the key lengths and value sizes of
+    /// every live key, across all namespaces
+    ///
+    /// Scans and reads every value under one storage read lock, so it is
+    /// O(n) in the number of keys and holds up writers for as long; meant
+    /// for capacity planning, not for a hot path. Keys past their TTL are
+    /// left out, as `get` would.
+    pub fn size_profile(&self) -> Result<SizeProfile> {
+        let storage = self.storage_handle();
+        let storage = storage.read().unwrap();
+        let mut key_sizes = Vec::new();
+        let mut value_sizes = Vec::new();
+        let mut largest: Option<(String, u64)> = None;
+        for storage_key in storage.list_keys()? {
+            if ttl::is_expired(storage.as_ref(), &storage_key, self.now_ms())? {
+                continue;
+            }
+            let Some(value) = storage.retrieve(&storage_key)? else {
+                continue;
+            };
+            let (_, key) = namespace::split_storage_key(&storage_key);
+            let size = value.len() as u64;
+            key_sizes.push(key.len() as u64);
+            value_sizes.push(size);
+            if largest.as_ref().is_none_or(|&(_, max)| size > max) {
+                largest = Some((storage_key, size));
+            }
+        }
+        drop(storage);
+        
+        let largest_value = largest.map(|(storage_key, bytes)| {
+            let (namespace, key) = namespace::split_storage_key(&storage_key);
+            LargestValue {
+                namespace: namespace.map(str::to_string),
+                key: key.to_string(),
+                bytes,
+            }
+        });
+        Ok(SizeProfile {
+            keys: key_sizes.len(),
+            key_bytes: SizeSummary::of(&mut key_sizes),
+            value_bytes: SizeSummary::of(&mut value_sizes),
+            largest_value,
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use crate::db::{Database, DatabaseConfig, WalEntry};
+use crate::Result;
+use std::fmt;
+use std::sync::Arc;
+
+/// Records replayed between progress reports unless
+/// `DatabaseConfig::recovery_progress_every` says otherwise
+pub const DEFAULT_RECOVERY_PROGRESS_EVERY: u64 = 10_000;
+
+/// Callback receiving WAL replay progress while a database opens
+pub type RecoveryObserver = Arc<dyn Fn(RecoveryProgress) + Send + Sync>;
+
+/// How far `Database::open` has got replaying the WAL
+///
+/// Reported to `DatabaseConfig::on_recovery_progress` every
+/// `recovery_progress_every` records, and once more with `done` set when the
+/// replay ends, even if there was nothing to replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryProgress {
+    /// Records replayed so far
+    pub entries: u64,
+    /// Bytes of WAL read so far, file headers included
+    pub bytes_read: u64,
+    /// Size of every WAL segment combined
+    pub total_bytes: u64,
+    /// Set on the last report, once every record has been replayed
+    pub done: bool,
+}
+
+/// How far to replay the WAL in `Database::open_at`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoverTo {
+    /// Include records appended at or before this time, in milliseconds since the Unix epoch
+    Timestamp(u64),
+    /// Include records with a sequence number at or below this one
+    Sequence(u64),
+}
+
+impl RecoverTo {
+    /// Whether `entry` falls at or before the recovery point
+    pub(crate) fn includes(&self, entry: &WalEntry) -> bool {
+        match *self {
+            RecoverTo::Timestamp(t) => entry.timestamp_ms <= t,
+            RecoverTo::Sequence(n) => entry.seq <= n,
+        }
+    }
+}
+
+impl fmt::Display for RecoverTo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoverTo::Timestamp(t) => write!(f, "timestamp {}ms", t),
+            RecoverTo::Sequence(n) => write!(f, "sequence {}", n),
+        }
+    }
+}
+
+impl Database {
+    /// Open the database as it was at an earlier point in its WAL
+    ///
+    /// Records are replayed in order until the first one past `target`, and
+    /// the result is opened read-only regardless of `config.read_only`; use
+    /// `export_json` to fork it into a new directory (`backup_to` copies the
+    /// files on disk, not the recovered state). Fails
+    /// once the WAL has been truncated and `target` does not cover every
+    /// remaining record, since the data file then holds a newer state.
+    pub fn open_at(config: DatabaseConfig, target: RecoverTo) -> Result<Self> {
+        Self::open_with(DatabaseConfig { read_only: true, ..config }, Some(target))
+    }
+}
@@ -0,0 +1,320 @@
+use crate::db::meta::KeyMeta;
+use crate::db::{CompactStats, SnapshotEntries, StorageEngine};
+use crate::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Bound, Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Storage split across independent engines by a hash of each key
+///
+/// Every key lives in exactly one shard, picked by `shard_for`, and reads
+/// and writes of a key go to that shard alone. Each shard has its own
+/// lock, which `Database` takes for single-key reads and writes instead of
+/// locking the whole store, so writes to keys in different shards apply
+/// to storage at the same time; only appending to the WAL stays serial.
+/// A flush, and so every checkpoint, only flushes the shards written
+/// since the last one, so with file engines it rewrites those shards'
+/// files instead of the whole data set. Listing and scanning merge every
+/// shard, and `snapshot` copies the key maps of all of them instead of
+/// sharing one copy-on-write.
+///
+/// The hash is fixed, so shards written by one build are read back by the
+/// next, but changing the number of shards moves most keys: reopen a
+/// sharded directory with the count it was created with. `Database::open`
+/// builds one of these over file engines when `DatabaseConfig::shards` is
+/// more than 1.
+pub struct ShardedStorageEngine {
+    shards: Arc<Shards>,
+}
+
+/// The shards of a `ShardedStorageEngine`, each behind its own lock
+///
+/// Shared with the `Database` that owns the engine, which holds its lock
+/// on the whole engine for reading while it holds one of these, so
+/// anything that locks the whole engine for writing has every shard to
+/// itself.
+pub struct Shards {
+    engines: Vec<RwLock<Box<dyn StorageEngine>>>,
+    /// Shards written since they were last flushed
+    dirty: Vec<AtomicBool>,
+}
+
+impl Shards {
+    /// Number of shards
+    pub fn count(&self) -> usize {
+        self.engines.len()
+    }
+    
+    /// Index of the shard holding `key`
+    pub fn shard_for(&self, key: &str) -> usize {
+        shard_index(key, self.engines.len())
+    }
+    
+    /// Lock the shard holding `key` for reading
+    pub fn read(&self, key: &str) -> RwLockReadGuard<'_, Box<dyn StorageEngine>> {
+        self.engines[self.shard_for(key)].read().unwrap()
+    }
+    
+    /// Lock the shard holding `key` for writing, marking it as written
+    pub fn write(&self, key: &str) -> RwLockWriteGuard<'_, Box<dyn StorageEngine>> {
+        let index = self.shard_for(key);
+        let engine = self.engines[index].write().unwrap();
+        self.dirty[index].store(true, Ordering::Relaxed);
+        engine
+    }
+}
+
+/// The part of storage that holds one key: its shard, locked, along with a
+/// read lock on the whole engine, or else the whole engine, locked
+///
+/// Fields drop in order, so the shard is released before the engine.
+pub(crate) struct KeyStorage<'a, G> {
+    engine: G,
+    _storage: Option<RwLockReadGuard<'a, Box<dyn StorageEngine>>>,
+}
+
+/// `KeyStorage` for reading a key
+pub(crate) type KeyStorageRead<'a> = KeyStorage<'a, RwLockReadGuard<'a, Box<dyn StorageEngine>>>;
+
+/// `KeyStorage` for writing a key
+pub(crate) type KeyStorageWrite<'a> = KeyStorage<'a, RwLockWriteGuard<'a, Box<dyn StorageEngine>>>;
+
+impl<'a> KeyStorageRead<'a> {
+    /// Lock the storage holding `key` for reading
+    pub(crate) fn read(storage: &'a RwLock<Box<dyn StorageEngine>>, shards: Option<&'a Shards>, key: &str) -> Self {
+        let storage = storage.read().unwrap();
+        match shards {
+            Some(shards) => Self { engine: shards.read(key), _storage: Some(storage) },
+            None => Self { engine: storage, _storage: None },
+        }
+    }
+}
+
+impl<'a> KeyStorageWrite<'a> {
+    /// Lock the storage holding `key` for writing: just its shard if
+    /// `shards` is given, or else the whole engine
+    pub(crate) fn write(storage: &'a RwLock<Box<dyn StorageEngine>>, shards: Option<&'a Shards>, key: &str) -> Self {
+        match shards {
+            Some(shards) => {
+                let storage = storage.read().unwrap();
+                Self { engine: shards.write(key), _storage: Some(storage) }
+            }
+            None => Self { engine: storage.write().unwrap(), _storage: None },
+        }
+    }
+}
+
+impl<G: Deref<Target = Box<dyn StorageEngine>>> Deref for KeyStorage<'_, G> {
+    type Target = dyn StorageEngine;
+    
+    fn deref(&self) -> &Self::Target {
+        self.engine.as_ref()
+    }
+}
+
+impl<G: DerefMut<Target = Box<dyn StorageEngine>>> DerefMut for KeyStorage<'_, G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.engine.as_mut()
+    }
+}
+
+impl ShardedStorageEngine {
+    /// Spread keys across `shards`; panics if there are none
+    pub fn new(shards: Vec<Box<dyn StorageEngine>>) -> Self {
+        assert!(!shards.is_empty(), "a sharded storage engine needs at least one shard");
+        let dirty = shards.iter().map(|_| AtomicBool::new(false)).collect();
+        let engines = shards.into_iter().map(RwLock::new).collect();
+        Self { shards: Arc::new(Shards { engines, dirty }) }
+    }
+    
+    /// Number of shards
+    pub fn shard_count(&self) -> usize {
+        self.shards.count()
+    }
+    
+    /// Index of the shard holding `key`
+    pub fn shard_for(&self, key: &str) -> usize {
+        self.shards.shard_for(key)
+    }
+    
+    /// Every shard, locked for reading, in index order
+    fn each(&self) -> impl Iterator<Item = RwLockReadGuard<'_, Box<dyn StorageEngine>>> {
+        self.shards.engines.iter().map(|shard| shard.read().unwrap())
+    }
+    
+    /// Every shard, locked for writing, in index order
+    fn each_mut(&self) -> impl Iterator<Item = RwLockWriteGuard<'_, Box<dyn StorageEngine>>> {
+        self.shards.engines.iter().map(|shard| shard.write().unwrap())
+    }
+    
+    /// Keys of every shard, sorted
+    fn merged(&self, keys_of: impl Fn(&dyn StorageEngine) -> Result<Vec<String>>) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for shard in self.each() {
+            keys.extend(keys_of(shard.as_ref())?);
+        }
+        keys.sort_unstable();
+        Ok(keys)
+    }
+    
+    fn clear_dirty(&self) {
+        self.shards.dirty.iter().for_each(|dirty| dirty.store(false, Ordering::Relaxed));
+    }
+}
+
+/// Shard of `key` among `shards`, by 64-bit FNV-1a
+pub(crate) fn shard_index(key: &str, shards: usize) -> usize {
+    (fnv1a(key.as_bytes()) % shards as u64) as usize
+}
+
+/// 64-bit FNV-1a hash of `bytes`
+///
+/// Spelled out rather than taken from `std::hash`, whose output may change
+/// between Rust releases, since shard files and blob ids outlive the build
+/// that wrote them.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Data files of a database split into `shards`: `data_file` itself for one
+/// shard, or else `data.0.db`, `data.1.db`, ... beside it
+pub(crate) fn shard_files(data_file: &Path, shards: usize) -> Vec<PathBuf> {
+    if shards <= 1 {
+        return vec![data_file.to_path_buf()];
+    }
+    let stem = data_file.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = data_file.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (0..shards)
+        .map(|index| data_file.with_file_name(format!("{}.{}{}", stem, index, extension)))
+        .collect()
+}
+
+impl StorageEngine for ShardedStorageEngine {
+    fn initialize(&mut self) -> Result<()> {
+        for mut shard in self.each_mut() {
+            shard.initialize()?;
+        }
+        self.clear_dirty();
+        Ok(())
+    }
+    
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.shards.write(key).store(key, value)
+    }
+    
+    fn store_at(&mut self, key: &str, value: &[u8], modified_ms: u64) -> Result<()> {
+        self.shards.write(key).store_at(key, value, modified_ms)
+    }
+    
+    fn store_shared(&mut self, key: &str, value: Arc<[u8]>, modified_ms: u64) -> Result<()> {
+        self.shards.write(key).store_shared(key, value, modified_ms)
+    }
+    
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.shards.read(key).retrieve(key)
+    }
+    
+    fn retrieve_shared(&self, key: &str) -> Result<Option<Arc<[u8]>>> {
+        self.shards.read(key).retrieve_shared(key)
+    }
+    
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        self.shards.write(key).remove(key)
+    }
+    
+    fn metadata(&self, key: &str) -> Result<Option<KeyMeta>> {
+        self.shards.read(key).metadata(key)
+    }
+    
+    fn supports_expiry(&self) -> bool {
+        self.each().all(|shard| shard.supports_expiry())
+    }
+    
+    fn set_expiry(&mut self, key: &str, expires_ms: Option<u64>) -> Result<bool> {
+        self.shards.write(key).set_expiry(key, expires_ms)
+    }
+    
+    fn expired_keys(&self, now_ms: u64) -> Result<Vec<String>> {
+        self.merged(|shard| shard.expired_keys(now_ms))
+    }
+    
+    fn supports_user_meta(&self) -> bool {
+        self.each().all(|shard| shard.supports_user_meta())
+    }
+    
+    fn set_user_meta(&mut self, key: &str, meta: Option<HashMap<String, String>>) -> Result<bool> {
+        self.shards.write(key).set_user_meta(key, meta)
+    }
+    
+    fn user_meta(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        self.shards.read(key).user_meta(key)
+    }
+    
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.merged(|shard| shard.list_keys())
+    }
+    
+    fn key_count(&self) -> Result<usize> {
+        self.each().map(|shard| shard.key_count()).sum()
+    }
+    
+    fn scan_keys(&self, start: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        // The first `limit` keys overall are among each shard's first `limit`
+        let mut keys = self.merged(|shard| shard.scan_keys(start, limit))?;
+        keys.truncate(limit);
+        Ok(keys)
+    }
+    
+    fn scan_keys_rev(&self, end: Bound<&str>, limit: usize) -> Result<Vec<String>> {
+        let mut keys = self.merged(|shard| shard.scan_keys_rev(end, limit))?;
+        keys.reverse();
+        keys.truncate(limit);
+        Ok(keys)
+    }
+    
+    fn flush(&mut self) -> Result<()> {
+        for (mut shard, dirty) in self.each_mut().zip(&self.shards.dirty) {
+            if dirty.load(Ordering::Relaxed) {
+                shard.flush()?;
+                dirty.store(false, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+    
+    fn disk_usage(&self) -> Result<u64> {
+        self.each().map(|shard| shard.disk_usage()).sum()
+    }
+    
+    fn compact(&mut self) -> Result<CompactStats> {
+        let mut stats = CompactStats::default();
+        for mut shard in self.each_mut() {
+            let shard_stats = shard.compact()?;
+            stats.bytes_before += shard_stats.bytes_before;
+            stats.bytes_after += shard_stats.bytes_after;
+        }
+        self.clear_dirty();
+        Ok(stats)
+    }
+    
+    fn snapshot(&self) -> Result<SnapshotEntries> {
+        let mut entries = BTreeMap::new();
+        for shard in self.each() {
+            let shard_entries = shard.snapshot()?;
+            entries.extend(shard_entries.iter().map(|(key, value)| (key.clone(), value.clone())));
+        }
+        Ok(Arc::new(entries))
+    }
+    
+    fn take_evicted(&mut self) -> Vec<String> {
+        self.each_mut().flat_map(|mut shard| shard.take_evicted()).collect()
+    }
+    
+    fn shards(&self) -> Option<Arc<Shards>> {
+        Some(self.shards.clone())
+    }
+}
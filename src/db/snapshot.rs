@@ -0,0 +1,65 @@
+use crate::db::engine::SnapshotEntries;
+use crate::db::{namespace, Database};
+use crate::Result;
+use std::ops::Bound;
+
+/// Immutable point-in-time view of the default namespace
+///
+/// A snapshot holds no lock: writers carry on while it is read, and it never
+/// sees their changes. It is `Send + Sync`, so it can be shared between
+/// threads behind an `Arc`.
+///
+/// Memory cost: taking a snapshot of the in-memory or file engine only
+/// clones a pointer. The first write after that copies the engine's key map
+/// (every key plus one pointer per value; values themselves are shared), and
+/// every value the snapshot can see stays alive until it is dropped, even
+/// once overwritten or deleted. Engines without copy-on-write support, such
+/// as the mmap engine, copy every key and value when the snapshot is taken.
+/// Drop snapshots promptly rather than keeping one around for long.
+pub struct Snapshot {
+    entries: SnapshotEntries,
+    seq: u64,
+}
+
+impl Snapshot {
+    /// WAL sequence number of the last write the snapshot includes
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    
+    /// Value of `key` at the time the snapshot was taken
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        if namespace::is_namespaced(key) {
+            return None;
+        }
+        self.entries.get(key).map(|v| &v[..])
+    }
+    
+    /// Entries whose key starts with `prefix`, in ascending key order
+    pub fn scan<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        self.entries
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(k, _)| k.starts_with(prefix))
+            .filter(|(k, _)| !namespace::is_namespaced(k))
+            .map(|(k, v)| (k.as_str(), &v[..]))
+    }
+    
+    /// Every entry, in ascending key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> + '_ {
+        self.scan("")
+    }
+}
+
+impl Database {
+    /// Capture a point-in-time view of the default namespace
+    ///
+    /// See `Snapshot` for what holding one costs.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        self.with_maintenance(|guard| {
+            Ok(Snapshot {
+                entries: guard.entries()?,
+                seq: guard.seq(),
+            })
+        })
+    }
+}
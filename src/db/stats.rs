@@ -0,0 +1,105 @@
+use crate::db::{DedupStats, ReplayReport};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+/// Point-in-time statistics for a `Database`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DbStats {
+    /// Number of keys across all namespaces
+    pub num_keys: usize,
+    /// Size of the data snapshot on disk
+    pub data_file_bytes: u64,
+    /// Distinct values and the keys sharing them, for a `dedup` database
+    pub dedup: Option<DedupStats>,
+    /// Size of the write-ahead log on disk
+    pub wal_file_bytes: u64,
+    /// Sequence number of the most recent WAL record (0 if none yet)
+    pub wal_sequence: u64,
+    /// Records in the write-ahead log: those written since the last checkpoint
+    pub wal_entries: u64,
+    /// WAL records not yet fsynced; see `Database::unsynced_count`
+    pub unsynced_count: u64,
+    /// WAL fsyncs issued by group commit (0 when it is disabled)
+    pub wal_syncs: u64,
+    /// `set` calls since the database was opened
+    pub sets: u64,
+    /// `get` calls since the database was opened
+    pub gets: u64,
+    /// `delete` calls since the database was opened
+    pub deletes: u64,
+    /// Live subscriptions, as of the last published event
+    pub subscribers: usize,
+    /// Events discarded because a subscriber's buffer was full
+    pub dropped_events: u64,
+    /// Keys deleted because their TTL ran out, since the database was opened
+    pub expired_keys: u64,
+    /// Per-subscription backlog and losses, to spot a lagging subscriber
+    pub subscriber_stats: Vec<SubscriberStats>,
+    /// Why the latest background checkpoint failed, until one succeeds
+    pub last_flush_error: Option<String>,
+    /// WAL records recovered when the database was opened, and any that
+    /// could not be, which are missing from the data
+    pub replay: ReplayReport,
+}
+
+/// State of a database's background checkpoints, from `Database::sync_health`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncHealth {
+    /// Whether the database is registered for background checkpoints at
+    /// all; `false` for in-memory and read-only databases and those with
+    /// no checkpoint trigger configured
+    pub scheduled: bool,
+    /// Whether a live scheduler thread still holds this database's job
+    pub thread_alive: bool,
+    /// Unix ms the thread last checkpointed or checked this database
+    /// (registration time until then)
+    pub last_heartbeat_ms: u64,
+    /// Whether the heartbeat is more than twice the checkpoint interval
+    /// (or ratio check) plus a second old
+    pub overdue: bool,
+    /// Checkpoints that panicked since open; each also sets `last_error`
+    pub panics: u64,
+    /// Why the latest checkpoint failed, until one succeeds
+    pub last_error: Option<String>,
+}
+
+impl SyncHealth {
+    /// Whether checkpoints are running and succeeding, or not scheduled
+    pub fn is_healthy(&self) -> bool {
+        !self.scheduled || (self.thread_alive && !self.overdue && self.last_error.is_none())
+    }
+}
+
+/// Statistics for one change subscription
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriberStats {
+    /// Matches `SubscriptionHandle::id`
+    pub id: Uuid,
+    pub prefix: String,
+    /// Events sent but not yet received
+    pub queued: usize,
+    /// Events this subscriber missed because its buffer was full
+    pub dropped_events: u64,
+    /// Events whose callback panicked (always 0 for channel subscriptions);
+    /// the subscription keeps receiving later events
+    pub callback_panics: u64,
+}
+
+/// Operation counters updated lock-free on the hot path
+#[derive(Default)]
+pub(crate) struct OpCounters {
+    pub sets: AtomicU64,
+    pub gets: AtomicU64,
+    pub deletes: AtomicU64,
+}
+
+impl OpCounters {
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    pub fn load(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,336 @@
+use crate::db::{namespace, perms, Database, Encryptor, Operation};
+use crate::{LohError, Result};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Name of the files in the data directory where `ValueWriter`s stage
+/// chunks: `stream-<uuid>.tmp`
+const STAGING_PREFIX: &str = "stream-";
+const STAGING_SUFFIX: &str = ".tmp";
+
+/// A value written to one key a piece at a time; see
+/// `Database::open_value_writer`
+///
+/// Every `stream_chunk_bytes` written are logged to the WAL as one record,
+/// so no record grows with the value, and nothing is visible until `finish`
+/// logs the commit and stores the whole value at once. Dropping the writer
+/// without calling `finish`, or a crash before it returns, discards what
+/// was written; readers keep seeing the old value throughout.
+///
+/// The writer holds at most one chunk: each logged chunk is also staged in
+/// a file in the data directory (encrypted if the database is), which
+/// `finish` reads back into the buffer storage then keeps, and removes.
+/// An in-memory database has no disk to stage on, so there the writer
+/// keeps the chunks in memory until `finish`.
+pub struct ValueWriter<'a> {
+    db: &'a Database,
+    namespace: Option<String>,
+    key: String,
+    chunk_bytes: usize,
+    /// Bytes written but not yet logged, fewer than `chunk_bytes`
+    pending: Vec<u8>,
+    /// Chunks logged so far
+    staged: Staged,
+    /// Bytes written so far, logged or not
+    len: u64,
+    /// Sequence number of the first chunk, once one is logged
+    stream: Option<u64>,
+    chunks: u64,
+}
+
+impl<'a> ValueWriter<'a> {
+    pub(crate) fn new(db: &'a Database, ns: Option<&str>, key: &str) -> Result<Self> {
+        db.ensure_writable()?;
+        if ns.is_none() {
+            namespace::validate_key(key)?;
+        }
+        Ok(Self {
+            db,
+            namespace: ns.map(str::to_string),
+            key: key.to_string(),
+            chunk_bytes: db.stream_chunk_bytes(),
+            pending: Vec::new(),
+            staged: Staged::new(db)?,
+            len: 0,
+            stream: None,
+            chunks: 0,
+        })
+    }
+    
+    /// Bytes written so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    /// Log whatever is still pending and commit the value, making it
+    /// visible to readers; returns its length
+    ///
+    /// Durable as any other write once this returns. Fails without storing
+    /// anything if the value breaks `max_value_bytes`.
+    pub fn finish(mut self) -> Result<u64> {
+        self.log_pending()?;
+        let value = self.staged.value(self.len)?;
+        self.db.apply_stream_commit(self.namespace.as_deref(), &self.key, self.stream, self.chunks, value)
+    }
+    
+    /// Log the pending bytes as the next chunk, and stage them
+    fn log_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let stream = self.db.log_stream_chunk(self.namespace.as_deref(), &self.key, self.stream, &self.pending)?;
+        self.stream = Some(stream);
+        self.chunks += 1;
+        self.staged.push(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Write for ValueWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Caught here rather than at `finish`, before the rest is streamed
+        self.db.check_value_limit(self.len as usize + buf.len()).map_err(io::Error::other)?;
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let taken = rest.len().min(self.chunk_bytes - self.pending.len());
+            self.pending.extend_from_slice(&rest[..taken]);
+            self.len += taken as u64;
+            rest = &rest[taken..];
+            if self.pending.len() == self.chunk_bytes {
+                self.log_pending().map_err(io::Error::other)?;
+            }
+        }
+        Ok(buf.len())
+    }
+    
+    /// Log the bytes written since the last full chunk, as a short chunk
+    ///
+    /// This only moves them into the WAL; the value stays invisible until
+    /// `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.log_pending().map_err(io::Error::other)
+    }
+}
+
+/// Where a `ValueWriter` keeps the chunks it has logged until `finish`
+///
+/// The WAL has them too, but a checkpoint may release them before the
+/// commit, so they cannot be read back from there.
+enum Staged {
+    /// A file of its own in the data directory, removed when dropped: per
+    /// chunk, its length as a little-endian `u32`, then its bytes, each
+    /// chunk encrypted on its own if `encryptor` is set
+    File {
+        path: PathBuf,
+        file: BufWriter<File>,
+        encryptor: Option<Arc<dyn Encryptor>>,
+    },
+    Memory(Vec<u8>),
+}
+
+impl Staged {
+    fn new(db: &Database) -> Result<Self> {
+        if db.is_in_memory() {
+            return Ok(Self::Memory(Vec::new()));
+        }
+        let name = format!("{}{}{}", STAGING_PREFIX, Uuid::new_v4(), STAGING_SUFFIX);
+        let path = Path::new(db.data_dir()).join(name);
+        let file = perms::open_options(db.file_mode()).read(true).write(true).create_new(true).open(&path)?;
+        Ok(Self::File { path, file: BufWriter::new(file), encryptor: db.encryptor().cloned() })
+    }
+    
+    fn push(&mut self, chunk: &[u8]) -> Result<()> {
+        match self {
+            Self::File { file, encryptor, .. } => {
+                let sealed;
+                let bytes = match encryptor {
+                    Some(encryptor) => {
+                        sealed = encryptor.encrypt(chunk)?;
+                        &sealed
+                    }
+                    None => chunk,
+                };
+                file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+                file.write_all(bytes)?;
+            }
+            Self::Memory(value) => value.extend_from_slice(chunk),
+        }
+        Ok(())
+    }
+    
+    /// The `len` bytes staged, concatenated into the one buffer storage
+    /// will keep
+    fn value(&mut self, len: u64) -> Result<Arc<[u8]>> {
+        let (file, encryptor) = match self {
+            Self::File { file, encryptor, .. } => (file, encryptor),
+            Self::Memory(value) => return Ok(Arc::from(std::mem::take(value))),
+        };
+        file.flush()?;
+        let mut reader = BufReader::new(file.get_mut());
+        reader.seek(SeekFrom::Start(0))?;
+        
+        // Allocated once, at its final size, and filled in place
+        let mut value: Arc<[u8]> = iter::repeat_n(0, len as usize).collect();
+        let buf = Arc::get_mut(&mut value).expect("not shared yet");
+        let mut filled = 0;
+        let mut header = [0u8; 4];
+        while filled < buf.len() {
+            reader.read_exact(&mut header).map_err(|e| staging_corrupt(&e.to_string()))?;
+            let chunk_len = u32::from_le_bytes(header) as usize;
+            match encryptor {
+                Some(encryptor) => {
+                    let mut sealed = vec![0; chunk_len];
+                    reader.read_exact(&mut sealed)?;
+                    let chunk = encryptor.decrypt(&sealed)?;
+                    let target = buf.get_mut(filled..filled + chunk.len()).ok_or_else(|| staging_corrupt("too long"))?;
+                    target.copy_from_slice(&chunk);
+                    filled += chunk.len();
+                }
+                None => {
+                    let target = buf.get_mut(filled..filled + chunk_len).ok_or_else(|| staging_corrupt("too long"))?;
+                    reader.read_exact(target)?;
+                    filled += chunk_len;
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        if let Self::File { path, .. } = self {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("cannot remove the staged stream '{}': {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn staging_corrupt(reason: &str) -> LohError {
+    LohError::DataCorrupt(format!("staged chunks of a streamed value do not add up to it: {}", reason))
+}
+
+/// Remove the chunks staged in `data_dir` by writers that never finished,
+/// such as those cut off by a crash
+pub(crate) fn remove_staged(data_dir: &str) -> Result<()> {
+    for entry in fs::read_dir(data_dir)? {
+        let path = entry?.path();
+        let staged = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(STAGING_PREFIX) && name.ends_with(STAGING_SUFFIX));
+        if staged {
+            log::debug!("removing '{}', left by an unfinished value stream", path.display());
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// A value read a piece at a time; see `Database::open_value_reader`
+///
+/// Shares the stored value with the storage engine instead of copying it
+/// (the built-in in-memory and file engines keep values as `Arc<[u8]>`;
+/// others hand over a copy), and holds it as it was when opened, so later
+/// writes to the key do not show through.
+pub struct ValueReader {
+    value: Arc<[u8]>,
+    /// Bytes read so far
+    position: usize,
+}
+
+impl ValueReader {
+    /// Total length of the value in bytes
+    pub fn len(&self) -> u64 {
+        self.value.len() as u64
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+    
+    /// Bytes not yet read
+    pub fn remaining(&self) -> u64 {
+        (self.value.len() - self.position) as u64
+    }
+}
+
+impl Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rest = &self.value[self.position..];
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Chunks of streamed values seen during a pass over the WAL, held until
+/// their commit
+#[derive(Default)]
+pub(crate) struct StagedStreams {
+    streams: HashMap<u64, Vec<Vec<u8>>>,
+}
+
+impl StagedStreams {
+    /// What `operation` amounts to once streams are accounted for: a chunk
+    /// is held back, a commit becomes a `Set` of the whole value, and
+    /// anything else passes through
+    ///
+    /// A commit missing some of its chunks was checkpointed into the data
+    /// file after a checkpoint released them (see
+    /// `Database::apply_stream_commit`), so it is dropped. Chunks never
+    /// committed stay staged and are never applied.
+    pub(crate) fn resolve(&mut self, operation: Operation) -> Option<Operation> {
+        match operation {
+            Operation::StreamChunk { stream, chunk, .. } => {
+                self.streams.entry(stream).or_default().push(chunk);
+                None
+            }
+            Operation::StreamCommit { namespace, key, stream, chunks } => {
+                let staged = self.streams.remove(&stream).unwrap_or_default();
+                (staged.len() as u64 == chunks).then(|| Operation::Set { namespace, key, value: staged.concat() })
+            }
+            operation => Some(operation),
+        }
+    }
+}
+
+impl Database {
+    /// Write the value at `key` through `io::Write`, for values too large
+    /// to build in one buffer or to log as one WAL record
+    ///
+    /// Written bytes are logged in `stream_chunk_bytes` pieces as they come;
+    /// `ValueWriter::finish` commits them as the new value. Until then
+    /// readers see the old value, and a crash or a dropped writer leaves
+    /// it in place. `max_value_bytes` applies to the whole value, and is
+    /// checked on every write.
+    pub fn open_value_writer(&mut self, key: &str) -> Result<ValueWriter<'_>> {
+        ValueWriter::new(self, None, key)
+    }
+    
+    /// Read the value at `key` through `io::Read`; fails with
+    /// `LohError::NotFound` if there is none
+    pub fn open_value_reader(&self, key: &str) -> Result<ValueReader> {
+        self.apply_open_value_reader(None, key)
+    }
+    
+    pub(crate) fn apply_open_value_reader(&self, ns: Option<&str>, key: &str) -> Result<ValueReader> {
+        let value = self.apply_get_shared(ns, key)?.ok_or_else(|| match ns {
+            Some(ns) => LohError::NotFound(format!("key '{}' in namespace '{}'", key, ns)),
+            None => LohError::NotFound(format!("key '{}'", key)),
+        })?;
+        Ok(ValueReader { value, position: 0 })
+    }
+}
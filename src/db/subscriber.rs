@@ -1,14 +1,158 @@
-use crate::Result;
-use crossbeam::channel::{self, Receiver, Sender};
+use crate::db::history::LoggedChange;
+use crate::db::namespace;
+use crate::db::stats::SubscriberStats;
+use crate::{LohError, Result};
+use crossbeam::channel::{self, Receiver, SendTimeoutError, Sender, TryRecvError, TrySendError};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::ops::BitOr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ChangeEvent {
-    Set { key: String, value: Vec<u8> },
-    Delete { key: String },
+    Set {
+        namespace: Option<String>,
+        key: String,
+        value: Vec<u8>,
+        /// Version of the key after this set (0 if the storage engine does
+        /// not track versions)
+        #[serde(default)]
+        version: u64,
+    },
+    Delete { namespace: Option<String>, key: String },
+    /// `chunk` was appended to a chunked value; see `Database::append`
+    Append {
+        namespace: Option<String>,
+        key: String,
+        chunk: Vec<u8>,
+        /// Version of the key after this append
+        version: u64,
+    },
+    /// A cache-mode database dropped the key to stay within its limits. This
+    /// is not logged to the WAL.
+    Evicted { namespace: Option<String>, key: String },
+    /// The expiry sweep removed the key once its TTL passed. Logged to the
+    /// WAL like a delete, so replicas following the log expire it too.
+    Expired { namespace: Option<String>, key: String },
+}
+
+impl ChangeEvent {
+    /// Key the event refers to, within its namespace
+    pub fn key(&self) -> &str {
+        match self {
+            ChangeEvent::Set { key, .. }
+            | ChangeEvent::Delete { key, .. }
+            | ChangeEvent::Append { key, .. }
+            | ChangeEvent::Evicted { key, .. }
+            | ChangeEvent::Expired { key, .. } => key,
+        }
+    }
+    
+    /// Namespace of the key, or `None` for the default namespace
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            ChangeEvent::Set { namespace, .. }
+            | ChangeEvent::Delete { namespace, .. }
+            | ChangeEvent::Append { namespace, .. }
+            | ChangeEvent::Evicted { namespace, .. }
+            | ChangeEvent::Expired { namespace, .. } => namespace.as_deref(),
+        }
+    }
+}
+
+/// Which kinds of `ChangeEvent` a subscription receives, for
+/// `Database::subscribe_filtered`
+///
+/// Kinds combine with `|`: `EventFilter::SET | EventFilter::APPEND` takes
+/// both. Like a key prefix, the filter is checked before an event is sent,
+/// so a subscriber is never woken for a kind it did not ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventFilter(u8);
+
+impl EventFilter {
+    /// `ChangeEvent::Set`
+    pub const SET: EventFilter = EventFilter(1);
+    /// `ChangeEvent::Delete`
+    pub const DELETE: EventFilter = EventFilter(1 << 1);
+    /// `ChangeEvent::Append`
+    pub const APPEND: EventFilter = EventFilter(1 << 2);
+    /// `ChangeEvent::Evicted`
+    pub const EVICTED: EventFilter = EventFilter(1 << 3);
+    /// `ChangeEvent::Expired`
+    pub const EXPIRED: EventFilter = EventFilter(1 << 4);
+    /// Every way a key can go away: deletes, evictions and expiries, as a
+    /// cache invalidator wants
+    pub const REMOVALS: EventFilter = EventFilter(Self::DELETE.0 | Self::EVICTED.0 | Self::EXPIRED.0);
+    /// Every kind, as unfiltered subscriptions get
+    pub const ALL: EventFilter = EventFilter(Self::SET.0 | Self::APPEND.0 | Self::REMOVALS.0);
+    
+    /// Whether `event` is of a kind this filter lets through
+    pub fn matches(self, event: &ChangeEvent) -> bool {
+        let kind = match event {
+            ChangeEvent::Set { .. } => Self::SET,
+            ChangeEvent::Delete { .. } => Self::DELETE,
+            ChangeEvent::Append { .. } => Self::APPEND,
+            ChangeEvent::Evicted { .. } => Self::EVICTED,
+            ChangeEvent::Expired { .. } => Self::EXPIRED,
+        };
+        self.contains(kind)
+    }
+    
+    /// Whether every kind in `other` is also in this filter
+    pub fn contains(self, other: EventFilter) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for EventFilter {
+    type Output = EventFilter;
+    
+    fn bitor(self, other: EventFilter) -> EventFilter {
+        EventFilter(self.0 | other.0)
+    }
+}
+
+/// Durability work done in the background, delivered by `Database::subscribe_lifecycle`
+///
+/// Kept apart from `ChangeEvent` so data subscribers never see them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LifecycleEvent {
+    /// A background checkpoint flushed the storage engine and released
+    /// the WAL segments it covers
+    Flushed {
+        /// WAL records logged since the previous background flush
+        wal_entries: u64,
+        /// Size of the data file after the flush
+        bytes: u64,
+        /// Time spent flushing, including waiting for the storage lock
+        duration: Duration,
+    },
+    /// `Database::checkpoint` flushed storage and released covered WAL segments
+    Checkpointed {
+        /// Every record up to this sequence number is now in the data file
+        through_seq: u64,
+        segments_released: usize,
+    },
+    /// A background checkpoint failed, for example because the disk is full
+    /// or the data directory stopped being writable
+    ///
+    /// Nothing is lost: the WAL still holds every record, and the checkpoint
+    /// is retried at the next interval.
+    FlushFailed {
+        error: String,
+    },
 }
 
 pub type Subscriber = Arc<dyn Fn(ChangeEvent) + Send + Sync>;
@@ -24,27 +168,268 @@ impl SubscriptionHandle {
     }
 }
 
+/// What `publish` does when a subscriber's buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discard the event being published (the default)
+    #[default]
+    DropNewest,
+    /// Discard the oldest buffered event to make room for the new one.
+    /// The bus keeps a handle on each buffer, so a channel subscription
+    /// then ends only when its `SubscriptionHandle` is dropped.
+    DropOldest,
+    /// Wait until the subscriber makes room, slowing writers down. One that
+    /// makes no room within the bus's block timeout (`DEFAULT_BLOCK_TIMEOUT`
+    /// unless set) is disconnected instead of stalling writers for good:
+    /// its channel ends once drained, and the event counts as dropped.
+    Block,
+    /// Wait up to the given time for room, then discard the event being
+    /// published. Bounds how long one stalled subscriber can hold writers up.
+    BlockFor(Duration),
+}
+
+/// Default number of events buffered per subscriber
+pub const DEFAULT_SUBSCRIBER_BUFFER: usize = 1024;
+
+/// Default longest wait for a `Block` subscriber to make room
+pub const DEFAULT_BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a subscriber's events go: bare, or with the WAL record they came from
+enum Outlet {
+    Events(Sender<ChangeEvent>, Option<Receiver<ChangeEvent>>),
+    Changes(Sender<LoggedChange>, Option<Receiver<LoggedChange>>),
+}
+
+impl Outlet {
+    fn queued(&self) -> usize {
+        match self {
+            Outlet::Events(sender, _) => sender.len(),
+            Outlet::Changes(sender, _) => sender.len(),
+        }
+    }
+}
+
+struct SubscriberEntry {
+    id: Uuid,
+    /// The channel, and for `DropOldest` a receiver that lets it evict from
+    /// the front of the buffer
+    outlet: Outlet,
+    /// Disconnects when the subscription's `SubscriptionHandle` is dropped
+    handle_alive: Receiver<()>,
+    /// Only events whose key starts with this are delivered
+    prefix: String,
+    /// Only events of these kinds are delivered
+    filter: EventFilter,
+    policy: OverflowPolicy,
+    /// Events this subscriber missed because its buffer was full
+    dropped: u64,
+    /// Changes logged at or before this sequence number are not delivered
+    after_seq: u64,
+    /// Panics caught from a callback subscription's callback; `None` for channels
+    callback_panics: Option<Arc<AtomicU64>>,
+}
+
+impl SubscriberEntry {
+    fn wants(&self, seq: u64, event: &ChangeEvent) -> bool {
+        seq > self.after_seq && self.filter.matches(event) && event.key().starts_with(&self.prefix)
+    }
+    
+    fn is_closed(&self) -> bool {
+        matches!(self.handle_alive.try_recv(), Err(TryRecvError::Disconnected))
+    }
+    
+    /// Deliver `event`, logged as WAL record `seq` at `timestamp_ms`, under
+    /// this subscriber's policy, returning `false` once the subscriber is gone
+    /// or, under `Block`, has made no room for `block_timeout`
+    fn deliver(&mut self, seq: u64, timestamp_ms: u64, event: ChangeEvent, block_timeout: Duration) -> bool {
+        let delivered = match &self.outlet {
+            Outlet::Events(sender, drain) => send(sender, drain.as_ref(), self.policy, block_timeout, &mut self.dropped, event),
+            Outlet::Changes(sender, drain) => {
+                let change = LoggedChange { seq, timestamp_ms, event };
+                send(sender, drain.as_ref(), self.policy, block_timeout, &mut self.dropped, change)
+            }
+        };
+        match delivered {
+            Delivery::Sent => true,
+            Delivery::Gone => false,
+            Delivery::Stalled => {
+                log::warn!("disconnecting subscriber {}: it made no room for {:?}", self.id, block_timeout);
+                false
+            }
+        }
+    }
+}
+
+/// What became of an item passed to `send`
+enum Delivery {
+    /// Buffered, or dropped as the policy allows
+    Sent,
+    /// The receiver is gone
+    Gone,
+    /// A `Block` subscriber made no room in time; the item was dropped
+    Stalled,
+}
+
+/// Send `item` under `policy`, counting in `dropped` what is lost to a full
+/// buffer
+///
+/// `Block` waits no longer than `block_timeout`: the bus lock, and often the
+/// WAL lock, is held meanwhile, so a subscriber that stops reading would
+/// otherwise hold up every writer and every other subscriber.
+fn send<T>(
+    sender: &Sender<T>,
+    drain: Option<&Receiver<T>>,
+    policy: OverflowPolicy,
+    block_timeout: Duration,
+    dropped: &mut u64,
+    mut item: T,
+) -> Delivery {
+    loop {
+        let full = match policy {
+            OverflowPolicy::Block => match sender.send_timeout(item, block_timeout) {
+                Ok(()) => return Delivery::Sent,
+                Err(SendTimeoutError::Disconnected(_)) => return Delivery::Gone,
+                Err(SendTimeoutError::Timeout(_)) => {
+                    *dropped += 1;
+                    return Delivery::Stalled;
+                }
+            },
+            OverflowPolicy::BlockFor(timeout) => match sender.send_timeout(item, timeout) {
+                Ok(()) => return Delivery::Sent,
+                Err(SendTimeoutError::Disconnected(_)) => return Delivery::Gone,
+                Err(SendTimeoutError::Timeout(rejected)) => rejected,
+            },
+            OverflowPolicy::DropNewest | OverflowPolicy::DropOldest => match sender.try_send(item) {
+                Ok(()) => return Delivery::Sent,
+                Err(TrySendError::Disconnected(_)) => return Delivery::Gone,
+                Err(TrySendError::Full(rejected)) => rejected,
+            },
+        };
+        
+        *dropped += 1;
+        match drain {
+            // Evict the oldest buffered item and retry
+            Some(drain) => {
+                let _ = drain.try_recv();
+                item = full;
+            }
+            None => return Delivery::Sent,
+        }
+    }
+}
+
+/// Text of a panic payload, as passed to `panic!`
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
 pub struct EventBus {
-    subscribers: Vec<(Uuid, Sender<ChangeEvent>)>,
+    subscribers: Vec<SubscriberEntry>,
+    buffer: usize,
+    policy: OverflowPolicy,
+    /// Longest a `Block` subscriber may keep a publish waiting
+    block_timeout: Duration,
+    dropped: u64,
+    /// Records held back, by WAL sequence number: `None` for one reserved
+    /// but not yet published, or else its timestamp, events and evictions
+    /// waiting on an earlier reservation
+    pending: BTreeMap<u64, Option<PendingRecord>>,
 }
 
+type PendingRecord = (u64, Vec<ChangeEvent>, Vec<String>);
+
 impl EventBus {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_SUBSCRIBER_BUFFER, OverflowPolicy::default())
+    }
+    
+    /// Bus whose subscribers each buffer up to `buffer` events
+    pub fn with_capacity(buffer: usize, policy: OverflowPolicy) -> Self {
         Self {
             subscribers: Vec::new(),
+            buffer: buffer.max(1),
+            policy,
+            block_timeout: DEFAULT_BLOCK_TIMEOUT,
+            dropped: 0,
+            pending: BTreeMap::new(),
         }
     }
     
+    /// Disconnect a `Block` subscriber that makes no room for `timeout`,
+    /// instead of after `DEFAULT_BLOCK_TIMEOUT`
+    pub fn with_block_timeout(mut self, timeout: Duration) -> Self {
+        self.block_timeout = timeout;
+        self
+    }
+    
+    /// Register a subscriber of bare events, returning its id and channel
+    fn add_subscriber(
+        &mut self,
+        handle_alive: Receiver<()>,
+        prefix: String,
+        filter: EventFilter,
+        policy: OverflowPolicy,
+        after_seq: u64,
+        callback_panics: Option<Arc<AtomicU64>>,
+    ) -> (Uuid, Receiver<ChangeEvent>) {
+        let id = Uuid::new_v4();
+        let (tx, rx) = channel::bounded(self.buffer);
+        let drain = (policy == OverflowPolicy::DropOldest).then(|| rx.clone());
+        self.subscribers.push(SubscriberEntry {
+            id,
+            outlet: Outlet::Events(tx, drain),
+            handle_alive,
+            prefix,
+            filter,
+            policy,
+            dropped: 0,
+            after_seq,
+            callback_panics,
+        });
+        (id, rx)
+    }
+    
     pub fn subscribe<F>(&mut self, callback: F) -> Result<SubscriptionHandle>
     where
         F: Fn(ChangeEvent) + Send + Sync + 'static,
     {
-        let id = Uuid::new_v4();
-        let (tx, rx): (Sender<ChangeEvent>, Receiver<ChangeEvent>) = channel::unbounded();
+        self.subscribe_prefix(String::new(), callback)
+    }
+    
+    /// Subscribe to events whose key starts with `prefix`
+    ///
+    /// Filtering happens before the event is sent, so non-matching changes
+    /// never wake the subscriber. An empty prefix matches every event.
+    ///
+    /// A panic in `callback` is caught and logged, and counted in
+    /// `SubscriberStats::callback_panics`; the subscription carries on with
+    /// the next event.
+    pub fn subscribe_prefix<F>(&mut self, prefix: String, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(ChangeEvent) + Send + Sync + 'static,
+    {
+        self.subscribe_filtered(prefix, EventFilter::ALL, callback)
+    }
+    
+    /// Subscribe to events of the kinds in `filter` whose key starts with
+    /// `prefix`
+    ///
+    /// Both are checked before the event is sent, so other changes never
+    /// wake the subscriber. Panics in `callback` are caught as in
+    /// `subscribe_prefix`.
+    pub fn subscribe_filtered<F>(&mut self, prefix: String, filter: EventFilter, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(ChangeEvent) + Send + Sync + 'static,
+    {
         let (shutdown_tx, shutdown_rx) = channel::bounded(1);
         
         // Store the sender for this subscriber
-        self.subscribers.push((id, tx));
+        let panics = Arc::new(AtomicU64::new(0));
+        let (id, rx) = self.add_subscriber(shutdown_rx.clone(), prefix, filter, self.policy, 0, Some(panics.clone()));
         
         // Spawn a thread to handle events for this subscriber
         thread::spawn(move || {
@@ -52,7 +437,17 @@ impl EventBus {
                 crossbeam::select! {
                     recv(rx) -> event => {
                         match event {
-                            Ok(event) => callback(event),
+                            Ok(event) => {
+                                let key = event.key().to_string();
+                                // One bad event must not end the subscription
+                                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(event))) {
+                                    panics.fetch_add(1, Ordering::Relaxed);
+                                    log::error!(
+                                        "subscriber {} panicked handling a change to '{}': {}",
+                                        id, key, panic_message(payload.as_ref())
+                                    );
+                                }
+                            }
                             Err(_) => break, // Channel closed
                         }
                     }
@@ -69,14 +464,315 @@ impl EventBus {
         })
     }
     
-    pub fn publish(&self, event: ChangeEvent) -> Result<()> {
-        // Send to all active subscribers
-        for (_, sender) in &self.subscribers {
-            // Use try_send to avoid blocking if a subscriber is slow
-            let _ = sender.try_send(event.clone());
+    /// Subscribe with a callback that receives events in batches
+    ///
+    /// A batch starts with the first event to arrive and is handed over
+    /// once it holds `max_batch` events or `max_delay` has passed since it
+    /// started, whichever comes first, so the subscriber thread wakes once
+    /// per batch rather than once per event. Events keep their publish
+    /// order. When the bus closes, the events already received are
+    /// delivered as a last, possibly short, batch. Panics in `callback` are
+    /// caught as in `subscribe_prefix`.
+    pub fn subscribe_batched<F>(&mut self, max_batch: usize, max_delay: Duration, callback: F) -> Result<SubscriptionHandle>
+    where
+        F: Fn(Vec<ChangeEvent>) + Send + Sync + 'static,
+    {
+        if max_batch == 0 {
+            return Err(LohError::InvalidArgument("max_batch must be at least 1".to_string()));
+        }
+        let (shutdown_tx, shutdown_rx) = channel::bounded(1);
+        let panics = Arc::new(AtomicU64::new(0));
+        let (id, rx) = self.add_subscriber(shutdown_rx.clone(), String::new(), EventFilter::ALL, self.policy, 0, Some(panics.clone()));
+        
+        thread::spawn(move || {
+            let mut open = true;
+            while open {
+                let mut batch = Vec::new();
+                crossbeam::select! {
+                    recv(rx) -> event => match event {
+                        Ok(event) => batch.push(event),
+                        Err(_) => break, // Channel closed
+                    },
+                    recv(shutdown_rx) -> _ => break,
+                }
+                
+                let deadline = Instant::now() + max_delay;
+                while batch.len() < max_batch {
+                    crossbeam::select! {
+                        recv(rx) -> event => match event {
+                            Ok(event) => batch.push(event),
+                            Err(_) => {
+                                open = false;
+                                break;
+                            }
+                        },
+                        recv(shutdown_rx) -> _ => return,
+                        default(deadline.saturating_duration_since(Instant::now())) => break,
+                    }
+                }
+                
+                let len = batch.len();
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(batch))) {
+                    panics.fetch_add(1, Ordering::Relaxed);
+                    log::error!(
+                        "subscriber {} panicked handling a batch of {} change(s): {}",
+                        id, len, panic_message(payload.as_ref())
+                    );
+                }
+            }
+        });
+        
+        Ok(SubscriptionHandle {
+            id,
+            _sender: shutdown_tx,
+        })
+    }
+    
+    /// Subscribe with a channel the caller reads from directly
+    ///
+    /// Dropping either the receiver or the handle ends the subscription.
+    pub fn subscribe_channel(&mut self) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        self.subscribe_channel_prefix(String::new())
+    }
+    
+    /// Channel subscription to events whose key starts with `prefix`
+    pub fn subscribe_channel_prefix(&mut self, prefix: String) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        self.subscribe_channel_with_policy(prefix, self.policy)
+    }
+    
+    /// Channel subscription with its own overflow policy instead of the bus's
+    ///
+    /// Use `Block` (or `BlockFor`) for a reliable subscriber, such as one
+    /// feeding a replica, that must see every event even if writers wait.
+    pub fn subscribe_channel_with_policy(
+        &mut self,
+        prefix: String,
+        policy: OverflowPolicy,
+    ) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        self.subscribe_channel_after(prefix, policy, 0)
+    }
+    
+    /// Channel subscription that skips changes logged at or before WAL
+    /// sequence number `after_seq`
+    ///
+    /// Only events published with `publish_logged` are skipped.
+    pub fn subscribe_channel_after(
+        &mut self,
+        prefix: String,
+        policy: OverflowPolicy,
+        after_seq: u64,
+    ) -> Result<(SubscriptionHandle, Receiver<ChangeEvent>)> {
+        let (handle_tx, handle_rx) = channel::bounded(1);
+        let (id, rx) = self.add_subscriber(handle_rx, prefix, EventFilter::ALL, policy, after_seq, None);
+        
+        Ok((SubscriptionHandle { id, _sender: handle_tx }, rx))
+    }
+    
+    /// Channel subscription whose events each come with the sequence number
+    /// and time of the WAL record they were logged in, skipping changes
+    /// logged at or before `after_seq`
+    ///
+    /// Only events published with `publish_logged` or `publish_record`
+    /// are delivered; see `Database::subscribe_sequenced` for the order
+    /// they arrive in.
+    pub fn subscribe_sequenced(
+        &mut self,
+        prefix: String,
+        policy: OverflowPolicy,
+        after_seq: u64,
+    ) -> Result<(SubscriptionHandle, Receiver<LoggedChange>)> {
+        let id = Uuid::new_v4();
+        let (handle_tx, handle_rx) = channel::bounded(1);
+        let (tx, rx) = channel::bounded(self.buffer);
+        let drain = (policy == OverflowPolicy::DropOldest).then(|| rx.clone());
+        self.subscribers.push(SubscriberEntry {
+            id,
+            outlet: Outlet::Changes(tx, drain),
+            handle_alive: handle_rx,
+            prefix,
+            filter: EventFilter::ALL,
+            policy,
+            dropped: 0,
+            after_seq,
+            callback_panics: None,
+        });
+        
+        Ok((SubscriptionHandle { id, _sender: handle_tx }, rx))
+    }
+    
+    /// Send `event` to every interested subscriber, returning how many of
+    /// them missed it (or, under `DropOldest`, lost an older event to it)
+    ///
+    /// Sequenced subscribers never see these, which belong to no WAL record.
+    pub fn publish(&mut self, event: ChangeEvent) -> Result<u64> {
+        self.publish_at(u64::MAX, 0, event)
+    }
+    
+    /// `publish` an event for the change logged as WAL record `seq`, which
+    /// sequenced subscribers see with a `timestamp_ms` of 0
+    pub fn publish_logged(&mut self, seq: u64, event: ChangeEvent) -> Result<u64> {
+        self.publish_at(seq, 0, event)
+    }
+    
+    /// Whether any subscription is registered, for writers to skip building
+    /// events no one receives
+    pub(crate) fn has_subscribers(&self) -> bool {
+        !self.subscribers.is_empty()
+    }
+    
+    /// Hold back the events of records logged after `seq` until
+    /// `publish_record` is called for `seq`
+    ///
+    /// For a writer that releases the WAL lock before it publishes, so its
+    /// events still go out ahead of those of the writers that log after it.
+    /// Called before the WAL lock is released.
+    pub(crate) fn reserve(&mut self, seq: u64) {
+        self.pending.insert(seq, None);
+    }
+    
+    /// Publish the events of WAL record `seq`, logged at `timestamp_ms`, in
+    /// order, followed by an `Evicted` event for each of the storage keys
+    /// `evicted` that the write pushed out of a cache
+    ///
+    /// Writers call this before they release the WAL lock, or `reserve`
+    /// `seq` first, which is what puts events from different records in
+    /// commit order: events of a record logged after one still reserved
+    /// wait for it.
+    pub(crate) fn publish_record(
+        &mut self,
+        seq: u64,
+        timestamp_ms: u64,
+        events: Vec<ChangeEvent>,
+        evicted: Vec<String>,
+    ) -> Result<()> {
+        self.pending.insert(seq, Some((timestamp_ms, events, evicted)));
+        while let Some(entry) = self.pending.first_entry() {
+            if entry.get().is_none() {
+                break;
+            }
+            let (seq, record) = entry.remove_entry();
+            let (timestamp_ms, events, evicted) = record.expect("checked above");
+            self.deliver_record(seq, timestamp_ms, events, evicted)?;
+        }
+        Ok(())
+    }
+    
+    fn deliver_record(&mut self, seq: u64, timestamp_ms: u64, events: Vec<ChangeEvent>, evicted: Vec<String>) -> Result<()> {
+        for event in events {
+            self.publish_at(seq, timestamp_ms, event)?;
+        }
+        // Evictions are cache bookkeeping, not deletes, so they bypass the WAL
+        for storage_key in evicted {
+            let (namespace, key) = namespace::split_storage_key(&storage_key);
+            self.publish_at(seq, timestamp_ms, ChangeEvent::Evicted {
+                namespace: namespace.map(str::to_string),
+                key: key.to_string(),
+            })?;
         }
         Ok(())
     }
+    
+    fn publish_at(&mut self, seq: u64, timestamp_ms: u64, event: ChangeEvent) -> Result<u64> {
+        let mut dropped = 0;
+        let block_timeout = self.block_timeout;
+        
+        // Send to all active subscribers, forgetting those that have gone away
+        self.subscribers.retain_mut(|entry| {
+            if entry.is_closed() {
+                return false;
+            }
+            if !entry.wants(seq, &event) {
+                return true;
+            }
+            if seq == u64::MAX && matches!(entry.outlet, Outlet::Changes(..)) {
+                return true;
+            }
+            
+            let before = entry.dropped;
+            let alive = entry.deliver(seq, timestamp_ms, event.clone(), block_timeout);
+            dropped += entry.dropped - before;
+            alive
+        });
+        
+        self.dropped += dropped;
+        Ok(dropped)
+    }
+    
+    /// Events discarded because a subscriber's buffer was full
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped
+    }
+    
+    /// Backlog and losses of each live subscription, as of the last publish
+    pub fn subscriber_stats(&self) -> Vec<SubscriberStats> {
+        self.subscribers
+            .iter()
+            .map(|entry| SubscriberStats {
+                id: entry.id,
+                prefix: entry.prefix.clone(),
+                queued: entry.outlet.queued(),
+                dropped_events: entry.dropped,
+                callback_panics: entry.callback_panics.as_ref().map_or(0, |p| p.load(Ordering::Relaxed)),
+            })
+            .collect()
+    }
+    
+    /// Stop delivering events to the subscription `id`
+    pub fn unsubscribe(&mut self, id: Uuid) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|entry| entry.id != id);
+        self.subscribers.len() != before
+    }
+    
+    /// Number of live subscriptions, as of the last publish
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+    
+    /// End every subscription: callback threads exit and channel receivers
+    /// disconnect once they have drained what was already sent
+    pub fn close(&mut self) {
+        self.subscribers.clear();
+    }
+
+}
+
+/// Fan-out of `LifecycleEvent`s to channel subscribers
+///
+/// Shared behind an `Arc<Mutex<_>>` by the database and its sync thread, so
+/// either may publish. `publish` never blocks: a subscriber whose buffer is
+/// full misses the event, so a slow reader cannot stall durability work.
+pub struct LifecycleBus {
+    subscribers: Vec<(Sender<LifecycleEvent>, Receiver<()>)>,
+    buffer: usize,
+}
+
+impl LifecycleBus {
+    pub fn with_capacity(buffer: usize) -> Self {
+        Self { subscribers: Vec::new(), buffer: buffer.max(1) }
+    }
+    
+    /// Subscribe with a channel; dropping either end ends the subscription
+    pub fn subscribe(&mut self) -> (SubscriptionHandle, Receiver<LifecycleEvent>) {
+        let (tx, rx) = channel::bounded(self.buffer);
+        let (handle_tx, handle_rx) = channel::bounded(1);
+        self.subscribers.push((tx, handle_rx));
+        (SubscriptionHandle { id: Uuid::new_v4(), _sender: handle_tx }, rx)
+    }
+    
+    /// End every subscription, disconnecting the receivers once drained
+    pub fn close(&mut self) {
+        self.subscribers.clear();
+    }
+    
+    pub fn publish(&mut self, event: LifecycleEvent) {
+        self.subscribers.retain(|(sender, handle_alive)| {
+            if matches!(handle_alive.try_recv(), Err(TryRecvError::Disconnected)) {
+                return false;
+            }
+            !matches!(sender.try_send(event.clone()), Err(TrySendError::Disconnected(_)))
+        });
+    }
 }
 
 impl Default for EventBus {
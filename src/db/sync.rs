@@ -0,0 +1,463 @@
+use crate::db::subscriber::{panic_message, LifecycleBus, LifecycleEvent};
+use crate::db::{meta, StorageEngine, WriteAheadLog};
+use crate::{LohError, Result};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What `Database::flush_with` makes durable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Write and fsync the data file, leaving the WAL as it is; what
+    /// `Database::flush` does. Every write so far is in the data file, but
+    /// WAL records still buffered or unsynced stay that way.
+    Snapshot,
+    /// Write out and fsync the WAL, leaving the data file as it is; every
+    /// write so far survives a power loss, replayed from the WAL on the
+    /// next open. Cheaper than rewriting the data file.
+    Wal,
+    /// Both, as one barrier; what `Database::sync` does
+    Both,
+}
+
+/// Background thread that periodically checkpoints any number of databases
+///
+/// Each database registers with a scheduler when it opens and is
+/// checkpointed every `wal_sync_interval_ms` from then on: the storage
+/// engine is flushed and the WAL segments the data file now covers are
+/// released, holding the WAL and storage locks throughout (in the same
+/// order writers take them) so no write can land between the snapshot and
+/// the release. Writers of that database wait for the flush to finish.
+///
+/// One thread serves every registered database, checkpointing them one at
+/// a time in order of their deadlines, so a slow flush delays the others.
+/// By default databases share `FlushScheduler::global`, started on first
+/// use; pass another through `DatabaseConfigBuilder::flush_scheduler` to
+/// isolate a group of databases. Clones share one thread, which exits
+/// while no database is registered and is respawned by the next one.
+///
+/// A database configured with `checkpoint_wal_bytes` is also checkpointed
+/// as soon as a write leaves its WAL larger than that, whether or not it
+/// has an interval: the write moves the database's deadline to now and
+/// returns without waiting for the checkpoint.
+///
+/// One configured with `checkpoint_ratio` is also polled every 200ms: the
+/// thread compares the WAL's record count with the number of live keys and
+/// checkpoints once the log holds more than `ratio` records per key. This
+/// catches workloads that overwrite the same keys, growing the WAL and
+/// replay time without growing the data, and writes never pay for it.
+///
+/// After each checkpoint that covered new WAL records, a database publishes
+/// `LifecycleEvent::Flushed`; its lifecycle bus is locked only to publish,
+/// after the other locks have been released. A failed checkpoint publishes
+/// `LifecycleEvent::FlushFailed` and is reported by `DbStats::last_flush_error`
+/// until one succeeds; the WAL keeps every record, so it is simply retried
+/// at the next interval.
+///
+/// A checkpoint that panics, say in a storage engine's `flush`, counts as
+/// a failed one: the panic is caught with the locks still held, so they
+/// are not poisoned, and the thread goes on serving every database. Should
+/// the thread itself die, its databases report it through
+/// `Database::sync_health` until the next registration starts a new one.
+#[derive(Clone)]
+pub struct FlushScheduler {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<SchedulerState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    jobs: BTreeMap<u64, FlushJob>,
+    next_id: u64,
+    /// Job being checkpointed, taken out of `jobs` while it runs
+    running: Option<u64>,
+    /// Set when the running job was deregistered mid-checkpoint
+    cancelled: bool,
+    /// Whether a thread is serving `jobs`
+    started: bool,
+}
+
+/// How often the WAL of a database with a `checkpoint_ratio` is compared
+/// with its key count
+pub(crate) const RATIO_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What checkpoints a registered database
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CheckpointTriggers {
+    /// Checkpoint every this long
+    pub interval: Option<Duration>,
+    /// Checkpoint as soon as an append leaves the WAL larger than this
+    pub wal_bytes: Option<u64>,
+    /// Checkpoint once the WAL holds more than this many records per live key
+    pub ratio: Option<f64>,
+}
+
+struct FlushJob {
+    /// `None` when only the WAL size or ratio triggers checkpoints
+    interval: Option<Duration>,
+    /// `None` until the WAL size triggers one, without an interval
+    due: Option<Instant>,
+    ratio: Option<f64>,
+    /// When the ratio is next checked; `None` without a ratio
+    ratio_check: Option<Instant>,
+    storage: Arc<RwLock<Box<dyn StorageEngine>>>,
+    wal: Arc<Mutex<WriteAheadLog>>,
+    lifecycle: Arc<Mutex<LifecycleBus>>,
+    health: Arc<FlushHealth>,
+    flushed_seq: u64,
+}
+
+/// How a database's background checkpoints are going, shared by its job
+/// and the `Database`
+#[derive(Default)]
+pub(crate) struct FlushHealth {
+    /// Error of the latest checkpoint, cleared when one succeeds
+    pub last_error: Mutex<Option<String>>,
+    /// Unix ms of registration, then of each checkpoint or ratio check
+    pub heartbeat_ms: AtomicU64,
+    /// Checkpoints that panicked
+    pub panics: AtomicU64,
+    /// Longest the thread should go without visiting the job: its interval
+    /// or ratio check, whichever is sooner; `None` when only the WAL size
+    /// triggers checkpoints
+    period: Option<Duration>,
+}
+
+impl FlushHealth {
+    pub fn new(triggers: &CheckpointTriggers) -> Self {
+        let ratio_check = triggers.ratio.map(|_| RATIO_CHECK_INTERVAL);
+        Self {
+            period: triggers.interval.into_iter().chain(ratio_check).min(),
+            ..Self::default()
+        }
+    }
+    
+    /// Whether the heartbeat is more than twice the period (plus a second
+    /// of slack for slow flushes) old at unix ms `now`
+    pub fn overdue(&self, now: u64) -> bool {
+        self.period.is_some_and(|period| {
+            let limit = 2 * period.as_millis() as u64 + 1000;
+            now.saturating_sub(self.heartbeat_ms.load(Ordering::Relaxed)) > limit
+        })
+    }
+    
+    fn beat(&self) {
+        self.heartbeat_ms.store(meta::now_ms(), Ordering::Relaxed);
+    }
+    
+    fn failed(&self, error: String, lifecycle: &Mutex<LifecycleBus>) {
+        *self.last_error.lock().unwrap() = Some(error.clone());
+        lifecycle.lock().unwrap().publish(LifecycleEvent::FlushFailed { error });
+    }
+}
+
+/// A database's place in a `FlushScheduler`, removed when dropped
+///
+/// Dropping waits for a checkpoint of this database already in progress,
+/// so none runs once the drop returns.
+pub(crate) struct FlushRegistration {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl FlushScheduler {
+    /// A scheduler with its own thread, spawned when the first database
+    /// registers
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(SchedulerState::default()),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+    
+    /// The scheduler databases use unless configured otherwise
+    pub fn global() -> &'static FlushScheduler {
+        static GLOBAL: OnceLock<FlushScheduler> = OnceLock::new();
+        GLOBAL.get_or_init(FlushScheduler::new)
+    }
+    
+    /// Number of databases currently registered
+    pub fn registered(&self) -> usize {
+        let state = self.shared.state.lock().unwrap();
+        state.jobs.len() + usize::from(state.running.is_some() && !state.cancelled)
+    }
+    
+    /// Checkpoint `storage` whenever one of `triggers` fires, until the
+    /// registration is dropped
+    pub(crate) fn register(
+        &self,
+        triggers: CheckpointTriggers,
+        storage: Arc<RwLock<Box<dyn StorageEngine>>>,
+        wal: Arc<Mutex<WriteAheadLog>>,
+        lifecycle: Arc<Mutex<LifecycleBus>>,
+        health: Arc<FlushHealth>,
+    ) -> FlushRegistration {
+        // Appenders lock the scheduler while holding the log, so take them
+        // in that order here too
+        let mut log = wal.lock().unwrap();
+        let flushed_seq = log.last_seq();
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        if let Some(threshold) = triggers.wal_bytes {
+            let shared = Arc::downgrade(&self.shared);
+            log.set_size_trigger(threshold, move || {
+                if let Some(shared) = shared.upgrade() {
+                    shared.make_due(id);
+                }
+            });
+        }
+        drop(log);
+        
+        health.beat();
+        let now = Instant::now();
+        state.jobs.insert(id, FlushJob {
+            interval: triggers.interval,
+            due: triggers.interval.map(|interval| now + interval),
+            ratio: triggers.ratio,
+            ratio_check: triggers.ratio.map(|_| now + RATIO_CHECK_INTERVAL),
+            storage,
+            wal,
+            lifecycle,
+            health,
+            flushed_seq,
+        });
+        
+        if !state.started {
+            state.started = true;
+            let shared = self.shared.clone();
+            thread::spawn(move || run(shared));
+        }
+        self.shared.changed.notify_all();
+        FlushRegistration {
+            shared: self.shared.clone(),
+            id,
+        }
+    }
+}
+
+impl Shared {
+    /// Checkpoint job `id` right away, unless it is running already
+    fn make_due(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.jobs.get_mut(&id) {
+            let now = Instant::now();
+            if job.due.is_none_or(|due| due > now) {
+                job.due = Some(now);
+                self.changed.notify_all();
+            }
+        }
+    }
+}
+
+impl Default for FlushScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Restarts the scheduler thread if it dies, for the jobs it left queued
+///
+/// A job being checkpointed when the thread died is lost with it; its
+/// database reports that through `Database::sync_health`.
+struct ThreadGuard(Arc<Shared>);
+
+impl Drop for ThreadGuard {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        self.0.state.clear_poison();
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.running = None;
+        state.cancelled = false;
+        state.started = false;
+        if !state.jobs.is_empty() {
+            let shared = self.0.clone();
+            match thread::Builder::new().spawn(move || run(shared)) {
+                Ok(_) => state.started = true,
+                Err(e) => log::error!("could not restart the background checkpoint thread: {}", e),
+            }
+        }
+        log::error!(
+            "background checkpoint thread panicked; {}",
+            if state.started { "restarted it" } else { "checkpoints stop until a database registers" },
+        );
+        self.0.changed.notify_all();
+    }
+}
+
+/// The scheduler thread: checkpoint whichever job is due next, or check
+/// its ratio, until no jobs are left
+fn run(shared: Arc<Shared>) {
+    let _guard = ThreadGuard(shared.clone());
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.jobs.is_empty() {
+            // Nothing to serve; the next registration starts a new thread
+            state.started = false;
+            return;
+        }
+        let next = state.jobs.iter()
+            .filter_map(|(&id, job)| job.wake_at().map(|at| (id, at)))
+            .min_by_key(|&(_, at)| at);
+        let Some((id, at)) = next else {
+            // Only size-triggered jobs, none of them triggered yet
+            state = shared.changed.wait(state).unwrap();
+            continue;
+        };
+        let now = Instant::now();
+        if at > now {
+            state = shared.changed.wait_timeout(state, at - now).unwrap().0;
+            continue;
+        }
+        
+        let mut job = state.jobs.remove(&id).expect("due job is registered");
+        state.running = Some(id);
+        drop(state);
+        
+        // Woken for the ratio alone unless the deadline has passed too
+        let checkpointed = job.due.is_some_and(|due| due <= now) || job.ratio_exceeded();
+        if checkpointed {
+            job.checkpoint();
+        }
+        job.health.beat();
+        
+        state = shared.state.lock().unwrap();
+        state.running = None;
+        if std::mem::take(&mut state.cancelled) {
+            drop(job);
+        } else {
+            let now = Instant::now();
+            if checkpointed {
+                job.due = job.interval.map(|interval| now + interval);
+            }
+            job.ratio_check = job.ratio.map(|_| now + RATIO_CHECK_INTERVAL);
+            state.jobs.insert(id, job);
+        }
+        shared.changed.notify_all();
+    }
+}
+
+impl FlushJob {
+    /// When the thread next has work for this job
+    fn wake_at(&self) -> Option<Instant> {
+        match (self.due, self.ratio_check) {
+            (Some(due), Some(check)) => Some(due.min(check)),
+            (due, check) => due.or(check),
+        }
+    }
+    
+    /// Whether the WAL holds more than `ratio` records per live key
+    ///
+    /// An empty database counts as one key, so a log of deletes alone still
+    /// gets checkpointed.
+    fn ratio_exceeded(&self) -> bool {
+        let Some(ratio) = self.ratio else {
+            return false;
+        };
+        let Ok(entries) = self.wal.lock().map(|wal| wal.entry_count()) else {
+            return false;
+        };
+        if entries == 0 {
+            return false;
+        }
+        let keys = match self.storage.read().map(|storage| storage.key_count()) {
+            Ok(Ok(keys)) => keys,
+            _ => return false,
+        };
+        let per_key = entries as f64 / keys.max(1) as f64;
+        if per_key > ratio {
+            log::info!(
+                "checkpointing: WAL holds {} records for {} live keys ({:.1} per key, ratio {})",
+                entries, keys, per_key, ratio
+            );
+            true
+        } else {
+            log::trace!("not checkpointing: WAL holds {:.1} records per live key, ratio {}", per_key, ratio);
+            false
+        }
+    }
+    
+    fn checkpoint(&mut self) {
+        let started = Instant::now();
+        let (Ok(mut wal), Ok(mut storage)) = (self.wal.lock(), self.storage.write()) else {
+            let error = "the WAL or storage lock was poisoned by a panicking writer".to_string();
+            log::error!("background checkpoint skipped: {}", error);
+            self.health.failed(error, &self.lifecycle);
+            return;
+        };
+        // Caught with the guards held, so the panic poisons neither lock
+        let result = panic::catch_unwind(AssertUnwindSafe(|| checkpoint(&mut wal, storage.as_mut())))
+            .unwrap_or_else(|payload| {
+                self.health.panics.fetch_add(1, Ordering::Relaxed);
+                let message = panic_message(payload.as_ref());
+                Err(LohError::DataCorrupt(format!("checkpoint panicked: {}", message)))
+            });
+        let seq = match result {
+            Ok((seq, _)) => seq,
+            Err(e) => {
+                drop((wal, storage));
+                log::warn!("background checkpoint failed: {}", e);
+                self.health.failed(e.to_string(), &self.lifecycle);
+                return;
+            }
+        };
+        let bytes = storage.disk_usage().unwrap_or(0);
+        drop((wal, storage));
+        
+        self.health.last_error.lock().unwrap().take();
+        if seq > self.flushed_seq {
+            self.lifecycle.lock().unwrap().publish(LifecycleEvent::Flushed {
+                wal_entries: seq - self.flushed_seq,
+                bytes,
+                duration: started.elapsed(),
+            });
+            self.flushed_seq = seq;
+        }
+    }
+}
+
+/// Flush `storage`, then delete the WAL segments it now covers
+///
+/// Callers must hold both locks for the whole call. Storage engines make
+/// their flush durable before returning, so no released record is lost.
+/// Returns the sequence number the data file now covers and how many
+/// segments were deleted.
+pub(crate) fn checkpoint(wal: &mut WriteAheadLog, storage: &mut dyn StorageEngine) -> Result<(u64, usize)> {
+    storage.flush()?;
+    wal.roll()?;
+    let through_seq = wal.last_seq();
+    let released = wal.release_through(through_seq)?;
+    Ok((through_seq, released))
+}
+
+impl FlushRegistration {
+    /// Whether a live thread is serving this database's checkpoints
+    pub(crate) fn thread_alive(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        state.started && (state.jobs.contains_key(&self.id) || state.running == Some(self.id))
+    }
+}
+
+impl Drop for FlushRegistration {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.jobs.remove(&self.id).is_some() {
+            // Wake the thread so it exits if this was the last job
+            self.shared.changed.notify_all();
+        } else if state.running == Some(self.id) {
+            state.cancelled = true;
+            let _state = self.shared.changed.wait_while(state, |s| s.running == Some(self.id)).unwrap();
+        }
+    }
+}
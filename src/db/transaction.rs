@@ -0,0 +1,152 @@
+use crate::db::kv::keys_with_prefix;
+use crate::db::{namespace, Database, Snapshot, StorageEngine};
+use crate::{LohError, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+/// Optimistic transaction over the default namespace; see `Database::begin`
+///
+/// Reads see the database as it was when the transaction began, plus the
+/// transaction's own staged writes. Writes are only staged until `commit`,
+/// which applies all of them as one WAL record, so recovery restores all of
+/// them or none, provided nothing the transaction read has changed in the
+/// meantime. Dropping a transaction discards its writes.
+pub struct Transaction<'a> {
+    db: &'a Database,
+    snapshot: Snapshot,
+    /// Keys read with `get`
+    reads: BTreeSet<String>,
+    /// Prefixes read with `scan`
+    scans: BTreeSet<String>,
+    /// Key -> staged value, or `None` for a staged delete
+    writes: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+impl<'a> Transaction<'a> {
+    /// WAL sequence number of the last write visible to the transaction
+    pub fn seq(&self) -> u64 {
+        self.snapshot.seq()
+    }
+    
+    /// Value of `key`: as staged by this transaction, or else as it was
+    /// when the transaction began
+    pub fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+        if let Some(staged) = self.writes.get(key) {
+            return staged.clone();
+        }
+        self.reads.insert(key.to_string());
+        self.snapshot.get(key).map(<[u8]>::to_vec)
+    }
+    
+    /// Entries whose key starts with `prefix`, in ascending key order, with
+    /// this transaction's staged writes applied
+    ///
+    /// The whole range counts as read: a key added to it, removed from it
+    /// or changed within it by another write fails the commit.
+    pub fn scan(&mut self, prefix: &str) -> Vec<(String, Vec<u8>)> {
+        self.scans.insert(prefix.to_string());
+        let mut entries: BTreeMap<String, Vec<u8>> = self.snapshot
+            .scan(prefix)
+            .map(|(key, value)| (key.to_string(), value.to_vec()))
+            .collect();
+        let staged = self.writes
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix));
+        for (key, value) in staged {
+            match value {
+                Some(value) => entries.insert(key.clone(), value.clone()),
+                None => entries.remove(key),
+            };
+        }
+        entries.into_iter().collect()
+    }
+    
+    /// Stage setting `key` to `value`
+    ///
+    /// Fails right away, rather than at `commit`, if `key` or `value` would
+    /// be rejected by `Database::set`.
+    pub fn set(&mut self, key: String, value: Vec<u8>) -> Result<()> {
+        namespace::validate_key(&key)?;
+        self.db.check_sizes(None, &key, &value)?;
+        self.writes.insert(key, Some(value));
+        Ok(())
+    }
+    
+    /// Stage deleting `key`
+    pub fn delete(&mut self, key: &str) -> Result<()> {
+        namespace::validate_key(key)?;
+        self.writes.insert(key.to_string(), None);
+        Ok(())
+    }
+    
+    /// Apply every staged write at once, if nothing read has changed since
+    /// the transaction began
+    ///
+    /// Every key read with `get` must still hold the value (or absence) the
+    /// transaction saw, and every prefix read with `scan` the same entries.
+    /// Otherwise nothing is written and `LohError::Conflict` is returned;
+    /// start a new transaction and retry. The check and the writes happen
+    /// under one hold of the database's write locks, so no other write can
+    /// slip in between them. Writes are published as ordinary `Set` and
+    /// `Delete` events sharing one sequence number.
+    ///
+    /// Changes are detected by comparing contents rather than versions:
+    /// versions start over when a key is deleted and created again, and not
+    /// every storage engine tracks them. A key changed and then changed back
+    /// before the commit therefore does not conflict.
+    pub fn commit(self) -> Result<()> {
+        let Transaction { db, snapshot, reads, scans, writes } = self;
+        db.apply_batch(writes.into_iter().collect(), |storage| {
+            for key in &reads {
+                if storage.retrieve(key)?.as_deref() != snapshot.get(key) {
+                    return Err(LohError::Conflict(format!("key '{}' changed since the transaction began", key)));
+                }
+            }
+            for prefix in &scans {
+                if !scan_unchanged(storage, &snapshot, prefix)? {
+                    return Err(LohError::Conflict(format!(
+                        "keys starting with '{}' changed since the transaction began",
+                        prefix
+                    )));
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Whether the keys starting with `prefix` hold the same entries in
+/// `storage` as in `snapshot`
+fn scan_unchanged(storage: &dyn StorageEngine, snapshot: &Snapshot, prefix: &str) -> Result<bool> {
+    let current = keys_with_prefix(storage, None, prefix)?;
+    let mut expected = snapshot.scan(prefix);
+    for key in &current {
+        match expected.next() {
+            Some((expected_key, expected_value)) if expected_key == key => {
+                if storage.retrieve(key)?.as_deref() != Some(expected_value) {
+                    return Ok(false);
+                }
+            }
+            _ => return Ok(false),
+        }
+    }
+    Ok(expected.next().is_none())
+}
+
+impl Database {
+    /// Start an optimistic transaction over the default namespace
+    ///
+    /// The transaction reads from a snapshot taken now (see `Snapshot` for
+    /// its cost) and holds no lock until it commits, so other writers carry
+    /// on meanwhile; a commit fails with `LohError::Conflict` if one of them
+    /// changed something the transaction read. See `Transaction`.
+    pub fn begin(&self) -> Result<Transaction<'_>> {
+        Ok(Transaction {
+            db: self,
+            snapshot: self.snapshot()?,
+            reads: BTreeSet::new(),
+            scans: BTreeSet::new(),
+            writes: BTreeMap::new(),
+        })
+    }
+}
@@ -0,0 +1,191 @@
+use crate::db::index::Indexes;
+use crate::db::{namespace, ChangeEvent, Clock, Database, EventBus, Operation, StorageEngine, WriteAheadLog};
+use crate::{LohError, Result};
+use crossbeam::channel::{self, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Everything an expiry sweep touches, shared with the background sweeper
+#[derive(Clone)]
+pub(crate) struct ExpiryTargets {
+    pub wal: Arc<Mutex<WriteAheadLog>>,
+    pub storage: Arc<RwLock<Box<dyn StorageEngine>>>,
+    pub indexes: Arc<RwLock<Indexes>>,
+    pub event_bus: Arc<Mutex<EventBus>>,
+    /// Keys removed by expiry since the database was opened
+    pub expired: Arc<AtomicU64>,
+    /// Decides which keys have expired
+    pub clock: Arc<dyn Clock>,
+}
+
+/// Delete every key whose expiry has passed at `now_ms`
+///
+/// Each removal is logged as an `Expire` and published as
+/// `ChangeEvent::Expired`. The WAL and storage stay locked from finding the
+/// keys to removing them, so concurrent sweeps serialize (the second finds
+/// nothing left to do) and a key overwritten in the meantime, which no
+/// longer expires, is neither removed nor reported as expired. Returns how many keys were removed.
+pub(crate) fn sweep(targets: &ExpiryTargets, now_ms: u64) -> Result<usize> {
+    let mut removed = Vec::new();
+    {
+        let mut wal = targets.wal.lock().unwrap();
+        let mut storage = targets.storage.write().unwrap();
+        for storage_key in storage.expired_keys(now_ms)? {
+            let (ns, key) = namespace::split_storage_key(&storage_key);
+            let (namespace, key) = (ns.map(str::to_string), key.to_string());
+            let seq = wal.append(&Operation::Expire { namespace: namespace.clone(), key: key.clone() })?;
+            storage.remove(&storage_key)?;
+            targets.indexes.write().unwrap().on_delete_storage_key(&storage_key);
+            removed.push((seq, wal.last_timestamp_ms(), ChangeEvent::Expired { namespace, key }));
+        }
+        // Published before another writer can log, but without holding up readers
+        drop(storage);
+        let mut event_bus = targets.event_bus.lock().unwrap();
+        for (seq, timestamp_ms, event) in &removed {
+            event_bus.publish_record(*seq, *timestamp_ms, vec![event.clone()], Vec::new())?;
+        }
+    }
+    targets.expired.fetch_add(removed.len() as u64, Ordering::Relaxed);
+    if !removed.is_empty() {
+        log::debug!("expired {} key(s)", removed.len());
+    }
+    Ok(removed.len())
+}
+
+/// Background thread that sweeps expired keys every interval
+///
+/// Reads already treat an expired key as absent; the sweep is what frees
+/// its memory and logs its deletion. Exits promptly when dropped.
+pub(crate) struct ExpirySweeper {
+    shutdown: Option<Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ExpirySweeper {
+    pub fn spawn(interval: Duration, targets: ExpiryTargets) -> Self {
+        let (shutdown_tx, shutdown_rx) = channel::bounded::<()>(1);
+        
+        // Any outcome other than a timeout means shutdown was requested
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = shutdown_rx.recv_timeout(interval) {
+                if let Err(e) = sweep(&targets, targets.clock.now_ms()) {
+                    log::warn!("expiry sweep failed: {}", e);
+                }
+            }
+        });
+        
+        Self {
+            shutdown: Some(shutdown_tx),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for ExpirySweeper {
+    fn drop(&mut self) {
+        self.shutdown.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Whether the key stored under `storage_key` has expired at `now_ms`
+pub(crate) fn is_expired(storage: &dyn StorageEngine, storage_key: &str, now_ms: u64) -> Result<bool> {
+    let expires_ms = storage.metadata(storage_key)?.and_then(|meta| meta.expires_ms);
+    Ok(expires_ms.is_some_and(|expires_ms| expires_ms <= now_ms))
+}
+
+/// Absolute expiry time for a key set at `now_ms` with `ttl`
+pub(crate) fn deadline_ms(now_ms: u64, ttl: Duration) -> Result<u64> {
+    if ttl.is_zero() {
+        return Err(LohError::InvalidArgument("ttl must be greater than zero".to_string()));
+    }
+    Ok(now_ms.saturating_add(ttl.as_millis().min(u64::MAX as u128) as u64))
+}
+
+impl Database {
+    /// Set `key` to expire `ttl` from now
+    ///
+    /// Once expired, `get` treats the key as absent, and the background
+    /// sweep (or `expire_now`) deletes it; until then it still shows up in
+    /// `list_keys`. Setting the key again without a TTL makes it permanent.
+    /// Fails if the storage engine does not support expiry.
+    pub fn set_with_ttl(&mut self, key: String, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        namespace::validate_key(&key)?;
+        let expires_ms = deadline_ms(self.now_ms(), ttl)?;
+        self.apply_versioned_set(None, key, value, None, Some(expires_ms)).map(|_| ())
+    }
+    
+    /// Time left before `key` expires, or `None` if it never does
+    ///
+    /// Fails with `LohError::NotFound` if the key does not exist, which
+    /// includes a key whose expiry has already passed but that has not been
+    /// swept yet.
+    pub fn ttl(&self, key: &str) -> Result<Option<Duration>> {
+        let storage_key = namespace::storage_key(None, key);
+        let storage = self.storage_handle();
+        let storage = storage.read().unwrap();
+        if storage.retrieve(&storage_key)?.is_none() {
+            return Err(LohError::NotFound(format!("key '{}'", key)));
+        }
+        match storage.metadata(&storage_key)?.and_then(|meta| meta.expires_ms) {
+            None => Ok(None),
+            Some(expires_ms) => match expires_ms.checked_sub(self.now_ms()) {
+                Some(left) if left > 0 => Ok(Some(Duration::from_millis(left))),
+                _ => Err(LohError::NotFound(format!("key '{}'", key))),
+            },
+        }
+    }
+    
+    /// Remove the expiry of `key`, so it is kept until deleted
+    ///
+    /// Returns whether there was an expiry to remove: `false` for a key
+    /// that never expires and for a missing or already expired key, in
+    /// which case nothing is logged. The removal is logged to the WAL and
+    /// survives recovery, but publishes no change event since the value
+    /// stays the same.
+    pub fn persist(&mut self, key: &str) -> Result<bool> {
+        self.ensure_writable()?;
+        namespace::validate_key(key)?;
+        let storage_key = namespace::storage_key(None, key);
+        
+        // Hold the WAL lock until storage matches, and the storage lock from
+        // checking the expiry until it is removed
+        let wal = self.wal_handle();
+        let mut wal = wal.lock().unwrap();
+        let storage = self.storage_handle();
+        let mut storage = storage.write().unwrap();
+        let expires_ms = storage.metadata(&storage_key)?.and_then(|meta| meta.expires_ms);
+        if expires_ms.is_none_or(|expires_ms| expires_ms <= self.now_ms()) {
+            return Ok(false);
+        }
+        
+        let seq = wal.append(&Operation::Persist {
+            namespace: None,
+            key: key.to_string(),
+        })?;
+        storage.set_expiry(&storage_key, None)?;
+        drop((wal, storage));
+        self.finish_write(seq)?;
+        Ok(true)
+    }
+    
+    /// Delete every expired key right away instead of waiting for the
+    /// background sweep, returning how many were removed
+    ///
+    /// Each removal is logged to the WAL and published as a
+    /// `ChangeEvent::Expired`, as the background sweep does. Safe to call while the background sweep runs: a key is removed once.
+    pub fn expire_now(&mut self) -> Result<usize> {
+        self.ensure_writable()?;
+        let targets = self.expiry_targets();
+        let removed = sweep(&targets, targets.clock.now_ms())?;
+        if removed > 0 {
+            let seq = targets.wal.lock().unwrap().last_seq();
+            self.finish_write(seq)?;
+        }
+        Ok(removed)
+    }
+}
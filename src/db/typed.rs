@@ -0,0 +1,50 @@
+use crate::db::{namespace, Database, SerializationFormat};
+use crate::{LohError, Result};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Decode the value of `key` as a `T`
+///
+/// Bincode is not self-describing, so bytes of another type often decode
+/// without complaint; rejecting leftover bytes catches most of those.
+pub(crate) fn decode<T: DeserializeOwned>(format: SerializationFormat, key: &str, bytes: &[u8]) -> Result<T> {
+    let decoded = match format {
+        SerializationFormat::Bincode => bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+            .deserialize(bytes)
+            .map_err(|e| e.to_string()),
+        format => format.deserialize(bytes).map_err(|e| e.to_string()),
+    };
+    decoded.map_err(|reason| LohError::TypeMismatch {
+        key: key.to_string(),
+        expected: std::any::type_name::<T>(),
+        reason,
+    })
+}
+
+impl Database {
+    /// Store `value` at `key`, encoded with the configured
+    /// `serialization_format` (bincode by default)
+    ///
+    /// The stored bytes are an ordinary value: `get` returns them as they
+    /// are, and `get_typed` decodes them again.
+    pub fn set_typed<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        namespace::validate_key(key)?;
+        let bytes = self.serialization_format().serialize(value)?;
+        self.apply_set(None, key.to_string(), bytes)
+    }
+    
+    /// The value of `key` decoded as a `T`, or `None` if the key is absent
+    ///
+    /// Fails with `LohError::TypeMismatch` if the stored bytes are not a
+    /// `T` in the configured format. With bincode some mismatches cannot
+    /// be detected and decode to a wrong value instead, for example when
+    /// both types have the same layout.
+    pub fn get_typed<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.apply_get(None, key)?
+            .map(|bytes| decode(self.serialization_format(), key, &bytes))
+            .transpose()
+    }
+}
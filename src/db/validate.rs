@@ -0,0 +1,94 @@
+use crate::db::{chunked, namespace, ttl, Database, Operation, StorageEngine};
+use crate::{LohError, Result};
+
+impl Database {
+    /// Check that every operation in `ops` would be accepted, without
+    /// applying any of them
+    ///
+    /// Runs the checks the write methods run before touching the WAL:
+    /// read-only mode, key and namespace constraints, `max_key_bytes`,
+    /// `max_value_bytes` and the WAL record limit, expiry support for
+    /// `SetWithExpiry` and metadata support for `SetWithMeta`, and, against
+    /// the current contents, that an `Append` targets a chunked value and
+    /// stays within `max_value_bytes`. Returns the first violation in order,
+    /// with the error the write would have returned.
+    ///
+    /// Nothing is logged, stored or published, and storage is only read
+    /// under a shared lock, so validating never blocks readers. Each
+    /// operation is checked against the current contents, not against the
+    /// effect of the operations before it, and a concurrent write can still
+    /// make a validated operation fail when it is applied.
+    pub fn validate_batch(&self, ops: &[Operation]) -> Result<()> {
+        self.ensure_writable()?;
+        let storage = self.storage_handle();
+        let storage = storage.read().unwrap();
+        self.validate_ops(storage.as_ref(), ops)
+    }
+    
+    fn validate_ops(&self, storage: &dyn StorageEngine, ops: &[Operation]) -> Result<()> {
+        for operation in ops {
+            match operation {
+                Operation::Set { namespace, key, value } => {
+                    validate_target(namespace.as_deref(), key)?;
+                    self.check_sizes(namespace.as_deref(), key, value)?;
+                }
+                Operation::SetWithExpiry { namespace, key, value, .. } => {
+                    validate_target(namespace.as_deref(), key)?;
+                    self.check_sizes(namespace.as_deref(), key, value)?;
+                    if !storage.supports_expiry() {
+                        return Err(LohError::InvalidArgument("this storage engine does not support key expiry".to_string()));
+                    }
+                }
+                Operation::SetWithMeta { namespace, key, value, .. } => {
+                    validate_target(namespace.as_deref(), key)?;
+                    self.check_sizes(namespace.as_deref(), key, value)?;
+                    if !storage.supports_user_meta() {
+                        return Err(LohError::InvalidArgument("this storage engine does not support key metadata".to_string()));
+                    }
+                }
+                Operation::Delete { namespace, key } | Operation::Expire { namespace, key } => {
+                    validate_target(namespace.as_deref(), key)?
+                }
+                Operation::DeletePrefix { namespace: Some(ns), .. } => namespace::validate_namespace(ns)?,
+                Operation::DeletePrefix { namespace: None, .. } => {}
+                Operation::Append { namespace, key, chunk, .. } => {
+                    let ns = namespace.as_deref();
+                    validate_target(ns, key)?;
+                    self.check_sizes(ns, key, chunk)?;
+                    
+                    let storage_key = namespace::storage_key(ns, key);
+                    let fresh = ttl::is_expired(storage, &storage_key, self.now_ms())? || storage.retrieve(&storage_key)?.is_none();
+                    let value = chunked::appended(storage, &storage_key, chunk, fresh)?;
+                    chunked::split_chunks(&value)?;
+                    self.check_value_limit(value.len())?;
+                }
+                Operation::Batch { ops } => self.validate_ops(storage, ops)?,
+                Operation::Persist { namespace, key } => {
+                    validate_target(namespace.as_deref(), key)?;
+                    if !storage.supports_expiry() {
+                        return Err(LohError::InvalidArgument("this storage engine does not support key expiry".to_string()));
+                    }
+                }
+                Operation::Rename { namespace, from, to, value } => {
+                    validate_target(namespace.as_deref(), from)?;
+                    validate_target(namespace.as_deref(), to)?;
+                    self.check_sizes(namespace.as_deref(), to, value)?;
+                }
+                Operation::StreamChunk { .. } | Operation::StreamCommit { .. } => {
+                    return Err(LohError::InvalidArgument(
+                        "streamed values are written with open_value_writer, not in a batch".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the namespace name, or for the default namespace the key
+fn validate_target(ns: Option<&str>, key: &str) -> Result<()> {
+    match ns {
+        Some(ns) => namespace::validate_namespace(ns),
+        None => namespace::validate_key(key),
+    }
+}
@@ -0,0 +1,185 @@
+use crate::db::kv::{configured_file_engine, configured_storage};
+use crate::db::{crypto, DatabaseConfig, Database, DirLock, StorageEngine, WriteAheadLog};
+use crate::{LohError, Result};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+/// Outcome of `Database::verify`, serializable for printing as JSON
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// WAL segment files found
+    pub wal_segments: usize,
+    /// Records read and decoded before the first problem (if any)
+    pub wal_records: u64,
+    /// Sequence number of the first record the WAL should hold
+    pub wal_first_seq: u64,
+    /// Sequence number of the last readable record (`wal_first_seq - 1` if none)
+    pub wal_last_seq: u64,
+    /// Where the WAL stopped being readable, if it did
+    pub wal_corruption: Option<WalCorruption>,
+    /// Whether the data file, or any of its shards, exists
+    pub data_file_present: bool,
+    /// Keys in the data file, once it loaded and passed its checksum
+    pub data_file_keys: usize,
+    /// Why the data file failed to load, if it did
+    pub data_file_error: Option<String>,
+    /// Whether the data file and the WAL together hold every write: both
+    /// read cleanly, and if the WAL no longer starts at the first record,
+    /// a data file covering the rest exists
+    pub consistent: bool,
+}
+
+/// The first unreadable spot in the WAL
+#[derive(Debug, Clone, Serialize)]
+pub struct WalCorruption {
+    pub segment: String,
+    /// Byte offset of the first unreadable record, or 0 if the whole
+    /// segment is missing or unreadable
+    pub offset: u64,
+    pub error: String,
+    /// Whether the problem is past the header of the last segment, so
+    /// `repair` can cut it off
+    pub at_tail: bool,
+}
+
+impl Database {
+    /// Check the WAL and data file of the database `config` describes
+    /// without opening it or changing anything
+    ///
+    /// Every WAL record is read and decoded, and the data file is loaded
+    /// and checksummed. WAL records carry no checksum of their own, so a
+    /// damaged record is only found if it no longer frames or decodes.
+    /// Takes no lock, so the result may be stale if the database is open.
+    pub fn verify(config: &DatabaseConfig) -> Result<VerifyReport> {
+        let dir = Path::new(&config.data_dir);
+        if !dir.is_dir() {
+            return Err(LohError::NotFound(format!("data directory '{}'", config.data_dir)));
+        }
+        let encryptor = config.encryption_key.map(crypto::encryptor_for_key).transpose()?;
+        
+        let mut report = VerifyReport::default();
+        let mut wal = WriteAheadLog::open_read_only(config.effective_wal_dir())?.with_format(config.serialization_format);
+        if let Some(encryptor) = &encryptor {
+            wal = wal.with_encryptor(encryptor.clone());
+        }
+        if let Some(compressor) = &config.wal_compressor {
+            wal = wal.with_compressor(compressor.clone());
+        }
+        let segments = wal.segment_paths();
+        report.wal_segments = segments.len();
+        report.wal_first_seq = wal.first_seq();
+        report.wal_last_seq = wal.first_seq() - 1;
+        
+        let check_format = wal.check_format(config.serialization_format);
+        let mut entries = wal.iter_entries();
+        let error = match check_format {
+            Ok(()) => entries.by_ref().find_map(|entry| match entry {
+                Ok(located) => {
+                    report.wal_records += 1;
+                    report.wal_last_seq = located.entry.seq;
+                    None
+                }
+                Err(e) => Some(e),
+            }),
+            Err(e) => Some(e),
+        };
+        if let Some(error) = error {
+            let (segment, offset) = match entries.failed_at() {
+                Some((path, offset)) => (path.to_path_buf(), offset),
+                None => (segments.first().cloned().unwrap_or_default(), 0),
+            };
+            report.wal_corruption = Some(WalCorruption {
+                at_tail: offset > 0 && segments.last() == Some(&segment),
+                segment: segment.display().to_string(),
+                offset,
+                error: error.to_string(),
+            });
+        }
+        
+        report.data_file_present = config.effective_data_files().iter().any(|path| path.exists());
+        let mut engine = configured_storage(config, encryptor.as_ref());
+        match engine.initialize() {
+            Ok(()) => report.data_file_keys = engine.key_count()?,
+            Err(e) => report.data_file_error = Some(e.to_string()),
+        }
+        
+        report.consistent = report.wal_corruption.is_none()
+            && report.data_file_error.is_none()
+            && (report.wal_first_seq == 1 || report.data_file_present);
+        Ok(report)
+    }
+    
+    /// Lay out the files of a database for `config` ahead of its first open,
+    /// or complete a partial layout
+    ///
+    /// Afterwards the directory holds its manifest, a WAL segment and the
+    /// data file (one per shard), whatever it held before:
+    ///
+    /// - An empty or missing directory gets a new, empty database.
+    /// - A WAL without a data file is recovered if the WAL still starts at
+    ///   the first record: the data file is rebuilt from it. If earlier
+    ///   records were checkpointed into the missing data file, it fails
+    ///   with `LohError::DataCorrupt`, as `open` does, instead of starting
+    ///   over without them.
+    /// - A complete database is opened and closed, which recovers and
+    ///   checkpoints it without changing its contents.
+    ///
+    /// Takes the directory lock, so the database must not be open. Only
+    /// on-disk, writable configurations can be initialized.
+    pub fn init(config: &DatabaseConfig) -> Result<()> {
+        if config.in_memory || config.read_only {
+            return Err(LohError::InvalidArgument(
+                "only an on-disk, writable database can be initialized".to_string(),
+            ));
+        }
+        Database::open(config.clone())?.close()?;
+        
+        // Nothing was checkpointed if nothing was ever written
+        let _lock = DirLock::acquire_with_mode(&config.data_dir, config.effective_file_mode())?;
+        let encryptor = config.encryption_key.map(crypto::encryptor_for_key).transpose()?;
+        for data_file in config.effective_data_files().into_iter().chain(config.effective_blob_file()) {
+            if !data_file.exists() {
+                configured_file_engine(config, encryptor.as_ref()).with_data_file(data_file).flush()?;
+            }
+        }
+        log::debug!("initialized '{}'", config.data_dir);
+        Ok(())
+    }
+    
+    /// Cut a corrupt tail off the WAL, then verify again
+    ///
+    /// Only a problem in the last segment is repaired: the segment is
+    /// truncated at the first unreadable record, losing that record and
+    /// anything after it. Damage further back, or in the data file, fails
+    /// with an error instead, since cutting there would drop good records
+    /// too. Takes the directory lock, so the database must not be open.
+    pub fn repair(config: &DatabaseConfig) -> Result<VerifyReport> {
+        if !Path::new(&config.data_dir).is_dir() {
+            return Err(LohError::NotFound(format!("data directory '{}'", config.data_dir)));
+        }
+        let _lock = DirLock::acquire(&config.data_dir)?;
+        let report = Self::verify(config)?;
+        if let Some(data_error) = &report.data_file_error {
+            return Err(LohError::DataCorrupt(format!("{}; repair only fixes the WAL tail", data_error)));
+        }
+        let Some(corruption) = &report.wal_corruption else {
+            return Ok(report);
+        };
+        if !corruption.at_tail {
+            return Err(LohError::WalCorrupt(format!(
+                "{} (segment '{}', offset {}) is not a damaged tail and cannot be repaired",
+                corruption.error, corruption.segment, corruption.offset
+            )));
+        }
+        
+        log::warn!(
+            "truncating WAL segment '{}' at offset {}: {}",
+            corruption.segment, corruption.offset, corruption.error
+        );
+        let file = OpenOptions::new().write(true).open(&corruption.segment)?;
+        file.set_len(corruption.offset)?;
+        file.sync_all()?;
+        Self::verify(config)
+    }
+}
@@ -1,107 +1,1457 @@
-use crate::Result;
+use crate::db::{perms, Clock, Compressor, Encryptor, RecoveryProgress, SerializationFormat, SystemClock};
+use crate::db::wal_backend::{FileBackend, SegmentFile, SegmentReader, WalBackend};
+use crate::{LohError, Result};
 use serde::{Deserialize, Serialize};
-use std::fs::{File, OpenOptions};
-use std::io::{Write, Seek, SeekFrom};
-use std::path::Path;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Magic bytes at the start of every versioned WAL file
+const WAL_MAGIC: &[u8; 6] = b"LOHWAL";
+
+/// Current on-disk file format
+///
+/// Every record is framed as `len: u32 | seq: u64 | timestamp_ms: u64 | payload`.
+/// Version 3 files start with `magic | version | serialization format: u8 |
+/// base_seq: u64`, and each payload with a flag byte saying whether the
+/// rest is compressed. Version 2 payloads have no flag byte; version 1
+/// files also lack the format byte and hold bincode. Logs without the file
+/// header predate versioning and hold bare `len: u32 | payload` frames.
+pub const WAL_FORMAT_VERSION: u8 = 3;
+
+/// Oldest version whose header carries the serialization format
+const FORMAT_BYTE_VERSION: u8 = 2;
+
+/// Flag byte of a record payload stored as it is
+const RECORD_STORED: u8 = 0;
+
+/// Flag byte of a record payload compressed with the log's compressor
+const RECORD_COMPRESSED: u8 = 1;
+
+/// Magic + version + serialization format + sequence number of the first record
+const FILE_HEADER_LEN: u64 = WAL_MAGIC.len() as u64 + 1 + 1 + 8;
+
+/// Version 1 header: no serialization format byte
+const V1_FILE_HEADER_LEN: u64 = WAL_MAGIC.len() as u64 + 1 + 8;
+
+/// Bytes of record header preceding each payload
+const RECORD_HEADER_LEN: usize = 4 + 8 + 8;
+
+/// Times a transient write failure is retried by default
+pub const DEFAULT_WAL_WRITE_RETRIES: u32 = 3;
+
+/// Wait before the first retry by default, doubled for each one after
+pub const DEFAULT_WAL_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+type SegmentWrapper = Arc<dyn Fn(Box<dyn SegmentFile>) -> Box<dyn SegmentFile> + Send + Sync>;
+
+/// Whether a failed write may succeed if simply tried again
+///
+/// Interrupted calls, `WouldBlock` and timeouts (as from a network
+/// filesystem) are transient. Anything else is permanent, a full disk
+/// included: retrying cannot free space, and the caller should hear about
+/// it right away.
+pub fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Run `op` until it succeeds, fails permanently, or has been retried
+/// `retries` times, sleeping `backoff`, then twice as long, between tries
+fn retry_transient(what: &str, retries: u32, backoff: Duration, mut op: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+    let mut delay = backoff;
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                log::warn!("WAL {} failed: {}; retrying in {:?} ({}/{})", what, e, delay, attempt, retries);
+                thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Largest record payload the `u32` length prefix can describe
+pub const MAX_RECORD_BYTES: u64 = u32::MAX as u64;
+
+/// Upper bound on what encoding and encryption add around a set's key and value
+const RECORD_OVERHEAD_BYTES: u64 = 64;
+
+/// Single-file log written before segmentation; migrated on the first writable open
+const LEGACY_WAL_FILE: &str = "wal.log";
+
+/// Default size at which the active segment is rolled over
+pub const DEFAULT_WAL_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Operation {
+    Set { namespace: Option<String>, key: String, value: Vec<u8> },
+    Delete { namespace: Option<String>, key: String },
+    /// A set of a key that expires at `expires_ms` (milliseconds since the
+    /// Unix epoch); see `Database::set_with_ttl`
+    SetWithExpiry { namespace: Option<String>, key: String, value: Vec<u8>, expires_ms: u64 },
+    /// A delete of `keys`, every key in `namespace` starting with `prefix`,
+    /// logged as one record so replay removes all of them or none; see
+    /// `Database::delete_prefix`
+    DeletePrefix { namespace: Option<String>, prefix: String, keys: Vec<String> },
+    /// `chunk` appended to the chunked value at `key`, which starts over
+    /// from nothing if `fresh`; see `Database::append`
+    Append { namespace: Option<String>, key: String, chunk: Vec<u8>, fresh: bool },
+    /// A set of a key together with its string attributes; see
+    /// `Database::set_with_meta`
+    SetWithMeta { namespace: Option<String>, key: String, value: Vec<u8>, meta: HashMap<String, String> },
+    /// Several writes logged as one record, so replay applies all of them or
+    /// none; see `Transaction::commit`. Holds only `Set` and `Delete`.
+    Batch { ops: Vec<Operation> },
+    /// Removal of a key's expiry, making it permanent; see `Database::persist`
+    Persist { namespace: Option<String>, key: String },
+    /// `from` moved to `to` with its expiry and attributes, replacing any
+    /// value at `to`; see `Database::rename`. Carries the value so change
+    /// history can report the new key's contents.
+    Rename { namespace: Option<String>, from: String, to: String, value: Vec<u8> },
+    /// Removal of a key by the expiry sweep once its TTL passed; replays as
+    /// a `Delete`, but change history reports `ChangeEvent::Expired`
+    Expire { namespace: Option<String>, key: String },
+    /// One piece of a value being streamed to `key`, held back from storage
+    /// until the matching `StreamCommit`; `stream` is the sequence number of
+    /// the stream's first chunk. See `Database::open_value_writer`.
+    StreamChunk { namespace: Option<String>, key: String, stream: u64, chunk: Vec<u8> },
+    /// The end of stream `stream`: its `chunks` chunks, concatenated in
+    /// order, become the value at `key`. A stream without one is discarded.
+    StreamCommit { namespace: Option<String>, key: String, stream: u64, chunks: u64 },
+}
+
+impl Operation {
+    /// Bytes of keys, values and attributes the operation carries, which its
+    /// encoded record holds at least, before any compression
+    pub(crate) fn payload_bytes(&self) -> u64 {
+        let ns = |namespace: &Option<String>| namespace.as_ref().map_or(0, |ns| ns.len() as u64);
+        match self {
+            Operation::Set { namespace, key, value }
+            | Operation::SetWithExpiry { namespace, key, value, .. } => ns(namespace) + (key.len() + value.len()) as u64,
+            Operation::Delete { namespace, key }
+            | Operation::Persist { namespace, key }
+            | Operation::Expire { namespace, key }
+            | Operation::StreamCommit { namespace, key, .. } => ns(namespace) + key.len() as u64,
+            Operation::DeletePrefix { namespace, prefix, keys } => {
+                ns(namespace) + prefix.len() as u64 + keys.iter().map(|key| key.len() as u64).sum::<u64>()
+            }
+            Operation::Append { namespace, key, chunk, .. }
+            | Operation::StreamChunk { namespace, key, chunk, .. } => ns(namespace) + (key.len() + chunk.len()) as u64,
+            Operation::SetWithMeta { namespace, key, value, meta } => {
+                let meta_bytes: u64 = meta.iter().map(|(name, value)| (name.len() + value.len()) as u64).sum();
+                ns(namespace) + (key.len() + value.len()) as u64 + meta_bytes
+            }
+            Operation::Batch { ops } => ops.iter().map(Operation::payload_bytes).sum(),
+            Operation::Rename { namespace, from, to, value } => ns(namespace) + (from.len() + to.len() + value.len()) as u64,
+        }
+    }
+}
+
+/// `Operation` as it was before namespaces, the shape of every record in a
+/// pre-versioning log
+///
+/// Such records decode into the default namespace.
+#[derive(Deserialize)]
+enum LegacyOperation {
     Set { key: String, value: Vec<u8> },
     Delete { key: String },
 }
 
-pub struct WriteAheadLog {
-    file: File,
-    path: String,
+impl From<LegacyOperation> for Operation {
+    fn from(operation: LegacyOperation) -> Self {
+        match operation {
+            LegacyOperation::Set { key, value } => Operation::Set { namespace: None, key, value },
+            LegacyOperation::Delete { key } => Operation::Delete { namespace: None, key },
+        }
+    }
 }
 
-impl WriteAheadLog {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path_str = path.as_ref().to_string_lossy().to_string();
-        
-        // Create parent directory if it doesn't exist
-        if let Some(parent) = path.as_ref().parent() {
-            std::fs::create_dir_all(parent)?;
+/// Decode a record payload of a pre-versioning log, always bincode
+fn decode_legacy(payload: &[u8]) -> Result<Operation> {
+    Ok(SerializationFormat::Bincode.deserialize::<LegacyOperation>(payload)?.into())
+}
+
+/// A replayed WAL record together with its header metadata
+#[derive(Debug, Clone)]
+pub struct WalEntry {
+    /// Log sequence number, starting at 1 and increasing by one per record
+    pub seq: u64,
+    /// Wall-clock time of the append, in milliseconds since the Unix epoch
+    ///
+    /// Zero for records upgraded from a pre-versioning log.
+    pub timestamp_ms: u64,
+    pub operation: Operation,
+}
+
+/// What `WriteAheadLog::replay` recovered
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReplayReport {
+    /// Records passed to the replay callback
+    pub applied: u64,
+    /// Records read but not passed on: the first one that failed to decode
+    /// and every record after it
+    pub skipped: u64,
+    /// Why the replay stopped applying records, if it did
+    pub first_error: Option<String>,
+}
+
+/// A record yielded by `WriteAheadLog::iter_entries`, with its location on disk
+#[derive(Debug, Clone)]
+pub struct LocatedEntry {
+    /// Segment file holding the record
+    pub segment: PathBuf,
+    /// Byte offset of the record header within the segment
+    pub offset: u64,
+    pub entry: WalEntry,
+}
+
+/// Iterator over the records of a log; see `WriteAheadLog::iter_entries`
+pub struct WalEntries<'a> {
+    wal: &'a WriteAheadLog,
+    /// Index into `wal.segments` of the segment being read
+    segment: usize,
+    file: Option<Box<dyn SegmentReader>>,
+    legacy_seq: u64,
+    expected_seq: u64,
+    /// Offset of the record being read in the current segment
+    offset: u64,
+    failed_at: Option<(PathBuf, u64)>,
+    done: bool,
+}
+
+impl WalEntries<'_> {
+    fn fail(&mut self, error: LohError) -> Option<Result<LocatedEntry>> {
+        self.done = true;
+        if let Some(segment) = self.wal.segments.get(self.segment) {
+            self.failed_at = Some((segment.path.clone(), self.offset));
         }
-        
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .read(true)
-            .open(&path)?;
+        Some(Err(error))
+    }
+    
+    /// Segment and byte offset of the problem, once the iterator has
+    /// stopped on an error
+    ///
+    /// The offset is that of the first unreadable record, or 0 when the
+    /// segment itself is missing or unreadable.
+    pub fn failed_at(&self) -> Option<(&Path, u64)> {
+        self.failed_at.as_ref().map(|(path, offset)| (path.as_path(), *offset))
+    }
+}
+
+impl Iterator for WalEntries<'_> {
+    type Item = Result<LocatedEntry>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let segment = self.wal.segments.get(self.segment)?;
+            
+            let file = match &mut self.file {
+                Some(file) => file,
+                None => {
+                    if segment.base_seq != self.expected_seq {
+                        return self.fail(LohError::WalCorrupt(format!(
+                            "segment '{}' starts at sequence {} but {} was expected; a segment is missing or damaged",
+                            segment.path.display(), segment.base_seq, self.expected_seq
+                        )));
+                    }
+                    let opened = self.wal.backend.open_read(&segment.path).and_then(|mut file| {
+                        file.seek(SeekFrom::Start(segment.header_len))?;
+                        Ok(file)
+                    });
+                    match opened {
+                        Ok(file) => {
+                            self.legacy_seq = segment.base_seq - 1;
+                            self.file.insert(file)
+                        }
+                        Err(e) => return self.fail(e),
+                    }
+                }
+            };
+            
+            let offset = match file.stream_position() {
+                Ok(offset) => offset,
+                Err(e) => return self.fail(e.into()),
+            };
+            self.offset = offset;
+            let frame = match read_frame(file, segment.versioned, &mut self.legacy_seq) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    // Either the segment ended cleanly or its last record is incomplete
+                    let len = match self.wal.backend.len(&segment.path) {
+                        Ok(len) => len,
+                        Err(e) => return self.fail(e),
+                    };
+                    if offset < len {
+                        return self.fail(LohError::WalCorrupt(format!(
+                            "'{}' ends with a torn record: {} bytes at offset {} are incomplete",
+                            segment.path.display(), len - offset, offset
+                        )));
+                    }
+                    self.file = None;
+                    self.segment += 1;
+                    self.offset = 0;
+                    continue;
+                }
+                Err(e) => return self.fail(e),
+            };
             
+            let payload = match self.wal.open_payload(segment, frame.seq, frame.payload) {
+                Ok(payload) => payload,
+                Err(e) => return self.fail(e),
+            };
+            let operation = match segment.decode(&payload) {
+                Ok(operation) => operation,
+                Err(e) => return self.fail(LohError::WalCorrupt(format!(
+                    "record {} at offset {} of '{}' cannot be decoded: {}",
+                    frame.seq, offset, segment.path.display(), e
+                ))),
+            };
+            
+            self.expected_seq = frame.seq + 1;
+            return Some(Ok(LocatedEntry {
+                segment: segment.path.clone(),
+                offset,
+                entry: WalEntry { seq: frame.seq, timestamp_ms: frame.timestamp_ms, operation },
+            }));
+        }
+        None
+    }
+}
+
+/// A raw record frame; the payload may still be flagged, encrypted and compressed
+struct Frame {
+    seq: u64,
+    timestamp_ms: u64,
+    payload: Vec<u8>,
+}
+
+/// One file of the log, holding a contiguous run of sequence numbers
+struct Segment {
+    index: u64,
+    path: PathBuf,
+    /// False only for a pre-versioning `wal.log` opened read-only
+    versioned: bool,
+    /// Sequence number of the first record in the segment
+    base_seq: u64,
+    /// Encoding of the segment's record payloads
+    format: SerializationFormat,
+    /// Whether record payloads start with a compression flag (version 3 on)
+    flagged: bool,
+    /// Bytes before the first record
+    header_len: u64,
+}
+
+impl Segment {
+    /// Decode a record payload of the segment, once opened
+    fn decode(&self, payload: &[u8]) -> Result<Operation> {
+        if self.versioned {
+            self.format.deserialize(payload)
+        } else {
+            decode_legacy(payload)
+        }
+    }
+    
+    fn from_header(index: u64, path: PathBuf, header: Option<FileHeader>) -> Result<Self> {
+        let header = header
+            .ok_or_else(|| LohError::WalCorrupt(format!("'{}' is not a WAL segment", path.display())))?;
         Ok(Self {
-            file,
-            path: path_str,
+            index,
+            path,
+            versioned: true,
+            base_seq: header.base_seq,
+            format: header.format,
+            flagged: header.flagged,
+            header_len: header.len,
         })
     }
+}
+
+/// What a versioned file header records
+struct FileHeader {
+    base_seq: u64,
+    format: SerializationFormat,
+    /// Whether record payloads start with a compression flag
+    flagged: bool,
+    len: u64,
+}
+
+/// Append-only operation log split into size-bounded segment files
+///
+/// Segments are named `wal.000001.log`, `wal.000002.log`, ... in the data
+/// directory; the highest-numbered one is active and receives appends. Index
+/// gaps are harmless, but every segment must continue the sequence numbers of
+/// the one before it.
+pub struct WriteAheadLog {
+    dir: PathBuf,
+    /// Where the segments are kept
+    backend: Arc<dyn WalBackend>,
+    segments: Vec<Segment>,
+    /// Active segment opened for appending; `None` when read-only
+    file: Option<BufWriter<Box<dyn SegmentFile>>>,
+    /// Put in front of each segment file opened for appending
+    segment_wrapper: Option<SegmentWrapper>,
+    /// Bytes of records held in memory before they are written to the
+    /// active segment; 0 writes every record straight through
+    buffer_bytes: usize,
+    active_len: u64,
+    /// Combined length of the segments before the active one
+    sealed_bytes: u64,
+    segment_bytes: u64,
+    read_only: bool,
+    /// Set for `in_memory` logs, which never touch the filesystem
+    in_memory: bool,
+    encryptor: Option<Arc<dyn Encryptor>>,
+    /// Compresses appended records that it makes smaller
+    compressor: Option<Arc<dyn Compressor>>,
+    /// Encoding of appended records
+    format: SerializationFormat,
+    /// Sequence number the next append will use
+    next_seq: u64,
+    /// Sequence number of the last record known to be fsynced
+    synced_seq: u64,
+    /// Fsync once this many records are unsynced; 0 leaves it to `sync`
+    commit_batch: usize,
+    /// Timestamp given to the most recent append
+    last_timestamp_ms: u64,
+    /// Source of record timestamps
+    clock: Arc<dyn Clock>,
+    /// Size in bytes past which each append calls the trigger
+    size_trigger: Option<(u64, Box<dyn Fn() + Send>)>,
+    /// Times a transient write failure is retried before `append` fails
+    write_retries: u32,
+    /// Wait before the first retry, doubled for each one after
+    retry_backoff: Duration,
+    /// Why appends are refused: a record was left partly written in the
+    /// active segment and could not be cut off again
+    failed: Option<String>,
+}
+
+impl WriteAheadLog {
+    /// Open or create the log in `dir` for appending
+    ///
+    /// A single-file `wal.log` from an older version is moved into the first
+    /// segment, rewriting it in the current format if it predates versioning.
+    /// Zero-length segments left by a crash during rollover are removed, and
+    /// so is a record a crash left half-written at the end of the active
+    /// segment.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::open_with_mode(dir, None)
+    }
+    
+    /// `open`, creating the directory and new segment files with
+    /// `file_mode` (Unix permission bits)
+    ///
+    /// Existing files and directories keep their permissions.
+    pub fn open_with_mode<P: AsRef<Path>>(dir: P, file_mode: Option<u32>) -> Result<Self> {
+        let dir = dir.as_ref();
+        perms::create_dir_all(dir, file_mode)?;
+        migrate_legacy_file(dir, file_mode)?;
+        Self::open_with_backend(dir, Arc::new(FileBackend::new(file_mode)))
+    }
+    
+    /// Open or create the log in `dir` of `backend` for appending
+    ///
+    /// `dir` only names the segments to the backend; nothing is created
+    /// for it. Zero-length segments and a torn last record are removed, as
+    /// with `open`, but a legacy `wal.log` is not looked for.
+    pub fn open_with_backend<P: AsRef<Path>>(dir: P, backend: Arc<dyn WalBackend>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut segments = Vec::new();
+        let mut total_len = 0;
+        for (index, path) in backend.list_segments(&dir)? {
+            let len = backend.len(&path)?;
+            if len == 0 {
+                backend.remove(&path)?;
+                continue;
+            }
+            total_len += len;
+            let header = read_file_header(&mut *backend.open_read(&path)?, &path)?;
+            segments.push(Segment::from_header(index, path, header)?);
+        }
+        
+        let mut wal = Self {
+            dir,
+            backend,
+            segments,
+            file: None,
+            segment_wrapper: None,
+            buffer_bytes: 0,
+            active_len: 0,
+            sealed_bytes: 0,
+            segment_bytes: DEFAULT_WAL_SEGMENT_BYTES,
+            read_only: false,
+            in_memory: false,
+            encryptor: None,
+            compressor: None,
+            format: SerializationFormat::default(),
+            next_seq: 1,
+            synced_seq: 0,
+            commit_batch: 0,
+            last_timestamp_ms: 0,
+            clock: Arc::new(SystemClock),
+            size_trigger: None,
+            write_retries: DEFAULT_WAL_WRITE_RETRIES,
+            retry_backoff: DEFAULT_WAL_RETRY_BACKOFF,
+            failed: None,
+        };
+        let valid_len = wal.recover_sequence()?;
+        // What is on disk at open is as durable as it will get
+        wal.synced_seq = wal.last_seq();
+        
+        match wal.segments.last() {
+            Some(active) => {
+                let len = wal.backend.len(&active.path)?;
+                wal.sealed_bytes = total_len - len;
+                // Left by a crash mid-append; a record appended after it would be misread
+                if valid_len < len {
+                    log::warn!(
+                        "cutting a torn record of {} bytes off the end of WAL segment '{}'",
+                        len - valid_len, active.path.display()
+                    );
+                    wal.backend.truncate(&active.path, valid_len)?;
+                }
+                let file = wal.backend.open_append(&active.path)?;
+                wal.file = Some(BufWriter::with_capacity(0, wal.wrap(file)));
+                wal.active_len = valid_len;
+            }
+            None => wal.start_segment(1)?,
+        }
+        Ok(wal)
+    }
     
-    pub fn append(&mut self, operation: &Operation) -> Result<()> {
-        let serialized = bincode::serialize(operation)?;
-        let len = serialized.len() as u32;
+    /// Open the log in `dir` for replay only; `append` and `truncate` will fail
+    ///
+    /// Nothing on disk is changed: an unmigrated `wal.log` is read in place
+    /// ahead of any segments, and zero-length segments are skipped.
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let backend = FileBackend::default();
         
-        // Write length prefix followed by the operation
-        self.file.write_all(&len.to_le_bytes())?;
-        self.file.write_all(&serialized)?;
-        self.file.flush()?;
+        let mut segments = Vec::new();
+        let legacy_path = dir.join(LEGACY_WAL_FILE);
+        if legacy_path.exists() {
+            let segment = match read_file_header(&mut File::open(&legacy_path)?, &legacy_path)? {
+                Some(header) => Segment::from_header(0, legacy_path, Some(header))?,
+                None => Segment {
+                    index: 0,
+                    path: legacy_path,
+                    versioned: false,
+                    base_seq: 1,
+                    format: SerializationFormat::Bincode,
+                    flagged: false,
+                    header_len: 0,
+                },
+            };
+            segments.push(segment);
+        }
         
+        for (index, path) in backend.list_segments(&dir)? {
+            if backend.len(&path)? == 0 {
+                continue;
+            }
+            let header = read_file_header(&mut *backend.open_read(&path)?, &path)?;
+            segments.push(Segment::from_header(index, path, header)?);
+        }
+        let mut sealed_bytes = 0;
+        for segment in &segments {
+            sealed_bytes += backend.len(&segment.path)?;
+        }
+        
+        let mut wal = Self {
+            dir,
+            backend: Arc::new(backend),
+            segments,
+            file: None,
+            segment_wrapper: None,
+            buffer_bytes: 0,
+            active_len: 0,
+            sealed_bytes,
+            segment_bytes: DEFAULT_WAL_SEGMENT_BYTES,
+            read_only: true,
+            in_memory: false,
+            encryptor: None,
+            compressor: None,
+            format: SerializationFormat::default(),
+            next_seq: 1,
+            synced_seq: 0,
+            commit_batch: 0,
+            last_timestamp_ms: 0,
+            clock: Arc::new(SystemClock),
+            size_trigger: None,
+            write_retries: DEFAULT_WAL_WRITE_RETRIES,
+            retry_backoff: DEFAULT_WAL_RETRY_BACKOFF,
+            failed: None,
+        };
+        wal.recover_sequence()?;
+        // What is on disk at open is as durable as it will get
+        wal.synced_seq = wal.last_seq();
+        Ok(wal)
+    }
+    
+    /// A log that keeps nothing: appends only assign sequence numbers
+    ///
+    /// Used by in-memory databases, where there is no disk to recover from.
+    pub fn in_memory() -> Self {
+        Self {
+            dir: PathBuf::new(),
+            backend: Arc::new(FileBackend::default()),
+            segments: Vec::new(),
+            file: None,
+            segment_wrapper: None,
+            buffer_bytes: 0,
+            active_len: 0,
+            sealed_bytes: 0,
+            segment_bytes: DEFAULT_WAL_SEGMENT_BYTES,
+            read_only: false,
+            in_memory: true,
+            encryptor: None,
+            compressor: None,
+            format: SerializationFormat::default(),
+            next_seq: 1,
+            synced_seq: 0,
+            commit_batch: 0,
+            last_timestamp_ms: 0,
+            clock: Arc::new(SystemClock),
+            size_trigger: None,
+            write_retries: DEFAULT_WAL_WRITE_RETRIES,
+            retry_backoff: DEFAULT_WAL_RETRY_BACKOFF,
+            failed: None,
+        }
+    }
+    
+    /// Encrypt every record payload with `encryptor`
+    ///
+    /// All records in the log must then be encrypted with the same key;
+    /// replay fails with a decryption error otherwise. Record headers
+    /// (sequence number and timestamp) are stored in the clear.
+    pub fn with_encryptor(mut self, encryptor: Arc<dyn Encryptor>) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+    
+    /// Compress each appended record payload with `compressor`, before it
+    /// is encrypted
+    ///
+    /// A record is only stored compressed when that makes it smaller, and a
+    /// flag byte in front of each payload says which it is, so small
+    /// records cost one byte and a log mixing both replays as usual. Reading
+    /// compressed records needs the same compressor; without one they fail
+    /// with a compression error.
+    pub fn with_compressor(mut self, compressor: Arc<dyn Compressor>) -> Self {
+        self.compressor = Some(compressor);
+        self
+    }
+    
+    /// Encode appended records with `format` instead of bincode
+    ///
+    /// Each segment records its own format and is always read with it; if
+    /// the active segment uses another format, the next append starts a new
+    /// segment (replacing the active one if it is still empty). Use
+    /// `check_format` to reject a log holding records in other formats.
+    pub fn with_format(mut self, format: SerializationFormat) -> Self {
+        self.format = format;
+        self
+    }
+    
+    /// Fail if any segment holds records written in a format other than `format`
+    pub fn check_format(&self, format: SerializationFormat) -> Result<()> {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if !self.segment_is_empty(i) {
+                format.expect(segment.format, &format!("WAL segment '{}'", segment.path.display()))?;
+            }
+        }
         Ok(())
     }
     
-    pub fn replay<F>(&mut self, mut callback: F) -> Result<()>
+    /// Whether segment `i` holds no records: the next one starts at the same sequence number
+    fn segment_is_empty(&self, i: usize) -> bool {
+        let next_base = self.segments.get(i + 1).map_or(self.next_seq, |s| s.base_seq);
+        self.segments[i].base_seq == next_base
+    }
+    
+    /// Roll over to a new segment once the active one reaches `segment_bytes`
+    pub fn with_segment_bytes(mut self, segment_bytes: u64) -> Self {
+        self.segment_bytes = segment_bytes;
+        self
+    }
+    
+    /// Hold up to `buffer_bytes` of records in memory and write them to the
+    /// active segment together, instead of one write per append
+    ///
+    /// Small appends then cost no system call until the buffer fills. The
+    /// buffer is written out by `flush`, `sync`, a rollover (and so every
+    /// checkpoint), and when the log is dropped, but records still in it
+    /// when the process crashes are lost even though `append` returned.
+    /// Only use it where losing the last few writes on a crash is
+    /// acceptable. The default, 0, writes every record straight through.
+    pub fn with_buffer_bytes(mut self, buffer_bytes: usize) -> Self {
+        self.buffer_bytes = buffer_bytes;
+        if let Some(file) = self.file.take() {
+            // Nothing is buffered yet when a log is being configured
+            let (file, _) = file.into_parts();
+            self.file = Some(BufWriter::with_capacity(buffer_bytes, file));
+        }
+        self
+    }
+    
+    /// Retry a write to the active segment that fails with a transient
+    /// error (see `is_transient`) up to `retries` times, waiting `backoff`
+    /// before the first retry and doubling it for each one after
+    ///
+    /// Bytes a failed write did get out are not written again. Once the
+    /// retries are exhausted, or on any other error, the write fails as
+    /// it would without retries. The fsync itself is never retried: after
+    /// a failed fsync the kernel may have dropped the pages it could not
+    /// write, so a second one could succeed without them reaching the disk.
+    pub fn with_write_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.write_retries = retries;
+        self.retry_backoff = backoff;
+        self
+    }
+    
+    /// Pass each segment file opened for appending, the active one
+    /// included, through `wrapper` and write through what it returns
+    pub fn with_segment_wrapper(
+        mut self,
+        wrapper: impl Fn(Box<dyn SegmentFile>) -> Box<dyn SegmentFile> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        self.segment_wrapper = Some(Arc::new(wrapper));
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+            let active = file.get_ref().try_clone()?;
+            self.file = Some(BufWriter::with_capacity(self.buffer_bytes, self.wrap(active)));
+        }
+        Ok(self)
+    }
+    
+    fn wrap(&self, file: Box<dyn SegmentFile>) -> Box<dyn SegmentFile> {
+        match &self.segment_wrapper {
+            Some(wrapper) => wrapper(file),
+            None => file,
+        }
+    }
+    
+    /// Fsync the log after every `commit_batch` appends
+    ///
+    /// Caps `unsynced_count` below `commit_batch`, so a crash of the machine
+    /// loses at most `commit_batch - 1` acknowledged records. The default, 0,
+    /// never syncs on its own: records wait for `sync`, a rollover, or a
+    /// checkpoint.
+    pub fn with_commit_batch(mut self, commit_batch: usize) -> Self {
+        self.commit_batch = commit_batch;
+        self
+    }
+    
+    /// Timestamp records with `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    /// Timestamp (ms since the Unix epoch) of the most recent append through
+    /// this handle, or 0 if there was none
+    pub fn last_timestamp_ms(&self) -> u64 {
+        self.last_timestamp_ms
+    }
+    
+    /// Sequence number of the most recent record, or 0 if nothing was ever logged
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+    
+    /// Sequence number of the oldest record still in the log
+    ///
+    /// Anything older has been released after reaching the data file.
+    pub fn first_seq(&self) -> u64 {
+        self.segments.first().map_or(self.next_seq, |s| s.base_seq)
+    }
+    
+    /// Number of records in the log, counting from `first_seq`
+    ///
+    /// Kept as records are appended and released, so it costs nothing to
+    /// ask; always 0 for in-memory logs.
+    pub fn entry_count(&self) -> u64 {
+        self.next_seq - self.first_seq()
+    }
+    
+    /// Records appended since the last fsync of the log
+    ///
+    /// These are what a power loss or OS crash can take with it; a crash of
+    /// the process alone loses only records still in the write buffer.
+    /// Always 0 for in-memory logs.
+    pub fn unsynced_count(&self) -> u64 {
+        self.last_seq() - self.synced_seq
+    }
+    
+    /// Record that every record through `seq` has been fsynced through
+    /// another handle to the active segment
+    pub(crate) fn mark_synced(&mut self, seq: u64) {
+        self.synced_seq = self.synced_seq.max(seq);
+    }
+    
+    /// Bytes in the log: segment headers and records, including records
+    /// still in the write buffer
+    ///
+    /// Kept as records are appended and segments released, unlike
+    /// `disk_usage`, which asks the filesystem; the two agree once the
+    /// buffer is flushed. Always 0 for in-memory logs.
+    pub fn size_bytes(&self) -> u64 {
+        self.sealed_bytes + self.active_len
+    }
+    
+    /// Call `trigger` after every append that leaves `size_bytes` above
+    /// `threshold`, with the log still locked by the appender
+    pub(crate) fn set_size_trigger(&mut self, threshold: u64, trigger: impl Fn() + Send + 'static) {
+        self.size_trigger = Some((threshold, Box::new(trigger)));
+    }
+    
+    /// Paths of the segment files, oldest first
+    pub fn segment_paths(&self) -> Vec<PathBuf> {
+        self.segments.iter().map(|s| s.path.clone()).collect()
+    }
+    
+    /// A second handle to the active segment, for syncing without holding the log
+    ///
+    /// Records still in the write buffer are not covered; `flush` first.
+    pub fn active_file(&self) -> Result<Box<dyn SegmentFile>> {
+        match &self.file {
+            Some(file) => Ok(file.get_ref().try_clone()?),
+            None => Err(LohError::ReadOnly),
+        }
+    }
+    
+    /// Write any buffered records out to the active segment
+    ///
+    /// They then survive a crash of the process, though not of the machine
+    /// until `sync`. A no-op without a write buffer.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            retry_transient("flush", self.write_retries, self.retry_backoff, || file.flush())?;
+        }
+        Ok(())
+    }
+    
+    /// Fsync the active segment and the directory holding the segments
+    ///
+    /// Rolled-over segments were synced when they were closed, so afterwards
+    /// every appended record is on stable storage. A no-op for in-memory logs.
+    pub fn sync(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(LohError::ReadOnly);
+        }
+        if let Some(file) = &mut self.file {
+            retry_transient("flush", self.write_retries, self.retry_backoff, || file.flush())?;
+            file.get_ref().sync_data()?;
+            self.backend.sync_dir(&self.dir)?;
+        }
+        self.synced_seq = self.last_seq();
+        Ok(())
+    }
+    
+    /// Total bytes used on disk by all segments
+    pub fn disk_usage(&self) -> Result<u64> {
+        let mut total = 0;
+        for segment in &self.segments {
+            total += self.backend.len(&segment.path)?;
+        }
+        Ok(total)
+    }
+    
+    /// Append `operation`, returning the sequence number it was assigned
+    ///
+    /// Fails with `LohError::TooLarge`, leaving the log as it was, if the
+    /// record would not fit the `u32` length prefix: up front, without
+    /// encoding anything, once the keys and values alone pass
+    /// `MAX_RECORD_BYTES`, or else once encoded. A write that fails for good
+    /// after part of the record went out cuts that part off again.
+    pub fn append(&mut self, operation: &Operation) -> Result<u64> {
+        if self.read_only {
+            return Err(LohError::ReadOnly);
+        }
+        if let Some(reason) = &self.failed {
+            return Err(LohError::WalCorrupt(format!("the WAL refuses appends until it is reopened: {}", reason)));
+        }
+        let size = operation.payload_bytes();
+        if size > MAX_RECORD_BYTES {
+            return Err(LohError::TooLarge { what: "WAL record", size, limit: MAX_RECORD_BYTES });
+        }
+        if self.in_memory {
+            self.last_timestamp_ms = self.clock.now_ms();
+            self.next_seq += 1;
+            self.synced_seq = self.last_seq();
+            return Ok(self.next_seq - 1);
+        }
+        
+        let mut serialized = self.format.serialize(operation)?;
+        let mut flag = RECORD_STORED;
+        if let Some(compressor) = &self.compressor {
+            let compressed = compressor.compress(&serialized)?;
+            if compressed.len() < serialized.len() {
+                serialized = compressed;
+                flag = RECORD_COMPRESSED;
+            }
+        }
+        if let Some(encryptor) = &self.encryptor {
+            serialized = encryptor.encrypt(&serialized)?;
+        }
+        serialized.insert(0, flag);
+        // Checked before a roll, so a rejected record leaves no trace
+        let len = u32::try_from(serialized.len()).map_err(|_| LohError::TooLarge {
+            what: "WAL record",
+            size: serialized.len() as u64,
+            limit: MAX_RECORD_BYTES,
+        })?;
+        
+        // Segments older than the current version have no flag bytes
+        let active = self.segments.len() - 1;
+        let outdated = self.segments[active].format != self.format || !self.segments[active].flagged;
+        if outdated && self.segment_is_empty(active) {
+            // Nothing was written in the old format yet, so start over in place
+            let stale = self.segments.pop().unwrap();
+            self.backend.remove(&stale.path)?;
+            self.active_len = 0;
+            self.start_segment(stale.index)?;
+        } else if self.active_len >= self.segment_bytes || outdated {
+            self.roll()?;
+        }
+        
+        let seq = self.next_seq;
+        let timestamp_ms = self.clock.now_ms();
+        self.last_timestamp_ms = timestamp_ms;
+        
+        // Write the record header followed by the operation in one call
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + serialized.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&seq.to_le_bytes());
+        record.extend_from_slice(&timestamp_ms.to_le_bytes());
+        record.extend_from_slice(&serialized);
+        let file = self.file.as_mut().expect("writable WAL has an active segment");
+        let mut written = 0;
+        let result = retry_transient("write", self.write_retries, self.retry_backoff, || {
+            // Like `write_all`, but resuming after whatever a failed try wrote
+            while written < record.len() {
+                match file.write(&record[written..]) {
+                    Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                    Ok(n) => written += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            if written > 0 {
+                self.discard_partial_record();
+            }
+            return Err(e.into());
+        }
+        
+        self.active_len += record.len() as u64;
+        self.next_seq += 1;
+        if self.commit_batch > 0 && self.unsynced_count() >= self.commit_batch as u64 {
+            self.sync()?;
+        }
+        if let Some((threshold, trigger)) = &self.size_trigger {
+            if self.size_bytes() > *threshold {
+                trigger();
+            }
+        }
+        Ok(seq)
+    }
+    
+    /// Walk every record in the log without applying anything
+    ///
+    /// Unlike `replay`, problems are not skipped over: a torn record at the
+    /// end of a segment, a record that fails to decode, or a missing segment
+    /// is yielded as an error, after which the iterator stops. Records still
+    /// in the write buffer are not seen; `flush` first.
+    pub fn iter_entries(&self) -> WalEntries<'_> {
+        WalEntries {
+            wal: self,
+            segment: 0,
+            file: None,
+            legacy_seq: 0,
+            expected_seq: self.first_seq(),
+            offset: 0,
+            failed_at: None,
+            done: false,
+        }
+    }
+    
+    /// Pass every record to `callback`, in order, and report how many were
+    /// applied
+    ///
+    /// A record that fails to decode stops the replay from applying
+    /// anything more, since later records may depend on it, but the rest of
+    /// the log is still read so the report counts what was left out; check
+    /// `ReplayReport::skipped` rather than assuming everything came back. A
+    /// missing segment, a record that fails to decrypt (usually a wrong
+    /// key), an I/O error or an error from `callback` still fails the replay.
+    pub fn replay<F>(&mut self, callback: F) -> Result<ReplayReport>
     where
-        F: FnMut(Operation) -> Result<()>,
+        F: FnMut(WalEntry) -> Result<()>,
     {
-        use std::io::Read;
-        
-        // Seek to beginning of file
-        self.file.seek(SeekFrom::Start(0))?;
-        
-        let mut len_buf = [0u8; 4];
-        
-        loop {
-            // Try to read the length prefix
-            match self.file.read_exact(&mut len_buf) {
-                Ok(()) => {
-                    let len = u32::from_le_bytes(len_buf) as usize;
-                    let mut operation_buf = vec![0u8; len];
-                    
-                    self.file.read_exact(&mut operation_buf)?;
-                    
-                    match bincode::deserialize::<Operation>(&operation_buf) {
-                        Ok(operation) => callback(operation)?,
-                        Err(e) => {
-                            eprintln!("Warning: Failed to deserialize WAL entry: {}", e);
-                            break;
-                        }
+        self.replay_with_progress(callback, u64::MAX, |_| {})
+    }
+    
+    /// `replay`, reporting to `progress` after every `every` applied
+    /// records and once more when the replay ends
+    ///
+    /// `bytes_read` counts from the start of the first segment, so it
+    /// reaches `total_bytes` once the whole log has been read, skipped
+    /// records included.
+    pub fn replay_with_progress<F, P>(&mut self, mut callback: F, every: u64, mut progress: P) -> Result<ReplayReport>
+    where
+        F: FnMut(WalEntry) -> Result<()>,
+        P: FnMut(RecoveryProgress),
+    {
+        let mut expected_seq = self.first_seq();
+        log::debug!("replaying WAL from sequence {} across {} segment(s)", expected_seq, self.segments.len());
+        
+        let every = every.max(1);
+        let mut report = RecoveryProgress {
+            entries: 0,
+            bytes_read: 0,
+            total_bytes: self.disk_usage()?,
+            done: false,
+        };
+        let mut outcome = ReplayReport::default();
+        let mut segments_read = 0;
+        
+        for segment in &self.segments {
+            // Once records are being skipped, sequence numbers no longer matter
+            if outcome.first_error.is_none() && segment.base_seq != expected_seq {
+                return Err(LohError::WalCorrupt(format!(
+                    "segment '{}' starts at sequence {} but {} was expected; a segment is missing or damaged",
+                    segment.path.display(), segment.base_seq, expected_seq
+                )));
+            }
+            
+            let mut file = self.backend.open_read(&segment.path)?;
+            let mut legacy_seq = segment.base_seq - 1;
+            file.seek(SeekFrom::Start(segment.header_len))?;
+            
+            while let Some(frame) = read_frame(&mut file, segment.versioned, &mut legacy_seq)? {
+                if outcome.first_error.is_some() {
+                    outcome.skipped += 1;
+                    continue;
+                }
+                // A failure to decrypt means a wrong key, and to decompress a
+                // missing compressor, never a torn tail; only a damaged flag
+                // byte is skipped like a record that cannot be decoded
+                let payload = match self.open_payload(segment, frame.seq, frame.payload) {
+                    Ok(payload) => payload,
+                    Err(LohError::WalCorrupt(error)) => {
+                        outcome.first_error = Some(error);
+                        outcome.skipped += 1;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                
+                match segment.decode(&payload) {
+                    Ok(operation) => callback(WalEntry {
+                        seq: frame.seq,
+                        timestamp_ms: frame.timestamp_ms,
+                        operation,
+                    })?,
+                    Err(e) => {
+                        outcome.first_error = Some(format!(
+                            "record {} in '{}' cannot be decoded: {}",
+                            frame.seq, segment.path.display(), e
+                        ));
+                        outcome.skipped += 1;
+                        continue;
                     }
                 }
-                Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    // End of file reached
-                    break;
+                expected_seq = frame.seq + 1;
+                
+                outcome.applied += 1;
+                report.entries += 1;
+                if report.entries.is_multiple_of(every) {
+                    report.bytes_read = segments_read + file.stream_position()?;
+                    progress(report);
                 }
-                Err(e) => return Err(e.into()),
             }
+            
+            segments_read += file.stream_position()?;
+            report.bytes_read = segments_read;
         }
         
-        // Seek back to end for future appends
-        self.file.seek(SeekFrom::End(0))?;
+        if let Some(error) = &outcome.first_error {
+            log::warn!(
+                "WAL replay stopped after sequence {}, skipping {} record(s): {}",
+                expected_seq - 1, outcome.skipped, error
+            );
+        } else {
+            log::debug!("replayed WAL through sequence {}", expected_seq - 1);
+        }
+        report.done = true;
+        progress(report);
+        Ok(outcome)
+    }
+    
+    /// Undo what `append` did to record `seq` of `segment`: strip the flag
+    /// byte, decrypt, then decompress
+    fn open_payload(&self, segment: &Segment, seq: u64, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        let flag = if segment.flagged {
+            match payload.first() {
+                Some(&flag @ (RECORD_STORED | RECORD_COMPRESSED)) => {
+                    payload.remove(0);
+                    flag
+                }
+                flag => {
+                    return Err(LohError::WalCorrupt(format!(
+                        "record {} in '{}' has an invalid compression flag {:?}",
+                        seq, segment.path.display(), flag
+                    )));
+                }
+            }
+        } else {
+            RECORD_STORED
+        };
+        if let Some(encryptor) = &self.encryptor {
+            payload = encryptor.decrypt(&payload)
+                .map_err(|e| LohError::Encryption(format!("failed to read WAL '{}': {}", segment.path.display(), e)))?;
+        }
+        if flag == RECORD_STORED {
+            return Ok(payload);
+        }
+        match &self.compressor {
+            Some(compressor) => compressor.decompress(&payload).map_err(|e| LohError::Compression(format!(
+                "record {} in '{}' cannot be decompressed: {}", seq, segment.path.display(), e
+            ))),
+            None => Err(LohError::Compression(format!(
+                "record {} in '{}' is compressed, but no WAL compressor is configured",
+                seq, segment.path.display()
+            ))),
+        }
+    }
+    
+    /// Start a new segment if the active one holds any records
+    pub fn roll(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(LohError::ReadOnly);
+        }
+        let header_len = self.segments.last().map_or(0, |s| s.header_len);
+        if self.in_memory || self.active_len <= header_len {
+            return Ok(());
+        }
         
-        Ok(())
+        let index = self.segments.last().map_or(1, |s| s.index + 1);
+        self.start_segment(index)
+    }
+    
+    /// Delete segments whose records all have a sequence number at or below `seq`
+    ///
+    /// The active segment is never deleted. Returns how many segments were removed.
+    pub fn release_through(&mut self, seq: u64) -> Result<usize> {
+        if self.read_only {
+            return Err(LohError::ReadOnly);
+        }
+        
+        let mut released = 0;
+        while self.segments.len() > 1 && self.segments[1].base_seq <= seq + 1 {
+            let segment = self.segments.remove(0);
+            self.sealed_bytes -= self.backend.len(&segment.path)?;
+            self.backend.remove(&segment.path)?;
+            released += 1;
+        }
+        Ok(released)
     }
     
+    /// Discard every record; sequence numbers keep counting from where they were
     pub fn truncate(&mut self) -> Result<()> {
-        use std::fs;
+        if self.read_only {
+            return Err(LohError::ReadOnly);
+        }
+        if self.in_memory {
+            return Ok(());
+        }
         
-        // Close current file and recreate it empty
-        fs::remove_file(&self.path)?;
+        let index = self.segments.last().map_or(1, |s| s.index + 1);
+        self.start_segment(index)?;
+        self.release_through(self.last_seq())?;
         
-        self.file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .read(true)
-            .open(&self.path)?;
-            
         Ok(())
     }
-}
\ No newline at end of file
+    
+    /// Create segment `index` starting at the next sequence number and make it active
+    fn start_segment(&mut self, index: u64) -> Result<()> {
+        let path = self.dir.join(segment_file_name(index));
+        let mut file = self.backend.create(&path)?;
+        write_file_header(&mut file, self.next_seq, self.format)?;
+        
+        // A segment is never written again once rolled over, so make it
+        // durable now; syncing the new active segment then covers everything
+        if let Some(previous) = &mut self.file {
+            retry_transient("flush", self.write_retries, self.retry_backoff, || previous.flush())?;
+            previous.get_ref().sync_data()?;
+            self.synced_seq = self.last_seq();
+        }
+        
+        self.segments.push(Segment {
+            index,
+            path,
+            versioned: true,
+            base_seq: self.next_seq,
+            format: self.format,
+            flagged: true,
+            header_len: FILE_HEADER_LEN,
+        });
+        self.file = Some(BufWriter::with_capacity(self.buffer_bytes, self.wrap(file)));
+        self.sealed_bytes += self.active_len;
+        self.active_len = FILE_HEADER_LEN;
+        Ok(())
+    }
+    
+    /// Walk the record headers of the last segment to find the next sequence
+    /// number, returning where its last complete record ends
+    fn recover_sequence(&mut self) -> Result<u64> {
+        let Some(last) = self.segments.last() else {
+            return Ok(0);
+        };
+        
+        self.next_seq = last.base_seq;
+        let mut file = self.backend.open_read(&last.path)?;
+        let mut legacy_seq = last.base_seq - 1;
+        let mut end = file.seek(SeekFrom::Start(last.header_len))?;
+        while let Some(frame) = read_frame(&mut file, last.versioned, &mut legacy_seq)? {
+            self.next_seq = frame.seq + 1;
+            end = file.stream_position()?;
+        }
+        Ok(end)
+    }
+    
+    /// Cut the partly written record that a write failing for good left at
+    /// the end of the active segment, so the next append does not follow it
+    ///
+    /// Whatever of the record the write buffer still holds goes with it;
+    /// the buffer held nothing else, since a record is only written past it
+    /// once everything before has been written out. If the cut fails too,
+    /// the log refuses further appends: the torn record is left for the
+    /// next writable open to cut.
+    fn discard_partial_record(&mut self) {
+        let path = self.segments.last().expect("writable WAL has an active segment").path.clone();
+        if let Some(file) = self.file.take() {
+            drop(file.into_parts());
+        }
+        let reopened = self.backend.truncate(&path, self.active_len).and_then(|()| self.backend.open_append(&path));
+        match reopened {
+            Ok(file) => self.file = Some(BufWriter::with_capacity(self.buffer_bytes, self.wrap(file))),
+            Err(e) => {
+                log::error!("cannot cut a partly written record off '{}': {}", path.display(), e);
+                self.failed = Some(format!(
+                    "a record was left partly written at offset {} of '{}' and could not be cut off ({})",
+                    self.active_len, path.display(), e
+                ));
+            }
+        }
+    }
+}
+
+/// Reject a set too large to frame as one record, before anything is copied
+pub(crate) fn check_record_size(namespace: Option<&str>, key: &str, value: &[u8]) -> Result<()> {
+    let size = (namespace.map_or(0, str::len) + key.len() + value.len()) as u64 + RECORD_OVERHEAD_BYTES;
+    if size > MAX_RECORD_BYTES {
+        return Err(LohError::TooLarge { what: "WAL record", size, limit: MAX_RECORD_BYTES });
+    }
+    Ok(())
+}
+
+/// Whether `dir` holds a WAL, either as segments or a legacy `wal.log`
+pub(crate) fn log_exists(dir: &Path) -> Result<bool> {
+    if dir.join(LEGACY_WAL_FILE).exists() {
+        return Ok(true);
+    }
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+    Ok(!FileBackend::default().list_segments(dir)?.is_empty())
+}
+
+fn segment_file_name(index: u64) -> String {
+    format!("wal.{:06}.log", index)
+}
+
+/// Move a single-file `wal.log` into the first segment
+fn migrate_legacy_file(dir: &Path, file_mode: Option<u32>) -> Result<()> {
+    let legacy_path = dir.join(LEGACY_WAL_FILE);
+    if !legacy_path.exists() {
+        return Ok(());
+    }
+    if !FileBackend::default().list_segments(dir)?.is_empty() {
+        return Err(LohError::WalCorrupt(format!(
+            "'{}' contains both '{}' and segment files; remove whichever is stale",
+            dir.display(), LEGACY_WAL_FILE
+        )));
+    }
+    
+    let segment_path = dir.join(segment_file_name(1));
+    let mut file = File::open(&legacy_path)?;
+    if file.metadata()?.len() == 0 {
+        fs::remove_file(&legacy_path)?;
+    } else if read_file_header(&mut file, &legacy_path)?.is_some() {
+        fs::rename(&legacy_path, &segment_path)?;
+    } else {
+        upgrade_legacy_log(&mut file, &segment_path, file_mode)?;
+        fs::remove_file(&legacy_path)?;
+    }
+    Ok(())
+}
+
+/// Read the next frame, or `None` at the end of the file or a torn tail
+fn read_frame<R: Read + ?Sized>(file: &mut R, versioned: bool, legacy_seq: &mut u64) -> Result<Option<Frame>> {
+    if !versioned {
+        return Ok(read_legacy_payload(file)?.map(|payload| {
+            *legacy_seq += 1;
+            Frame { seq: *legacy_seq, timestamp_ms: 0, payload }
+        }));
+    }
+    
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    if !read_exact_or_eof(file, &mut header)? {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let seq = u64::from_le_bytes(header[4..12].try_into().unwrap());
+    let timestamp_ms = u64::from_le_bytes(header[12..20].try_into().unwrap());
+    
+    let mut payload = vec![0u8; len];
+    if !read_exact_or_eof(file, &mut payload)? {
+        return Ok(None);
+    }
+    
+    Ok(Some(Frame { seq, timestamp_ms, payload }))
+}
+
+/// Fill `buf`, returning false if the file ends first
+fn read_exact_or_eof<R: Read + ?Sized>(file: &mut R, buf: &mut [u8]) -> Result<bool> {
+    match file.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read one `len | payload` frame from a pre-versioning log
+fn read_legacy_payload<R: Read + ?Sized>(file: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut len_buf = [0u8; 4];
+    if !read_exact_or_eof(file, &mut len_buf)? {
+        return Ok(None);
+    }
+    let mut payload = vec![0u8; u32::from_le_bytes(len_buf) as usize];
+    if !read_exact_or_eof(file, &mut payload)? {
+        return Ok(None);
+    }
+    Ok(Some(payload))
+}
+
+/// Read the file header
+///
+/// Returns `None` for an empty or pre-versioning log and fails on a
+/// version this build does not understand.
+fn read_file_header<R: Read + Seek + ?Sized>(file: &mut R, path: &Path) -> Result<Option<FileHeader>> {
+    file.seek(SeekFrom::Start(0))?;
+    
+    let mut prefix = [0u8; WAL_MAGIC.len() + 1];
+    if !read_exact_or_eof(file, &mut prefix)? || &prefix[..WAL_MAGIC.len()] != WAL_MAGIC {
+        return Ok(None);
+    }
+    
+    let (format, len) = match prefix[WAL_MAGIC.len()] {
+        1 => (SerializationFormat::Bincode, V1_FILE_HEADER_LEN),
+        FORMAT_BYTE_VERSION..=WAL_FORMAT_VERSION => {
+            let mut id = [0u8; 1];
+            if !read_exact_or_eof(file, &mut id)? {
+                return Ok(None);
+            }
+            (SerializationFormat::from_id(id[0])?, FILE_HEADER_LEN)
+        }
+        version => {
+            return Err(LohError::WalCorrupt(format!(
+                "'{}' uses format version {}, but this build only supports versions 1 to {}",
+                path.display(), version, WAL_FORMAT_VERSION
+            )));
+        }
+    };
+    
+    let mut base_seq = [0u8; 8];
+    if !read_exact_or_eof(file, &mut base_seq)? {
+        return Ok(None);
+    }
+    let flagged = prefix[WAL_MAGIC.len()] > FORMAT_BYTE_VERSION;
+    Ok(Some(FileHeader { base_seq: u64::from_le_bytes(base_seq), format, flagged, len }))
+}
+
+fn write_file_header<W: Write + ?Sized>(file: &mut W, base_seq: u64, format: SerializationFormat) -> Result<()> {
+    let mut header = Vec::with_capacity(FILE_HEADER_LEN as usize);
+    header.extend_from_slice(WAL_MAGIC);
+    header.push(WAL_FORMAT_VERSION);
+    header.push(format.id());
+    header.extend_from_slice(&base_seq.to_le_bytes());
+    file.write_all(&header)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Rewrite a pre-versioning log in the current format at `dest`
+///
+/// Records are decoded in their pre-namespace shape and encoded again as
+/// the current `Operation`, numbered from 1 with a zero timestamp. A record
+/// that does not decode fails the upgrade, and with it the open, rather
+/// than being carried into a segment where it would be misread. The result
+/// is written to a temporary file and renamed into place, so a crash or a
+/// failure leaves the old log intact.
+fn upgrade_legacy_log(file: &mut File, dest: &Path, file_mode: Option<u32>) -> Result<()> {
+    let tmp_path = dest.with_extension("upgrade");
+    let result = write_upgraded_log(file, &tmp_path, file_mode);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+    fs::rename(&tmp_path, dest)?;
+    Ok(())
+}
+
+/// Write the records of pre-versioning `file` to `tmp_path` in the current format
+fn write_upgraded_log(file: &mut File, tmp_path: &Path, file_mode: Option<u32>) -> Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    
+    let mut upgraded = perms::open_options(file_mode).write(true).create(true).truncate(true).open(tmp_path)?;
+    write_file_header(&mut upgraded, 1, SerializationFormat::Bincode)?;
+    
+    let mut seq = 1u64;
+    while let Some(payload) = read_legacy_payload(file)? {
+        let operation = decode_legacy(&payload).map_err(|e| LohError::WalCorrupt(format!(
+            "record {} of '{}' cannot be decoded as a pre-versioning record ({}); the log was left as it is",
+            seq, LEGACY_WAL_FILE, e
+        )))?;
+        let payload = SerializationFormat::Bincode.serialize(&operation)?;
+        // One more byte for the flag, which a full-size legacy payload has no room for
+        let len = u32::try_from(payload.len() + 1).map_err(|_| LohError::TooLarge {
+            what: "WAL record",
+            size: payload.len() as u64 + 1,
+            limit: MAX_RECORD_BYTES,
+        })?;
+        upgraded.write_all(&len.to_le_bytes())?;
+        upgraded.write_all(&seq.to_le_bytes())?;
+        upgraded.write_all(&0u64.to_le_bytes())?;
+        upgraded.write_all(&[RECORD_STORED])?;
+        upgraded.write_all(&payload)?;
+        seq += 1;
+    }
+    
+    upgraded.sync_all()?;
+    Ok(())
+}
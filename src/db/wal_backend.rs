@@ -0,0 +1,260 @@
+use crate::db::perms;
+use crate::Result;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The active segment as a writable log sees it
+///
+/// Implemented for `File` and for the segments of a `MemoryBackend`.
+/// `WriteAheadLog::with_segment_wrapper` puts another implementation in
+/// front of each segment, such as one that injects faults in tests.
+pub trait SegmentFile: Write + Send {
+    /// Make everything written so far durable, like `File::sync_data`
+    fn sync_data(&self) -> io::Result<()>;
+    
+    /// A second handle to the same segment, for syncing it elsewhere
+    fn try_clone(&self) -> io::Result<Box<dyn SegmentFile>>;
+}
+
+impl SegmentFile for File {
+    fn sync_data(&self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+    
+    fn try_clone(&self) -> io::Result<Box<dyn SegmentFile>> {
+        Ok(Box::new(File::try_clone(self)?))
+    }
+}
+
+/// A segment opened for replay
+pub trait SegmentReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> SegmentReader for T {}
+
+/// Where a `WriteAheadLog` keeps its segments
+///
+/// Segments are named by path, `dir` joined with the segment file name, so
+/// backends other than the filesystem treat paths as plain keys. The log
+/// only ever appends to the newest segment and removes whole segments from
+/// the front.
+pub trait WalBackend: Send + Sync {
+    /// Segments in `dir` as `(index, path)`, in index order
+    fn list_segments(&self, dir: &Path) -> Result<Vec<(u64, PathBuf)>>;
+    
+    /// Current length in bytes of the segment at `path`
+    fn len(&self, path: &Path) -> Result<u64>;
+    
+    /// Open the segment at `path` for reading from the start
+    fn open_read(&self, path: &Path) -> Result<Box<dyn SegmentReader>>;
+    
+    /// Create a segment at `path`, failing if one exists, and open it for
+    /// appending
+    fn create(&self, path: &Path) -> Result<Box<dyn SegmentFile>>;
+    
+    /// Open the existing segment at `path` for appending
+    fn open_append(&self, path: &Path) -> Result<Box<dyn SegmentFile>>;
+    
+    /// Delete the segment at `path`
+    fn remove(&self, path: &Path) -> Result<()>;
+    
+    /// Cut the segment at `path` back to its first `len` bytes, dropping a
+    /// partly written record from its end
+    fn truncate(&self, path: &Path, len: u64) -> Result<()>;
+    
+    /// Make the creation and removal of segments in `dir` durable
+    fn sync_dir(&self, dir: &Path) -> Result<()>;
+}
+
+/// Segments as files in a directory: the backend of every on-disk database
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileBackend {
+    /// Unix permission bits for new segment files
+    file_mode: Option<u32>,
+}
+
+impl FileBackend {
+    /// Create new segment files with `file_mode` (Unix permission bits)
+    pub fn new(file_mode: Option<u32>) -> Self {
+        Self { file_mode }
+    }
+}
+
+impl WalBackend for FileBackend {
+    fn list_segments(&self, dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if let Some(index) = entry.file_name().to_str().and_then(segment_index) {
+                files.push((index, entry.path()));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+    
+    fn len(&self, path: &Path) -> Result<u64> {
+        Ok(fs::metadata(path)?.len())
+    }
+    
+    fn open_read(&self, path: &Path) -> Result<Box<dyn SegmentReader>> {
+        Ok(Box::new(File::open(path)?))
+    }
+    
+    fn create(&self, path: &Path) -> Result<Box<dyn SegmentFile>> {
+        let file = perms::open_options(self.file_mode)
+            .create_new(true)
+            .append(true)
+            .read(true)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+    
+    fn open_append(&self, path: &Path) -> Result<Box<dyn SegmentFile>> {
+        Ok(Box::new(OpenOptions::new().append(true).read(true).open(path)?))
+    }
+    
+    fn remove(&self, path: &Path) -> Result<()> {
+        Ok(fs::remove_file(path)?)
+    }
+    
+    fn truncate(&self, path: &Path, len: u64) -> Result<()> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(len)?;
+        file.sync_data()?;
+        Ok(())
+    }
+    
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        #[cfg(unix)]
+        File::open(dir)?.sync_all()?;
+        #[cfg(not(unix))]
+        let _ = dir;
+        Ok(())
+    }
+}
+
+/// Index of the segment named `name`, or `None` if it names no segment
+fn segment_index(name: &str) -> Option<u64> {
+    name.strip_prefix("wal.")
+        .and_then(|n| n.strip_suffix(".log"))
+        .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|n| n.parse::<u64>().ok())
+}
+
+/// Segments held in memory, for tests and for logs that need not outlive
+/// the process
+///
+/// Clones share the same segments, so a log reopened on a clone replays
+/// what an earlier one appended, much like reopening a directory after a
+/// crash. Syncing does nothing.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    segments: Arc<Mutex<BTreeMap<PathBuf, MemorySegment>>>,
+}
+
+type MemorySegment = Arc<Mutex<Vec<u8>>>;
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Total bytes held across every segment
+    pub fn total_bytes(&self) -> u64 {
+        let segments = self.segments.lock().unwrap();
+        segments.values().map(|segment| segment.lock().unwrap().len() as u64).sum()
+    }
+    
+    fn segment(&self, path: &Path) -> Result<MemorySegment> {
+        self.segments.lock().unwrap().get(path).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no WAL segment '{}'", path.display())).into()
+        })
+    }
+}
+
+impl WalBackend for MemoryBackend {
+    fn list_segments(&self, dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+        let segments = self.segments.lock().unwrap();
+        let mut listed: Vec<(u64, PathBuf)> = segments
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .filter_map(|path| {
+                let index = path.file_name()?.to_str().and_then(segment_index)?;
+                Some((index, path.clone()))
+            })
+            .collect();
+        listed.sort();
+        Ok(listed)
+    }
+    
+    fn len(&self, path: &Path) -> Result<u64> {
+        Ok(self.segment(path)?.lock().unwrap().len() as u64)
+    }
+    
+    fn open_read(&self, path: &Path) -> Result<Box<dyn SegmentReader>> {
+        // Reads see the segment as it was when opened
+        let contents = self.segment(path)?.lock().unwrap().clone();
+        Ok(Box::new(Cursor::new(contents)))
+    }
+    
+    fn create(&self, path: &Path) -> Result<Box<dyn SegmentFile>> {
+        let mut segments = self.segments.lock().unwrap();
+        if segments.contains_key(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("WAL segment '{}' already exists", path.display()),
+            ).into());
+        }
+        let segment = MemorySegment::default();
+        segments.insert(path.to_path_buf(), segment.clone());
+        Ok(Box::new(MemorySegmentWriter { segment }))
+    }
+    
+    fn open_append(&self, path: &Path) -> Result<Box<dyn SegmentFile>> {
+        Ok(Box::new(MemorySegmentWriter { segment: self.segment(path)? }))
+    }
+    
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.segments.lock().unwrap().remove(path);
+        Ok(())
+    }
+    
+    fn truncate(&self, path: &Path, len: u64) -> Result<()> {
+        self.segment(path)?.lock().unwrap().truncate(len as usize);
+        Ok(())
+    }
+    
+    fn sync_dir(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends to one segment of a `MemoryBackend`
+struct MemorySegmentWriter {
+    segment: MemorySegment,
+}
+
+impl Write for MemorySegmentWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.segment.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SegmentFile for MemorySegmentWriter {
+    fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+    
+    fn try_clone(&self) -> io::Result<Box<dyn SegmentFile>> {
+        Ok(Box::new(MemorySegmentWriter { segment: self.segment.clone() }))
+    }
+}
+
@@ -0,0 +1,67 @@
+use thiserror::Error;
+
+/// Errors returned by LohDB
+///
+/// Variants carrying a `String` hold a human-readable description; match on
+/// the variant rather than the text.
+#[derive(Debug, Error)]
+pub enum LohError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    /// A record or data file could not be encoded or decoded
+    #[error("serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+    
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    /// The WAL is damaged, incomplete, or in a format this build cannot read
+    #[error("corrupt WAL: {0}")]
+    WalCorrupt(String),
+    
+    /// The data file failed its integrity check or is unreadable
+    #[error("corrupt data file: {0}")]
+    DataCorrupt(String),
+    
+    /// Another process holds the data directory's lock
+    #[error("database directory '{0}' is locked by another process")]
+    Locked(String),
+    
+    /// A write was attempted on a database or log opened read-only
+    #[error("database is read-only")]
+    ReadOnly,
+    
+    #[error("{0} not found")]
+    NotFound(String),
+    
+    /// Encryption is misconfigured, or data failed to decrypt (usually a wrong key)
+    #[error("{0}")]
+    Encryption(String),
+    
+    /// The data file's codec is missing, mismatched, or its contents are damaged
+    #[error("{0}")]
+    Compression(String),
+    
+    /// Files were written in a different serialization format than configured,
+    /// or the configured format is unavailable or failed
+    #[error("{0}")]
+    Format(String),
+    
+    /// A key, value, or WAL record exceeds a configured or format limit
+    #[error("{what} of {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge { what: &'static str, size: u64, limit: u64 },
+    
+    /// A key, name, configuration value, or other input was rejected
+    #[error("{0}")]
+    InvalidArgument(String),
+    
+    /// A stored value could not be decoded as the type asked for
+    #[error("value of '{key}' is not a valid {expected}: {reason}")]
+    TypeMismatch { key: String, expected: &'static str, reason: String },
+    
+    /// A transaction read something another write changed before it could
+    /// commit; nothing was written, so it can be retried from the start
+    #[error("transaction conflict: {0}")]
+    Conflict(String),
+}
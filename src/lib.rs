@@ -6,12 +6,24 @@
 //! - Change subscriptions/notifications
 //! - Pluggable storage backends
 //! - Crash recovery
+//! - Namespaces (logical partitions within one database)
+//! - Embedded TCP server with a line-based text protocol
 
 pub mod db;
+pub mod error;
 pub mod cli;
+pub mod server;
+#[cfg(feature = "tokio")]
+pub mod async_db;
 
-pub use db::{Database, DatabaseConfig, StorageEngine, Operation, ChangeEvent};
-pub use cli::run_cli;
+pub use db::{Database, DatabaseConfig, DatabaseConfigBuilder, DbIterator, StorageEngine, CompactStats, Operation, ChangeEvent, EventFilter, LoggedChange, LifecycleEvent, NamespaceHandle, RecoverTo, RecoveryProgress, ReplayReport, Snapshot, Transaction, DbStats, SizeProfile, SyncHealth, FlushMode, FlushScheduler, Compressor, Encryptor, OverflowPolicy, SerializationFormat, ValueMeta, VersionedPut, VerifyReport};
+pub use error::LohError;
+pub use cli::{run_cli, run_command, run_script};
+pub use server::run_server;
+#[cfg(feature = "tokio")]
+pub use async_db::AsyncDatabase;
+#[cfg(feature = "metrics")]
+pub use db::MetricsSnapshot;
 
 /// Result type used throughout the library
-pub type Result<T> = anyhow::Result<T>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, LohError>;
\ No newline at end of file
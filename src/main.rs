@@ -1,6 +1,12 @@
 use anyhow::Result;
-use clap::Parser;
-use lohdb::{run_cli, Database, DatabaseConfig};
+use clap::{Parser, Subcommand};
+use lohdb::cli::{dump_wal, init_logging, print_recovery_progress, print_verify_report, watch, OutputFormat};
+use lohdb::server::serve;
+use lohdb::{run_cli, run_command, run_script, run_server, Database, DatabaseConfig, DatabaseConfigBuilder};
+use std::io::IsTerminal;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 #[derive(Parser)]
 #[command(name = "lohdb")]
@@ -9,26 +15,201 @@ struct Cli {
     #[arg(short, long, default_value = "./lohdb_data")]
     data_dir: String,
     
+    /// Keep the WAL segments here instead of in --data-dir
+    #[arg(long, value_name = "DIR")]
+    wal_dir: Option<String>,
+    
+    /// Keep the data file here instead of data.db in --data-dir
+    #[arg(long, value_name = "PATH")]
+    data_file: Option<String>,
+    
     #[arg(short, long)]
     interactive: bool,
+    
+    /// Run a single CLI command (e.g. "set foo bar") and exit
+    #[arg(short, long, value_name = "COMMAND", conflicts_with_all = ["interactive", "server"])]
+    exec: Option<String>,
+    
+    /// Output of --exec and piped commands: human text or one JSON object per command
+    #[arg(long, value_enum, default_value = "human")]
+    format: OutputFormat,
+    
+    /// Serve the database over TCP on this address (e.g. 127.0.0.1:7878)
+    #[arg(long, value_name = "ADDR")]
+    server: Option<String>,
+    
+    /// While serving, print each change (to keys starting with PREFIX, if
+    /// given) until Ctrl-C
+    #[arg(long, value_name = "PREFIX", num_args = 0..=1, default_missing_value = "", requires = "server")]
+    watch: Option<String>,
+    
+    /// Open the database without allowing any writes
+    #[arg(long)]
+    read_only: bool,
+    
+    /// Keep everything in memory; nothing is written to --data-dir
+    #[arg(long, conflicts_with = "read_only")]
+    in_memory: bool,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the records in a WAL, oldest first, without opening the database
+    WalDump {
+        /// Directory holding the WAL (defaults to --wal-dir, then --data-dir)
+        dir: Option<String>,
+        
+        /// Show at most this many bytes of each value
+        #[arg(long, default_value_t = 64)]
+        max_value_bytes: usize,
+    },
+    /// Check the WAL and data file without opening the database; exits
+    /// non-zero if they are not consistent
+    Verify {
+        /// Data directory to check (defaults to --data-dir)
+        dir: Option<String>,
+        
+        /// Truncate a damaged tail of the last WAL segment first
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Create the files of a new database, or complete a partial one, then
+    /// print the result as `verify` does
+    Init {
+        /// Data directory to initialize (defaults to --data-dir)
+        dir: Option<String>,
+    },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    init_logging();
     
-    let config = DatabaseConfig {
-        data_dir: cli.data_dir,
-        wal_sync_interval_ms: 1000,
-    };
+    match cli.command {
+        Some(Command::WalDump { dir, max_value_bytes }) => {
+            let dir = dir.or(cli.wal_dir).unwrap_or(cli.data_dir);
+            dump_wal(dir.as_ref(), max_value_bytes, cli.format, std::io::stdout().lock())?;
+            return Ok(());
+        }
+        Some(Command::Verify { dir, repair }) => {
+            let config = with_layout(DatabaseConfig::builder().data_dir(dir.unwrap_or(cli.data_dir)), cli.wal_dir, cli.data_file)
+                .build()?;
+            let report = if repair { Database::repair(&config)? } else { Database::verify(&config)? };
+            print_verify_report(&report, cli.format, std::io::stdout().lock())?;
+            if !report.consistent {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Init { dir }) => {
+            let config = with_layout(DatabaseConfig::builder().data_dir(dir.unwrap_or(cli.data_dir)), cli.wal_dir, cli.data_file)
+                .build()?;
+            Database::init(&config)?;
+            let report = Database::verify(&config)?;
+            print_verify_report(&report, cli.format, std::io::stdout().lock())?;
+            return Ok(());
+        }
+        None => {}
+    }
+    
+    let mut builder = DatabaseConfig::builder()
+        .data_dir(cli.data_dir)
+        .read_only(cli.read_only)
+        .in_memory(cli.in_memory);
+    builder = with_layout(builder, cli.wal_dir, cli.data_file);
+    if std::io::stderr().is_terminal() {
+        builder = builder.on_recovery_progress(print_recovery_progress);
+    }
+    let config = builder.build()?;
     
-    let db = Database::open(config)?;
+    let output = cli.format;
     
-    if cli.interactive {
+    let mut db = match Database::open(config) {
+        Ok(db) => db,
+        Err(e) if matches!(output, OutputFormat::Json) => {
+            output.print_error(&e, None);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    
+    if let Some(addr) = cli.server {
+        match cli.watch {
+            Some(prefix) => serve_and_watch(db, &addr, prefix, output)?,
+            None => run_server(db, &addr)?,
+        }
+    } else if cli.interactive {
         run_cli(db)?;
+    } else if let Some(command) = cli.exec {
+        if let Err(e) = run_command(&mut db, &command, output) {
+            output.print_error(&e, None);
+            drop(db);
+            std::process::exit(1);
+        }
+        db.close()?;
+    } else if !std::io::stdin().is_terminal() {
+        // Piped input: run it as a script, one command per line
+        if run_script(&mut db, std::io::stdin().lock(), output).is_err() {
+            drop(db);
+            std::process::exit(1);
+        }
+        db.close()?;
     } else {
-        println!("LohDB started. Use --interactive for CLI mode.");
-        // In a real application, you might start a server here
+        println!("LohDB started. Use --interactive for CLI mode, --exec <command> to run one command, pipe commands on stdin, or --server <addr> to serve over TCP.");
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Apply --wal-dir and --data-file, when given
+fn with_layout(mut builder: DatabaseConfigBuilder, wal_dir: Option<String>, data_file: Option<String>) -> DatabaseConfigBuilder {
+    if let Some(wal_dir) = wal_dir {
+        builder = builder.wal_dir(wal_dir);
+    }
+    if let Some(data_file) = data_file {
+        builder = builder.data_file(data_file);
+    }
+    builder
+}
+
+/// Serve `db` on `addr` in the background and print its change events until Ctrl-C
+fn serve_and_watch(mut db: Database, addr: &str, prefix: String, output: OutputFormat) -> Result<()> {
+    let (subscription, events) = db.subscribe_channel_prefix(prefix)?;
+    let listener = TcpListener::bind(addr)?;
+    // Stdout carries the events, so the banner goes to stderr
+    eprintln!("LohDB listening on {}, watching for changes (Ctrl-C to stop)", listener.local_addr()?);
+    
+    let db = Arc::new(Mutex::new(db));
+    let server_db = db.clone();
+    let (stop_tx, stop_rx) = crossbeam::channel::bounded(1);
+    // A server that stops ends the watch too, rather than leave it printing alone
+    let server_stop = stop_tx.clone();
+    let server = thread::spawn(move || {
+        let result = serve(listener, server_db);
+        let _ = server_stop.try_send(());
+        result
+    });
+    
+    ctrlc::set_handler(move || {
+        let _ = stop_tx.try_send(());
+    })?;
+    watch(&events, &stop_rx, output, std::io::stdout().lock())?;
+    
+    // Unsubscribe, then make sure every write is in the data file before exiting
+    drop(subscription);
+    let mut db = db.lock().unwrap();
+    if !db.is_read_only() {
+        db.flush()?;
+    }
+    if server.is_finished() {
+        match server.join() {
+            Ok(Ok(())) => anyhow::bail!("server on {} stopped", addr),
+            Ok(Err(e)) => anyhow::bail!("server on {} failed: {}", addr, e),
+            Err(_) => anyhow::bail!("server on {} panicked", addr),
+        }
+    }
+    Ok(())
+}
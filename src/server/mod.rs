@@ -0,0 +1,148 @@
+use crate::{Database, LohError, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "server")]
+pub mod resp;
+
+/// Longest text command accepted, terminator included
+pub const MAX_LINE_BYTES: usize = 1024 * 1024;
+
+/// Serve `db` over TCP on `addr` until the process exits
+///
+/// Each connection is handled on its own thread; all connections share the
+/// one database behind a mutex. See `serve` for the protocol.
+pub fn run_server(db: Database, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("LohDB listening on {}", listener.local_addr()?);
+    serve(listener, Arc::new(Mutex::new(db)))
+}
+
+/// Accept connections on `listener` and answer line-based commands
+///
+/// Commands (case-insensitive) and their replies:
+///
+/// - `SET <key> <value...>` → `OK` (the value is the rest of the line)
+/// - `GET <key>` → `VALUE <value>` or `NOT_FOUND`
+/// - `DEL <key>` → `DELETED` or `NOT_FOUND`
+/// - `KEYS` → `KEYS <n>` followed by one key per line
+/// - `QUIT` → `BYE`, then the connection is closed
+///
+/// Failures are reported as `ERR <message>`. Values are returned as lossy
+/// UTF-8, so binary values do not round-trip through this protocol. A line
+/// longer than `MAX_LINE_BYTES` gets an `ERR` and the connection is closed.
+///
+/// With the `server` feature, a connection whose first byte is `*` speaks
+/// RESP, the Redis protocol, instead, so `redis-cli` and Redis client
+/// libraries can connect. It understands `GET`, `SET`, `DEL` and `EXISTS`
+/// (several keys each), `KEYS <pattern>` with `*` and `?` wildcards,
+/// `PING` and `QUIT`. Values are binary-safe; keys must be UTF-8.
+pub fn serve(listener: TcpListener, db: Arc<Mutex<Database>>) -> Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                // Aborted handshakes and running out of descriptors pass;
+                // pause so the latter does not spin
+                log::warn!("failed to accept a connection: {}", e);
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+        };
+        let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown peer".to_string());
+        let db = db.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, db) {
+                log::warn!("connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, db: Arc<Mutex<Database>>) -> Result<()> {
+    // Every RESP command is an array, and no text command starts with '*'
+    #[cfg(feature = "server")]
+    {
+        let mut first = [0; 1];
+        if stream.peek(&mut first)? == 1 && first[0] == b'*' {
+            return resp::handle_connection(stream, db);
+        }
+    }
+    
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    
+    loop {
+        let mut line = Vec::new();
+        // A peer that never ends its line is cut off instead of buffered
+        if Read::take(&mut reader, MAX_LINE_BYTES as u64).read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if line.last() != Some(&b'\n') && line.len() == MAX_LINE_BYTES {
+            writer.write_all(format!("ERR line longer than {} bytes\n", MAX_LINE_BYTES).as_bytes())?;
+            writer.flush()?;
+            break;
+        }
+        let line = String::from_utf8(line).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let line = line.trim_end_matches('\n').trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let (reply, quit) = execute(line, &db);
+        writer.write_all(reply.as_bytes())?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        if quit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn execute(line: &str, db: &Mutex<Database>) -> (String, bool) {
+    let (command, args) = line.trim_start().split_once(' ').unwrap_or((line.trim(), ""));
+    let args = args.trim_start();
+    
+    let result = match command.to_uppercase().as_str() {
+        "SET" => match args.split_once(' ') {
+            Some((key, value)) => db
+                .lock()
+                .unwrap()
+                .set(key.to_string(), value.as_bytes().to_vec())
+                .map(|_| "OK".to_string()),
+            None => Err(LohError::InvalidArgument("usage: SET <key> <value>".to_string())),
+        },
+        "GET" if single_arg(args) => db.lock().unwrap().get(args).map(|value| match value {
+            Some(value) => format!("VALUE {}", String::from_utf8_lossy(&value)),
+            None => "NOT_FOUND".to_string(),
+        }),
+        "DEL" if single_arg(args) => db.lock().unwrap().delete(args).map(|existed| {
+            if existed { "DELETED" } else { "NOT_FOUND" }.to_string()
+        }),
+        "KEYS" if args.is_empty() => db.lock().unwrap().list_keys().map(|mut keys| {
+            keys.sort();
+            let mut reply = format!("KEYS {}", keys.len());
+            for key in keys {
+                reply.push('\n');
+                reply.push_str(&key);
+            }
+            reply
+        }),
+        "QUIT" => return ("BYE".to_string(), true),
+        _ => Err(LohError::InvalidArgument(format!("unknown command '{}'", line.trim()))),
+    };
+    
+    match result {
+        Ok(reply) => (reply, false),
+        Err(e) => (format!("ERR {}", e), false),
+    }
+}
+
+fn single_arg(args: &str) -> bool {
+    !args.is_empty() && !args.contains(char::is_whitespace)
+}
@@ -0,0 +1,245 @@
+use crate::{Database, LohError, Result};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+/// Longest bulk string accepted
+pub const MAX_BULK_LEN: usize = 64 * 1024 * 1024;
+/// Most arguments accepted in one command
+pub const MAX_ARGS: usize = 4096;
+
+/// A reply in the Redis serialization protocol (RESP2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// `+OK`-style status line
+    Simple(String),
+    /// `-ERR ...` error line
+    Error(String),
+    Integer(i64),
+    /// Binary-safe string, or the null bulk string for `None`
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn error(message: impl Into<String>) -> Self {
+        Reply::Error(format!("ERR {}", message.into()))
+    }
+    
+    /// Encode the reply onto `out`
+    ///
+    /// Line breaks in simple strings and errors, which would end the line
+    /// early, are replaced with spaces.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        match self {
+            Reply::Simple(text) => write!(out, "+{}\r\n", single_line(text)),
+            Reply::Error(text) => write!(out, "-{}\r\n", single_line(text)),
+            Reply::Integer(n) => write!(out, ":{}\r\n", n),
+            Reply::Bulk(None) => out.write_all(b"$-1\r\n"),
+            Reply::Bulk(Some(bytes)) => {
+                write!(out, "${}\r\n", bytes.len())?;
+                out.write_all(bytes)?;
+                out.write_all(b"\r\n")
+            }
+            Reply::Array(items) => {
+                write!(out, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(out))
+            }
+        }
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+/// Read one command, an array of bulk strings, from `reader`
+///
+/// Returns `None` if the stream ends cleanly before the next command.
+/// Malformed framing, including more than `MAX_ARGS` arguments or a bulk
+/// string longer than `MAX_BULK_LEN`, fails with `LohError::InvalidArgument`;
+/// the stream cannot be resynchronized after that, so the connection should
+/// be closed.
+pub fn read_command(reader: &mut impl BufRead) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(header) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let count = match header.strip_prefix(b"*") {
+        Some(count) => parse_len(count, MAX_ARGS)?,
+        None => return Err(protocol_error("expected '*', got something else")),
+    };
+    
+    let mut args = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        let header = read_line(reader)?.ok_or_else(|| protocol_error("connection closed mid-command"))?;
+        let len = match header.strip_prefix(b"$") {
+            Some(len) => parse_len(len, MAX_BULK_LEN)?,
+            None => return Err(protocol_error("expected '$', got something else")),
+        };
+        // Grows only as the bytes arrive, not to whatever length was claimed
+        let mut arg = Vec::new();
+        Read::take(&mut *reader, len as u64 + 2).read_to_end(&mut arg)?;
+        if arg.len() < len + 2 {
+            return Err(protocol_error("connection closed mid-command"));
+        }
+        if !arg.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// Next CRLF-terminated line without its terminator, or `None` at end of stream
+fn read_line(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    // Headers are short; a peer streaming an endless line is cut off
+    if Read::take(&mut *reader, 64).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    match line.strip_suffix(b"\r\n") {
+        Some(line) => Ok(Some(line.to_vec())),
+        None => Err(protocol_error("line not terminated by CRLF")),
+    }
+}
+
+fn parse_len(digits: &[u8], max: usize) -> Result<usize> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&len| len <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn protocol_error(message: &str) -> LohError {
+    LohError::InvalidArgument(format!("Protocol error: {}", message))
+}
+
+/// Answer RESP commands on `stream` until the client disconnects or quits
+pub(crate) fn handle_connection(stream: TcpStream, db: Arc<Mutex<Database>>) -> Result<()> {
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    let mut reader = BufReader::new(stream);
+    
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => break,
+            Err(LohError::Io(e)) => return Err(e.into()),
+            Err(e) => {
+                Reply::error(e.to_string()).write_to(&mut writer)?;
+                writer.flush()?;
+                break;
+            }
+        };
+        if args.is_empty() {
+            continue;
+        }
+        
+        let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+        let reply = execute(&name, &args[1..], &db);
+        reply.write_to(&mut writer)?;
+        writer.flush()?;
+        if name == "quit" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Run one command, with `name` lowercased and `args` after the name
+fn execute(name: &str, args: &[Vec<u8>], db: &Mutex<Database>) -> Reply {
+    run(name, args, db).unwrap_or_else(|e| Reply::error(e.to_string()))
+}
+
+fn run(name: &str, args: &[Vec<u8>], db: &Mutex<Database>) -> Result<Reply> {
+    Ok(match (name, args) {
+        ("get", [key]) => Reply::Bulk(db.lock().unwrap().get(utf8_key(key)?)?),
+        ("set", [key, value]) => {
+            db.lock().unwrap().set(utf8_key(key)?.to_string(), value.clone())?;
+            Reply::Simple("OK".to_string())
+        }
+        ("del", keys) if !keys.is_empty() => {
+            let keys = keys.iter().map(|key| utf8_key(key)).collect::<Result<Vec<_>>>()?;
+            let mut db = db.lock().unwrap();
+            let mut deleted = 0;
+            for key in keys {
+                deleted += i64::from(db.delete(key)?);
+            }
+            Reply::Integer(deleted)
+        }
+        ("exists", keys) if !keys.is_empty() => {
+            let keys = keys.iter().map(|key| utf8_key(key)).collect::<Result<Vec<_>>>()?;
+            let db = db.lock().unwrap();
+            let mut found = 0;
+            for key in keys {
+                found += i64::from(db.get(key)?.is_some());
+            }
+            Reply::Integer(found)
+        }
+        ("keys", [pattern]) => {
+            let mut keys = db.lock().unwrap().list_keys()?;
+            keys.retain(|key| glob_match(pattern, key.as_bytes()));
+            keys.sort();
+            Reply::Array(keys.into_iter().map(|key| Reply::Bulk(Some(key.into_bytes()))).collect())
+        }
+        ("ping", []) => Reply::Simple("PONG".to_string()),
+        ("ping", [message]) => Reply::Bulk(Some(message.clone())),
+        ("quit", _) => Reply::Simple("OK".to_string()),
+        ("get" | "set" | "del" | "exists" | "keys" | "ping", _) => {
+            Reply::error(format!("wrong number of arguments for '{}' command", name))
+        }
+        _ => Reply::error(format!("unknown command '{}'", name)),
+    })
+}
+
+/// Keys are strings in LohDB, so RESP keys must be UTF-8
+fn utf8_key(key: &[u8]) -> Result<&str> {
+    std::str::from_utf8(key).map_err(|_| LohError::InvalidArgument("key is not valid UTF-8".to_string()))
+}
+
+/// Whether `text` matches the `KEYS` pattern `pattern`
+///
+/// `*` matches any run of bytes, `?` any single byte, and `\` escapes the
+/// byte after it. Redis's `[...]` classes are not supported and match
+/// literally.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Pattern and text positions just after the last `*`, for backtracking
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, t));
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some(b'\\') if p + 1 < pattern.len() && pattern[p + 1] == text[t] => {
+                p += 2;
+                t += 1;
+                continue;
+            }
+            Some(&byte) if byte != b'\\' && byte == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match star {
+            // Let the last `*` swallow one more byte and retry
+            Some((star_p, star_t)) => {
+                p = star_p;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
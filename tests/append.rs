@@ -0,0 +1,109 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{ChangeEvent, Database, DatabaseConfig, LohError};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn config(temp_dir: &TempDir) -> DatabaseConfig {
+    common::builder(temp_dir)
+        .ttl_sweep_interval_ms(0)
+        .build()
+        .unwrap()
+}
+
+fn logged_operations(temp_dir: &TempDir) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    WriteAheadLog::open_read_only(temp_dir.path())
+        .unwrap()
+        .replay(|entry| {
+            operations.push(entry.operation);
+            Ok(())
+        })
+        .unwrap();
+    operations
+}
+
+#[test]
+fn test_append_and_read_chunks() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut db = Database::open(config(&temp_dir)).unwrap();
+        let (_handle, events) = db.subscribe_channel().unwrap();
+        assert!(db.read_chunks("events").unwrap().is_empty());
+
+        assert_eq!(db.append("events", b"created").unwrap(), 4 + 7);
+        assert_eq!(db.append("events", b"").unwrap(), 4 + 7 + 4);
+        assert_eq!(db.append("events", b"renamed").unwrap(), 4 + 7 + 4 + 4 + 7);
+        assert_eq!(
+            db.read_chunks("events").unwrap(),
+            vec![b"created".to_vec(), Vec::new(), b"renamed".to_vec()]
+        );
+
+        // The stored value is the framed chunks
+        let value = db.get("events").unwrap().unwrap();
+        assert_eq!(&value[..11], b"\x07\0\0\0created");
+        assert_eq!(db.get_with_metadata("events").unwrap().unwrap().version, 3);
+        // Subscribers receive just the chunk
+        let last = events.try_iter().last().unwrap();
+        assert!(matches!(last, ChangeEvent::Append { version: 3, chunk, .. } if chunk == b"renamed"));
+    }
+
+    // Only the chunks are logged, and replay rebuilds the same value
+    let chunks: Vec<Vec<u8>> = logged_operations(&temp_dir)
+        .into_iter()
+        .map(|operation| match operation {
+            Operation::Append { chunk, .. } => chunk,
+            other => panic!("unexpected operation {:?}", other),
+        })
+        .collect();
+    assert_eq!(chunks, vec![b"created".to_vec(), Vec::new(), b"renamed".to_vec()]);
+
+    let mut db = Database::open(config(&temp_dir)).unwrap();
+    assert_eq!(db.read_chunks("events").unwrap().len(), 3);
+    assert_eq!(db.append("events", b"deleted").unwrap(), 4 + 7 + 4 + 4 + 7 + 4 + 7);
+}
+
+#[test]
+fn test_append_rejects_plain_values_and_oversized_results() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = config(&temp_dir);
+    config.max_value_bytes = Some(16);
+    let mut db = Database::open(config).unwrap();
+
+    db.set("plain".to_string(), b"hello".to_vec()).unwrap();
+    assert!(matches!(db.append("plain", b"x"), Err(LohError::InvalidArgument(_))));
+    assert!(matches!(db.read_chunks("plain"), Err(LohError::InvalidArgument(_))));
+    assert_eq!(db.get("plain").unwrap(), Some(b"hello".to_vec()));
+
+    db.append("log", b"12345678").unwrap();
+    assert!(matches!(db.append("log", b"1234"), Err(LohError::TooLarge { what: "value", size: 20, .. })));
+    assert_eq!(db.read_chunks("log").unwrap(), vec![b"12345678".to_vec()]);
+
+    let ns = db.namespace("tenant").unwrap();
+    ns.append("log", b"a").unwrap();
+    assert_eq!(ns.read_chunks("log").unwrap(), vec![b"a".to_vec()]);
+    assert_eq!(db.read_chunks("log").unwrap().len(), 1);
+}
+
+#[test]
+fn test_append_to_expired_value_starts_over() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut db = Database::open(config(&temp_dir)).unwrap();
+        db.set_with_ttl("log".to_string(), b"\x03\0\0\0old".to_vec(), Duration::from_secs(60)).unwrap();
+        db.append("log", b"kept").unwrap();
+        assert_eq!(db.read_chunks("log").unwrap(), vec![b"old".to_vec(), b"kept".to_vec()]);
+
+        db.set_with_ttl("log".to_string(), b"\x03\0\0\0old".to_vec(), Duration::from_millis(20)).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        db.append("log", b"new").unwrap();
+        assert_eq!(db.read_chunks("log").unwrap(), vec![b"new".to_vec()]);
+    }
+
+    // Replay starts over at the same append even though the expiry is long past
+    let db = Database::open(config(&temp_dir)).unwrap();
+    assert_eq!(db.read_chunks("log").unwrap(), vec![b"new".to_vec()]);
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use lohdb::{AsyncDatabase, ChangeEvent};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_async_database_operations_and_subscription() {
+    let dir = TempDir::new().unwrap();
+    let config = common::config(&dir);
+
+    let db = AsyncDatabase::open(config).await.unwrap();
+    let (_handle, mut events) = db.subscribe().await.unwrap();
+
+    db.set("key".to_string(), b"value".to_vec()).await.unwrap();
+    assert_eq!(db.get("key").await.unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.list_keys().await.unwrap(), vec!["key".to_string()]);
+
+    // Clones share the same database
+    let clone = db.clone();
+    assert!(clone.delete("key").await.unwrap());
+    assert_eq!(db.get("key").await.unwrap(), None);
+
+    match events.recv().await.unwrap() {
+        ChangeEvent::Set { key, .. } => assert_eq!(key, "key"),
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(matches!(events.recv().await.unwrap(), ChangeEvent::Delete { .. }));
+}
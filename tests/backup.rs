@@ -0,0 +1,46 @@
+mod common;
+
+use tempfile::TempDir;
+
+#[test]
+fn test_backup_reflects_state_at_backup_time() {
+    let source_dir = TempDir::new().unwrap();
+    let backup_root = TempDir::new().unwrap();
+    let backup_dir = backup_root.path().join("backup").to_string_lossy().to_string();
+
+    let mut db = common::open(&source_dir);
+    db.set("kept".to_string(), b"v1".to_vec()).unwrap();
+    db.set("deleted_later".to_string(), b"v1".to_vec()).unwrap();
+    db.flush().unwrap();
+    // Only in the WAL at backup time
+    db.set("wal_only".to_string(), b"v1".to_vec()).unwrap();
+
+    db.backup_to(&backup_dir).unwrap();
+
+    db.set("kept".to_string(), b"v2".to_vec()).unwrap();
+    db.delete("deleted_later").unwrap();
+    db.set("added_later".to_string(), b"v2".to_vec()).unwrap();
+
+    // The backup opens standalone, even while the source is still open
+    let backup = common::open(&backup_dir);
+    assert_eq!(backup.get("kept").unwrap(), Some(b"v1".to_vec()));
+    assert_eq!(backup.get("deleted_later").unwrap(), Some(b"v1".to_vec()));
+    assert_eq!(backup.get("wal_only").unwrap(), Some(b"v1".to_vec()));
+    assert_eq!(backup.get("added_later").unwrap(), None);
+
+    // Refuses to overwrite an existing database
+    assert!(db.backup_to(&backup_dir).is_err());
+}
+
+#[test]
+fn test_backup_includes_manifest() {
+    let source_dir = TempDir::new().unwrap();
+    let backup_root = TempDir::new().unwrap();
+    let backup_dir = backup_root.path().join("backup");
+
+    let db = common::open(&source_dir);
+    db.backup_to(&backup_dir.to_string_lossy()).unwrap();
+
+    let source = std::fs::read(source_dir.path().join("MANIFEST")).unwrap();
+    assert_eq!(std::fs::read(backup_dir.join("MANIFEST")).unwrap(), source);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use lohdb::LohError;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const ENTRIES: usize = 20_000;
+
+fn entries() -> impl Iterator<Item = (String, Vec<u8>)> {
+    (0..ENTRIES).map(|i| (format!("key{:06}", i), vec![b'v'; 64]))
+}
+
+#[test]
+fn test_bulk_load_checkpoints_without_logging() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut db = common::open(&temp_dir);
+        let (_handle, events) = db.subscribe_channel().unwrap();
+        db.bulk_load(entries()).unwrap();
+
+        assert_eq!(db.list_keys().unwrap().len(), ENTRIES);
+        assert!(events.recv_timeout(Duration::from_millis(50)).is_err());
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.wal_sequence, 0);
+        assert!(stats.data_file_bytes > 0);
+    }
+
+    // Everything is in the data file; there is nothing to replay
+    let db = common::open(&temp_dir);
+    assert_eq!(db.list_keys().unwrap().len(), ENTRIES);
+    assert_eq!(db.get("key000042").unwrap(), Some(vec![b'v'; 64]));
+}
+
+#[test]
+fn test_bulk_load_keeps_entries_before_a_rejected_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    let bad = [
+        ("good".to_string(), b"1".to_vec()),
+        ("\0bad".to_string(), b"2".to_vec()),
+        ("never".to_string(), b"3".to_vec()),
+    ];
+    let err = db.bulk_load(bad.into_iter()).unwrap_err();
+    assert!(matches!(err, LohError::InvalidArgument(_)), "{:?}", err);
+    drop(db);
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.list_keys().unwrap(), vec!["good"]);
+}
+
+#[test]
+fn test_bulk_load_faster_than_individual_sets() {
+    let set_dir = TempDir::new().unwrap();
+    let mut db = common::open(&set_dir);
+    let started = Instant::now();
+    for (key, value) in entries() {
+        db.set(key, value).unwrap();
+    }
+    db.checkpoint().unwrap();
+    let with_sets = started.elapsed();
+
+    let bulk_dir = TempDir::new().unwrap();
+    let mut db = common::open(&bulk_dir);
+    let started = Instant::now();
+    db.bulk_load(entries()).unwrap();
+    let with_bulk_load = started.elapsed();
+
+    println!("{} entries: set loop {:?}, bulk_load {:?}", ENTRIES, with_sets, with_bulk_load);
+    assert!(with_bulk_load < with_sets);
+}
@@ -0,0 +1,72 @@
+use lohdb::db::{CachingStorageEngine, InMemoryStorageEngine};
+use lohdb::{Database, StorageEngine};
+
+fn cached(max_entries: usize) -> CachingStorageEngine<InMemoryStorageEngine> {
+    let mut engine = CachingStorageEngine::new(InMemoryStorageEngine::new(), max_entries);
+    engine.initialize().unwrap();
+    engine
+}
+
+#[test]
+fn test_hits_return_the_value_and_writes_invalidate_it() {
+    let mut engine = cached(8);
+    engine.store("key", b"one").unwrap();
+
+    assert_eq!(engine.retrieve("key").unwrap(), Some(b"one".to_vec()));
+    assert_eq!(engine.retrieve("key").unwrap(), Some(b"one".to_vec()));
+    assert_eq!((engine.hits(), engine.misses()), (1, 1));
+
+    // An overwrite is read through, never answered from the stale entry
+    engine.store("key", b"two").unwrap();
+    assert_eq!(engine.cached(), 0);
+    assert_eq!(engine.retrieve("key").unwrap(), Some(b"two".to_vec()));
+    assert_eq!(engine.retrieve("key").unwrap(), Some(b"two".to_vec()));
+    assert_eq!((engine.hits(), engine.misses()), (2, 2));
+
+    engine.store_at("key", b"three", 1_000).unwrap();
+    assert_eq!(engine.retrieve("key").unwrap(), Some(b"three".to_vec()));
+
+    assert!(engine.remove("key").unwrap());
+    assert_eq!(engine.retrieve("key").unwrap(), None);
+    assert_eq!(engine.cached(), 0);
+}
+
+#[test]
+fn test_cache_is_bounded_and_follows_inner_evictions() {
+    let mut engine = cached(2);
+    for key in ["a", "b", "c"] {
+        engine.store(key, key.as_bytes()).unwrap();
+        engine.retrieve(key).unwrap();
+    }
+    // "a" was least recently used
+    assert_eq!(engine.cached(), 2);
+    engine.retrieve("a").unwrap();
+    assert_eq!(engine.misses(), 4);
+
+    // Keys the wrapped engine evicts leave the cache and are reported
+    let mut inner = InMemoryStorageEngine::new().with_max_entries(1);
+    inner.initialize().unwrap();
+    let mut engine = CachingStorageEngine::new(inner, 8);
+    engine.store("old", b"1").unwrap();
+    assert_eq!(engine.retrieve("old").unwrap(), Some(b"1".to_vec()));
+    engine.store("new", b"2").unwrap();
+    assert_eq!(engine.retrieve("old").unwrap(), None);
+    assert_eq!(engine.take_evicted(), vec!["old".to_string()]);
+}
+
+#[test]
+fn test_database_reads_through_the_cache() {
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("kept".to_string(), b"before".to_vec()).unwrap();
+    db.migrate_to(CachingStorageEngine::new(InMemoryStorageEngine::new(), 16)).unwrap();
+
+    assert_eq!(db.get("kept").unwrap(), Some(b"before".to_vec()));
+    for i in 0..3 {
+        let value = format!("v{}", i).into_bytes();
+        db.set("hot".to_string(), value.clone()).unwrap();
+        assert_eq!(db.get("hot").unwrap(), Some(value.clone()));
+        assert_eq!(db.get("hot").unwrap(), Some(value));
+    }
+    db.delete("hot").unwrap();
+    assert_eq!(db.get("hot").unwrap(), None);
+}
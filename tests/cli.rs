@@ -0,0 +1,429 @@
+mod common;
+
+use lohdb::cli::{self, dump_wal, parse_command, split_args, watch, Flow, OutputFormat, ValueFormat};
+use lohdb::{run_command, run_script};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use tempfile::TempDir;
+
+#[test]
+fn test_split_args_handles_quotes() {
+    assert_eq!(split_args("  set key  value \n").unwrap(), vec!["set", "key", "value"]);
+    assert_eq!(
+        split_args(r#"set key "hello  world" 'it is'"#).unwrap(),
+        vec!["set", "key", "hello  world", "it is"]
+    );
+    assert_eq!(split_args(r#"set "a\"b" "line\nbreak""#).unwrap(), vec!["set", "a\"b", "line\nbreak"]);
+    assert_eq!(split_args(r#"set key pre"fix""#).unwrap(), vec!["set", "key", "prefix"]);
+    assert_eq!(split_args(r#"set key """#).unwrap(), vec!["set", "key", ""]);
+
+    let err = split_args(r#"set key "open"#).unwrap_err();
+    assert!(err.to_string().contains("unterminated"));
+}
+
+#[test]
+fn test_parse_command_multi_word_and_quoted_values() {
+    let set = |key: &str, value: &str| Some(cli::Command::Set { key: key.to_string(), value: value.as_bytes().to_vec() });
+
+    // Single words work as they always have
+    assert_eq!(parse_command("set greeting hello").unwrap(), set("greeting", "hello"));
+    // Everything after the key is the value, unquoted words joined by one space
+    assert_eq!(parse_command("set greeting hello world").unwrap(), set("greeting", "hello world"));
+    assert_eq!(parse_command("set greeting  hello    world ").unwrap(), set("greeting", "hello world"));
+    // Quotes keep whitespace as typed and may mix with bare words
+    assert_eq!(parse_command(r#"set x "a b""#).unwrap(), set("x", "a b"));
+    assert_eq!(parse_command("set x 'a  b' c").unwrap(), set("x", "a  b c"));
+    assert_eq!(parse_command(r#"set "two words" "tab\there""#).unwrap(), set("two words", "tab\there"));
+    assert_eq!(parse_command(r#"set x """#).unwrap(), set("x", ""));
+    // Flags still decode the value, and a key alone is not enough
+    assert_eq!(
+        parse_command("set --hex blob 00ff").unwrap(),
+        Some(cli::Command::Set { key: "blob".to_string(), value: vec![0x00, 0xff] })
+    );
+    assert!(parse_command("set --hex blob").is_err());
+    assert!(parse_command("set key").is_err());
+    assert!(parse_command(r#"set x "open"#).is_err());
+}
+
+#[test]
+fn test_parse_command_other_commands() {
+    assert_eq!(parse_command("   ").unwrap(), None);
+    assert_eq!(
+        parse_command("GET --base64 key").unwrap(),
+        Some(cli::Command::Get { key: "key".to_string(), format: ValueFormat::Base64 })
+    );
+    assert_eq!(
+        parse_command("rename --overwrite a b").unwrap(),
+        Some(cli::Command::Rename { from: "a".to_string(), to: "b".to_string(), overwrite: true })
+    );
+    assert_eq!(
+        parse_command("list --limit 5 --after k").unwrap(),
+        Some(cli::Command::ListPage { after: Some("k".to_string()), limit: 5 })
+    );
+    assert_eq!(parse_command("list").unwrap(), Some(cli::Command::List));
+    assert_eq!(parse_command("exit").unwrap(), Some(cli::Command::Quit));
+    assert!(parse_command("delprefix ''").is_err());
+    let err = parse_command("frobnicate now").unwrap_err();
+    assert!(err.to_string().contains("unknown command 'frobnicate now'"));
+}
+
+#[test]
+fn test_value_formats_round_trip_binary() {
+    let bytes = vec![0u8, 1, 0x7f, 0x80, 0xfe, 0xff];
+
+    let hex = ValueFormat::Hex.encode(&bytes).unwrap();
+    assert_eq!(hex, "00017f80feff");
+    assert_eq!(ValueFormat::Hex.decode(&hex).unwrap(), bytes);
+    assert_eq!(ValueFormat::Hex.decode("ABcd").unwrap(), vec![0xab, 0xcd]);
+
+    let b64 = ValueFormat::Base64.encode(&bytes).unwrap();
+    assert_eq!(ValueFormat::Base64.decode(&b64).unwrap(), bytes);
+
+    assert_eq!(ValueFormat::Text.encode(&bytes), None);
+    assert_eq!(ValueFormat::Text.encode(b"plain").as_deref(), Some("plain"));
+
+    let args = ["--hex", "key", "00"];
+    assert_eq!(ValueFormat::from_args(&args), (ValueFormat::Hex, &args[1..]));
+    assert_eq!(ValueFormat::from_args(&args[1..]), (ValueFormat::Text, &args[1..]));
+}
+
+#[test]
+fn test_malformed_encodings_rejected() {
+    let err = ValueFormat::Hex.decode("abc").unwrap_err();
+    assert!(err.to_string().contains("even number of digits"));
+
+    let err = ValueFormat::Hex.decode("zz").unwrap_err();
+    assert!(err.to_string().contains("'zz' at position 0"));
+
+    let err = ValueFormat::Base64.decode("not base64!").unwrap_err();
+    assert!(err.to_string().starts_with("invalid base64 value"));
+}
+
+#[test]
+fn test_run_command_dispatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    assert_eq!(run_command(&mut db, "set greeting hello   there", OutputFormat::Human).unwrap(), Flow::Continue);
+    assert_eq!(db.get("greeting").unwrap(), Some(b"hello there".to_vec()));
+    run_command(&mut db, "set --hex blob 00ff", OutputFormat::Human).unwrap();
+    assert_eq!(db.get("blob").unwrap(), Some(vec![0x00, 0xff]));
+    run_command(&mut db, "get --base64 blob", OutputFormat::Human).unwrap();
+    run_command(&mut db, "info blob", OutputFormat::Json).unwrap();
+    run_command(&mut db, "info missing", OutputFormat::Human).unwrap();
+    run_command(&mut db, "delete greeting", OutputFormat::Human).unwrap();
+    assert_eq!(db.get("greeting").unwrap(), None);
+    assert_eq!(run_command(&mut db, "   ", OutputFormat::Human).unwrap(), Flow::Continue);
+    assert_eq!(run_command(&mut db, "quit", OutputFormat::Human).unwrap(), Flow::Quit);
+
+    assert!(run_command(&mut db, "frobnicate", OutputFormat::Human).unwrap_err().to_string().contains("unknown command"));
+    assert!(run_command(&mut db, "get --hex", OutputFormat::Human).is_err());
+    assert!(run_command(&mut db, "set --hex key", OutputFormat::Human).unwrap_err().to_string().starts_with("usage"));
+}
+
+#[test]
+fn test_run_script_stops_at_first_error() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    let script = "# seed some keys\nset a 1\n\nset b 2\nquit\nset c 3\n";
+    run_script(&mut db, script.as_bytes(), OutputFormat::Human).unwrap();
+    assert_eq!(db.list_keys().unwrap(), vec!["a", "b"]);
+
+    let script = "set d 4\nset --base64 e !!!\nset f 6\n";
+    assert!(run_script(&mut db, script.as_bytes(), OutputFormat::Human).is_err());
+    assert_eq!(db.get("d").unwrap(), Some(b"4".to_vec()));
+    assert_eq!(db.get("f").unwrap(), None);
+}
+
+/// Run the `lohdb` binary against `temp_dir` with `args`, feeding it `stdin`
+fn run_lohdb(temp_dir: &TempDir, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lohdb"))
+        .arg("--data-dir")
+        .arg(temp_dir.path())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn json_lines(output: &Output) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_json_output_mode() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = run_lohdb(&temp_dir, &["--format", "json", "--exec", "set --hex blob 00ff"], "");
+    assert!(output.status.success());
+    assert_eq!(json_lines(&output), vec![serde_json::json!({ "key": "blob", "ok": true })]);
+
+    let script = "set name Ada\nget name\nget blob\nlist\nstats\n";
+    let output = run_lohdb(&temp_dir, &["--format", "json"], script);
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines[1], serde_json::json!({ "key": "name", "found": true, "value": "Ada", "encoding": "utf8" }));
+    assert_eq!(lines[2]["value"], "AP8=");
+    assert_eq!(lines[2]["encoding"], "base64");
+    assert_eq!(lines[3]["keys"], serde_json::json!(["blob", "name"]));
+    assert_eq!(lines[4]["num_keys"], 2);
+}
+
+#[test]
+fn test_failures_exit_non_zero() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = run_lohdb(&temp_dir, &["--format", "json"], "set a 1\nnonsense\nset b 2\n");
+    assert!(!output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["line"], 2);
+    assert!(lines[1]["error"].as_str().unwrap().contains("unknown command"));
+
+    // Human output reports errors on stderr
+    let output = run_lohdb(&temp_dir, &["--exec", "set --hex k 0"], "");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("even number of digits"));
+
+    // So does a get of a missing key, naming it
+    let output = run_lohdb(&temp_dir, &["--format", "json"], "get missing\nset c 3\n");
+    assert!(!output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines, vec![serde_json::json!({ "error": "key 'missing' not found", "line": 1 })]);
+}
+
+#[test]
+fn test_watch_streams_events_until_stopped() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    let (handle, events) = db.subscribe_channel_prefix("user:".to_string()).unwrap();
+    let (_stop_tx, stop_rx) = crossbeam::channel::bounded::<()>(1);
+
+    // A burst larger than one batch, plus changes the prefix filters out
+    for i in 0..300 {
+        db.set(format!("user:{}", i), b"x".to_vec()).unwrap();
+        db.set(format!("other:{}", i), b"x".to_vec()).unwrap();
+    }
+    db.namespace("ns").unwrap().set("user:ns".to_string(), vec![0xff]).unwrap();
+    db.delete("user:0").unwrap();
+
+    // Closing the database ends the stream once every queued event is printed
+    let watcher = thread::spawn(move || {
+        let mut out = Vec::new();
+        let printed = watch(&events, &stop_rx, OutputFormat::Json, &mut out).unwrap();
+        (printed, out)
+    });
+    drop(handle);
+    drop(db);
+    let (printed, out) = watcher.join().unwrap();
+
+    let lines: Vec<serde_json::Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(printed, 302);
+    assert_eq!(lines.len(), 302);
+    assert_eq!(lines[0], serde_json::json!({
+        "event": "set", "namespace": null, "key": "user:0", "value": "x", "encoding": "utf8"
+    }));
+    assert_eq!(lines[300]["namespace"], "ns");
+    assert_eq!(lines[300]["encoding"], "base64");
+    assert_eq!(lines[301], serde_json::json!({ "event": "delete", "namespace": null, "key": "user:0" }));
+
+    // An idle watch returns as soon as it is told to stop
+    let (_events_tx, events) = crossbeam::channel::unbounded();
+    let (stop_tx, stop_rx) = crossbeam::channel::bounded(1);
+    stop_tx.send(()).unwrap();
+    assert_eq!(watch(&events, &stop_rx, OutputFormat::Human, Vec::new()).unwrap(), 0);
+}
+
+#[test]
+fn test_dump_wal_prints_pending_records() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = common::open(&temp_dir);
+        db.set("short".to_string(), b"value".to_vec()).unwrap();
+        db.set("long".to_string(), vec![b'x'; 100]).unwrap();
+        db.namespace("ns").unwrap().set("bin".to_string(), vec![0, 255]).unwrap();
+        db.delete("short").unwrap();
+    }
+
+    let mut out = Vec::new();
+    assert_eq!(dump_wal(temp_dir.path(), 8, OutputFormat::Human, &mut out).unwrap(), 4);
+    let text = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with("#1 wal.000001.log@16 "), "{}", lines[0]);
+    assert!(lines[0].ends_with("SET 'short' = 'value'"));
+    assert!(lines[1].ends_with("SET 'long' = 'xxxxxxxx'… (100 bytes)"));
+    assert!(lines[2].ends_with("SET [ns] 'bin' = 0x00ff"));
+    assert!(lines[3].ends_with("DELETE 'short'"));
+
+    let mut out = Vec::new();
+    dump_wal(temp_dir.path(), 8, OutputFormat::Json, &mut out).unwrap();
+    let first: serde_json::Value = serde_json::from_slice(out.split(|&b| b == b'\n').next().unwrap()).unwrap();
+    assert_eq!(first["seq"], 1);
+    assert_eq!(first["value"], "value");
+    assert_eq!(first["truncated"], false);
+}
+
+#[test]
+fn test_verify_subcommand() {
+    let temp_dir = TempDir::new().unwrap();
+    drop(common::open(&temp_dir));
+    run_lohdb(&temp_dir, &["--exec", "set a 1"], "");
+
+    let output = run_lohdb(&temp_dir, &["--format", "json", "verify"], "");
+    assert!(output.status.success());
+    let report = &json_lines(&output)[0];
+    assert_eq!(report["consistent"], true);
+    assert_eq!(report["data_file_keys"], 1);
+
+    let segment = temp_dir.path().join("wal.000002.log");
+    let mut bytes = std::fs::read(&segment).unwrap();
+    bytes.push(9);
+    std::fs::write(&segment, bytes).unwrap();
+    let output = run_lohdb(&temp_dir, &["verify"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("torn record"));
+
+    let output = run_lohdb(&temp_dir, &["verify", "--repair"], "");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn test_delprefix_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    for line in ["set cache:a 1", "set cache:b 2", "set keep 3", "delprefix cache:"] {
+        run_command(&mut db, line, OutputFormat::Human).unwrap();
+    }
+    assert_eq!(db.list_keys().unwrap(), vec!["keep"]);
+
+    let err = run_command(&mut db, "delprefix \"\"", OutputFormat::Human).unwrap_err();
+    assert!(err.to_string().contains("must not be empty"));
+    assert_eq!(db.list_keys().unwrap(), vec!["keep"]);
+}
+
+#[test]
+fn test_ttl_and_persist_commands() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set_with_ttl("session".to_string(), b"token".to_vec(), std::time::Duration::from_secs(60)).unwrap();
+
+    run_command(&mut db, "ttl session", OutputFormat::Human).unwrap();
+    run_command(&mut db, "ttl missing", OutputFormat::Json).unwrap();
+    run_command(&mut db, "persist session", OutputFormat::Human).unwrap();
+    assert_eq!(db.ttl("session").unwrap(), None);
+    run_command(&mut db, "persist session", OutputFormat::Json).unwrap();
+    assert!(run_command(&mut db, "ttl", OutputFormat::Human).unwrap_err().to_string().contains("unknown command"));
+    drop(db);
+
+    let output = run_lohdb(&temp_dir, &["--format", "json"], "set plain 1\nttl plain\nttl missing\npersist plain\n");
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines[1], serde_json::json!({ "key": "plain", "found": true, "ttl_ms": null }));
+    assert_eq!(lines[2], serde_json::json!({ "key": "missing", "found": false }));
+    assert_eq!(lines[3], serde_json::json!({ "key": "plain", "persisted": false }));
+}
+
+#[test]
+fn test_compact_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = run_lohdb(&temp_dir, &["--format", "json"], "set a 1\ncompact\ncompact\n");
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert!(lines[1]["bytes_after"].as_u64().unwrap() > 0);
+    assert_eq!(lines[2]["bytes_reclaimed"], 0);
+    assert_eq!(lines[2]["bytes_before"], lines[2]["bytes_after"]);
+}
+
+#[test]
+fn test_rename_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = "set a 1\nset b 2\nrename a c\nrename missing d\nrename --overwrite c b\nget b\n";
+    let output = run_lohdb(&temp_dir, &["--format", "json"], script);
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines[2], serde_json::json!({ "from": "a", "to": "c", "renamed": true }));
+    assert_eq!(lines[3]["renamed"], false);
+    assert_eq!(lines[4]["renamed"], true);
+    assert_eq!(lines[5]["value"], "1");
+
+    // Without --overwrite an existing target is an error
+    let output = run_lohdb(&temp_dir, &["--exec", "set x 2"], "");
+    assert!(output.status.success());
+    let output = run_lohdb(&temp_dir, &["--exec", "rename x b"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+}
+
+#[test]
+fn test_profile_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = run_lohdb(&temp_dir, &["--format", "json"], "profile\nset a 1\nset bb 12345\nprofile\n");
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines[0]["keys"], 0);
+    assert_eq!(lines[3]["keys"], 2);
+    assert_eq!(lines[3]["key_bytes"]["max"], 2);
+    assert_eq!(lines[3]["value_bytes"]["total"], 6);
+    assert_eq!(lines[3]["largest_value"]["key"], "bb");
+
+    let output = run_lohdb(&temp_dir, &["--exec", "profile"], "");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Largest value: bb (5 bytes)"));
+}
+
+#[test]
+fn test_take_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = run_lohdb(&temp_dir, &["--format", "json"], "set job resize\ntake job\ntake job\n");
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines[1], serde_json::json!({ "key": "job", "taken": true, "value": "resize", "encoding": "utf8" }));
+    assert_eq!(lines[2], serde_json::json!({ "key": "job", "taken": false }));
+    assert_eq!(
+        parse_command("take --hex job").unwrap(),
+        Some(cli::Command::Take { key: "job".to_string(), format: ValueFormat::Hex })
+    );
+}
+
+#[test]
+fn test_delif_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = "set lock worker one\ndelif lock worker two\ndelif lock worker one\ndelif lock worker one\n";
+    let output = run_lohdb(&temp_dir, &["--format", "json"], script);
+    assert!(output.status.success());
+    let lines = json_lines(&output);
+    assert_eq!(lines[1], serde_json::json!({ "key": "lock", "deleted": false }));
+    assert_eq!(lines[2], serde_json::json!({ "key": "lock", "deleted": true }));
+    assert_eq!(lines[3], serde_json::json!({ "key": "lock", "deleted": false }));
+    assert_eq!(
+        parse_command("delif --hex k 00ff").unwrap(),
+        Some(cli::Command::DeleteIf { key: "k".to_string(), expected: vec![0, 255] })
+    );
+    assert!(parse_command("delif k").is_err());
+}
+
+#[test]
+fn test_init_subcommand() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("provisioned");
+    let output = run_lohdb(&temp_dir, &["--format", "json", "init", dir.to_str().unwrap()], "");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report = &json_lines(&output)[0];
+    assert_eq!(report["consistent"], true);
+    assert_eq!(report["data_file_present"], true);
+    assert!(dir.join("MANIFEST").exists());
+}
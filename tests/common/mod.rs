@@ -0,0 +1,24 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use lohdb::{Database, DatabaseConfig, DatabaseConfigBuilder};
+use std::path::Path;
+
+/// Config builder for a database in `dir` with background checkpoints off,
+/// so only the test decides when the WAL is checkpointed
+pub fn builder(dir: impl AsRef<Path>) -> DatabaseConfigBuilder {
+    DatabaseConfig::builder()
+        .data_dir(dir.as_ref().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+}
+
+/// `builder(dir)` with nothing else set
+pub fn config(dir: impl AsRef<Path>) -> DatabaseConfig {
+    builder(dir).build().unwrap()
+}
+
+/// Opens `config(dir)`
+pub fn open(dir: impl AsRef<Path>) -> Database {
+    Database::open(config(dir)).unwrap()
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use lohdb::{CompactStats, Database, DatabaseConfig};
+use tempfile::TempDir;
+
+fn data_file_len(temp_dir: &TempDir) -> u64 {
+    std::fs::metadata(temp_dir.path().join("data.db")).unwrap().len()
+}
+
+#[test]
+fn test_compact_shrinks_data_file_after_overwrites_and_deletes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    for i in 0..100 {
+        db.set(format!("key{:03}", i), vec![b'a'; 1024]).unwrap();
+    }
+    db.checkpoint().unwrap();
+    let before = data_file_len(&temp_dir);
+
+    for i in 0..100 {
+        if i % 10 == 0 {
+            db.set(format!("key{:03}", i), b"small".to_vec()).unwrap();
+        } else {
+            db.delete(&format!("key{:03}", i)).unwrap();
+        }
+    }
+    // The data file still holds every value as of the checkpoint
+    assert_eq!(data_file_len(&temp_dir), before);
+
+    let stats = db.compact().unwrap();
+    assert_eq!(stats.bytes_before, before);
+    assert_eq!(stats.bytes_after, data_file_len(&temp_dir));
+    assert!(stats.bytes_after < before / 10);
+    assert_eq!(stats.bytes_reclaimed(), before - stats.bytes_after);
+
+    // Compacting again has nothing left to reclaim
+    assert_eq!(db.compact().unwrap().bytes_reclaimed(), 0);
+    db.close().unwrap();
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.key_count().unwrap(), 10);
+    assert_eq!(db.get("key050").unwrap(), Some(b"small".to_vec()));
+    assert_eq!(db.get("key051").unwrap(), None);
+}
+
+#[test]
+fn test_compact_in_memory_reclaims_nothing() {
+    let config = DatabaseConfig::builder().in_memory(true).build().unwrap();
+    let mut db = Database::open(config).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(db.compact().unwrap(), CompactStats::default());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_compact_collects_mmap_garbage() {
+    use lohdb::db::MmapStorageEngine;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.migrate_to(MmapStorageEngine::new(temp_dir.path().to_string_lossy().to_string())).unwrap();
+
+    // Too little dead space for flush to compact on its own
+    for round in 0..4 {
+        for i in 0..20 {
+            db.set(format!("key{:02}", i), vec![round; 1024]).unwrap();
+        }
+    }
+    for i in 10..20 {
+        db.delete(&format!("key{:02}", i)).unwrap();
+    }
+    db.flush().unwrap();
+    let before = db.stats().unwrap().data_file_bytes;
+
+    let stats = db.compact().unwrap();
+    assert_eq!(stats.bytes_before, before);
+    assert!(stats.bytes_after < before / 4);
+    assert_eq!(db.key_count().unwrap(), 10);
+    assert_eq!(db.get("key09").unwrap(), Some(vec![3; 1024]));
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{Compressor, Database, DatabaseConfig, LohError, Result};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Byte-level run-length encoding, enough to exercise the codec plumbing
+struct RleCompressor;
+
+impl Compressor for RleCompressor {
+    fn name(&self) -> &str {
+        "rle"
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for chunk in data.chunk_by(|a, b| a == b) {
+            for run in chunk.chunks(u8::MAX as usize) {
+                out.push(run.len() as u8);
+                out.push(run[0]);
+            }
+        }
+        Ok(out)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.chunks(2).flat_map(|pair| std::iter::repeat_n(pair[1], pair[0] as usize)).collect())
+    }
+}
+
+struct OtherCompressor;
+
+impl Compressor for OtherCompressor {
+    fn name(&self) -> &str {
+        "other"
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+fn write_and_flush(config: DatabaseConfig) {
+    let mut db = Database::open(config).unwrap();
+    db.set("big".to_string(), vec![b'x'; 64 * 1024]).unwrap();
+    db.flush().unwrap();
+}
+
+#[test]
+fn test_compressed_data_file_round_trip() {
+    let plain_dir = TempDir::new().unwrap();
+    let compressed_dir = TempDir::new().unwrap();
+
+    write_and_flush(common::config(&plain_dir));
+    let config = common::builder(&compressed_dir)
+        .compressor(RleCompressor)
+        .build()
+        .unwrap();
+    write_and_flush(config.clone());
+
+    let plain_len = std::fs::metadata(plain_dir.path().join("data.db")).unwrap().len();
+    let compressed_len = std::fs::metadata(compressed_dir.path().join("data.db")).unwrap().len();
+    assert!(compressed_len * 10 < plain_len);
+
+    // Drop the WAL so the value can only come from the compressed snapshot
+    std::fs::remove_file(compressed_dir.path().join("wal.000001.log")).unwrap();
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("big").unwrap(), Some(vec![b'x'; 64 * 1024]));
+}
+
+#[test]
+fn test_legacy_uncompressed_file_loads_and_codec_mismatch_is_detected() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().to_string_lossy().to_string();
+
+    // An uncompressed data file still loads once a compressor is configured
+    write_and_flush(common::config(&data_dir));
+    std::fs::remove_file(temp_dir.path().join("wal.000001.log")).unwrap();
+    let compressed = common::builder(&data_dir)
+        .compressor(RleCompressor)
+        .build()
+        .unwrap();
+    {
+        let mut db = Database::open(compressed).unwrap();
+        assert_eq!(db.get("big").unwrap(), Some(vec![b'x'; 64 * 1024]));
+        db.set("other".to_string(), b"1".to_vec()).unwrap();
+        db.flush().unwrap();
+    }
+
+    // Reading it back with a different codec, or none, is an error
+    let other = DatabaseConfig::builder().data_dir(&data_dir).compressor(OtherCompressor).build().unwrap();
+    let err = Database::open(other).err().expect("codec mismatch must fail");
+    assert!(err.to_string().contains("'rle'"));
+    let none = DatabaseConfig::builder().data_dir(&data_dir).build().unwrap();
+    assert!(Database::open(none).is_err());
+}
+
+fn wal_config(temp_dir: &TempDir, compress: bool) -> DatabaseConfig {
+    let builder = common::builder(temp_dir);
+    let builder = if compress { builder.wal_compressor(RleCompressor) } else { builder };
+    builder.build().unwrap()
+}
+
+#[test]
+fn test_compressed_wal_is_smaller_and_replays() {
+    let plain_dir = TempDir::new().unwrap();
+    let compressed_dir = TempDir::new().unwrap();
+    for (dir, compress) in [(&plain_dir, false), (&compressed_dir, true)] {
+        let mut db = Database::open(wal_config(dir, compress)).unwrap();
+        for i in 0..20 {
+            db.set(format!("key{}", i), vec![b'a' + i as u8; 16 * 1024]).unwrap();
+        }
+        // Dropped without a checkpoint, so the values are only in the WAL
+    }
+
+    let plain = Database::open(wal_config(&plain_dir, false)).unwrap().stats().unwrap().wal_file_bytes;
+    let db = Database::open(wal_config(&compressed_dir, true)).unwrap();
+    let compressed = db.stats().unwrap().wal_file_bytes;
+    assert!(compressed * 20 < plain, "{} compressed vs {} plain", compressed, plain);
+    for i in 0..20 {
+        assert_eq!(db.get(&format!("key{}", i)).unwrap(), Some(vec![b'a' + i as u8; 16 * 1024]));
+    }
+    drop(db);
+
+    // Without the codec the compressed records cannot be read
+    let err = Database::open(wal_config(&compressed_dir, false)).err().expect("compressed records need the codec");
+    assert!(matches!(err, LohError::Compression(_)), "{}", err);
+}
+
+#[test]
+fn test_mixed_compressed_and_stored_records_replay() {
+    let temp_dir = TempDir::new().unwrap();
+    let set = |key: &str, value: Vec<u8>| Operation::Set { namespace: None, key: key.to_string(), value };
+
+    // Records from before compression was turned on
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap();
+    wal.append(&set("before", vec![b'z'; 4096])).unwrap();
+    drop(wal);
+
+    // Short, varied values would grow under RLE and are stored as they are
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap().with_compressor(Arc::new(RleCompressor));
+    wal.append(&set("small", b"abcdef".to_vec())).unwrap();
+    wal.append(&set("large", vec![b'y'; 4096])).unwrap();
+    wal.append(&Operation::Delete { namespace: None, key: "before".to_string() }).unwrap();
+    drop(wal);
+
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap().with_compressor(Arc::new(RleCompressor));
+    let mut replayed = Vec::new();
+    let report = wal.replay(|entry| {
+        replayed.push(match entry.operation {
+            Operation::Set { key, value, .. } => (key, value.len()),
+            Operation::Delete { key, .. } => (key, 0),
+            other => panic!("unexpected {:?}", other),
+        });
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(report.skipped, 0);
+    let expected = [("before", 4096), ("small", 6), ("large", 4096), ("before", 0)];
+    assert_eq!(replayed, expected.map(|(key, len)| (key.to_string(), len)));
+    assert!(wal.iter_entries().all(|entry| entry.is_ok()));
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn test_lz4_compressor_round_trip() {
+    let lz4 = lohdb::db::Lz4Compressor;
+    let data = b"hello hello hello hello hello".repeat(100);
+    let compressed = lz4.compress(&data).unwrap();
+    assert!(compressed.len() < data.len());
+    assert_eq!(lz4.decompress(&compressed).unwrap(), data);
+}
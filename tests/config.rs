@@ -0,0 +1,23 @@
+use lohdb::DatabaseConfig;
+
+#[test]
+fn test_config_builder_defaults_and_validation() {
+    let default = DatabaseConfig::default();
+    assert_eq!(default.data_dir, "./lohdb_data");
+    assert_eq!(default.wal_sync_interval_ms, 1000);
+    assert!(!default.read_only);
+
+    let config = DatabaseConfig::builder()
+        .data_dir("/tmp/somewhere")
+        .wal_sync_interval_ms(250)
+        .read_only(true)
+        .build()
+        .unwrap();
+    assert_eq!(config.data_dir, "/tmp/somewhere");
+    assert_eq!(config.wal_sync_interval_ms, 250);
+    assert!(config.read_only);
+
+    assert!(DatabaseConfig::builder().data_dir("").build().is_err());
+    // Zero is valid and disables the background sync thread
+    assert!(DatabaseConfig::builder().wal_sync_interval_ms(0).build().is_ok());
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use lohdb::db::{DedupStats, DedupStorageEngine, InMemoryStorageEngine, StorageEngine};
+use lohdb::{Database, DatabaseConfig, LohError};
+use tempfile::TempDir;
+
+fn config_for(temp_dir: &TempDir) -> DatabaseConfig {
+    common::builder(temp_dir)
+        .dedup(true)
+        .build()
+        .unwrap()
+}
+
+fn in_memory_engine() -> DedupStorageEngine {
+    let mut engine = DedupStorageEngine::new(Box::new(InMemoryStorageEngine::new()), Box::new(InMemoryStorageEngine::new()));
+    engine.initialize().unwrap();
+    engine
+}
+
+#[test]
+fn test_keys_with_the_same_value_share_one_blob() {
+    let mut engine = in_memory_engine();
+    let value = vec![7u8; 4096];
+    engine.store("a", &value).unwrap();
+    engine.store("b", &value).unwrap();
+    engine.store("c", b"other").unwrap();
+
+    let stats = engine.dedup_stats().unwrap();
+    assert_eq!((stats.blobs, stats.references), (2, 3));
+    assert_eq!(engine.refcount(&value).unwrap(), 2);
+    assert_eq!(engine.retrieve("a").unwrap(), Some(value.clone()));
+    assert_eq!(engine.retrieve("b").unwrap(), Some(value.clone()));
+
+    // Overwriting with the same bytes changes nothing
+    engine.store("a", &value).unwrap();
+    assert_eq!(engine.refcount(&value).unwrap(), 2);
+
+    // The snapshot hands out one copy for both keys
+    let snapshot = engine.snapshot().unwrap();
+    assert!(std::sync::Arc::ptr_eq(&snapshot["a"], &snapshot["b"]));
+}
+
+#[test]
+fn test_blob_outlives_all_but_its_last_reference() {
+    let mut engine = in_memory_engine();
+    let value = b"shared value".to_vec();
+    engine.store("a", &value).unwrap();
+    engine.store("b", &value).unwrap();
+
+    assert!(engine.remove("a").unwrap());
+    assert_eq!(engine.refcount(&value).unwrap(), 1);
+    assert_eq!(engine.retrieve("b").unwrap(), Some(value.clone()));
+
+    // Overwriting the last key drops its reference too; the blob stays
+    // until a flush
+    engine.store("b", b"new").unwrap();
+    assert_eq!(engine.refcount(&value).unwrap(), 0);
+    assert_eq!(engine.dedup_stats().unwrap().blobs, 2);
+    engine.flush().unwrap();
+    assert_eq!(engine.dedup_stats().unwrap().blobs, 1);
+    assert!(!engine.remove("a").unwrap());
+}
+
+#[test]
+fn test_dedup_database_survives_reopen() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = config_for(&temp_dir);
+    let value = vec![42u8; 10_000];
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for key in ["a", "b", "c"] {
+            db.set(key.to_string(), value.clone()).unwrap();
+        }
+        db.set("d".to_string(), b"unique".to_vec()).unwrap();
+        db.checkpoint().unwrap();
+        assert!(temp_dir.path().join("data.blobs.db").exists());
+
+        // Logged after the checkpoint, so only the WAL has them
+        db.delete("a").unwrap();
+        db.delete("d").unwrap();
+    }
+
+    // The blob of "d" stays stored until the next checkpoint
+    let mut db = Database::open(config.clone()).unwrap();
+    let stats = db.stats().unwrap().dedup.unwrap();
+    assert_eq!((stats.blobs, stats.references), (2, 2));
+    assert_eq!(db.get("b").unwrap(), Some(value.clone()));
+    assert_eq!(db.get("a").unwrap(), None);
+
+    // Blobs stay stored until a checkpoint writes the keys without them
+    db.delete("b").unwrap();
+    db.delete("c").unwrap();
+    assert_eq!(db.stats().unwrap().dedup.unwrap(), DedupStats { blobs: 2, references: 0 });
+    db.checkpoint().unwrap();
+    assert_eq!(db.stats().unwrap().dedup.unwrap().blobs, 0);
+    db.close().unwrap();
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.stats().unwrap().dedup.unwrap().blobs, 0);
+    assert!(db.list_keys().unwrap().is_empty());
+}
+
+#[test]
+fn test_dedup_stores_shared_values_once_on_disk() {
+    let value = vec![1u8; 64 * 1024];
+    let usage = |dedup: bool| {
+        let temp_dir = TempDir::new().unwrap();
+        let config = common::builder(&temp_dir)
+            .dedup(dedup)
+            .build()
+            .unwrap();
+        let mut db = Database::open(config).unwrap();
+        for i in 0..8 {
+            db.set(format!("copy{}", i), value.clone()).unwrap();
+        }
+        db.checkpoint().unwrap();
+        db.stats().unwrap().data_file_bytes
+    };
+
+    let (plain, dedup) = (usage(false), usage(true));
+    assert!(dedup < plain / 4, "dedup {} bytes vs plain {} bytes", dedup, plain);
+}
+
+#[test]
+fn test_dedup_is_recorded_in_the_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    Database::open(config_for(&temp_dir)).unwrap().close().unwrap();
+
+    let plain = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .build()
+        .unwrap();
+    assert!(matches!(Database::open(plain), Err(LohError::InvalidArgument(_))));
+}
+
+#[test]
+fn test_dedup_in_memory() {
+    let config = DatabaseConfig::builder().in_memory(true).dedup(true).build().unwrap();
+    let mut db = Database::open(config).unwrap();
+    db.set("a".to_string(), b"same".to_vec()).unwrap();
+    db.set("b".to_string(), b"same".to_vec()).unwrap();
+    let stats = db.stats().unwrap().dedup.unwrap();
+    assert_eq!((stats.blobs, stats.references), (1, 2));
+
+    // Nothing flushes an in-memory database, so replaced values go at once
+    for i in 0..100 {
+        db.set("a".to_string(), format!("value{}", i).into_bytes()).unwrap();
+    }
+    db.delete("b").unwrap();
+    assert_eq!(db.stats().unwrap().dedup.unwrap(), DedupStats { blobs: 1, references: 1 });
+
+    let cached = DatabaseConfig::builder().in_memory(true).dedup(true).cache_max_entries(10).build();
+    assert!(matches!(cached, Err(LohError::InvalidArgument(_))));
+}
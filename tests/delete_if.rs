@@ -0,0 +1,78 @@
+mod common;
+
+use lohdb::db::ChangeEvent;
+use lohdb::Database;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use tempfile::TempDir;
+
+#[test]
+fn test_deletes_only_the_expected_value() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("lock".to_string(), b"worker-1".to_vec()).unwrap();
+        db.set("other".to_string(), b"worker-2".to_vec()).unwrap();
+        let (_handle, events) = db.subscribe_channel().unwrap();
+
+        // Someone else's lock, and a missing one, are both left alone
+        assert!(!db.delete_if("lock", b"worker-2").unwrap());
+        assert!(!db.delete_if("missing", b"worker-1").unwrap());
+        assert_eq!(db.get("lock").unwrap(), Some(b"worker-1".to_vec()));
+        assert_eq!(db.stats().unwrap().wal_sequence, 2);
+        assert!(events.try_recv().is_err());
+
+        assert!(db.delete_if("lock", b"worker-1").unwrap());
+        assert_eq!(db.get("lock").unwrap(), None);
+        assert!(!db.delete_if("lock", b"worker-1").unwrap());
+        assert!(matches!(events.try_recv().unwrap(), ChangeEvent::Delete { key, .. } if key == "lock"));
+        assert!(events.try_recv().is_err());
+    }
+
+    // The delete was logged and survives a restart
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("lock").unwrap(), None);
+    assert_eq!(db.get("other").unwrap(), Some(b"worker-2".to_vec()));
+}
+
+#[test]
+fn test_concurrent_releases_delete_once() {
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 20;
+    let temp_dir = TempDir::new().unwrap();
+    let db = Arc::new(common::open(&temp_dir));
+
+    for round in 0..ROUNDS {
+        let token = format!("lease{}", round).into_bytes();
+        let ns = db.namespace("leases").unwrap();
+        ns.set("job".to_string(), token.clone()).unwrap();
+
+        // Every thread releases the same lease, plus one holding a stale token
+        let barrier = Arc::new(Barrier::new(THREADS + 1));
+        let mut handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let (db, barrier, token) = (db.clone(), barrier.clone(), token.clone());
+                thread::spawn(move || {
+                    let ns = db.namespace("leases").unwrap();
+                    barrier.wait();
+                    ns.delete_if("job", &token).unwrap()
+                })
+            })
+            .collect();
+        let stale = {
+            let (db, barrier) = (db.clone(), barrier.clone());
+            thread::spawn(move || {
+                let ns = db.namespace("leases").unwrap();
+                barrier.wait();
+                ns.delete_if("job", b"expired-lease").unwrap()
+            })
+        };
+        handles.push(stale);
+
+        let deleted = handles.into_iter().map(|h| h.join().unwrap()).filter(|&d| d).count();
+        assert_eq!(deleted, 1);
+        assert_eq!(ns.get("job").unwrap(), None);
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{ChangeEvent, Database, LohError};
+use tempfile::TempDir;
+
+fn populate(db: &mut Database) {
+    for key in ["user:1", "user:2", "user:3", "userx", "session:1"] {
+        db.set(key.to_string(), key.as_bytes().to_vec()).unwrap();
+    }
+    db.namespace("tenant").unwrap().set("user:9".to_string(), b"9".to_vec()).unwrap();
+}
+
+#[test]
+fn test_delete_prefix_removes_matches_atomically() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut db = common::open(&temp_dir);
+        populate(&mut db);
+        db.create_index("first", |_, value| Some(value[..1].to_vec())).unwrap();
+        let (_handle, events) = db.subscribe_channel().unwrap();
+
+        assert_eq!(db.delete_prefix("user:").unwrap(), 3);
+        assert_eq!(db.list_keys().unwrap(), vec!["session:1", "userx"]);
+        assert_eq!(db.index_lookup("first", b"u").unwrap(), vec!["userx"]);
+        assert_eq!(db.stats().unwrap().deletes, 3);
+
+        let deleted: Vec<String> = events
+            .try_iter()
+            .map(|event| match event {
+                ChangeEvent::Delete { namespace: None, key } => key,
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(deleted, vec!["user:1", "user:2", "user:3"]);
+
+        // Other namespaces are untouched, and no match is not an error
+        assert_eq!(db.namespace("tenant").unwrap().list_keys().unwrap(), vec!["user:9"]);
+        assert_eq!(db.delete_prefix("nothing").unwrap(), 0);
+    }
+
+    // One record covers the whole delete, and replay applies it
+    let mut wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let mut operations = Vec::new();
+    wal.replay(|entry| {
+        operations.push(entry.operation);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(operations.len(), 7);
+    assert!(matches!(
+        &operations[6],
+        Operation::DeletePrefix { namespace: None, prefix, keys } if prefix == "user:" && keys == &["user:1", "user:2", "user:3"]
+    ));
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.list_keys().unwrap(), vec!["session:1", "userx"]);
+    assert_eq!(db.namespace("tenant").unwrap().list_keys().unwrap(), vec!["user:9"]);
+}
+
+#[test]
+fn test_empty_prefix_clears_only_its_namespace() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    populate(&mut db);
+    db.namespace("other").unwrap().set("a".to_string(), b"1".to_vec()).unwrap();
+
+    assert_eq!(db.namespace("tenant").unwrap().delete_prefix("").unwrap(), 1);
+    assert_eq!(db.list_namespaces().unwrap(), vec!["other"]);
+
+    assert_eq!(db.delete_prefix("").unwrap(), 5);
+    assert!(db.list_keys().unwrap().is_empty());
+    assert_eq!(db.namespace("other").unwrap().get("a").unwrap(), Some(b"1".to_vec()));
+}
+
+#[test]
+fn test_delete_prefix_spans_scan_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.bulk_load((0..3000).map(|i| (format!("bulk:{:05}", i), vec![]))).unwrap();
+    db.set("bulk;after".to_string(), vec![]).unwrap();
+
+    assert_eq!(db.delete_prefix("bulk:").unwrap(), 3000);
+    assert_eq!(db.list_keys().unwrap(), vec!["bulk;after"]);
+}
+
+#[test]
+fn test_delete_prefix_rejected_when_read_only() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&mut common::open(&temp_dir));
+
+    let mut config = common::config(&temp_dir);
+    config.read_only = true;
+    let mut db = Database::open(config).unwrap();
+    assert!(matches!(db.delete_prefix("user:"), Err(LohError::ReadOnly)));
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig};
+use tempfile::TempDir;
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_encrypted_round_trip_and_wrong_key_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().to_string_lossy().to_string();
+    let config = |key: [u8; 32]| {
+        common::builder(&data_dir)
+            .encryption_key(key)
+            .build()
+            .unwrap()
+    };
+
+    {
+        let mut db = Database::open(config([7; 32])).unwrap();
+        db.set("secret".to_string(), b"plaintext-marker".to_vec()).unwrap();
+        db.flush().unwrap();
+        db.set("pending".to_string(), b"only-in-wal".to_vec()).unwrap();
+    }
+
+    // Neither file contains the plaintext
+    for file in ["data.db", "wal.000001.log"] {
+        let bytes = std::fs::read(temp_dir.path().join(file)).unwrap();
+        assert!(!bytes.windows(16).any(|w| w == b"plaintext-marker"));
+    }
+
+    let db = Database::open(config([7; 32])).unwrap();
+    assert_eq!(db.get("secret").unwrap(), Some(b"plaintext-marker".to_vec()));
+    assert_eq!(db.get("pending").unwrap(), Some(b"only-in-wal".to_vec()));
+    drop(db);
+
+    let err = Database::open(config([8; 32])).err().expect("wrong key must fail");
+    assert!(err.to_string().contains("wrong encryption key"));
+
+    let no_key = DatabaseConfig::builder().data_dir(&data_dir).build().unwrap();
+    assert!(Database::open(no_key).is_err());
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_encryptor_uses_fresh_nonces() {
+    use lohdb::db::ChaCha20Poly1305Encryptor;
+    use lohdb::Encryptor;
+
+    let encryptor = ChaCha20Poly1305Encryptor::new([1; 32]);
+    let a = encryptor.encrypt(b"same input").unwrap();
+    let b = encryptor.encrypt(b"same input").unwrap();
+    assert_ne!(a, b);
+    assert_eq!(encryptor.decrypt(&a).unwrap(), b"same input");
+    assert!(ChaCha20Poly1305Encryptor::new([2; 32]).decrypt(&a).is_err());
+}
+
+#[cfg(not(feature = "crypto"))]
+#[test]
+fn test_encryption_key_requires_crypto_feature() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy())
+        .encryption_key([7; 32])
+        .build()
+        .unwrap();
+
+    let err = Database::open(config).err().expect("open must fail without the crypto feature");
+    assert!(err.to_string().contains("crypto"));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig, LohError};
+use tempfile::TempDir;
+
+#[test]
+fn test_error_variants_can_be_matched() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+
+    let mut db = Database::open(config.clone()).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+
+    assert!(matches!(Database::open(config.clone()), Err(LohError::Locked(_))));
+    assert!(matches!(db.set("\0bad".to_string(), vec![]), Err(LohError::InvalidArgument(_))));
+    assert!(matches!(db.list_keys_paged(None, 0), Err(LohError::InvalidArgument(_))));
+    drop(db);
+
+    let mut read_only = Database::open(DatabaseConfig { read_only: true, ..config }).unwrap();
+    assert!(matches!(read_only.set("k".to_string(), vec![]), Err(LohError::ReadOnly)));
+
+    let missing = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().join("missing").to_string_lossy().to_string())
+        .read_only(true)
+        .build()
+        .unwrap();
+    assert!(matches!(Database::open(missing), Err(LohError::NotFound(_))));
+
+    assert!(matches!(
+        DatabaseConfig::builder().data_dir("").build(),
+        Err(LohError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_damaged_wal_reports_wal_corrupt() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .wal_segment_bytes(1)
+        .build()
+        .unwrap();
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for key in ["a", "b", "c"] {
+            db.set(key.to_string(), b"v".to_vec()).unwrap();
+        }
+    }
+    std::fs::remove_file(temp_dir.path().join("wal.000002.log")).unwrap();
+
+    assert!(matches!(Database::open(config), Err(LohError::WalCorrupt(_))));
+}
+
+#[test]
+fn test_io_errors_convert() {
+    let err: LohError = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied").into();
+    assert!(matches!(&err, LohError::Io(e) if e.kind() == std::io::ErrorKind::PermissionDenied));
+    assert!(err.to_string().contains("denied"));
+}
+
+#[test]
+fn test_get_required_names_the_missing_key() {
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("present".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(db.get_required("present").unwrap(), b"value");
+
+    let err = db.get_required("absent").unwrap_err();
+    assert!(matches!(err, LohError::NotFound(_)));
+    assert_eq!(err.to_string(), "key 'absent' not found");
+
+    let ns = db.namespace("users").unwrap();
+    assert!(matches!(ns.get_required("present"), Err(LohError::NotFound(_))));
+    ns.set("present".to_string(), b"other".to_vec()).unwrap();
+    assert_eq!(ns.get_required("present").unwrap(), b"other");
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use tempfile::TempDir;
+
+#[test]
+fn test_json_export_import_round_trip() {
+    let source_dir = TempDir::new().unwrap();
+    let mut source = common::open(&source_dir);
+    source.set("text".to_string(), b"hello".to_vec()).unwrap();
+    source.set("binary".to_string(), vec![0, 159, 146, 150, 255]).unwrap();
+    source.namespace("ns").unwrap().set("inner".to_string(), b"scoped".to_vec()).unwrap();
+
+    let mut exported = Vec::new();
+    source.export_json(&mut exported).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+    assert_eq!(json["text"], "aGVsbG8=");
+
+    let target_dir = TempDir::new().unwrap();
+    let mut target = common::open(&target_dir);
+    target.set("text".to_string(), b"stale".to_vec()).unwrap();
+
+    // Importing twice is idempotent and overwrites existing keys
+    target.import_json(exported.as_slice()).unwrap();
+    target.import_json(exported.as_slice()).unwrap();
+    assert_eq!(target.get("text").unwrap(), Some(b"hello".to_vec()));
+    assert_eq!(target.get("binary").unwrap(), Some(vec![0, 159, 146, 150, 255]));
+    assert_eq!(target.namespace("ns").unwrap().get("inner").unwrap(), Some(b"scoped".to_vec()));
+    assert_eq!(target.list_keys().unwrap().len(), 2);
+}
+
+#[test]
+fn test_json_import_rejects_bad_base64_without_partial_writes() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+
+    let err = db.import_json(&br#"{"a": "aGVsbG8=", "b": "not base64!"}"#[..]).unwrap_err();
+    assert!(err.to_string().contains("'b'"));
+    assert_eq!(db.get("a").unwrap(), None);
+}
+
+#[test]
+fn test_csv_export_import_round_trip() {
+    let tricky_keys = ["plain", "with,comma", "with \"quotes\"", "multi\nline", "\"leading quote"];
+
+    let source_dir = TempDir::new().unwrap();
+    let mut source = common::open(&source_dir);
+    for (i, key) in tricky_keys.iter().enumerate() {
+        source.set(key.to_string(), vec![i as u8, 0, 255]).unwrap();
+    }
+    source.namespace("ns").unwrap().set("inner,key".to_string(), b"scoped".to_vec()).unwrap();
+
+    let mut exported = Vec::new();
+    source.export_csv(&mut exported).unwrap();
+    let csv = String::from_utf8(exported.clone()).unwrap();
+    assert!(csv.starts_with("key,value_base64\r\n"));
+    assert!(csv.contains("\r\nplain,AAD/\r\n"));
+    assert!(csv.contains("\"with \"\"quotes\"\"\""));
+
+    let target_dir = TempDir::new().unwrap();
+    let mut target = common::open(&target_dir);
+    target.set("plain".to_string(), b"stale".to_vec()).unwrap();
+    target.import_csv(exported.as_slice()).unwrap();
+
+    for (i, key) in tricky_keys.iter().enumerate() {
+        assert_eq!(target.get(key).unwrap(), Some(vec![i as u8, 0, 255]), "key {:?}", key);
+    }
+    assert_eq!(target.list_keys().unwrap().len(), tricky_keys.len());
+    assert_eq!(target.namespace("ns").unwrap().get("inner,key").unwrap(), Some(b"scoped".to_vec()));
+
+    // Hand-written files may use bare LF line endings and blank lines
+    target.import_csv(&b"key,value_base64\n\nlf,aGk=\n"[..]).unwrap();
+    assert_eq!(target.get("lf").unwrap(), Some(b"hi".to_vec()));
+}
+
+#[test]
+fn test_csv_import_reports_malformed_rows() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+
+    let err = db.import_csv(&b"name,value\n"[..]).unwrap_err();
+    assert!(err.to_string().contains("header"));
+
+    let err = db.import_csv(&b"key,value_base64\na,aGk=\nb,not base64!\n"[..]).unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+    assert_eq!(db.get("a").unwrap(), Some(b"hi".to_vec()));
+
+    let err = db.import_csv(&b"key,value_base64\n\"open,aGk=\n"[..]).unwrap_err();
+    assert!(err.to_string().contains("unterminated"));
+
+    let err = db.import_csv(&b"key,value_base64\nx,aGk=,extra\n"[..]).unwrap_err();
+    assert!(err.to_string().contains("expected 2 fields"));
+}
@@ -0,0 +1,92 @@
+#![cfg(unix)]
+
+mod common;
+
+use lohdb::{Database, DatabaseConfig, LohError};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn mode(path: &Path) -> u32 {
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+/// Modes of the data directory and everything in it
+fn modes(dir: &Path) -> Vec<(String, u32)> {
+    let mut modes = vec![(".".to_string(), mode(dir))];
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        modes.push((path.file_name().unwrap().to_string_lossy().to_string(), mode(&path)));
+    }
+    modes.sort();
+    modes
+}
+
+#[test]
+fn test_created_files_use_file_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("nested").join("db");
+    let config = common::builder(&data_dir)
+        .wal_segment_bytes(1)
+        .file_mode(0o600)
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config).unwrap();
+    db.set("secret".to_string(), b"hunter2".to_vec()).unwrap();
+    db.set("rolled".to_string(), b"over".to_vec()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    let modes = modes(&data_dir);
+    let names: Vec<&str> = modes.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec![".", "LOCK", "MANIFEST", "data.db", "wal.000001.log", "wal.000002.log"]);
+    for (name, mode) in &modes {
+        let expected = if name == "." { 0o700 } else { 0o600 };
+        assert_eq!(*mode, expected, "{} has mode {:o}", name, mode);
+    }
+    assert_eq!(mode(&temp_dir.path().join("nested")), 0o700);
+}
+
+#[test]
+fn test_existing_directory_keeps_its_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::set_permissions(temp_dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let config = common::builder(&temp_dir)
+        .file_mode(0o600)
+        .build()
+        .unwrap();
+
+    drop(Database::open(config).unwrap());
+    assert_eq!(mode(temp_dir.path()), 0o755);
+    assert_eq!(mode(&temp_dir.path().join("wal.000001.log")), 0o600);
+}
+
+#[test]
+fn test_file_mode_is_validated() {
+    for bad in [0o400, 0o200, 0o10600] {
+        let result = DatabaseConfig::builder().file_mode(bad).build();
+        assert!(matches!(result, Err(LohError::InvalidArgument(_))), "{:o} accepted", bad);
+    }
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_encrypted_databases_default_to_owner_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("db");
+    let config = common::builder(&data_dir)
+        .encryption_key([7; 32])
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config).unwrap();
+    db.set("secret".to_string(), b"hunter2".to_vec()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    for (name, mode) in modes(&data_dir) {
+        let expected = if name == "." { 0o700 } else { 0o600 };
+        assert_eq!(mode, expected, "{} has mode {:o}", name, mode);
+    }
+}
@@ -0,0 +1,269 @@
+mod common;
+
+use lohdb::db::InMemoryStorageEngine;
+use lohdb::{Database, DatabaseConfig, FlushScheduler, LifecycleEvent, Result, StorageEngine};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const DATABASES: usize = 12;
+
+fn config(temp_dir: &TempDir, interval_ms: u64, scheduler: &FlushScheduler) -> DatabaseConfig {
+    DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(interval_ms)
+        .flush_scheduler(scheduler.clone())
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_one_scheduler_flushes_many_databases() {
+    let scheduler = FlushScheduler::new();
+    let dirs: Vec<TempDir> = (0..DATABASES).map(|_| TempDir::new().unwrap()).collect();
+
+    // Different intervals, all served by the same thread
+    let mut dbs: Vec<Database> = dirs
+        .iter()
+        .enumerate()
+        .map(|(i, dir)| Database::open(config(dir, 10 + i as u64 * 5, &scheduler)).unwrap())
+        .collect();
+    assert_eq!(scheduler.registered(), DATABASES);
+
+    let mut receivers = Vec::new();
+    for (i, db) in dbs.iter_mut().enumerate() {
+        let (handle, events) = db.subscribe_lifecycle();
+        db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+        receivers.push((handle, events));
+    }
+    for (_, events) in &receivers {
+        match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            LifecycleEvent::Flushed { wal_entries, .. } => assert_eq!(wal_entries, 1),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    // Dropping and closing both deregister
+    let last = dbs.pop().unwrap();
+    last.close().unwrap();
+    assert_eq!(scheduler.registered(), DATABASES - 1);
+    drop(dbs);
+    assert_eq!(scheduler.registered(), 0);
+
+    // The data file covers each write, so nothing is left to replay
+    for (i, dir) in dirs.iter().enumerate() {
+        let db = Database::open(config(dir, 0, &scheduler)).unwrap();
+        assert_eq!(db.get(&format!("key{}", i)).unwrap(), Some(b"value".to_vec()));
+    }
+    assert_eq!(scheduler.registered(), 0);
+}
+
+#[test]
+fn test_close_does_not_race_a_running_checkpoint() {
+    let scheduler = FlushScheduler::new();
+    let temp_dir = TempDir::new().unwrap();
+
+    // A 1ms interval keeps a checkpoint in flight most of the time
+    for round in 0..50 {
+        let mut db = Database::open(config(&temp_dir, 1, &scheduler)).unwrap();
+        for i in 0..20 {
+            db.set(format!("round{}-{}", round, i), vec![b'v'; 64]).unwrap();
+        }
+        if round % 2 == 0 {
+            db.close().unwrap();
+        } else {
+            drop(db);
+        }
+        // No checkpoint of the old instance may run once it is gone
+        assert_eq!(scheduler.registered(), 0);
+    }
+
+    let db = Database::open(config(&temp_dir, 1, &scheduler)).unwrap();
+    assert_eq!(db.key_count().unwrap(), 50 * 20);
+}
+
+#[test]
+fn test_databases_use_the_global_scheduler_by_default() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(10)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    assert!(FlushScheduler::global().registered() >= 1);
+
+    let (_handle, events) = db.subscribe_lifecycle();
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    assert!(matches!(
+        events.recv_timeout(Duration::from_secs(5)).unwrap(),
+        LifecycleEvent::Flushed { wal_entries: 1, .. }
+    ));
+}
+
+#[test]
+fn test_wal_size_triggers_a_checkpoint_without_an_interval() {
+    let scheduler = FlushScheduler::new();
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .checkpoint_wal_bytes(4096)
+        .flush_scheduler(scheduler.clone())
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    assert_eq!(scheduler.registered(), 1);
+    let (_handle, events) = db.subscribe_lifecycle();
+
+    // Well under the threshold: no checkpoint comes
+    db.set("small".to_string(), vec![b'v'; 16]).unwrap();
+    assert!(events.recv_timeout(Duration::from_millis(100)).is_err());
+    assert_eq!(db.stats().unwrap().wal_entries, 1);
+
+    // Crossing it checkpoints the WAL without waiting for an interval
+    for i in 0..8 {
+        db.set(format!("big{}", i), vec![b'v'; 1024]).unwrap();
+    }
+    match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+        LifecycleEvent::Flushed { wal_entries, .. } => assert!(wal_entries >= 5, "{} entries", wal_entries),
+        other => panic!("unexpected event {:?}", other),
+    }
+    // Writes that landed after the checkpoint started are all that is left
+    assert!(db.stats().unwrap().wal_entries < 9);
+}
+
+#[test]
+fn test_checkpoint_wal_bytes_must_be_positive() {
+    let result = DatabaseConfig::builder().checkpoint_wal_bytes(0).build();
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_churn_triggers_a_ratio_checkpoint() {
+    let scheduler = FlushScheduler::new();
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .checkpoint_ratio(4.0)
+        .flush_scheduler(scheduler.clone())
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    assert_eq!(scheduler.registered(), 1);
+    let (_handle, events) = db.subscribe_lifecycle();
+
+    // One record per key stays under the ratio
+    for i in 0..10 {
+        db.set(format!("key{}", i), b"0".to_vec()).unwrap();
+    }
+    assert!(events.recv_timeout(Duration::from_millis(500)).is_err());
+    assert_eq!(db.stats().unwrap().wal_entries, 10);
+
+    // Overwriting the same keys grows the WAL but not the key count
+    for round in 1..10 {
+        for i in 0..10 {
+            db.set(format!("key{}", i), round.to_string().into_bytes()).unwrap();
+        }
+    }
+    match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+        LifecycleEvent::Flushed { wal_entries, .. } => assert!(wal_entries > 40, "{} entries", wal_entries),
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert_eq!(db.key_count().unwrap(), 10);
+    assert_eq!(db.get("key3").unwrap(), Some(b"9".to_vec()));
+}
+
+#[test]
+fn test_checkpoint_ratio_must_be_positive() {
+    for ratio in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        let result = DatabaseConfig::builder().checkpoint_ratio(ratio).build();
+        assert!(result.is_err(), "ratio {} accepted", ratio);
+    }
+}
+
+/// An in-memory engine whose `flush` panics while armed
+struct PanickingEngine {
+    inner: InMemoryStorageEngine,
+    armed: Arc<AtomicBool>,
+}
+
+impl StorageEngine for PanickingEngine {
+    fn initialize(&mut self) -> Result<()> {
+        self.inner.initialize()
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.inner.store(key, value)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.retrieve(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        self.inner.remove(key)
+    }
+
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.inner.list_keys()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.armed.load(Ordering::SeqCst) {
+            panic!("injected flush panic");
+        }
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn test_panicking_checkpoint_is_detected() {
+    let scheduler = FlushScheduler::new();
+    let broken_dir = TempDir::new().unwrap();
+    let healthy_dir = TempDir::new().unwrap();
+    let mut broken = Database::open(config(&broken_dir, 20, &scheduler)).unwrap();
+    let mut healthy = Database::open(config(&healthy_dir, 20, &scheduler)).unwrap();
+    assert!(broken.is_sync_healthy());
+    assert!(broken.sync_health().scheduled);
+
+    let armed = Arc::new(AtomicBool::new(false));
+    broken
+        .migrate_to(PanickingEngine { inner: InMemoryStorageEngine::new(), armed: armed.clone() })
+        .unwrap();
+    let (_handle, lifecycle) = broken.subscribe_lifecycle();
+    armed.store(true, Ordering::SeqCst);
+    broken.set("key".to_string(), b"value".to_vec()).unwrap();
+
+    let error = loop {
+        match lifecycle.recv_timeout(Duration::from_secs(5)).expect("flush failure event") {
+            LifecycleEvent::FlushFailed { error } => break error,
+            _ => continue,
+        }
+    };
+    assert!(error.contains("injected flush panic"), "{}", error);
+    let health = broken.sync_health();
+    assert!(!health.is_healthy());
+    assert!(health.panics >= 1);
+    assert!(health.thread_alive);
+    assert_eq!(health.last_error.as_deref(), broken.stats().unwrap().last_flush_error.as_deref());
+
+    // The same thread still checkpoints the other database
+    let (_healthy_handle, healthy_lifecycle) = healthy.subscribe_lifecycle();
+    healthy.set("key".to_string(), b"value".to_vec()).unwrap();
+    loop {
+        if let LifecycleEvent::Flushed { .. } = healthy_lifecycle.recv_timeout(Duration::from_secs(5)).expect("flush event") {
+            break;
+        }
+    }
+    assert!(healthy.is_sync_healthy());
+
+    // No lock was poisoned, and the next good checkpoint clears the error
+    armed.store(false, Ordering::SeqCst);
+    broken.set("other".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(broken.get("key").unwrap(), Some(b"value".to_vec()));
+    loop {
+        if let LifecycleEvent::Flushed { .. } = lifecycle.recv_timeout(Duration::from_secs(5)).expect("flush event") {
+            break;
+        }
+    }
+    assert!(broken.is_sync_healthy());
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use lohdb::db::WriteAheadLog;
+use lohdb::{Database, DatabaseConfig, LohError, SerializationFormat};
+use tempfile::TempDir;
+
+fn config(temp_dir: &TempDir, format: SerializationFormat) -> DatabaseConfig {
+    common::builder(temp_dir)
+        .serialization_format(format)
+        .build()
+        .unwrap()
+}
+
+fn round_trip(format: SerializationFormat) {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut db = Database::open(config(&temp_dir, format)).unwrap();
+        db.set("flushed".to_string(), vec![0, 159, 255]).unwrap();
+        db.flush().unwrap();
+        db.set("logged".to_string(), b"only in the WAL".to_vec()).unwrap();
+        db.delete("missing").unwrap();
+    }
+
+    let db = Database::open(config(&temp_dir, format)).unwrap();
+    assert_eq!(db.get("flushed").unwrap(), Some(vec![0, 159, 255]));
+    assert_eq!(db.get("logged").unwrap(), Some(b"only in the WAL".to_vec()));
+}
+
+#[test]
+fn test_json_round_trip_across_reopen() {
+    round_trip(SerializationFormat::Json);
+
+    // The WAL records are plain JSON
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::open(config(&temp_dir, SerializationFormat::Json)).unwrap();
+        db.set("visible".to_string(), b"x".to_vec()).unwrap();
+    }
+    let segment = WriteAheadLog::open_read_only(temp_dir.path()).unwrap().segment_paths().pop().unwrap();
+    let bytes = std::fs::read(segment).unwrap();
+    assert!(bytes.windows(9).any(|w| w == b"\"visible\""));
+}
+
+#[test]
+fn test_reopen_with_other_format_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::open(config(&temp_dir, SerializationFormat::Json)).unwrap();
+        db.set("key".to_string(), b"value".to_vec()).unwrap();
+        db.flush().unwrap();
+    }
+
+    let err = Database::open(config(&temp_dir, SerializationFormat::Bincode)).err().unwrap();
+    assert!(matches!(err, LohError::Format(_)), "{:?}", err);
+    assert!(err.to_string().contains("written as json"), "{}", err);
+
+    // The files are untouched and still open with the right format
+    let db = Database::open(config(&temp_dir, SerializationFormat::Json)).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+}
+
+#[test]
+fn test_bincode_files_predating_formats_still_open() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+
+    // Version 1 segment: no format byte, bincode records
+    let mut segment = b"LOHWAL".to_vec();
+    segment.push(1);
+    segment.extend_from_slice(&1u64.to_le_bytes());
+    let payload = bincode::serialize(&lohdb::db::Operation::Set {
+        namespace: None,
+        key: "old".to_string(),
+        value: b"v1".to_vec(),
+    })
+    .unwrap();
+    segment.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    segment.extend_from_slice(&1u64.to_le_bytes());
+    segment.extend_from_slice(&0u64.to_le_bytes());
+    segment.extend_from_slice(&payload);
+    std::fs::write(dir.join("wal.000001.log"), segment).unwrap();
+
+    let mut db = Database::open(config(&temp_dir, SerializationFormat::Bincode)).unwrap();
+    assert_eq!(db.get("old").unwrap(), Some(b"v1".to_vec()));
+    db.set("new".to_string(), b"v2".to_vec()).unwrap();
+    drop(db);
+
+    let db = Database::open(config(&temp_dir, SerializationFormat::Bincode)).unwrap();
+    assert_eq!(db.get("new").unwrap(), Some(b"v2".to_vec()));
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn test_messagepack_round_trip_across_reopen() {
+    round_trip(SerializationFormat::MessagePack);
+}
+
+#[cfg(not(feature = "msgpack"))]
+#[test]
+fn test_messagepack_requires_feature() {
+    let err = DatabaseConfig::builder()
+        .serialization_format(SerializationFormat::MessagePack)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("msgpack"));
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use lohdb::db::ChangeEvent;
+use lohdb::Database;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use tempfile::TempDir;
+
+#[test]
+fn test_inserts_once_then_returns_existing() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        let (_handle, events) = db.subscribe_channel().unwrap();
+
+        let value = db.get_or_insert_with("key", || b"computed".to_vec()).unwrap();
+        assert_eq!(value, b"computed");
+        let value = db.get_or_insert_with("key", || panic!("key is present")).unwrap();
+        assert_eq!(value, b"computed");
+
+        // Only the insert was logged and published
+        assert_eq!(db.stats().unwrap().wal_sequence, 1);
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(&received[0], ChangeEvent::Set { key, value, .. } if key == "key" && value == b"computed"));
+    }
+
+    // The inserted value was logged and survives a restart
+    let mut db = Database::open(config).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"computed".to_vec()));
+    assert_eq!(db.get_or_insert_with("key", || b"other".to_vec()).unwrap(), b"computed");
+}
+
+#[test]
+fn test_concurrent_callers_compute_once() {
+    const THREADS: usize = 8;
+    let temp_dir = TempDir::new().unwrap();
+    let db = Arc::new(common::open(&temp_dir));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let barrier = Arc::new(Barrier::new(THREADS));
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let (db, calls, barrier) = (db.clone(), calls.clone(), barrier.clone());
+            thread::spawn(move || {
+                let ns = db.namespace("cache").unwrap();
+                barrier.wait();
+                ns.get_or_insert_with("shared", || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(std::time::Duration::from_millis(10));
+                    format!("thread{}", i).into_bytes()
+                })
+                .unwrap()
+            })
+        })
+        .collect();
+    let values: Vec<Vec<u8>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(values.windows(2).all(|w| w[0] == w[1]));
+    assert_eq!(db.stats().unwrap().wal_sequence, 1);
+}
+
+#[test]
+fn test_oversized_value_is_rejected_and_not_stored() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .max_value_bytes(4)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+
+    assert!(db.get_or_insert_with("key", || b"too long".to_vec()).is_err());
+    assert_eq!(db.get("key").unwrap(), None);
+    assert_eq!(db.get_or_insert_with("key", || b"ok".to_vec()).unwrap(), b"ok");
+}
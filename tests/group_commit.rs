@@ -0,0 +1,77 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use tempfile::TempDir;
+
+const WRITERS: usize = 8;
+const WRITES_PER_WRITER: usize = 50;
+
+fn group_commit_config(temp_dir: &TempDir) -> DatabaseConfig {
+    common::builder(temp_dir)
+        .group_commit(true)
+        .group_commit_delay_us(2000)
+        .build()
+        .unwrap()
+}
+
+/// Run `writers` threads doing `writes` sets each, returning writes per second
+fn run_writers(db: &Arc<Database>, writers: usize, writes: usize) -> f64 {
+    let started = Instant::now();
+    let handles: Vec<_> = (0..writers)
+        .map(|w| {
+            let db = db.clone();
+            thread::spawn(move || {
+                let ns = db.namespace(&format!("writer{}", w)).unwrap();
+                for i in 0..writes {
+                    ns.set(format!("key{}", i), vec![b'v'; 32]).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (writers * writes) as f64 / started.elapsed().as_secs_f64()
+}
+
+#[test]
+fn test_group_commit_batches_concurrent_writers() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = group_commit_config(&temp_dir);
+
+    {
+        let db = Arc::new(Database::open(config.clone()).unwrap());
+        run_writers(&db, WRITERS, WRITES_PER_WRITER);
+
+        // Concurrent writers shared fsyncs instead of paying one each
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.wal_sequence, (WRITERS * WRITES_PER_WRITER) as u64);
+        assert!(stats.wal_syncs > 0);
+        assert!(stats.wal_syncs < stats.wal_sequence / 2, "{} syncs", stats.wal_syncs);
+    }
+
+    // Every acknowledged write is in the log
+    let db = Database::open(config).unwrap();
+    for w in 0..WRITERS {
+        let ns = db.namespace(&format!("writer{}", w)).unwrap();
+        assert_eq!(ns.list_keys().unwrap().len(), WRITES_PER_WRITER);
+    }
+}
+
+#[test]
+fn test_group_commit_throughput_single_vs_batched() {
+    // One writer pays a full delay + fsync per write; many writers share them
+    let single_dir = TempDir::new().unwrap();
+    let single_db = Arc::new(Database::open(group_commit_config(&single_dir)).unwrap());
+    let single = run_writers(&single_db, 1, 20);
+
+    let batched_dir = TempDir::new().unwrap();
+    let batched_db = Arc::new(Database::open(group_commit_config(&batched_dir)).unwrap());
+    let batched = run_writers(&batched_db, WRITERS, 20);
+
+    println!("group commit: single writer {:.0} ops/s, {} writers {:.0} ops/s", single, WRITERS, batched);
+    assert!(batched > single);
+}
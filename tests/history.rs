@@ -0,0 +1,107 @@
+mod common;
+
+use lohdb::db::EventBus;
+use lohdb::{ChangeEvent, Database, OverflowPolicy};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn set_event(namespace: Option<&str>, key: &str, value: &[u8]) -> ChangeEvent {
+    ChangeEvent::Set {
+        namespace: namespace.map(str::to_string),
+        key: key.to_string(),
+        value: value.to_vec(),
+        version: 0,
+    }
+}
+
+/// Events compared through their JSON form, since `ChangeEvent` has no `PartialEq`
+fn json(events: impl IntoIterator<Item = ChangeEvent>) -> Vec<serde_json::Value> {
+    events.into_iter().map(|event| serde_json::to_value(event).unwrap()).collect()
+}
+
+#[test]
+fn test_change_history_maps_every_operation() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.set_with_ttl("b".to_string(), b"2".to_vec(), Duration::from_secs(60)).unwrap();
+    db.namespace("ns").unwrap().set("a".to_string(), b"3".to_vec()).unwrap();
+    db.append("log", b"chunk").unwrap();
+    db.delete("a").unwrap();
+    db.set("p:1".to_string(), vec![]).unwrap();
+    db.set("p:2".to_string(), vec![]).unwrap();
+    db.delete_prefix("p:").unwrap();
+
+    let history = db.change_history().unwrap();
+    let seqs: Vec<u64> = history.iter().map(|change| change.seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3, 4, 5, 6, 7, 8, 8]);
+    assert!(history.iter().all(|change| change.timestamp_ms > 0));
+
+    let expected = vec![
+        set_event(None, "a", b"1"),
+        set_event(None, "b", b"2"),
+        set_event(Some("ns"), "a", b"3"),
+        ChangeEvent::Append { namespace: None, key: "log".to_string(), chunk: b"chunk".to_vec(), version: 0 },
+        ChangeEvent::Delete { namespace: None, key: "a".to_string() },
+        set_event(None, "p:1", b""),
+        set_event(None, "p:2", b""),
+        ChangeEvent::Delete { namespace: None, key: "p:1".to_string() },
+        ChangeEvent::Delete { namespace: None, key: "p:2".to_string() },
+    ];
+    assert_eq!(json(history.into_iter().map(|change| change.event)), json(expected));
+
+    // Survives a restart, since the WAL does
+    drop(db);
+    let db = common::open(&temp_dir);
+    assert_eq!(db.change_history().unwrap().len(), 9);
+}
+
+#[test]
+fn test_checkpoint_limits_history() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    db.set("before".to_string(), b"1".to_vec()).unwrap();
+    db.checkpoint().unwrap();
+    db.set("after".to_string(), b"2".to_vec()).unwrap();
+
+    let history = db.change_history().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].seq, 2);
+    assert_eq!(history[0].event.key(), "after");
+
+    assert!(Database::open_in_memory().unwrap().change_history().unwrap().is_empty());
+}
+
+#[test]
+fn test_history_then_live_events_without_gap() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("user:1".to_string(), b"a".to_vec()).unwrap();
+    db.set("other".to_string(), b"b".to_vec()).unwrap();
+
+    let (history, _handle, events) = db
+        .subscribe_channel_with_history("user:".to_string(), OverflowPolicy::Block)
+        .unwrap();
+    assert_eq!(json(history.into_iter().map(|change| change.event)), json([set_event(None, "user:1", b"a")]));
+
+    db.set("user:2".to_string(), b"c".to_vec()).unwrap();
+    db.set("other".to_string(), b"d".to_vec()).unwrap();
+    let live: Vec<String> = events.try_iter().map(|event| event.key().to_string()).collect();
+    assert_eq!(live, vec!["user:2"]);
+}
+
+#[test]
+fn test_subscription_skips_changes_already_in_history() {
+    let mut bus = EventBus::new();
+    let (_handle, events) = bus.subscribe_channel_after(String::new(), OverflowPolicy::Block, 5).unwrap();
+
+    // A write logged as record 5 but published after the subscription started
+    bus.publish_logged(5, set_event(None, "in_history", b"")).unwrap();
+    bus.publish_logged(6, set_event(None, "new", b"")).unwrap();
+    bus.publish(ChangeEvent::Evicted { namespace: None, key: "unlogged".to_string() }).unwrap();
+
+    let keys: Vec<String> = events.try_iter().map(|event| event.key().to_string()).collect();
+    assert_eq!(keys, vec!["new", "unlogged"]);
+}
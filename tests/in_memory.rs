@@ -0,0 +1,99 @@
+use lohdb::{ChangeEvent, Database, DatabaseConfig, StorageEngine};
+use lohdb::db::InMemoryStorageEngine;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_in_memory_database_creates_no_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("db");
+
+    let config = DatabaseConfig::builder()
+        .data_dir(data_dir.to_string_lossy().to_string())
+        .wal_sync_interval_ms(10)
+        .group_commit(true)
+        .in_memory(true)
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config.clone()).unwrap();
+    assert!(db.is_in_memory());
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("b".to_string(), b"2".to_vec()).unwrap();
+    assert!(db.delete("a").unwrap());
+    db.flush().unwrap();
+    db.checkpoint().unwrap();
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.num_keys, 1);
+    assert_eq!((stats.data_file_bytes, stats.wal_file_bytes), (0, 0));
+    assert_eq!(stats.wal_sequence, 3);
+
+    // No directory lock either, so a second in-memory database can share the config
+    let other = Database::open(config).unwrap();
+    assert!(other.list_keys().unwrap().is_empty());
+    assert!(db.backup_to(&temp_dir.path().join("backup").to_string_lossy()).is_err());
+
+    drop(db);
+    drop(other);
+    assert!(std::fs::read_dir(temp_dir.path()).unwrap().next().is_none());
+}
+
+#[test]
+fn test_open_in_memory_and_invalid_combinations() {
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+
+    assert!(DatabaseConfig::builder().in_memory(true).read_only(true).build().is_err());
+}
+
+#[test]
+fn test_lru_evicts_least_recently_used() {
+    let mut engine = InMemoryStorageEngine::new().with_max_entries(3);
+    engine.store("a", b"1").unwrap();
+    engine.store("b", b"2").unwrap();
+    engine.store("c", b"3").unwrap();
+
+    // Reading `a` makes `b` the oldest untouched key
+    assert_eq!(engine.retrieve("a").unwrap(), Some(b"1".to_vec()));
+    engine.store("d", b"4").unwrap();
+    assert_eq!(engine.retrieve("b").unwrap(), None);
+    assert_eq!(engine.list_keys().unwrap(), vec!["a", "c", "d"]);
+    assert_eq!(engine.take_evicted(), vec!["b".to_string()]);
+    assert!(engine.take_evicted().is_empty());
+
+    // Byte limits count keys plus values
+    let mut engine = InMemoryStorageEngine::new().with_max_bytes(10);
+    engine.store("a", b"1234").unwrap();
+    engine.store("b", b"1234").unwrap();
+    engine.store("c", b"1234").unwrap();
+    assert_eq!(engine.list_keys().unwrap(), vec!["b", "c"]);
+}
+
+#[test]
+fn test_cache_mode_publishes_evictions() {
+    let config = DatabaseConfig::builder().in_memory(true).cache_max_entries(2).build().unwrap();
+    let mut db = Database::open(config).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("b".to_string(), b"2".to_vec()).unwrap();
+    db.set("c".to_string(), b"3".to_vec()).unwrap();
+    assert_eq!(db.get("a").unwrap(), None);
+    assert_eq!(db.list_keys().unwrap(), vec!["c".to_string()]);
+
+    let timeout = Duration::from_secs(1);
+    let received: Vec<ChangeEvent> = (0..4).map(|_| events.recv_timeout(timeout).unwrap()).collect();
+    assert!(matches!(
+        &received[3],
+        ChangeEvent::Evicted { namespace: None, key } if key == "a"
+    ));
+
+    // Evictions are not deletes
+    let stats = db.stats().unwrap();
+    assert_eq!((stats.deletes, stats.wal_sequence), (0, 3));
+
+    let err = DatabaseConfig::builder().cache_max_bytes(1024).build().err().expect("cache limits need in_memory");
+    assert!(err.to_string().contains("in_memory"));
+}
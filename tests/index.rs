@@ -0,0 +1,62 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig, LohError};
+use tempfile::TempDir;
+
+/// Index users by the `status` field of their JSON value
+fn status(_key: &str, value: &[u8]) -> Option<Vec<u8>> {
+    let user: serde_json::Value = serde_json::from_slice(value).ok()?;
+    Some(user.get("status")?.as_str()?.as_bytes().to_vec())
+}
+
+fn user(status: &str) -> Vec<u8> {
+    format!(r#"{{"status":"{}"}}"#, status).into_bytes()
+}
+
+#[test]
+fn test_index_follows_sets_and_deletes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    // Existing entries are indexed when the index is created
+    db.set("user:1".to_string(), user("active")).unwrap();
+    db.set("user:2".to_string(), user("banned")).unwrap();
+    db.set("note".to_string(), b"not json".to_vec()).unwrap();
+    db.create_index("status", status).unwrap();
+    assert_eq!(db.index_lookup("status", b"active").unwrap(), vec!["user:1"]);
+
+    // New and changed entries move between index keys
+    db.set("user:3".to_string(), user("active")).unwrap();
+    db.set("user:2".to_string(), user("active")).unwrap();
+    assert_eq!(db.index_lookup("status", b"active").unwrap(), vec!["user:1", "user:2", "user:3"]);
+    assert!(db.index_lookup("status", b"banned").unwrap().is_empty());
+
+    // An entry the extractor rejects drops out, as does a deleted one
+    db.set("user:1".to_string(), b"corrupt".to_vec()).unwrap();
+    db.delete("user:3").unwrap();
+    assert_eq!(db.index_lookup("status", b"active").unwrap(), vec!["user:2"]);
+
+    // Only the default namespace is indexed
+    db.namespace("archive").unwrap().set("user:9".to_string(), user("active")).unwrap();
+    assert_eq!(db.index_lookup("status", b"active").unwrap(), vec!["user:2"]);
+
+    let err = db.create_index("status", status).unwrap_err();
+    assert!(matches!(err, LohError::InvalidArgument(_)));
+    assert!(db.drop_index("status"));
+    assert!(matches!(db.index_lookup("status", b"active").unwrap_err(), LohError::NotFound(_)));
+}
+
+#[test]
+fn test_index_forgets_evicted_keys() {
+    let config = DatabaseConfig::builder().in_memory(true).cache_max_entries(2).build().unwrap();
+    let mut db = Database::open(config).unwrap();
+    db.create_index("status", status).unwrap();
+
+    for key in ["a", "b", "c"] {
+        db.set(key.to_string(), user("active")).unwrap();
+    }
+    assert_eq!(db.index_lookup("status", b"active").unwrap(), vec!["b", "c"]);
+
+    db.bulk_load([("d".to_string(), user("active"))].into_iter()).unwrap();
+    assert_eq!(db.index_lookup("status", b"active").unwrap(), vec!["c", "d"]);
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig, LohError};
+use tempfile::TempDir;
+
+fn config_at(dir: &std::path::Path) -> DatabaseConfig {
+    common::config(dir)
+}
+
+fn file_names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_init_lays_out_an_empty_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("fresh");
+    let config = config_at(&dir);
+
+    Database::init(&config).unwrap();
+    let names = file_names(&dir);
+    assert!(names.contains(&"MANIFEST".to_string()), "{:?}", names);
+    assert!(names.contains(&"data.db".to_string()), "{:?}", names);
+    assert!(names.iter().any(|name| name.starts_with("wal.")), "{:?}", names);
+
+    let report = Database::verify(&config).unwrap();
+    assert!(report.consistent);
+    assert!(report.data_file_present);
+    assert_eq!(report.data_file_keys, 0);
+
+    // Running it again changes nothing, and the result opens as an empty database
+    Database::init(&config).unwrap();
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.key_count().unwrap(), 0);
+}
+
+#[test]
+fn test_init_rebuilds_a_data_file_from_a_complete_wal() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = config_at(temp_dir.path());
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+        db.set("b".to_string(), b"2".to_vec()).unwrap();
+    }
+    // Never checkpointed, so the WAL still holds every write
+    std::fs::remove_file(temp_dir.path().join("data.db")).ok();
+    std::fs::remove_file(temp_dir.path().join("MANIFEST")).unwrap();
+
+    Database::init(&config).unwrap();
+    let report = Database::verify(&config).unwrap();
+    assert!(report.consistent);
+    assert_eq!(report.data_file_keys, 2);
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("b").unwrap(), Some(b"2".to_vec()));
+}
+
+#[test]
+fn test_missing_data_file_with_a_checkpointed_wal_is_refused() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = config_at(temp_dir.path());
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("checkpointed".to_string(), b"1".to_vec()).unwrap();
+        db.checkpoint().unwrap();
+        db.set("logged".to_string(), b"2".to_vec()).unwrap();
+    }
+    std::fs::remove_file(temp_dir.path().join("data.db")).unwrap();
+
+    // Starting from the WAL alone would silently lose "checkpointed"
+    for result in [Database::init(&config), Database::open(config.clone()).map(drop)] {
+        assert!(matches!(result, Err(LohError::DataCorrupt(msg)) if msg.contains("sequence 1")));
+    }
+    assert!(!temp_dir.path().join("data.db").exists());
+}
+
+#[test]
+fn test_init_leaves_a_complete_database_intact() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = config_at(temp_dir.path());
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("kept".to_string(), b"1".to_vec()).unwrap();
+        db.checkpoint().unwrap();
+        db.set("in_wal".to_string(), b"2".to_vec()).unwrap();
+    }
+
+    Database::init(&config).unwrap();
+    let db = Database::open(config.clone()).unwrap();
+    assert_eq!(db.list_keys().unwrap(), vec!["in_wal".to_string(), "kept".to_string()]);
+    drop(db);
+
+    // The directory lock keeps it from initializing an open database
+    let _db = Database::open(config.clone()).unwrap();
+    assert!(Database::init(&config).is_err());
+    let in_memory = DatabaseConfig::builder().in_memory(true).build().unwrap();
+    assert!(matches!(Database::init(&in_memory), Err(LohError::InvalidArgument(_))));
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use tempfile::TempDir;
+
+#[test]
+fn test_iter_and_for_each_visit_default_namespace() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    for i in 0..5 {
+        db.set(format!("key{}", i), vec![i]).unwrap();
+    }
+    db.namespace("other").unwrap().set("hidden".to_string(), b"x".to_vec()).unwrap();
+
+    let mut entries: Vec<(String, Vec<u8>)> = db.iter().unwrap().map(Result::unwrap).collect();
+    entries.sort();
+    assert_eq!(entries.len(), 5);
+    assert_eq!(entries[3], ("key3".to_string(), vec![3]));
+
+    let mut total = 0u32;
+    db.for_each(|_, value| total += value[0] as u32).unwrap();
+    assert_eq!(total, 10);
+}
+
+#[test]
+fn test_iter_skips_keys_deleted_after_creation() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.set("b".to_string(), b"2".to_vec()).unwrap();
+
+    let iter = db.iter().unwrap();
+    db.delete("a").unwrap();
+    db.set("b".to_string(), b"updated".to_vec()).unwrap();
+    db.set("c".to_string(), b"3".to_vec()).unwrap();
+
+    let entries: Vec<(String, Vec<u8>)> = iter.map(Result::unwrap).collect();
+    assert_eq!(entries, vec![("b".to_string(), b"updated".to_vec())]);
+}
+
+#[test]
+fn test_list_keys_paged_walks_sorted_pages() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    for key in ["e", "a", "d", "", "c", "b"] {
+        db.set(key.to_string(), b"v".to_vec()).unwrap();
+    }
+    // Namespaced keys sort between "" and "a" internally but must not appear
+    db.namespace("ns").unwrap().set("zzz".to_string(), b"v".to_vec()).unwrap();
+
+    let (page, next) = db.list_keys_paged(None, 2).unwrap();
+    assert_eq!(page, vec!["".to_string(), "a".to_string()]);
+    assert_eq!(next.as_deref(), Some("a"));
+
+    let (page, next) = db.list_keys_paged(next.as_deref(), 2).unwrap();
+    assert_eq!(page, vec!["b".to_string(), "c".to_string()]);
+
+    let (page, next) = db.list_keys_paged(next.as_deref(), 2).unwrap();
+    assert_eq!(page, vec!["d".to_string(), "e".to_string()]);
+    assert_eq!(next, None);
+
+    // A cursor at a deleted key resumes at the next greater key
+    db.delete("b").unwrap();
+    let (page, _) = db.list_keys_paged(Some("b"), 10).unwrap();
+    assert_eq!(page, vec!["c".to_string(), "d".to_string(), "e".to_string()]);
+    let (page, next) = db.list_keys_paged(Some("bb"), 1).unwrap();
+    assert_eq!((page, next.as_deref()), (vec!["c".to_string()], Some("c")));
+
+    assert!(db.list_keys_paged(None, 0).is_err());
+}
+
+fn keys_of(iter: impl Iterator<Item = lohdb::Result<(String, Vec<u8>)>>) -> Vec<String> {
+    iter.map(|entry| entry.unwrap().0).collect()
+}
+
+#[test]
+fn test_iter_range_ascending_and_descending_mirror() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    // More keys than one scan batch, zero-padded so they sort by time
+    for ts in 0..2500 {
+        db.set(format!("event:{:05}", ts), ts.to_string().into_bytes()).unwrap();
+    }
+    db.set("other".to_string(), b"x".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("event:99999".to_string(), b"hidden".to_vec()).unwrap();
+
+    let ascending = keys_of(db.iter_range(Some("event:00100"), Some("event:02200"), false).unwrap());
+    let mut descending = keys_of(db.iter_range(Some("event:00100"), Some("event:02200"), true).unwrap());
+    assert_eq!(ascending.len(), 2100);
+    assert_eq!(ascending.first().unwrap(), "event:00100");
+    assert_eq!(ascending.last().unwrap(), "event:02199");
+    descending.reverse();
+    assert_eq!(ascending, descending);
+
+    // Latest N first
+    let latest = keys_of(db.iter_range(Some("event:"), Some("event;"), true).unwrap().take(3));
+    assert_eq!(latest, vec!["event:02499", "event:02498", "event:02497"]);
+
+    // Open bounds cover the whole default namespace, never namespaced keys
+    let all = keys_of(db.iter_range(None, None, false).unwrap());
+    assert_eq!(all.len(), 2501);
+    assert_eq!(all.last().unwrap(), "other");
+    let all_reversed = keys_of(db.iter_range(None, None, true).unwrap());
+    assert_eq!(all_reversed.first().unwrap(), "other");
+    assert_eq!(all_reversed.last().unwrap(), "event:00000");
+    assert_eq!(keys_of(db.iter_range(None, Some("event:00002"), true).unwrap()), vec!["event:00001", "event:00000"]);
+
+    // An empty or inverted range yields nothing
+    assert!(keys_of(db.iter_range(Some("b"), Some("b"), false).unwrap()).is_empty());
+    assert!(keys_of(db.iter_range(Some("z"), Some("a"), true).unwrap()).is_empty());
+}
+
+#[test]
+fn test_db_iterator_is_double_ended() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    for key in ["a", "b", "c", "d"] {
+        db.set(key.to_string(), key.as_bytes().to_vec()).unwrap();
+    }
+
+    assert_eq!(keys_of(db.iter_range(None, None, false).unwrap().rev()), vec!["d", "c", "b", "a"]);
+
+    let mut iter = db.iter_range(Some("a"), None, false).unwrap();
+    db.delete("d").unwrap();
+    assert_eq!(iter.next().unwrap().unwrap().0, "a");
+    // "d" was deleted after creation and is skipped from the back too
+    assert_eq!(iter.next_back().unwrap().unwrap().0, "c");
+    assert_eq!(iter.next().unwrap().unwrap().0, "b");
+    assert!(iter.next_back().is_none());
+}
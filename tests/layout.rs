@@ -0,0 +1,146 @@
+use lohdb::db::Manifest;
+use lohdb::{Database, DatabaseConfig, DatabaseConfigBuilder, LohError};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// A database in `root/data`, with its WAL in `root/wal` and its data file at
+/// `root/bulk/snapshot.db`
+fn split_builder(root: &Path) -> DatabaseConfigBuilder {
+    DatabaseConfig::builder()
+        .data_dir(root.join("data").to_string_lossy().to_string())
+        .wal_dir(root.join("wal").to_string_lossy().to_string())
+        .data_file(root.join("bulk/snapshot.db").to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+}
+
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_wal_and_data_file_live_where_configured() {
+    let root = TempDir::new().unwrap();
+    let config = split_builder(root.path()).build().unwrap();
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("replayed".to_string(), b"1".to_vec()).unwrap();
+    } // Dropped without a checkpoint, so the write comes back from the WAL
+
+    assert!(file_names(&root.path().join("wal")).iter().any(|n| n.starts_with("wal.")));
+    assert_eq!(file_names(&root.path().join("data")), vec!["LOCK", "MANIFEST"]);
+
+    let mut db = Database::open(config.clone()).unwrap();
+    assert_eq!(db.get("replayed").unwrap(), Some(b"1".to_vec()));
+    db.set("flushed".to_string(), b"2".to_vec()).unwrap();
+    db.close().unwrap();
+
+    assert!(root.path().join("bulk/snapshot.db").exists());
+    assert!(!root.path().join("data/data.db").exists());
+    let db = Database::open(config.clone()).unwrap();
+    assert!(db.change_history().unwrap().is_empty());
+    assert_eq!(db.get("flushed").unwrap(), Some(b"2".to_vec()));
+    drop(db);
+
+    let report = Database::verify(&config).unwrap();
+    assert!(report.consistent);
+    assert!(report.data_file_present);
+    assert_eq!(report.data_file_keys, 2);
+}
+
+#[test]
+fn test_reopening_with_another_layout_fails() {
+    let root = TempDir::new().unwrap();
+    let mut db = Database::open(split_builder(root.path()).build().unwrap()).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    drop(db);
+
+    let manifest = Manifest::read(root.path().join("data")).unwrap().unwrap();
+    assert_eq!(manifest.wal_dir, Some(root.path().join("wal").to_string_lossy().to_string()));
+    assert!(manifest.data_file.is_some());
+
+    // Forgetting the layout would otherwise open an empty database
+    let default_layout = DatabaseConfig::builder()
+        .data_dir(root.path().join("data").to_string_lossy().to_string())
+        .build()
+        .unwrap();
+    let err = Database::open(default_layout).err().expect("open must fail");
+    assert!(matches!(&err, LohError::InvalidArgument(m) if m.contains("keeps its WAL")), "{}", err);
+
+    let moved_wal = split_builder(root.path())
+        .wal_dir(root.path().join("elsewhere").to_string_lossy().to_string())
+        .build()
+        .unwrap();
+    assert!(Database::open(moved_wal).is_err());
+    assert!(!root.path().join("elsewhere").exists());
+
+    let moved_data = split_builder(root.path())
+        .data_file(root.path().join("bulk/other.db").to_string_lossy().to_string())
+        .build()
+        .unwrap();
+    let err = Database::open(moved_data).err().expect("open must fail");
+    assert!(err.to_string().contains("keeps its data file"), "{}", err);
+
+    let db = Database::open(split_builder(root.path()).build().unwrap()).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+}
+
+#[test]
+fn test_spelling_out_the_default_layout_is_no_change() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().to_string_lossy().to_string();
+    let mut db = Database::open(DatabaseConfig::builder().data_dir(dir.clone()).build().unwrap()).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.close().unwrap();
+
+    let explicit = DatabaseConfig::builder()
+        .data_dir(dir.clone())
+        .wal_dir(dir.clone())
+        .data_file(temp_dir.path().join("data.db").to_string_lossy().to_string())
+        .build()
+        .unwrap();
+    let db = Database::open(explicit).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+}
+
+#[test]
+fn test_unusable_wal_dir_fails_at_open() {
+    let root = TempDir::new().unwrap();
+    std::fs::write(root.path().join("wal"), b"not a directory").unwrap();
+
+    let err = Database::open(split_builder(root.path()).build().unwrap()).err().expect("open must fail");
+    assert!(err.to_string().contains("cannot create WAL directory"), "{}", err);
+}
+
+#[test]
+fn test_backup_of_split_layout_opens_with_defaults() {
+    let root = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let backup_path = backup_dir.path().join("copy");
+
+    let mut db = Database::open(split_builder(root.path()).build().unwrap()).unwrap();
+    db.set("flushed".to_string(), b"1".to_vec()).unwrap();
+    db.checkpoint().unwrap();
+    db.set("logged".to_string(), b"2".to_vec()).unwrap();
+    db.backup_to(&backup_path.to_string_lossy()).unwrap();
+
+    let copy = Database::open(
+        DatabaseConfig::builder()
+            .data_dir(backup_path.to_string_lossy().to_string())
+            .build()
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(copy.get("flushed").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(copy.get("logged").unwrap(), Some(b"2".to_vec()));
+}
+
+#[test]
+fn test_empty_layout_paths_rejected() {
+    assert!(DatabaseConfig::builder().wal_dir("").build().is_err());
+    assert!(DatabaseConfig::builder().data_file("").build().is_err());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use lohdb::{Database, LohError};
+use tempfile::TempDir;
+
+#[test]
+fn test_oversized_keys_and_values_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .max_key_bytes(8)
+        .max_value_bytes(16)
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config).unwrap();
+    db.set("12345678".to_string(), vec![0; 16]).unwrap();
+
+    let err = db.set("123456789".to_string(), vec![]).unwrap_err();
+    assert!(matches!(err, LohError::TooLarge { what: "key", size: 9, limit: 8 }));
+    assert!(err.to_string().contains("key of 9 bytes exceeds the limit of 8 bytes"));
+
+    let err = db.namespace("ns").unwrap().set("k".to_string(), vec![0; 17]).unwrap_err();
+    assert!(matches!(err, LohError::TooLarge { what: "value", size: 17, limit: 16 }));
+
+    // Rejected writes never reach the WAL
+    assert_eq!(db.stats().unwrap().wal_sequence, 1);
+}
+
+#[test]
+fn test_value_near_u32_max_does_not_corrupt_wal() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("before".to_string(), b"1".to_vec()).unwrap();
+
+        // Zeroed allocations are lazily mapped, so this costs no real memory
+        let huge = vec![0u8; u32::MAX as usize - 8];
+        let err = db.set("huge".to_string(), huge).unwrap_err();
+        assert!(matches!(err, LohError::TooLarge { what: "WAL record", .. }));
+
+        db.set("after".to_string(), b"2".to_vec()).unwrap();
+    }
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("before").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(db.get("after").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(db.get("huge").unwrap(), None);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use lohdb::Database;
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+/// Collects every record so tests can assert on what the library logged
+struct CapturingLogger {
+    records: Mutex<Vec<(log::Level, String)>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.records.lock().unwrap().push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger { records: Mutex::new(Vec::new()) };
+
+/// Records mentioning `needle`, so tests running in parallel don't see each other's
+fn captured(needle: &str) -> Vec<(log::Level, String)> {
+    LOGGER.records.lock().unwrap().iter().filter(|(_, msg)| msg.contains(needle)).cloned().collect()
+}
+
+fn install() {
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Debug);
+}
+
+#[test]
+fn test_recovery_warning_is_logged() {
+    install();
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().to_string_lossy().to_string();
+    let config = common::config(&dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+        db.flush().unwrap();
+    }
+    let data_file = temp_dir.path().join("data.db");
+    let mut bytes = std::fs::read(&data_file).unwrap();
+    *bytes.last_mut().unwrap() ^= 0xff;
+    std::fs::write(&data_file, bytes).unwrap();
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("a").unwrap(), Some(b"1".to_vec()));
+
+    let records = captured(&dir);
+    assert!(
+        records.iter().any(|(level, msg)| *level == log::Level::Warn && msg.contains("corrupt data file")),
+        "{:?}",
+        records
+    );
+    assert!(records.iter().any(|(level, msg)| *level == log::Level::Debug && msg.starts_with("opened database")));
+}
+
+#[test]
+fn test_checkpoint_is_logged() {
+    install();
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().to_string_lossy().to_string();
+    let config = common::config(&dir);
+
+    let mut db = Database::open(config).unwrap();
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.flush().unwrap();
+    assert_eq!(captured(&format!("flushing storage in '{}'", dir)).len(), 1);
+
+    db.checkpoint().unwrap();
+    assert_eq!(captured(&format!("checkpointed '{}' through sequence 1", dir)).len(), 1);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_writers_block_until_maintenance_completes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("before".to_string(), b"1".to_vec()).unwrap();
+    let db = Arc::new(db);
+
+    let barrier = Arc::new(Barrier::new(2));
+    let written = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (db, barrier, written) = (db.clone(), barrier.clone(), written.clone());
+        thread::spawn(move || {
+            barrier.wait();
+            db.namespace("jobs").unwrap().set("during".to_string(), b"2".to_vec()).unwrap();
+            written.store(true, Ordering::SeqCst);
+        })
+    };
+
+    let seen = db
+        .with_maintenance(|guard| {
+            barrier.wait();
+            thread::sleep(Duration::from_millis(200));
+            assert!(!written.load(Ordering::SeqCst), "a write went through the barrier");
+
+            // Reads carry on, on this thread and on others
+            assert_eq!(db.get("before").unwrap(), Some(b"1".to_vec()));
+            let (tx, rx) = mpsc::channel();
+            let reader = db.clone();
+            thread::spawn(move || tx.send(reader.get("before").unwrap()).unwrap());
+            assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), Some(b"1".to_vec()));
+
+            assert_eq!(guard.seq(), 1);
+            assert_eq!(guard.storage().key_count()?, 1);
+            Ok(guard.entries()?.keys().cloned().collect::<Vec<_>>())
+        })
+        .unwrap();
+    assert_eq!(seen, vec!["before".to_string()]);
+
+    writer.join().unwrap();
+    assert!(written.load(Ordering::SeqCst));
+    assert_eq!(db.namespace("jobs").unwrap().get("during").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(db.stats().unwrap().wal_sequence, 2);
+}
+
+#[test]
+fn test_maintenance_error_releases_the_barrier() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+
+    let result: lohdb::Result<()> =
+        db.with_maintenance(|_guard| Err(lohdb::LohError::InvalidArgument("backup failed".to_string())));
+    assert!(matches!(result, Err(lohdb::LohError::InvalidArgument(_))));
+
+    db.set("after".to_string(), b"ok".to_vec()).unwrap();
+    assert_eq!(db.get("after").unwrap(), Some(b"ok".to_vec()));
+}
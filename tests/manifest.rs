@@ -0,0 +1,124 @@
+mod common;
+
+use lohdb::db::manifest::FORMAT_VERSION;
+use lohdb::db::Manifest;
+use lohdb::{Database, DatabaseConfigBuilder, LohError, SerializationFormat};
+use tempfile::TempDir;
+
+fn builder(temp_dir: &TempDir) -> DatabaseConfigBuilder {
+    common::builder(temp_dir)
+}
+
+fn open(builder: DatabaseConfigBuilder) -> lohdb::Result<Database> {
+    Database::open(builder.build().unwrap())
+}
+
+#[test]
+fn test_manifest_written_on_first_open() {
+    let temp_dir = TempDir::new().unwrap();
+    assert_eq!(Manifest::read(temp_dir.path()).unwrap(), None);
+    drop(open(builder(&temp_dir)).unwrap());
+
+    let manifest = Manifest::read(temp_dir.path()).unwrap().unwrap();
+    assert_eq!(manifest.format_version, FORMAT_VERSION);
+    assert_eq!(manifest.created_by, env!("CARGO_PKG_VERSION"));
+    assert_eq!(manifest.serialization_format, "bincode");
+    assert_eq!(manifest.compression, None);
+    assert!(!manifest.encrypted);
+
+    // Plain JSON, so other tools can inspect it
+    let json: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp_dir.path().join("MANIFEST")).unwrap()).unwrap();
+    assert_eq!(json["format_version"], FORMAT_VERSION);
+}
+
+#[test]
+fn test_newer_format_version_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open(builder(&temp_dir)).unwrap();
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    drop(db);
+
+    let path = temp_dir.path().join("MANIFEST");
+    let mut manifest = Manifest::read(temp_dir.path()).unwrap().unwrap();
+    manifest.format_version = FORMAT_VERSION + 1;
+    manifest.created_by = "99.0.0".to_string();
+    std::fs::write(&path, serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+    for read_only in [false, true] {
+        let err = open(builder(&temp_dir).read_only(read_only)).err().expect("newer format must fail");
+        assert!(matches!(err, LohError::Format(_)), "{:?}", err);
+        assert!(err.to_string().contains("lohdb 99.0.0"), "{}", err);
+    }
+
+    // A damaged manifest fails just as clearly
+    std::fs::write(&path, b"{ not json").unwrap();
+    let err = open(builder(&temp_dir)).err().unwrap();
+    assert!(err.to_string().contains("not a valid manifest"), "{}", err);
+}
+
+#[test]
+fn test_config_contradicting_manifest_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    drop(open(builder(&temp_dir)).unwrap());
+
+    let err = open(builder(&temp_dir).serialization_format(SerializationFormat::Json)).err().unwrap();
+    assert!(matches!(err, LohError::Format(_)), "{:?}", err);
+    assert!(err.to_string().contains("written as bincode"), "{}", err);
+
+    let err = open(builder(&temp_dir).encryption_key([1; 32])).err().unwrap();
+    assert!(matches!(err, LohError::Encryption(_)), "{:?}", err);
+    assert!(err.to_string().contains("is not encrypted"), "{}", err);
+
+    // The failed opens left the manifest alone
+    assert_eq!(Manifest::read(temp_dir.path()).unwrap().unwrap().serialization_format, "bincode");
+    open(builder(&temp_dir)).unwrap();
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_encrypted_store_needs_its_key() {
+    let temp_dir = TempDir::new().unwrap();
+    drop(open(builder(&temp_dir).encryption_key([1; 32])).unwrap());
+    assert!(Manifest::read(temp_dir.path()).unwrap().unwrap().encrypted);
+
+    let err = open(builder(&temp_dir)).err().unwrap();
+    assert!(matches!(err, LohError::Encryption(_)), "{:?}", err);
+    assert!(err.to_string().contains("open it with its encryption_key"), "{}", err);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn test_compression_can_be_added_but_not_removed() {
+    use lohdb::db::Lz4Compressor;
+
+    let temp_dir = TempDir::new().unwrap();
+    drop(open(builder(&temp_dir)).unwrap());
+
+    drop(open(builder(&temp_dir).compressor(Lz4Compressor)).unwrap());
+    assert_eq!(Manifest::read(temp_dir.path()).unwrap().unwrap().compression.as_deref(), Some("lz4"));
+
+    let err = open(builder(&temp_dir)).err().unwrap();
+    assert!(matches!(err, LohError::Compression(_)), "{:?}", err);
+}
+
+#[test]
+fn test_directory_without_manifest_gets_one() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open(builder(&temp_dir)).unwrap();
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    drop(db);
+
+    // As if written before manifests existed
+    std::fs::remove_file(temp_dir.path().join("MANIFEST")).unwrap();
+    let db = open(builder(&temp_dir).read_only(true)).unwrap();
+    assert_eq!(db.get("a").unwrap(), Some(b"1".to_vec()));
+    drop(db);
+    assert_eq!(Manifest::read(temp_dir.path()).unwrap(), None);
+
+    // Only a writable open that loaded the files records one
+    assert!(open(builder(&temp_dir).serialization_format(SerializationFormat::Json)).is_err());
+    assert_eq!(Manifest::read(temp_dir.path()).unwrap(), None);
+    drop(open(builder(&temp_dir)).unwrap());
+    assert!(Manifest::read(temp_dir.path()).unwrap().is_some());
+}
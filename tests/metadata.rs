@@ -0,0 +1,134 @@
+mod common;
+
+use lohdb::{ChangeEvent, Database, VersionedPut};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+#[test]
+fn test_versions_count_sets_and_survive_restart() {
+    let temp_dir = TempDir::new().unwrap();
+    let before = SystemTime::now() - Duration::from_secs(1);
+
+    {
+        let mut db = common::open(&temp_dir);
+        assert_eq!(db.get_with_metadata("key").unwrap(), None);
+        for value in ["a", "bb", "ccc"] {
+            db.set("key".to_string(), value.as_bytes().to_vec()).unwrap();
+        }
+        db.namespace("ns").unwrap().set("key".to_string(), b"x".to_vec()).unwrap();
+
+        let meta = db.get_with_metadata("key").unwrap().unwrap();
+        assert_eq!(meta.value, b"ccc");
+        assert_eq!(meta.size, 3);
+        assert_eq!(meta.version, 3);
+        assert!(meta.last_modified >= before && meta.last_modified <= SystemTime::now());
+    }
+
+    // Rebuilt by replaying the WAL, with the logged timestamps
+    let modified = {
+        let mut db = common::open(&temp_dir);
+        let meta = db.get_with_metadata("key").unwrap().unwrap();
+        assert_eq!(meta.version, 3);
+        assert_eq!(db.namespace("ns").unwrap().get_with_metadata("key").unwrap().unwrap().version, 1);
+
+        // Checkpointing moves the metadata into the data file
+        db.checkpoint().unwrap();
+        meta.last_modified
+    };
+
+    let mut db = common::open(&temp_dir);
+    let meta = db.get_with_metadata("key").unwrap().unwrap();
+    assert_eq!((meta.version, meta.last_modified), (3, modified));
+    db.set("key".to_string(), b"dddd".to_vec()).unwrap();
+    assert_eq!(db.get_with_metadata("key").unwrap().unwrap().version, 4);
+
+    // Deleting forgets the history
+    db.delete("key").unwrap();
+    assert_eq!(db.get_with_metadata("key").unwrap(), None);
+    db.set("key".to_string(), b"new".to_vec()).unwrap();
+    assert_eq!(db.get_with_metadata("key").unwrap().unwrap().version, 1);
+}
+
+#[test]
+fn test_in_memory_tracks_versions() {
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("key".to_string(), b"1".to_vec()).unwrap();
+    db.set("key".to_string(), b"2".to_vec()).unwrap();
+    assert_eq!(db.get_with_metadata("key").unwrap().unwrap().version, 2);
+}
+
+#[test]
+fn test_put_if_version_rejects_stale_writer() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    let (_subscription, events) = db.subscribe_channel().unwrap();
+
+    // Version 0 means "must not exist yet"
+    assert_eq!(db.put_if_version("doc".to_string(), b"v1".to_vec(), 0).unwrap(), VersionedPut::Written { version: 1 });
+    assert_eq!(
+        db.put_if_version("doc".to_string(), b"again".to_vec(), 0).unwrap(),
+        VersionedPut::Stale { current_version: 1 }
+    );
+
+    // Two writers read the same version; only the first write lands
+    let seen_by_a = db.get_with_metadata("doc").unwrap().unwrap().version;
+    let seen_by_b = db.get_with_metadata("doc").unwrap().unwrap().version;
+    assert!(db.put_if_version("doc".to_string(), b"from a".to_vec(), seen_by_a).unwrap().is_written());
+    let stale = db.put_if_version("doc".to_string(), b"from b".to_vec(), seen_by_b).unwrap();
+    assert_eq!(stale, VersionedPut::Stale { current_version: 2 });
+    assert_eq!(db.get("doc").unwrap(), Some(b"from a".to_vec()));
+
+    // B retries against the version it was told about
+    let VersionedPut::Stale { current_version } = stale else { unreachable!() };
+    assert_eq!(
+        db.put_if_version("doc".to_string(), b"from b".to_vec(), current_version).unwrap(),
+        VersionedPut::Written { version: 3 }
+    );
+
+    // Events carry the version each successful write produced
+    let versions: Vec<u64> = (0..3)
+        .map(|_| match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            ChangeEvent::Set { version, .. } => version,
+            other => panic!("unexpected event {:?}", other),
+        })
+        .collect();
+    assert_eq!(versions, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_concurrent_writers_retry_until_their_update_lands() {
+    const WRITERS: usize = 4;
+    const INCREMENTS: usize = 25;
+    let temp_dir = TempDir::new().unwrap();
+    let db = Arc::new(common::open(&temp_dir));
+
+    let handles: Vec<_> = (0..WRITERS)
+        .map(|_| {
+            let db = db.clone();
+            thread::spawn(move || {
+                let ns = db.namespace("counters").unwrap();
+                for _ in 0..INCREMENTS {
+                    loop {
+                        let (count, version) = match ns.get_with_metadata("hits").unwrap() {
+                            Some(meta) => (String::from_utf8(meta.value).unwrap().parse::<usize>().unwrap(), meta.version),
+                            None => (0, 0),
+                        };
+                        let next = (count + 1).to_string().into_bytes();
+                        if ns.put_if_version("hits".to_string(), next, version).unwrap().is_written() {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let meta = db.namespace("counters").unwrap().get_with_metadata("hits").unwrap().unwrap();
+    assert_eq!(meta.value, (WRITERS * INCREMENTS).to_string().into_bytes());
+    assert_eq!(meta.version, (WRITERS * INCREMENTS) as u64);
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use lohdb::{Database, FlushMode};
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_operations_are_timed() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    assert_eq!(db.metrics().set.count, 0);
+
+    for i in 0..100 {
+        db.set(format!("key{}", i), vec![b'v'; 64]).unwrap();
+    }
+    for i in 0..50 {
+        db.get(&format!("key{}", i)).unwrap();
+    }
+    db.namespace("ns").unwrap().get("missing").unwrap();
+    db.delete("key0").unwrap();
+    db.flush().unwrap();
+
+    let metrics = db.metrics();
+    assert_eq!(metrics.set.count, 100);
+    assert_eq!(metrics.get.count, 51);
+    assert_eq!(metrics.delete.count, 1);
+    assert_eq!(metrics.wal_append.count, 101);
+    assert_eq!(metrics.flush.count, 1);
+
+    for summary in [metrics.set, metrics.get, metrics.delete, metrics.wal_append, metrics.flush] {
+        assert!(summary.max > Duration::ZERO);
+        assert!(summary.p50 <= summary.p99);
+        assert!(summary.p99 <= summary.max);
+        assert!(summary.mean <= summary.max);
+    }
+}
+
+#[test]
+fn test_hits_misses_and_absent_deletes_are_timed() {
+    let mut db = Database::open_in_memory().unwrap();
+    db.get_or_insert_with("key", || b"value".to_vec()).unwrap();
+    db.get_or_insert_with("key", || unreachable!()).unwrap();
+    assert!(!db.delete("missing").unwrap());
+
+    // The hit is a get; the miss is a get followed by a set
+    let metrics = db.metrics();
+    assert_eq!(metrics.get.count, 2);
+    assert_eq!(metrics.set.count, 1);
+    assert_eq!(metrics.delete.count, 1);
+    assert_eq!(metrics.wal_append.count, 2);
+}
+
+#[test]
+fn test_wal_syncs_are_timed_apart_from_flushes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.flush_with(FlushMode::Wal).unwrap();
+
+    let metrics = db.metrics();
+    assert_eq!(metrics.wal_sync.count, 1);
+    assert_eq!(metrics.flush.count, 0);
+
+    db.flush_with(FlushMode::Snapshot).unwrap();
+    let metrics = db.metrics();
+    assert_eq!(metrics.wal_sync.count, 1);
+    assert_eq!(metrics.flush.count, 1);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use lohdb::db::{FileStorageEngine, InMemoryStorageEngine};
+use lohdb::Database;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_in_memory_database_migrates_to_file_engine() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().to_string_lossy().to_string();
+
+    let mut db = Database::open_in_memory().unwrap();
+    for i in 0..100 {
+        db.set(format!("key{}", i), format!("value{}", i).into_bytes()).unwrap();
+    }
+    db.namespace("ns").unwrap().set("inner".to_string(), b"nested".to_vec()).unwrap();
+    db.set_with_ttl("session".to_string(), b"temp".to_vec(), Duration::from_secs(3600)).unwrap();
+    let modified_before = db.get_with_metadata("key1").unwrap().unwrap().last_modified;
+
+    db.migrate_to(FileStorageEngine::new(data_dir)).unwrap();
+
+    // The database keeps serving, now from the file engine
+    assert_eq!(db.get("key42").unwrap(), Some(b"value42".to_vec()));
+    assert_eq!(db.get_with_metadata("key1").unwrap().unwrap().last_modified, modified_before);
+    db.set("after".to_string(), b"migration".to_vec()).unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.key_count().unwrap(), 103);
+    assert_eq!(db.get("key99").unwrap(), Some(b"value99".to_vec()));
+    assert_eq!(db.namespace("ns").unwrap().get("inner").unwrap(), Some(b"nested".to_vec()));
+    assert_eq!(db.get("after").unwrap(), Some(b"migration".to_vec()));
+    assert_eq!(db.get("session").unwrap(), Some(b"temp".to_vec()));
+}
+
+#[test]
+fn test_file_database_migration_releases_wal() {
+    let temp_dir = TempDir::new().unwrap();
+    let target_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    for i in 0..10 {
+        db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+    }
+
+    db.migrate_to(FileStorageEngine::new(target_dir.path().to_string_lossy().to_string())).unwrap();
+    db.set("more".to_string(), b"value".to_vec()).unwrap();
+
+    // Records written before the migration were checkpointed away
+    assert_eq!(db.change_history().unwrap().len(), 1);
+    assert_eq!(db.key_count().unwrap(), 11);
+}
+
+#[test]
+fn test_failed_migration_keeps_old_engine() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+
+    // The target directory cannot be created under a regular file
+    let file = temp_dir.path().join("not-a-dir");
+    std::fs::write(&file, b"").unwrap();
+    let blocked = FileStorageEngine::new(file.join("db").to_string_lossy().to_string());
+    assert!(db.migrate_to(blocked).is_err());
+
+    // A target that already holds data is refused
+    let populated_dir = TempDir::new().unwrap();
+    {
+        let mut other = common::open(&populated_dir);
+        other.set("existing".to_string(), b"data".to_vec()).unwrap();
+        other.checkpoint().unwrap();
+    }
+    let populated = FileStorageEngine::new(populated_dir.path().to_string_lossy().to_string());
+    let err = db.migrate_to(populated).unwrap_err();
+    assert!(err.to_string().contains("not empty"), "{}", err);
+
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+    db.set("still".to_string(), b"writable".to_vec()).unwrap();
+    drop(db);
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get("still").unwrap(), Some(b"writable".to_vec()));
+}
+
+#[test]
+fn test_migrate_back_into_memory() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.set_with_ttl("short".to_string(), b"lived".to_vec(), Duration::from_millis(100)).unwrap();
+
+    db.migrate_to(InMemoryStorageEngine::new()).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.stats().unwrap().data_file_bytes, 0);
+
+    // Expiries carry over
+    assert_eq!(db.get("short").unwrap(), Some(b"lived".to_vec()));
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(db.get("short").unwrap(), None);
+}
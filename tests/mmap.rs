@@ -0,0 +1,97 @@
+#![cfg(feature = "mmap")]
+
+use lohdb::db::MmapStorageEngine;
+use lohdb::StorageEngine;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use tempfile::TempDir;
+
+fn open_engine(temp_dir: &TempDir) -> MmapStorageEngine {
+    let mut engine = MmapStorageEngine::new(temp_dir.path().to_string_lossy().to_string());
+    engine.initialize().unwrap();
+    engine
+}
+
+#[test]
+fn test_mmap_engine_persists_across_reopen() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut engine = open_engine(&temp_dir);
+        engine.store("a", b"1").unwrap();
+        engine.store("b", b"2").unwrap();
+        engine.store("a", b"updated").unwrap();
+        assert!(engine.remove("b").unwrap());
+        assert!(!engine.remove("missing").unwrap());
+        engine.flush().unwrap();
+    }
+
+    let mut engine = open_engine(&temp_dir);
+    assert_eq!(engine.retrieve("a").unwrap(), Some(b"updated".to_vec()));
+    assert_eq!(engine.retrieve("b").unwrap(), None);
+    assert_eq!(engine.list_keys().unwrap(), vec!["a".to_string()]);
+
+    // Appends after a reopen land after the existing records
+    engine.store("c", b"3").unwrap();
+    engine.flush().unwrap();
+    drop(engine);
+    let engine = open_engine(&temp_dir);
+    assert_eq!(engine.list_keys().unwrap(), vec!["a".to_string(), "c".to_string()]);
+}
+
+#[test]
+fn test_mmap_engine_grows_and_compacts() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut engine = open_engine(&temp_dir);
+
+    // Well past the initial 1 MiB mapping
+    let value = vec![b'v'; 4096];
+    for round in 0..4 {
+        for i in 0..200 {
+            engine.store(&format!("key{:03}", i), &value).unwrap();
+        }
+        assert_eq!(engine.list_keys().unwrap().len(), 200, "round {}", round);
+    }
+    let before = engine.disk_usage().unwrap();
+    assert!(engine.dead_bytes() > 0);
+
+    // Three quarters of the file is dead, so flush compacts it
+    engine.flush().unwrap();
+    assert_eq!(engine.dead_bytes(), 0);
+    assert!(engine.disk_usage().unwrap() < before / 2);
+    assert!(!temp_dir.path().join("data.compact").exists());
+    drop(engine);
+
+    let engine = open_engine(&temp_dir);
+    assert_eq!(engine.dead_bytes(), 0);
+    assert_eq!(engine.list_keys().unwrap().len(), 200);
+    assert_eq!(engine.retrieve("key199").unwrap(), Some(value));
+}
+
+#[test]
+fn test_mmap_engine_discards_torn_tail() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let end = {
+        let mut engine = open_engine(&temp_dir);
+        engine.store("kept", b"value").unwrap();
+        engine.flush().unwrap();
+        engine.disk_usage().unwrap()
+    };
+
+    // A put header claiming a value longer than the file, as left by a crash mid-grow
+    let mut file = OpenOptions::new().write(true).open(temp_dir.path().join("data.mmap")).unwrap();
+    file.seek(SeekFrom::Start(end)).unwrap();
+    file.write_all(&[1, 4, 0, 0, 0, 0, 0, 0, 1, b'l', b'o', b's', b't']).unwrap();
+    drop(file);
+
+    let mut engine = open_engine(&temp_dir);
+    assert_eq!(engine.list_keys().unwrap(), vec!["kept".to_string()]);
+    assert_eq!(engine.disk_usage().unwrap(), end);
+
+    engine.store("next", b"value").unwrap();
+    engine.flush().unwrap();
+    drop(engine);
+    let engine = open_engine(&temp_dir);
+    assert_eq!(engine.list_keys().unwrap(), vec!["kept".to_string(), "next".to_string()]);
+}
@@ -0,0 +1,60 @@
+use lohdb::{Database, DatabaseConfig};
+use tempfile::TempDir;
+
+#[test]
+fn test_namespaces_are_isolated_and_recovered() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().to_string_lossy().to_string();
+
+    let config = DatabaseConfig::builder()
+        .data_dir(data_dir)
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("shared".to_string(), b"default".to_vec()).unwrap();
+
+        let tenant_a = db.namespace("tenant_a").unwrap();
+        let tenant_b = db.namespace("tenant_b").unwrap();
+        tenant_a.set("shared".to_string(), b"a".to_vec()).unwrap();
+        tenant_a.set("only_a".to_string(), b"a2".to_vec()).unwrap();
+        tenant_b.set("shared".to_string(), b"b".to_vec()).unwrap();
+
+        assert_eq!(db.get("shared").unwrap(), Some(b"default".to_vec()));
+        assert_eq!(tenant_a.get("shared").unwrap(), Some(b"a".to_vec()));
+        assert_eq!(tenant_b.get("shared").unwrap(), Some(b"b".to_vec()));
+        assert_eq!(tenant_b.get("only_a").unwrap(), None);
+
+        assert_eq!(db.list_keys().unwrap(), vec!["shared".to_string()]);
+        assert_eq!(db.list_namespaces().unwrap(), vec!["tenant_a".to_string(), "tenant_b".to_string()]);
+
+        assert_eq!(tenant_b.clear().unwrap(), 1);
+        assert!(tenant_b.list_keys().unwrap().is_empty());
+    }
+
+    // Namespaced writes survive WAL replay
+    let db = Database::open(config).unwrap();
+    let tenant_a = db.namespace("tenant_a").unwrap();
+    let mut keys = tenant_a.list_keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["only_a".to_string(), "shared".to_string()]);
+    assert_eq!(db.list_namespaces().unwrap(), vec!["tenant_a".to_string()]);
+    assert_eq!(db.get("shared").unwrap(), Some(b"default".to_vec()));
+}
+
+#[test]
+fn test_reserved_keys_and_namespace_names_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config).unwrap();
+    assert!(db.set("\0sneaky".to_string(), b"x".to_vec()).is_err());
+    assert!(db.namespace("").is_err());
+    assert!(db.namespace("bad\0name").is_err());
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig, LohError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+#[test]
+fn test_read_only_rejects_writes_and_serves_reads() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().to_string_lossy().to_string();
+
+    let config = DatabaseConfig::builder()
+        .data_dir(data_dir)
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("key1".to_string(), b"value1".to_vec()).unwrap();
+        db.namespace("ns").unwrap().set("key2".to_string(), b"value2".to_vec()).unwrap();
+    }
+
+    let wal_before = std::fs::read(temp_dir.path().join("wal.000001.log")).unwrap();
+
+    let mut db = Database::open(DatabaseConfig { read_only: true, ..config }).unwrap();
+    assert!(db.is_read_only());
+    assert_eq!(db.get("key1").unwrap(), Some(b"value1".to_vec()));
+    assert_eq!(db.namespace("ns").unwrap().get("key2").unwrap(), Some(b"value2".to_vec()));
+
+    let err = db.set("key3".to_string(), b"value3".to_vec()).unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+    assert!(db.delete("key1").is_err());
+    assert!(db.namespace("ns").unwrap().delete("key2").is_err());
+    assert!(db.flush().is_err());
+
+    // The WAL was not touched
+    assert_eq!(std::fs::read(temp_dir.path().join("wal.000001.log")).unwrap(), wal_before);
+}
+
+#[test]
+fn test_read_only_requires_existing_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let missing = temp_dir.path().join("missing");
+
+    let config = DatabaseConfig::builder()
+        .data_dir(missing.to_string_lossy().to_string())
+        .wal_sync_interval_ms(100)
+        .read_only(true)
+        .build()
+        .unwrap();
+
+    assert!(Database::open(config).is_err());
+    assert!(!missing.exists());
+}
+
+#[test]
+fn test_second_open_of_locked_directory_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
+
+    let db = Database::open(config.clone()).unwrap();
+
+    // flock is per file handle, so this conflicts even within one process
+    let err = Database::open(config.clone()).err().expect("second open must fail");
+    assert!(err.to_string().contains("locked"));
+
+    // A read-only open does not take the lock
+    assert!(Database::open(DatabaseConfig { read_only: true, ..config.clone() }).is_ok());
+
+    // The lock is released on drop
+    drop(db);
+    assert!(Database::open(config).is_ok());
+}
+
+#[test]
+fn test_open_timeout_gives_up_with_locked() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .open_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let _db = Database::open(config.clone()).unwrap();
+
+    let started = Instant::now();
+    assert!(matches!(Database::open(config.clone()), Err(LohError::Locked(_))));
+    let waited = started.elapsed();
+    assert!(waited >= Duration::from_millis(200), "gave up after {:?}", waited);
+    assert!(waited < Duration::from_secs(5), "hung for {:?}", waited);
+
+    // try_open ignores the timeout
+    let started = Instant::now();
+    assert!(matches!(Database::try_open(config), Err(LohError::Locked(_))));
+    assert!(started.elapsed() < Duration::from_millis(200));
+}
+
+#[test]
+fn test_open_timeout_waits_for_the_lock_to_be_released() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .open_timeout(Duration::from_secs(10))
+        .build()
+        .unwrap();
+    let mut db = Database::open(config.clone()).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+
+    let holder = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(db);
+    });
+    let started = Instant::now();
+    let db = Database::open(config).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+    holder.join().unwrap();
+}
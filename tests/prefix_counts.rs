@@ -0,0 +1,78 @@
+use lohdb::Database;
+use std::collections::HashMap;
+
+fn populated() -> Database {
+    let mut db = Database::open_in_memory().unwrap();
+    for i in 0..1500 {
+        db.set(format!("user:{}", i), b"v".to_vec()).unwrap();
+    }
+    for i in 0..20 {
+        db.set(format!("user:admin:{}", i), b"v".to_vec()).unwrap();
+    }
+    for i in 0..5 {
+        db.set(format!("order:{}", i), b"v".to_vec()).unwrap();
+    }
+    let ns = db.namespace("other").unwrap();
+    ns.set("user:ghost".to_string(), b"v".to_vec()).unwrap();
+    ns.set("user:admin:ghost".to_string(), b"v".to_vec()).unwrap();
+    db
+}
+
+#[test]
+fn test_count_prefix() {
+    let db = populated();
+
+    // Spans more than one scan batch
+    assert_eq!(db.count_prefix("user:").unwrap(), 1520);
+    assert_eq!(db.count_prefix("user:admin:").unwrap(), 20);
+    assert_eq!(db.count_prefix("user:1").unwrap(), 1 + 10 + 100 + 500);
+    assert_eq!(db.count_prefix("missing").unwrap(), 0);
+
+    // Namespaced keys never count towards the default namespace
+    assert_eq!(db.count_prefix("").unwrap(), 1525);
+    assert_eq!(db.namespace("other").unwrap().count_prefix("user:").unwrap(), 2);
+}
+
+#[test]
+fn test_prefix_stats_counts_nested_prefixes_independently() {
+    let db = populated();
+    let prefixes: Vec<String> = ["user:", "user:admin:", "user:admin:1", "order:", "nothing:", "user:"]
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+
+    let stats = db.prefix_stats(&prefixes).unwrap();
+    let expected: HashMap<String, usize> = [
+        ("user:", 1520),
+        ("user:admin:", 20),
+        ("user:admin:1", 11),
+        ("order:", 5),
+        ("nothing:", 0),
+    ]
+    .into_iter()
+    .map(|(p, n)| (p.to_string(), n))
+    .collect();
+    assert_eq!(stats, expected);
+
+    // Each count agrees with count_prefix
+    for (prefix, count) in &stats {
+        assert_eq!(db.count_prefix(prefix).unwrap(), *count, "{}", prefix);
+    }
+
+    let ns_stats = db.namespace("other").unwrap().prefix_stats(&prefixes[..2]).unwrap();
+    assert_eq!(ns_stats["user:"], 2);
+    assert_eq!(ns_stats["user:admin:"], 1);
+    assert!(db.prefix_stats(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn test_prefix_stats_with_shared_multibyte_prefix() {
+    let mut db = Database::open_in_memory().unwrap();
+    for key in ["über:a", "über:b", "übel", "ü"] {
+        db.set(key.to_string(), b"v".to_vec()).unwrap();
+    }
+    let stats = db.prefix_stats(&["über:".to_string(), "übel".to_string(), "ü".to_string()]).unwrap();
+    assert_eq!(stats["über:"], 2);
+    assert_eq!(stats["übel"], 1);
+    assert_eq!(stats["ü"], 4);
+}
@@ -1,5 +1,6 @@
-use lohdb::{Database, DatabaseConfig};
+use lohdb::{Database, DatabaseConfig, LohError, RecoverTo};
 use tempfile::TempDir;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
@@ -8,10 +9,11 @@ fn test_crash_recovery() {
     let temp_dir = TempDir::new().unwrap();
     let data_dir = temp_dir.path().to_string_lossy().to_string();
     
-    let config = DatabaseConfig {
-        data_dir: data_dir.clone(),
-        wal_sync_interval_ms: 100,
-    };
+    let config = DatabaseConfig::builder()
+        .data_dir(data_dir.clone())
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
     
     // Create database and insert some data
     {
@@ -55,10 +57,11 @@ fn test_change_subscriptions() {
     let temp_dir = TempDir::new().unwrap();
     let data_dir = temp_dir.path().to_string_lossy().to_string();
     
-    let config = DatabaseConfig {
-        data_dir,
-        wal_sync_interval_ms: 100,
-    };
+    let config = DatabaseConfig::builder()
+        .data_dir(data_dir)
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
     
     let mut db = Database::open(config).unwrap();
     
@@ -77,7 +80,7 @@ fn test_change_subscriptions() {
     thread::sleep(Duration::from_millis(100));
     
     let captured_events = events.lock().unwrap();
-    assert!(captured_events.len() >= 1); // At least one event should be captured
+    assert!(!captured_events.is_empty()); // At least one event should be captured
 }
 
 #[test]
@@ -85,10 +88,11 @@ fn test_concurrent_operations() {
     let temp_dir = TempDir::new().unwrap();
     let data_dir = temp_dir.path().to_string_lossy().to_string();
     
-    let config = DatabaseConfig {
-        data_dir,
-        wal_sync_interval_ms: 50,
-    };
+    let config = DatabaseConfig::builder()
+        .data_dir(data_dir)
+        .wal_sync_interval_ms(50)
+        .build()
+        .unwrap();
     
     let db = std::sync::Arc::new(std::sync::Mutex::new(Database::open(config).unwrap()));
     
@@ -118,6 +122,346 @@ fn test_concurrent_operations() {
     }
     
     // Verify all data is present
-    let keys = db.lock().unwrap().list_keys().unwrap();
-    assert_eq!(keys.len(), 50); // 5 threads × 10 operations each
-}
\ No newline at end of file
+    assert_eq!(db.lock().unwrap().key_count().unwrap(), 50); // 5 threads × 10 operations each
+}
+#[test]
+fn test_background_sync_interval_and_disabled_sync() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_file = temp_dir.path().join("data.db");
+
+    // With sync disabled nothing is snapshotted, but the WAL still recovers
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .build()
+        .unwrap();
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("key".to_string(), b"value".to_vec()).unwrap();
+        thread::sleep(Duration::from_millis(150));
+        assert!(!data_file.exists());
+    }
+    assert_eq!(Database::open(config).unwrap().get("key").unwrap(), Some(b"value".to_vec()));
+
+    // With a short interval the background thread writes the snapshot
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(20)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    db.set("key2".to_string(), b"value2".to_vec()).unwrap();
+    thread::sleep(Duration::from_millis(150));
+    assert!(data_file.exists());
+
+    // Dropping stops the thread promptly instead of leaking it
+    let started = std::time::Instant::now();
+    drop(db);
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn test_point_in_time_recovery() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .build()
+        .unwrap();
+
+    let midpoint = {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+        db.set("b".to_string(), b"2".to_vec()).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let midpoint = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        thread::sleep(Duration::from_millis(20));
+
+        // The "bad bulk write"
+        db.set("a".to_string(), b"overwritten".to_vec()).unwrap();
+        db.delete("b").unwrap();
+        db.set("c".to_string(), b"3".to_vec()).unwrap();
+        db.flush().unwrap();
+        midpoint
+    };
+
+    let db = Database::open_at(config.clone(), RecoverTo::Timestamp(midpoint)).unwrap();
+    assert!(db.is_read_only());
+    assert_eq!(db.get("a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(db.get("b").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(db.get("c").unwrap(), None);
+    drop(db);
+
+    let db = Database::open_at(config.clone(), RecoverTo::Sequence(3)).unwrap();
+    assert_eq!(db.get("a").unwrap(), Some(b"overwritten".to_vec()));
+    assert_eq!(db.get("b").unwrap(), Some(b"2".to_vec()));
+    drop(db);
+
+    // The live database is untouched
+    assert_eq!(Database::open(config.clone()).unwrap().get("c").unwrap(), Some(b"3".to_vec()));
+
+    // Once the WAL is checkpointed the data file is newer than any earlier point
+    Database::open(config.clone()).unwrap().checkpoint().unwrap();
+    let err = Database::open_at(config.clone(), RecoverTo::Sequence(2)).err().expect("must fail");
+    assert!(err.to_string().contains("newer than the requested point"));
+    assert!(Database::open_at(config, RecoverTo::Sequence(5)).is_ok());
+}
+
+#[test]
+fn test_corrupt_data_file_is_detected_and_rebuilt_from_wal() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_file = temp_dir.path().join("data.db");
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .build()
+        .unwrap();
+    let flip_last_byte = || {
+        let mut bytes = std::fs::read(&data_file).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&data_file, bytes).unwrap();
+    };
+
+    // While the WAL holds every write, a damaged snapshot is set aside and rebuilt
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+        db.set("b".to_string(), b"2".to_vec()).unwrap();
+        db.flush().unwrap();
+    }
+    flip_last_byte();
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        assert_eq!(db.get("a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get("b").unwrap(), Some(b"2".to_vec()));
+        assert!(temp_dir.path().join("data.db.corrupt").exists());
+
+        // Once checkpointed, the snapshot is the only copy of these writes
+        db.checkpoint().unwrap();
+    }
+    flip_last_byte();
+
+    let err = Database::open(config).err().expect("corrupt snapshot must fail");
+    assert!(matches!(err, LohError::DataCorrupt(_)), "{:?}", err);
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+}
+
+#[test]
+fn test_close_checkpoints_and_ends_subscriptions() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(10)
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config.clone()).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    db.set("kept".to_string(), b"value".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("also".to_string(), b"kept".to_vec()).unwrap();
+    db.close().unwrap();
+
+    // Buffered events drain, then the channel disconnects
+    assert_eq!(events.iter().count(), 2);
+
+    // The final checkpoint left nothing to replay
+    let mut wal = lohdb::db::WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    wal.replay(|entry| panic!("unexpected WAL record {}", entry.seq)).unwrap();
+
+    let db = Database::open(config.clone()).unwrap();
+    assert_eq!(db.get("kept").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.namespace("ns").unwrap().get("also").unwrap(), Some(b"kept".to_vec()));
+    assert_eq!(db.stats().unwrap().wal_sequence, 2);
+    db.close().unwrap();
+
+    // Read-only and in-memory databases close without writing anything
+    let read_only = DatabaseConfig { read_only: true, ..config };
+    Database::open(read_only).unwrap().close().unwrap();
+    Database::open_in_memory().unwrap().close().unwrap();
+}
+
+#[test]
+fn test_background_checkpoints_interleaved_with_writes_lose_nothing() {
+    const WRITERS: usize = 4;
+    const WRITES: usize = 500;
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(5)
+        .build()
+        .unwrap();
+
+    {
+        let db = Arc::new(Database::open(config.clone()).unwrap());
+        let handles: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    let ns = db.namespace(&format!("writer{}", w)).unwrap();
+                    for i in 0..WRITES {
+                        // Every key is overwritten several times, so replay order matters
+                        ns.set(format!("key{}", i % 100), format!("{}", i).into_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Checkpoints kept the log from growing with every write
+        thread::sleep(Duration::from_millis(50));
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.wal_sequence, (WRITERS * WRITES) as u64);
+        assert!(stats.wal_file_bytes < 4096, "WAL still holds {} bytes", stats.wal_file_bytes);
+
+        // A few more writes that only the WAL holds when the "crash" happens
+        db.namespace("writer0").unwrap().set("late".to_string(), b"write".to_vec()).unwrap();
+    }
+
+    let db = Database::open(config).unwrap();
+    for w in 0..WRITERS {
+        let ns = db.namespace(&format!("writer{}", w)).unwrap();
+        for k in 0..100 {
+            let last = (0..WRITES).filter(|i| i % 100 == k).max().unwrap();
+            assert_eq!(ns.get(&format!("key{}", k)).unwrap(), Some(last.to_string().into_bytes()));
+        }
+    }
+    assert_eq!(db.namespace("writer0").unwrap().get("late").unwrap(), Some(b"write".to_vec()));
+}
+
+#[test]
+fn test_corrupt_wal_record_is_reported_not_silently_dropped() {
+    use lohdb::db::WriteAheadLog;
+    use std::io::{Seek, SeekFrom, Write};
+
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .build()
+        .unwrap();
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for i in 1..=5 {
+            db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+        }
+    } // Dropped without a checkpoint, so every write is only in the WAL
+
+    // Overwrite the start of record 3's payload, past its 20-byte header
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let third = wal.iter_entries().nth(2).unwrap().unwrap();
+    drop(wal);
+    let mut file = std::fs::OpenOptions::new().write(true).open(&third.segment).unwrap();
+    file.seek(SeekFrom::Start(third.offset + 20)).unwrap();
+    file.write_all(&[0xff; 4]).unwrap();
+    drop(file);
+
+    let mut wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let mut seqs = Vec::new();
+    let report = wal.replay(|entry| {
+        seqs.push(entry.seq);
+        Ok(())
+    }).unwrap();
+    assert_eq!(seqs, vec![1, 2]);
+    assert_eq!((report.applied, report.skipped), (2, 3));
+    assert!(report.first_error.unwrap().contains("record 3"));
+    drop(wal);
+
+    let db = Database::open(DatabaseConfig { read_only: true, ..config }).unwrap();
+    assert_eq!(db.get("key2").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get("key3").unwrap(), None);
+    let replay = db.stats().unwrap().replay;
+    assert_eq!((replay.applied, replay.skipped), (2, 3));
+    assert!(replay.first_error.is_some());
+}
+
+/// Five writes to a WAL whose third record is then damaged
+fn corrupt_third_record(config: &DatabaseConfig) {
+    use lohdb::db::WriteAheadLog;
+    use std::io::{Seek, SeekFrom, Write};
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for i in 1..=5 {
+            db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+        }
+    }
+    let wal = WriteAheadLog::open_read_only(&config.data_dir).unwrap();
+    let third = wal.iter_entries().nth(2).unwrap().unwrap();
+    drop(wal);
+    let mut file = std::fs::OpenOptions::new().write(true).open(&third.segment).unwrap();
+    file.seek(SeekFrom::Start(third.offset + 20)).unwrap();
+    file.write_all(&[0xff; 4]).unwrap();
+}
+
+#[test]
+fn test_writable_open_refuses_a_wal_it_cannot_fully_replay() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .build()
+        .unwrap();
+    corrupt_third_record(&config);
+    let before: Vec<_> = std::fs::read_dir(temp_dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+
+    let err = Database::open(config.clone()).err().expect("a partial replay must not open writable");
+    assert!(matches!(err, LohError::WalCorrupt(_)));
+    assert!(err.to_string().contains("salvage_wal"), "{}", err);
+
+    // The WAL is untouched, so the open fails the same way again
+    let after: Vec<_> = std::fs::read_dir(temp_dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(before, after);
+    assert!(Database::open(config).is_err());
+}
+
+#[test]
+fn test_salvaged_open_keeps_writes_made_after_a_corrupt_record() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .salvage_wal(true)
+        .build()
+        .unwrap();
+    corrupt_third_record(&config);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        let replay = db.stats().unwrap().replay;
+        assert_eq!((replay.applied, replay.skipped), (2, 3));
+        db.set("new".to_string(), b"write".to_vec()).unwrap();
+    } // Dropped without a checkpoint, so the new write is only in the WAL
+
+    // The damaged log was set aside, not replayed again
+    assert!(std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .any(|e| e.unwrap().file_name().to_string_lossy().ends_with(".log.corrupt")));
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.stats().unwrap().replay, lohdb::ReplayReport { applied: 1, skipped: 0, first_error: None });
+    assert_eq!(db.get("new").unwrap(), Some(b"write".to_vec()));
+    assert_eq!(db.get("key2").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get("key3").unwrap(), None);
+}
+
+#[test]
+fn test_clean_replay_reports_nothing_skipped() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(0)
+        .build()
+        .unwrap();
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("key".to_string(), b"value".to_vec()).unwrap();
+    }
+
+    let replay = Database::open(config).unwrap().stats().unwrap().replay;
+    assert_eq!(replay, lohdb::ReplayReport { applied: 1, skipped: 0, first_error: None });
+}
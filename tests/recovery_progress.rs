@@ -0,0 +1,78 @@
+mod common;
+
+use lohdb::db::WriteAheadLog;
+use lohdb::{Database, DatabaseConfig, LohError, RecoveryProgress};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// Open with an observer reporting every `every` records, returning the reports
+fn open_recording(config: DatabaseConfig, every: u64) -> (Database, Vec<RecoveryProgress>) {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    let config = DatabaseConfig {
+        on_recovery_progress: Some(Arc::new(move |progress| sink.lock().unwrap().push(progress))),
+        recovery_progress_every: every,
+        ..config
+    };
+    let db = Database::open(config).unwrap();
+    let reports = reports.lock().unwrap().clone();
+    (db, reports)
+}
+
+#[test]
+fn test_recovery_progress_is_throttled_and_ends_with_done() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for i in 0..250 {
+            db.set(format!("key{:03}", i), vec![b'v'; 32]).unwrap();
+        }
+    } // Dropped without a checkpoint, so reopening replays all 250 records
+
+    let (db, reports) = open_recording(config.clone(), 100);
+    assert_eq!(db.key_count().unwrap(), 250);
+
+    let entries: Vec<u64> = reports.iter().map(|p| p.entries).collect();
+    assert_eq!(entries, vec![100, 200, 250]);
+    assert!(reports[..2].iter().all(|p| !p.done));
+    assert!(reports[2].done);
+
+    // Bytes only grow, and the whole log has been read by the end
+    let total = reports[0].total_bytes;
+    assert!(total > 0);
+    assert!(reports.iter().all(|p| p.total_bytes == total));
+    assert!(reports[0].bytes_read < reports[1].bytes_read);
+    assert!(reports[1].bytes_read < total);
+    assert_eq!(reports[2].bytes_read, total);
+    drop(db);
+
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    assert_eq!(wal.disk_usage().unwrap(), total);
+}
+
+#[test]
+fn test_recovery_progress_reports_once_with_nothing_to_replay() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("key".to_string(), b"value".to_vec()).unwrap();
+        db.close().unwrap();
+    }
+
+    let (db, reports) = open_recording(config, 100);
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].entries, 0);
+    assert!(reports[0].done);
+}
+
+#[test]
+fn test_recovery_progress_every_must_be_positive() {
+    let result = DatabaseConfig::builder()
+        .on_recovery_progress(|_| {})
+        .recovery_progress_every(0)
+        .build();
+    assert!(matches!(result, Err(LohError::InvalidArgument(_))));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{ChangeEvent, Database, LohError};
+use std::collections::HashMap;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_rename_moves_the_value() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("old".to_string(), b"value".to_vec()).unwrap();
+    db.set("old".to_string(), b"value".to_vec()).unwrap();
+
+    assert!(db.rename("old", "new", false).unwrap());
+    assert_eq!(db.get("old").unwrap(), None);
+    assert_eq!(db.get("new").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get_with_metadata("new").unwrap().unwrap().version, 1);
+
+    assert!(!db.rename("missing", "other", false).unwrap());
+    assert_eq!(db.get("other").unwrap(), None);
+
+    // Renaming a key to itself leaves it as it is
+    assert!(db.rename("new", "new", false).unwrap());
+    assert_eq!(db.get("new").unwrap(), Some(b"value".to_vec()));
+}
+
+#[test]
+fn test_rename_onto_existing_key_needs_overwrite() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("a".to_string(), b"from a".to_vec()).unwrap();
+    db.set("b".to_string(), b"from b".to_vec()).unwrap();
+
+    let err = db.rename("a", "b", false).unwrap_err();
+    assert!(matches!(&err, LohError::InvalidArgument(m) if m.contains("already exists")), "{}", err);
+    assert_eq!(db.get("a").unwrap(), Some(b"from a".to_vec()));
+    assert_eq!(db.get("b").unwrap(), Some(b"from b".to_vec()));
+
+    assert!(db.rename("a", "b", true).unwrap());
+    assert_eq!(db.get("a").unwrap(), None);
+    assert_eq!(db.get("b").unwrap(), Some(b"from a".to_vec()));
+}
+
+#[test]
+fn test_rename_carries_expiry_and_attributes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set_with_ttl("session".to_string(), b"token".to_vec(), Duration::from_secs(60)).unwrap();
+    let attrs: HashMap<String, String> = [("owner".to_string(), "ada".to_string())].into();
+    db.set_with_meta("doc".to_string(), b"{}".to_vec(), attrs.clone()).unwrap();
+
+    db.rename("session", "session:renamed", false).unwrap();
+    db.rename("doc", "doc:renamed", false).unwrap();
+    assert!(db.ttl("session:renamed").unwrap().is_some());
+    assert_eq!(db.get_meta("doc:renamed").unwrap(), Some(attrs));
+}
+
+#[test]
+fn test_rename_publishes_delete_then_set() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("old".to_string(), b"value".to_vec()).unwrap();
+
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    db.rename("old", "new", false).unwrap();
+    let events: Vec<ChangeEvent> = events.try_iter().collect();
+    assert_eq!(events.len(), 2);
+    assert!(matches!(&events[0], ChangeEvent::Delete { key, .. } if key == "old"));
+    assert!(matches!(&events[1], ChangeEvent::Set { key, value, version: 1, .. } if key == "new" && value == b"value"));
+}
+
+#[test]
+fn test_rename_is_one_wal_record_and_survives_recovery() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("old".to_string(), b"value".to_vec()).unwrap();
+        db.set("taken".to_string(), b"replaced".to_vec()).unwrap();
+        db.rename("old", "taken", true).unwrap();
+        db.namespace("ns").unwrap().set("a".to_string(), b"ns value".to_vec()).unwrap();
+        db.namespace("ns").unwrap().rename("a", "b", false).unwrap();
+    } // Dropped without a checkpoint, so everything comes back from the WAL
+
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let ops: Vec<Operation> = wal.iter_entries().map(|e| e.unwrap().entry.operation).collect();
+    assert_eq!(ops.len(), 5);
+    assert!(matches!(&ops[2], Operation::Rename { namespace: None, from, to, .. } if from == "old" && to == "taken"));
+    drop(wal);
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("old").unwrap(), None);
+    assert_eq!(db.get("taken").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.namespace("ns").unwrap().get("a").unwrap(), None);
+    assert_eq!(db.namespace("ns").unwrap().get("b").unwrap(), Some(b"ns value".to_vec()));
+
+    // History reports the rename as the events subscribers saw
+    let history: Vec<ChangeEvent> = db.change_history().unwrap().into_iter().map(|c| c.event).collect();
+    assert!(matches!(&history[2], ChangeEvent::Delete { key, .. } if key == "old"));
+    assert!(matches!(&history[3], ChangeEvent::Set { key, value, .. } if key == "taken" && value == b"value"));
+}
@@ -0,0 +1,175 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use lohdb::server::resp::{read_command, Reply, MAX_ARGS, MAX_BULK_LEN};
+use lohdb::server::serve;
+use lohdb::{Database, LohError};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tempfile::TempDir;
+
+fn start_server(dir: &TempDir) -> (SocketAddr, Arc<Mutex<Database>>) {
+    let db = Arc::new(Mutex::new(common::open(dir)));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_db = db.clone();
+    thread::spawn(move || serve(listener, server_db));
+    (addr, db)
+}
+
+/// Encode `args` as a RESP command
+fn command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// Read one reply, decoded into a `Reply`
+fn read_reply(reader: &mut impl BufRead) -> Reply {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line).unwrap();
+    let line = String::from_utf8(line.strip_suffix(b"\r\n").unwrap().to_vec()).unwrap();
+    let (kind, rest) = line.split_at(1);
+    match kind {
+        "+" => Reply::Simple(rest.to_string()),
+        "-" => Reply::Error(rest.to_string()),
+        ":" => Reply::Integer(rest.parse().unwrap()),
+        "$" if rest == "-1" => Reply::Bulk(None),
+        "$" => {
+            let mut bytes = vec![0; rest.parse::<usize>().unwrap() + 2];
+            reader.read_exact(&mut bytes).unwrap();
+            bytes.truncate(bytes.len() - 2);
+            Reply::Bulk(Some(bytes))
+        }
+        "*" => Reply::Array((0..rest.parse::<usize>().unwrap()).map(|_| read_reply(reader)).collect()),
+        other => panic!("unexpected reply type {:?}", other),
+    }
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        Self { writer: stream.try_clone().unwrap(), reader: BufReader::new(stream) }
+    }
+
+    fn send(&mut self, args: &[&[u8]]) -> Reply {
+        self.writer.write_all(&command(args)).unwrap();
+        read_reply(&mut self.reader)
+    }
+}
+
+fn bulk(bytes: &[u8]) -> Reply {
+    Reply::Bulk(Some(bytes.to_vec()))
+}
+
+#[test]
+fn test_resp_set_and_get_are_binary_safe() {
+    let dir = TempDir::new().unwrap();
+    let (addr, db) = start_server(&dir);
+    let mut client = Client::connect(addr);
+
+    // Bytes that would break a line-based protocol
+    let value: &[u8] = b"line one\r\nline two\0\xff";
+    assert_eq!(client.send(&[b"SET", b"blob", value]), Reply::Simple("OK".to_string()));
+    assert_eq!(client.send(&[b"GET", b"blob"]), bulk(value));
+    assert_eq!(client.send(&[b"get", b"missing"]), Reply::Bulk(None));
+    assert_eq!(db.lock().unwrap().get("blob").unwrap(), Some(value.to_vec()));
+}
+
+#[test]
+fn test_resp_del_exists_and_keys() {
+    let dir = TempDir::new().unwrap();
+    let (addr, _db) = start_server(&dir);
+    let mut client = Client::connect(addr);
+
+    for key in ["user:1", "user:2", "user:10", "order:1"] {
+        client.send(&[b"SET", key.as_bytes(), b"x"]);
+    }
+    assert_eq!(client.send(&[b"EXISTS", b"user:1", b"missing", b"user:1"]), Reply::Integer(2));
+    assert_eq!(
+        client.send(&[b"KEYS", b"user:?"]),
+        Reply::Array(vec![bulk(b"user:1"), bulk(b"user:2")])
+    );
+    assert_eq!(
+        client.send(&[b"KEYS", b"*:1*"]),
+        Reply::Array(vec![bulk(b"order:1"), bulk(b"user:1"), bulk(b"user:10")])
+    );
+    assert_eq!(client.send(&[b"DEL", b"user:1", b"user:2", b"missing"]), Reply::Integer(2));
+    assert_eq!(client.send(&[b"KEYS", b"*"]), Reply::Array(vec![bulk(b"order:1"), bulk(b"user:10")]));
+    assert_eq!(client.send(&[b"PING"]), Reply::Simple("PONG".to_string()));
+}
+
+#[test]
+fn test_resp_errors_keep_the_connection() {
+    let dir = TempDir::new().unwrap();
+    let (addr, _db) = start_server(&dir);
+    let mut client = Client::connect(addr);
+
+    assert!(matches!(client.send(&[b"GET"]), Reply::Error(m) if m.contains("wrong number of arguments")));
+    assert!(matches!(client.send(&[b"FLUSHALL"]), Reply::Error(m) if m.contains("unknown command")));
+    assert!(matches!(client.send(&[b"SET", b"\xff", b"v"]), Reply::Error(m) if m.contains("UTF-8")));
+    assert_eq!(client.send(&[b"SET", b"k", b"v"]), Reply::Simple("OK".to_string()));
+    assert_eq!(client.send(&[b"QUIT"]), Reply::Simple("OK".to_string()));
+}
+
+#[test]
+fn test_resp_malformed_framing_closes_the_connection() {
+    let dir = TempDir::new().unwrap();
+    let (addr, _db) = start_server(&dir);
+    let mut client = Client::connect(addr);
+
+    client.writer.write_all(b"*1\r\n+GET\r\n").unwrap();
+    assert!(matches!(read_reply(&mut client.reader), Reply::Error(m) if m.contains("Protocol error")));
+    let mut rest = Vec::new();
+    client.reader.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_text_protocol_still_served_alongside_resp() {
+    let dir = TempDir::new().unwrap();
+    let (addr, _db) = start_server(&dir);
+    Client::connect(addr).send(&[b"SET", b"shared", b"value"]);
+
+    let mut text = Client::connect(addr);
+    writeln!(text.writer, "GET shared").unwrap();
+    let mut line = String::new();
+    text.reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "VALUE value\n");
+}
+
+#[test]
+fn test_read_command_decodes_pipelined_commands() {
+    let mut input = command(&[b"SET", b"k", b"a\r\nb"]);
+    input.extend(command(&[b"GET", b"k"]));
+    let mut reader = &input[..];
+    assert_eq!(read_command(&mut reader).unwrap(), Some(vec![b"SET".to_vec(), b"k".to_vec(), b"a\r\nb".to_vec()]));
+    assert_eq!(read_command(&mut reader).unwrap(), Some(vec![b"GET".to_vec(), b"k".to_vec()]));
+    assert_eq!(read_command(&mut reader).unwrap(), None);
+    assert!(read_command(&mut &b"*1\r\n$5\r\nab"[..]).is_err());
+}
+
+#[test]
+fn test_read_command_refuses_oversized_commands() {
+    // A claimed length alone is not trusted: the payload never arrives
+    let claimed = format!("*1\r\n${}\r\n", MAX_BULK_LEN);
+    assert!(read_command(&mut claimed.as_bytes()).is_err());
+
+    let too_long = format!("*1\r\n${}\r\n", MAX_BULK_LEN + 1);
+    assert!(matches!(read_command(&mut too_long.as_bytes()), Err(LohError::InvalidArgument(_))));
+    let too_many = format!("*{}\r\n", MAX_ARGS + 1);
+    assert!(matches!(read_command(&mut too_many.as_bytes()), Err(LohError::InvalidArgument(_))));
+}
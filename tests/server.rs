@@ -0,0 +1,99 @@
+mod common;
+
+use lohdb::server::{serve, MAX_LINE_BYTES};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tempfile::TempDir;
+
+fn start_server(dir: &TempDir) -> SocketAddr {
+    let db = Arc::new(Mutex::new(common::open(dir)));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || serve(listener, db));
+    addr
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        Self { writer: stream.try_clone().unwrap(), reader: BufReader::new(stream) }
+    }
+
+    fn send(&mut self, command: &str) -> String {
+        writeln!(self.writer, "{}", command).unwrap();
+        self.read_line()
+    }
+
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+}
+
+#[test]
+fn test_text_protocol_commands() {
+    let dir = TempDir::new().unwrap();
+    let mut client = Client::connect(start_server(&dir));
+
+    assert_eq!(client.send("SET greeting hello world"), "OK");
+    assert_eq!(client.send("get greeting"), "VALUE hello world");
+    assert_eq!(client.send("GET missing"), "NOT_FOUND");
+    assert_eq!(client.send("SET other 1"), "OK");
+    assert_eq!(client.send("KEYS"), "KEYS 2");
+    assert_eq!(client.read_line(), "greeting");
+    assert_eq!(client.read_line(), "other");
+    assert_eq!(client.send("DEL other"), "DELETED");
+    assert_eq!(client.send("DEL other"), "NOT_FOUND");
+    assert!(client.send("SET lonely").starts_with("ERR"));
+    assert!(client.send("FROB x").starts_with("ERR"));
+    assert_eq!(client.send("QUIT"), "BYE");
+}
+
+#[test]
+fn test_many_connections_share_one_database() {
+    let dir = TempDir::new().unwrap();
+    let addr = start_server(&dir);
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            thread::spawn(move || {
+                let mut client = Client::connect(addr);
+                for j in 0..20 {
+                    assert_eq!(client.send(&format!("SET k{}_{} v{}", i, j, j)), "OK");
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let mut client = Client::connect(addr);
+    assert_eq!(client.send("KEYS"), "KEYS 160");
+    for _ in 0..160 {
+        client.read_line();
+    }
+    assert_eq!(client.send("GET k7_19"), "VALUE v19");
+}
+
+#[test]
+fn test_overlong_line_closes_only_its_connection() {
+    let dir = TempDir::new().unwrap();
+    let addr = start_server(&dir);
+    let mut client = Client::connect(addr);
+
+    client.writer.write_all(&vec![b'x'; MAX_LINE_BYTES]).unwrap();
+    assert!(client.read_line().starts_with("ERR line longer than"));
+    assert_eq!(client.read_line(), "");
+
+    assert_eq!(Client::connect(addr).send("SET key value"), "OK");
+}
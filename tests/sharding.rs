@@ -0,0 +1,304 @@
+mod common;
+
+use lohdb::db::{InMemoryStorageEngine, ShardedStorageEngine};
+use lohdb::{Database, DatabaseConfig, LohError, Result, StorageEngine};
+use std::ops::Bound;
+use std::path::Path;
+use lohdb::OverflowPolicy;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn sharded_config(dir: &Path, shards: usize) -> DatabaseConfig {
+    common::builder(dir)
+        .shards(shards)
+        .build()
+        .unwrap()
+}
+
+/// In-memory engine counting its flushes
+struct CountingEngine {
+    inner: InMemoryStorageEngine,
+    flushes: Arc<AtomicUsize>,
+}
+
+impl StorageEngine for CountingEngine {
+    fn initialize(&mut self) -> Result<()> {
+        self.inner.initialize()
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.inner.store(key, value)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.retrieve(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        self.inner.remove(key)
+    }
+
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.inner.list_keys()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flushes.fetch_add(1, Ordering::SeqCst);
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn test_list_keys_returns_every_shard() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = sharded_config(temp_dir.path(), 4);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for i in 0..200 {
+            db.set(format!("key{:03}", i), format!("value{}", i).into_bytes()).unwrap();
+        }
+        db.namespace("users").unwrap().set("alice".to_string(), b"1".to_vec()).unwrap();
+        db.delete("key007").unwrap();
+
+        let keys = db.list_keys().unwrap();
+        assert_eq!(keys.len(), 199);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(db.key_count().unwrap(), 200);
+        assert_eq!(db.list_keys_paged(Some("key100"), 3).unwrap().0, vec!["key101", "key102", "key103"]);
+        db.checkpoint().unwrap();
+    }
+
+    // Each shard has a file of its own, and reopening reads them all back
+    for index in 0..4 {
+        assert!(temp_dir.path().join(format!("data.{}.db", index)).exists());
+    }
+    assert!(!temp_dir.path().join("data.db").exists());
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.list_keys().unwrap().len(), 199);
+    assert_eq!(db.get("key042").unwrap(), Some(b"value42".to_vec()));
+    assert_eq!(db.get("key007").unwrap(), None);
+    assert_eq!(db.namespace("users").unwrap().get("alice").unwrap(), Some(b"1".to_vec()));
+}
+
+#[test]
+fn test_shard_count_is_fixed_once_created() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(sharded_config(temp_dir.path(), 4)).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.close().unwrap();
+
+    for shards in [1, 8] {
+        let result = Database::open(sharded_config(temp_dir.path(), shards));
+        assert!(matches!(result, Err(LohError::InvalidArgument(msg)) if msg.contains("4 shard")));
+    }
+
+    let invalid = DatabaseConfig::builder().shards(0).build();
+    assert!(matches!(invalid, Err(LohError::InvalidArgument(_))));
+    let in_memory = DatabaseConfig::builder().in_memory(true).shards(2).build();
+    assert!(matches!(in_memory, Err(LohError::InvalidArgument(_))));
+}
+
+#[test]
+fn test_flush_skips_shards_not_written() {
+    let counters: Vec<Arc<AtomicUsize>> = (0..8).map(|_| Arc::default()).collect();
+    let shards = counters
+        .iter()
+        .map(|flushes| {
+            Box::new(CountingEngine { inner: InMemoryStorageEngine::new(), flushes: flushes.clone() }) as Box<dyn StorageEngine>
+        })
+        .collect();
+    let mut engine = ShardedStorageEngine::new(shards);
+    engine.initialize().unwrap();
+
+    engine.store("only", b"one").unwrap();
+    engine.flush().unwrap();
+    engine.flush().unwrap();
+    let written = engine.shard_for("only");
+    for (index, flushes) in counters.iter().enumerate() {
+        assert_eq!(flushes.load(Ordering::SeqCst), usize::from(index == written), "shard {}", index);
+    }
+
+    // Scans merge the shards back into one ordered key space
+    for i in 0..50 {
+        engine.store(&format!("k{:02}", i), b"v").unwrap();
+    }
+    assert_eq!(engine.scan_keys(Bound::Excluded("k10"), 3).unwrap(), vec!["k11", "k12", "k13"]);
+    assert_eq!(engine.scan_keys_rev(Bound::Unbounded, 2).unwrap(), vec!["only", "k49"]);
+    assert_eq!(engine.key_count().unwrap(), 51);
+    assert_eq!(engine.snapshot().unwrap().len(), 51);
+}
+
+/// Shared by the shards of a `GatedEngine` setup: once armed, a store of a
+/// key ending in "slow" reports it has started and waits to be released
+#[derive(Default)]
+struct Gate {
+    armed: AtomicBool,
+    entered: Mutex<Option<Sender<()>>>,
+    release: Mutex<Option<Receiver<()>>>,
+    /// Storage keys stored so far, with the shard each went to
+    stored: Mutex<Vec<(usize, String)>>,
+}
+
+struct GatedEngine {
+    index: usize,
+    inner: InMemoryStorageEngine,
+    gate: Arc<Gate>,
+}
+
+impl StorageEngine for GatedEngine {
+    fn initialize(&mut self) -> Result<()> {
+        self.inner.initialize()
+    }
+
+    fn store(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.gate.stored.lock().unwrap().push((self.index, key.to_string()));
+        if self.gate.armed.load(Ordering::SeqCst) && key.ends_with("slow") {
+            self.gate.entered.lock().unwrap().take().unwrap().send(()).unwrap();
+            self.gate.release.lock().unwrap().take().unwrap().recv().unwrap();
+        }
+        self.inner.store(key, value)
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.retrieve(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool> {
+        self.inner.remove(key)
+    }
+
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.inner.list_keys()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn test_writes_to_other_shards_go_ahead_of_a_slow_one() {
+    let gate = Arc::new(Gate::default());
+    let shards = (0..2)
+        .map(|index| {
+            Box::new(GatedEngine { index, inner: InMemoryStorageEngine::new(), gate: gate.clone() }) as Box<dyn StorageEngine>
+        })
+        .collect();
+    let mut db = Database::open(DatabaseConfig::builder().in_memory(true).build().unwrap()).unwrap();
+    db.migrate_to(ShardedStorageEngine::new(shards)).unwrap();
+    let (_handle, changes) = db.subscribe_sequenced(String::new(), OverflowPolicy::Block).unwrap();
+    let db = Arc::new(db);
+    let ns = db.namespace("hot").unwrap();
+
+    // Find a key in the other shard from the slow key
+    ns.set("slow".to_string(), b"0".to_vec()).unwrap();
+    for i in 0..8 {
+        ns.set(format!("fast{}", i), b"0".to_vec()).unwrap();
+    }
+    let stored = gate.stored.lock().unwrap().clone();
+    let shard_of = |key: &str| stored.iter().find(|(_, stored)| stored.ends_with(key)).unwrap().0;
+    let fast = (0..8).map(|i| format!("fast{}", i)).find(|key| shard_of(key) != shard_of("slow")).unwrap();
+    let mut logged = 0;
+    for _ in 0..9 {
+        logged = changes.recv().unwrap().seq;
+    }
+
+    let (entered_tx, entered) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel();
+    *gate.entered.lock().unwrap() = Some(entered_tx);
+    *gate.release.lock().unwrap() = Some(release_rx);
+    gate.armed.store(true, Ordering::SeqCst);
+    let slow = {
+        let db = db.clone();
+        thread::spawn(move || db.namespace("hot").unwrap().set("slow".to_string(), b"1".to_vec()).unwrap())
+    };
+    entered.recv().unwrap();
+
+    // The slow write holds its shard, not the WAL or the other shard
+    let (done_tx, done) = mpsc::channel();
+    let writer = {
+        let (db, fast) = (db.clone(), fast.clone());
+        thread::spawn(move || {
+            let ns = db.namespace("hot").unwrap();
+            ns.set(fast.clone(), b"1".to_vec()).unwrap();
+            done_tx.send(ns.get(&fast).unwrap()).unwrap();
+        })
+    };
+    assert_eq!(done.recv_timeout(Duration::from_secs(10)).unwrap(), Some(b"1".to_vec()));
+    writer.join().unwrap();
+
+    // Its event waits for the slow write's, which was logged first
+    assert!(changes.recv_timeout(Duration::from_millis(50)).is_err());
+    release.send(()).unwrap();
+    slow.join().unwrap();
+    let seqs: Vec<u64> = (0..2).map(|_| changes.recv().unwrap().seq).collect();
+    assert_eq!(seqs, vec![logged + 1, logged + 2]);
+    assert_eq!(ns.get("slow").unwrap(), Some(b"1".to_vec()));
+}
+
+/// Concurrent writers with a checkpoint every few writes over a large data
+/// set, unsharded and then sharded; run with
+/// `cargo test --release --test sharding -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_concurrent_writes_with_frequent_checkpoints() {
+    const KEYS: usize = 50_000;
+    const THREADS: usize = 4;
+    const WRITES_PER_THREAD: usize = 500;
+
+    for shards in [1, 16] {
+        let temp_dir = TempDir::new().unwrap();
+        let config = common::builder(&temp_dir)
+            .flush_every_n_writes(8)
+            .shards(shards)
+            .build()
+            .unwrap();
+        let mut db = Database::open(config).unwrap();
+        db.bulk_load((0..KEYS).map(|i| (format!("cold{}", i), vec![b'x'; 100]))).unwrap();
+        let db = Arc::new(db);
+
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let started = Instant::now();
+        let writers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let (db, barrier) = (db.clone(), barrier.clone());
+                thread::spawn(move || {
+                    let ns = db.namespace("hot").unwrap();
+                    barrier.wait();
+                    for i in 0..WRITES_PER_THREAD {
+                        ns.set(format!("counter{}", (thread + i) % 4), i.to_le_bytes().to_vec()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let elapsed = started.elapsed();
+        let writes = THREADS * WRITES_PER_THREAD;
+        println!(
+            "{:>2} shard(s): {} writes in {:?} ({:.0} writes/sec)",
+            shards, writes, elapsed, writes as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+#[test]
+fn test_backup_copies_every_shard() {
+    let temp_dir = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let mut db = Database::open(sharded_config(temp_dir.path(), 3)).unwrap();
+    for i in 0..30 {
+        db.set(format!("key{}", i), vec![i as u8]).unwrap();
+    }
+    db.backup_to(&backup_dir.path().to_string_lossy()).unwrap();
+
+    let restored = Database::open(sharded_config(backup_dir.path(), 3)).unwrap();
+    assert_eq!(restored.list_keys().unwrap().len(), 30);
+    assert_eq!(restored.get("key29").unwrap(), Some(vec![29]));
+}
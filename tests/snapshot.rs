@@ -0,0 +1,79 @@
+mod common;
+
+use lohdb::Database;
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+#[test]
+fn test_snapshot_is_unaffected_by_later_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.set("b".to_string(), b"2".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("a".to_string(), b"hidden".to_vec()).unwrap();
+
+    let snapshot = db.snapshot().unwrap();
+    assert_eq!(snapshot.seq(), 3);
+
+    db.set("a".to_string(), b"changed".to_vec()).unwrap();
+    db.delete("b").unwrap();
+    db.set("c".to_string(), b"3".to_vec()).unwrap();
+
+    assert_eq!(snapshot.get("a"), Some(&b"1"[..]));
+    assert_eq!(snapshot.get("b"), Some(&b"2"[..]));
+    assert_eq!(snapshot.get("c"), None);
+    let entries: Vec<_> = snapshot.iter().collect();
+    assert_eq!(entries, vec![("a", &b"1"[..]), ("b", &b"2"[..])]);
+
+    // The live database moved on
+    assert_eq!(db.get("a").unwrap(), Some(b"changed".to_vec()));
+    assert_eq!(db.list_keys().unwrap(), vec!["a".to_string(), "c".to_string()]);
+    assert_eq!(db.snapshot().unwrap().seq(), 6);
+}
+
+#[test]
+fn test_snapshot_prefix_scan() {
+    let mut db = Database::open_in_memory().unwrap();
+    for key in ["user:1", "user:2", "order:1", "userx"] {
+        db.set(key.to_string(), key.as_bytes().to_vec()).unwrap();
+    }
+
+    let snapshot = db.snapshot().unwrap();
+    let keys: Vec<_> = snapshot.scan("user:").map(|(k, _)| k).collect();
+    assert_eq!(keys, vec!["user:1", "user:2"]);
+    assert_eq!(snapshot.scan("missing").count(), 0);
+}
+
+#[test]
+fn test_writers_proceed_while_snapshot_is_held() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    for i in 0..100 {
+        db.set(format!("key{}", i), b"old".to_vec()).unwrap();
+    }
+    let db = Arc::new(db);
+    let snapshot = Arc::new(db.snapshot().unwrap());
+
+    // The writer never waits on the snapshot, however long it is read
+    let writer_db = db.clone();
+    let writer = thread::spawn(move || {
+        let ns = writer_db.namespace("w").unwrap();
+        for i in 0..200 {
+            ns.set(format!("key{}", i), b"new".to_vec()).unwrap();
+        }
+    });
+    let reader_snapshot = snapshot.clone();
+    let reader = thread::spawn(move || {
+        for _ in 0..50 {
+            assert_eq!(reader_snapshot.iter().count(), 100);
+            assert!(reader_snapshot.iter().all(|(_, v)| v == b"old"));
+        }
+    });
+    writer.join().unwrap();
+    reader.join().unwrap();
+
+    assert_eq!(db.namespace("w").unwrap().list_keys().unwrap().len(), 200);
+    assert_eq!(snapshot.seq(), 100);
+    assert_eq!(db.snapshot().unwrap().seq(), 300);
+}
@@ -0,0 +1,79 @@
+use lohdb::{Database, DatabaseConfig, SizeProfile};
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_stats_reports_counts_and_sizes() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(100)
+        .build()
+        .unwrap();
+
+    let mut db = Database::open(config).unwrap();
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.num_keys, 0);
+    assert_eq!(stats.wal_sequence, 0);
+    // A fresh log holds only its format header
+    let empty_wal_bytes = stats.wal_file_bytes;
+
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.set("b".to_string(), b"2".to_vec()).unwrap();
+    db.get("a").unwrap();
+    db.delete("b").unwrap();
+    db.flush().unwrap();
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.num_keys, 1);
+    assert_eq!((stats.sets, stats.gets, stats.deletes), (2, 1, 1));
+    assert!(stats.wal_file_bytes > empty_wal_bytes);
+    assert_eq!(stats.wal_sequence, 3);
+    assert!(stats.data_file_bytes > 0);
+}
+
+#[test]
+fn test_key_count_spans_namespaces() {
+    let mut db = Database::open_in_memory().unwrap();
+    assert_eq!(db.key_count().unwrap(), 0);
+
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.set("b".to_string(), b"2".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("a".to_string(), b"3".to_vec()).unwrap();
+    db.set("a".to_string(), b"overwrite".to_vec()).unwrap();
+    assert_eq!(db.key_count().unwrap(), 3);
+
+    db.delete("b").unwrap();
+    assert_eq!(db.key_count().unwrap(), 2);
+    assert_eq!(db.stats().unwrap().num_keys, 2);
+}
+
+#[test]
+fn test_size_profile_of_a_known_distribution() {
+    let mut db = Database::open_in_memory().unwrap();
+    assert_eq!(db.size_profile().unwrap(), SizeProfile::default());
+
+    // Values of 1 to 100 bytes under 4-byte keys
+    for i in 0..100 {
+        db.set(format!("k{:03}", i), vec![b'x'; i + 1]).unwrap();
+    }
+    let profile = db.size_profile().unwrap();
+    assert_eq!(profile.keys, 100);
+    assert_eq!((profile.key_bytes.min, profile.key_bytes.max, profile.key_bytes.total), (4, 4, 400));
+    let values = &profile.value_bytes;
+    assert_eq!((values.min, values.p50, values.p99, values.max, values.total), (1, 50, 99, 100, 5050));
+    assert_eq!(values.mean, 50.5);
+    let largest = profile.largest_value.unwrap();
+    assert_eq!((largest.namespace, largest.key.as_str(), largest.bytes), (None, "k099", 100));
+
+    // One outlier in a namespace stands out; expired keys are left out
+    db.namespace("blobs").unwrap().set("big".to_string(), vec![0; 10_000]).unwrap();
+    db.set_with_ttl("gone".to_string(), vec![0; 20_000], Duration::from_millis(1)).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    let profile = db.size_profile().unwrap();
+    assert_eq!(profile.keys, 101);
+    assert_eq!(profile.key_bytes.min, 3);
+    assert_eq!((profile.value_bytes.p50, profile.value_bytes.max), (51, 10_000));
+    let largest = profile.largest_value.unwrap();
+    assert_eq!((largest.namespace.as_deref(), largest.key.as_str()), (Some("blobs"), "big"));
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{ChangeEvent, Database, DatabaseConfig, LohError};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use tempfile::TempDir;
+
+const CHUNK: usize = 64;
+
+fn config_for(temp_dir: &TempDir) -> DatabaseConfig {
+    common::builder(temp_dir)
+        .stream_chunk_bytes(CHUNK)
+        .build()
+        .unwrap()
+}
+
+/// A value of `len` bytes that tells its chunks apart
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Files a writer has staged chunks in and not yet removed
+fn staged_files(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("stream-"))
+        .collect()
+}
+
+fn read_all(db: &Database, key: &str) -> Vec<u8> {
+    let mut reader = db.open_value_reader(key).unwrap();
+    let mut value = Vec::new();
+    // Small reads, to go through the value a piece at a time
+    let mut buf = [0u8; 10];
+    loop {
+        let n = reader.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        value.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(reader.remaining(), 0);
+    value
+}
+
+#[test]
+fn test_streams_a_value_larger_than_a_chunk() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = config_for(&temp_dir);
+    let value = pattern(CHUNK * 10 + 17);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        let mut writer = db.open_value_writer("blob").unwrap();
+        for piece in value.chunks(25) {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.len(), value.len() as u64);
+        assert_eq!(staged_files(temp_dir.path()).len(), 1);
+        assert_eq!(writer.finish().unwrap(), value.len() as u64);
+        assert!(staged_files(temp_dir.path()).is_empty());
+
+        assert_eq!(db.get("blob").unwrap(), Some(value.clone()));
+        assert_eq!(db.open_value_reader("blob").unwrap().len(), value.len() as u64);
+        assert_eq!(read_all(&db, "blob"), value);
+    }
+
+    // Logged as chunk-sized records and a commit, and replayed from them
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let operations: Vec<Operation> = wal.iter_entries().map(|e| e.unwrap().entry.operation).collect();
+    assert_eq!(operations.len(), 12);
+    for operation in &operations[..11] {
+        assert!(matches!(operation, Operation::StreamChunk { chunk, .. } if chunk.len() <= CHUNK));
+    }
+    assert!(matches!(&operations[11], Operation::StreamCommit { key, chunks: 11, .. } if key == "blob"));
+
+    let mut db = Database::open(config).unwrap();
+    assert_eq!(read_all(&db, "blob"), value);
+
+    // A reader keeps the value it opened
+    let mut reader = db.open_value_reader("blob").unwrap();
+    db.set("blob".to_string(), b"new".to_vec()).unwrap();
+    let mut held = Vec::new();
+    reader.read_to_end(&mut held).unwrap();
+    assert_eq!(held, value);
+}
+
+#[test]
+fn test_unfinished_stream_leaves_the_old_value() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = config_for(&temp_dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("blob".to_string(), b"old".to_vec()).unwrap();
+        let (_handle, events) = db.subscribe_channel().unwrap();
+
+        let mut writer = db.open_value_writer("blob").unwrap();
+        writer.write_all(&pattern(CHUNK * 3)).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert!(staged_files(temp_dir.path()).is_empty());
+
+        assert_eq!(db.get("blob").unwrap(), Some(b"old".to_vec()));
+        assert!(events.try_recv().is_err());
+
+        // A second stream, cut off by a "crash" with its chunks in the WAL
+        let mut writer = db.open_value_writer("blob").unwrap();
+        writer.write_all(&pattern(CHUNK * 2 + 5)).unwrap();
+        writer.flush().unwrap();
+        std::mem::forget(writer);
+    }
+    assert_eq!(staged_files(temp_dir.path()).len(), 1);
+
+    // Opening clears away what the cut-off writer staged
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("blob").unwrap(), Some(b"old".to_vec()));
+    assert_eq!(db.list_keys().unwrap(), vec!["blob".to_string()]);
+    assert!(staged_files(temp_dir.path()).is_empty());
+}
+
+#[test]
+fn test_in_memory_stream() {
+    let mut db = Database::open(DatabaseConfig::builder().in_memory(true).stream_chunk_bytes(CHUNK).build().unwrap()).unwrap();
+    let value = pattern(CHUNK * 3 + 1);
+    let mut writer = db.open_value_writer("blob").unwrap();
+    writer.write_all(&value).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(read_all(&db, "blob"), value);
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_staged_chunks_are_encrypted() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .stream_chunk_bytes(CHUNK)
+        .encryption_key([7; 32])
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    let value = vec![b's'; CHUNK * 3];
+
+    let mut writer = db.open_value_writer("secret").unwrap();
+    writer.write_all(&value).unwrap();
+    let staged = fs::read(temp_dir.path().join(&staged_files(temp_dir.path())[0])).unwrap();
+    assert!(!staged.windows(16).any(|window| window == &value[..16]));
+    writer.finish().unwrap();
+    assert_eq!(read_all(&db, "secret"), value);
+}
+
+#[test]
+fn test_checkpoint_during_a_stream_keeps_the_value() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .stream_chunk_bytes(CHUNK)
+        .flush_every_n_writes(1)
+        .build()
+        .unwrap();
+    let value = pattern(CHUNK * 4);
+
+    {
+        let db = Database::open(config.clone()).unwrap();
+        let files = db.namespace("files").unwrap();
+        let mut writer = files.open_value_writer("blob").unwrap();
+        writer.write_all(&value[..CHUNK * 2]).unwrap();
+
+        // Checkpoints after this write, releasing the chunks logged so far
+        db.namespace("other").unwrap().set("k".to_string(), b"v".to_vec()).unwrap();
+        assert!(db.stats().unwrap().wal_entries < 3);
+
+        writer.write_all(&value[CHUNK * 2..]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(files.get("blob").unwrap(), Some(value.clone()));
+    }
+
+    let db = Database::open(config).unwrap();
+    let files = db.namespace("files").unwrap();
+    let mut restored = Vec::new();
+    files.open_value_reader("blob").unwrap().read_to_end(&mut restored).unwrap();
+    assert_eq!(restored, value);
+}
+
+#[test]
+fn test_stream_publishes_one_set() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(config_for(&temp_dir)).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    let value = pattern(CHUNK * 2 + 1);
+
+    let mut writer = db.open_value_writer("blob").unwrap();
+    writer.write_all(&value).unwrap();
+    writer.finish().unwrap();
+    let writer = db.open_value_writer("empty").unwrap();
+    assert!(writer.is_empty());
+    assert_eq!(writer.finish().unwrap(), 0);
+
+    assert!(matches!(events.try_recv().unwrap(), ChangeEvent::Set { key, value: v, .. } if key == "blob" && v == value));
+    assert!(matches!(events.try_recv().unwrap(), ChangeEvent::Set { key, value: v, .. } if key == "empty" && v.is_empty()));
+    assert!(events.try_recv().is_err());
+    assert_eq!(db.get("empty").unwrap(), Some(Vec::new()));
+
+    let history = db.change_history().unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(matches!(&history[0].event, ChangeEvent::Set { value: v, .. } if *v == value));
+}
+
+#[test]
+fn test_stream_enforces_the_value_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .stream_chunk_bytes(CHUNK)
+        .max_value_bytes(CHUNK * 2)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+
+    let mut writer = db.open_value_writer("blob").unwrap();
+    writer.write_all(&pattern(CHUNK * 2)).unwrap();
+    let error = writer.write_all(&[0]).unwrap_err();
+    assert!(error.to_string().contains("exceeds the limit"), "{}", error);
+    drop(writer);
+    assert_eq!(db.get("blob").unwrap(), None);
+
+    assert!(matches!(db.open_value_reader("blob"), Err(LohError::NotFound(_))));
+}
+
+#[test]
+fn test_stream_chunk_bytes_must_be_positive() {
+    let result = DatabaseConfig::builder().data_dir("unused".to_string()).stream_chunk_bytes(0).build();
+    assert!(matches!(result, Err(LohError::InvalidArgument(_))));
+}
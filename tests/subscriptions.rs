@@ -0,0 +1,450 @@
+mod common;
+
+use lohdb::{ChangeEvent, Database, DatabaseConfig, EventFilter, LifecycleEvent, OverflowPolicy};
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_subscribe_channel_delivers_and_unsubscribes_on_drop() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    let (other_handle, _other_events) = db.subscribe_channel().unwrap();
+
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.delete("key").unwrap();
+
+    let timeout = Duration::from_secs(1);
+    assert!(matches!(events.recv_timeout(timeout).unwrap(), ChangeEvent::Set { key, .. } if key == "key"));
+    assert!(matches!(events.recv_timeout(timeout).unwrap(), ChangeEvent::Delete { .. }));
+    assert_eq!(db.stats().unwrap().subscribers, 2);
+
+    // Dropping the receiver of one and the handle of the other unsubscribes both
+    drop(events);
+    drop(other_handle);
+    db.set("key".to_string(), b"again".to_vec()).unwrap();
+    assert_eq!(db.stats().unwrap().subscribers, 0);
+}
+
+fn open_with_policy(dir: &TempDir, policy: OverflowPolicy) -> Database {
+    let config = common::builder(dir)
+        .subscriber_buffer(2)
+        .overflow_policy(policy)
+        .build()
+        .unwrap();
+    Database::open(config).unwrap()
+}
+
+fn set_keys(db: &mut Database, count: usize) {
+    for i in 0..count {
+        db.set(format!("k{}", i), vec![]).unwrap();
+    }
+}
+
+fn drain_keys(events: &crossbeam::channel::Receiver<ChangeEvent>) -> Vec<String> {
+    events
+        .try_iter()
+        .map(|event| match event {
+            ChangeEvent::Set { key, .. } => key,
+            other => panic!("unexpected event {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn test_bounded_buffer_overflow_policies() {
+    let dir = TempDir::new().unwrap();
+    let mut db = open_with_policy(&dir, OverflowPolicy::DropNewest);
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    set_keys(&mut db, 5);
+    assert_eq!(drain_keys(&events), vec!["k0", "k1"]);
+    assert_eq!(db.stats().unwrap().dropped_events, 3);
+    drop(db);
+
+    let dir = TempDir::new().unwrap();
+    let mut db = open_with_policy(&dir, OverflowPolicy::DropOldest);
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    set_keys(&mut db, 5);
+    assert_eq!(drain_keys(&events), vec!["k3", "k4"]);
+    assert_eq!(db.stats().unwrap().dropped_events, 3);
+}
+
+#[test]
+fn test_block_policy_delivers_everything() {
+    let dir = TempDir::new().unwrap();
+    let mut db = open_with_policy(&dir, OverflowPolicy::Block);
+    let (_handle, events) = db.subscribe_channel().unwrap();
+
+    let reader = std::thread::spawn(move || {
+        let mut seen = 0;
+        while seen < 20 {
+            events.recv_timeout(Duration::from_secs(5)).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+            seen += 1;
+        }
+        seen
+    });
+    set_keys(&mut db, 20);
+
+    assert_eq!(reader.join().unwrap(), 20);
+    assert_eq!(db.stats().unwrap().dropped_events, 0);
+}
+
+#[test]
+fn test_reliable_subscriber_beside_lossy_one() {
+    let dir = TempDir::new().unwrap();
+    let mut db = open_with_policy(&dir, OverflowPolicy::DropNewest);
+    let (lossy_handle, _lossy) = db.subscribe_channel().unwrap();
+    let (reliable_handle, reliable) = db.subscribe_channel_with_policy(String::new(), OverflowPolicy::Block).unwrap();
+
+    let reader = std::thread::spawn(move || {
+        let keys: Vec<_> = (0..20).map(|_| reliable.recv_timeout(Duration::from_secs(5)).unwrap().key().to_string()).collect();
+        keys.len()
+    });
+    set_keys(&mut db, 20);
+    assert_eq!(reader.join().unwrap(), 20);
+
+    // Only the lossy subscriber lost events, and the stats say which one
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.dropped_events, 18);
+    let lossy = stats.subscriber_stats.iter().find(|s| s.id == lossy_handle.id()).unwrap();
+    assert_eq!((lossy.queued, lossy.dropped_events), (2, 18));
+    let reliable = stats.subscriber_stats.iter().find(|s| s.id == reliable_handle.id()).unwrap();
+    assert_eq!(reliable.dropped_events, 0);
+}
+
+#[test]
+fn test_block_for_gives_up_on_a_stalled_subscriber() {
+    use lohdb::db::EventBus;
+
+    let mut bus = EventBus::with_capacity(1, OverflowPolicy::BlockFor(Duration::from_millis(20)));
+    let (_handle, events) = bus.subscribe_channel().unwrap();
+    let event = |key: &str| ChangeEvent::Delete { namespace: None, key: key.to_string() };
+
+    assert_eq!(bus.publish(event("a")).unwrap(), 0);
+    assert_eq!(bus.publish(event("b")).unwrap(), 1);
+    assert_eq!(bus.subscriber_stats()[0].dropped_events, 1);
+
+    events.recv().unwrap();
+    assert_eq!(bus.publish(event("c")).unwrap(), 0);
+    assert_eq!(events.recv().unwrap().key(), "c");
+}
+
+#[test]
+fn test_prefix_subscribers_only_see_matching_keys() {
+    use std::sync::{Arc, Mutex};
+
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+
+    let users = Arc::new(Mutex::new(Vec::new()));
+    let orders = Arc::new(Mutex::new(Vec::new()));
+    let all = Arc::new(Mutex::new(Vec::new()));
+    let (u, o, a) = (users.clone(), orders.clone(), all.clone());
+    let _users = db.subscribe_prefix("user:".to_string(), move |e| u.lock().unwrap().push(e.key().to_string())).unwrap();
+    let _orders = db.subscribe_prefix("order:".to_string(), move |e| o.lock().unwrap().push(e.key().to_string())).unwrap();
+    let _all = db.subscribe_prefix(String::new(), move |e| a.lock().unwrap().push(e.key().to_string())).unwrap();
+
+    db.set("user:1".to_string(), b"alice".to_vec()).unwrap();
+    db.set("order:1".to_string(), b"book".to_vec()).unwrap();
+    db.set("misc".to_string(), b"x".to_vec()).unwrap();
+    db.delete("user:1").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(*users.lock().unwrap(), vec!["user:1", "user:1"]);
+    assert_eq!(*orders.lock().unwrap(), vec!["order:1"]);
+    assert_eq!(all.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn test_lifecycle_events_report_flushes_and_checkpoints() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .wal_sync_interval_ms(20)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    let (_data_handle, data_events) = db.subscribe_channel().unwrap();
+    let (_lifecycle_handle, lifecycle_events) = db.subscribe_lifecycle();
+
+    for key in ["a", "b", "c"] {
+        db.set(key.to_string(), b"value".to_vec()).unwrap();
+    }
+
+    // The writes may be split across several background flushes
+    let mut flushed = 0;
+    while flushed < 3 {
+        match lifecycle_events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            LifecycleEvent::Flushed { wal_entries, bytes, .. } => {
+                assert!(bytes > 0);
+                flushed += wal_entries;
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    assert_eq!(flushed, 3);
+
+    db.checkpoint().unwrap();
+    let checkpointed = lifecycle_events
+        .iter()
+        .find(|event| matches!(event, LifecycleEvent::Checkpointed { .. }))
+        .unwrap();
+    assert!(matches!(checkpointed, LifecycleEvent::Checkpointed { through_seq: 3, .. }));
+
+    // Data subscribers only ever see the writes
+    let data: Vec<_> = data_events.try_iter().collect();
+    assert_eq!(data.len(), 3);
+    assert!(data.iter().all(|event| matches!(event, ChangeEvent::Set { .. })));
+}
+
+#[test]
+fn test_panicking_callback_keeps_its_subscription() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+
+    let (tx, rx) = crossbeam::channel::unbounded();
+    let _handle = db
+        .subscribe(move |event| {
+            if event.key() == "bad" {
+                panic!("cannot handle this event");
+            }
+            tx.send(event.key().to_string()).unwrap();
+        })
+        .unwrap();
+
+    db.set("before".to_string(), vec![]).unwrap();
+    db.set("bad".to_string(), vec![]).unwrap();
+    db.set("after".to_string(), vec![]).unwrap();
+
+    let timeout = Duration::from_secs(1);
+    assert_eq!(rx.recv_timeout(timeout).unwrap(), "before");
+    assert_eq!(rx.recv_timeout(timeout).unwrap(), "after");
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.subscribers, 1);
+    assert_eq!(stats.subscriber_stats[0].callback_panics, 1);
+}
+
+/// Subscribe in batches, forwarding each batch's keys to the returned receiver
+fn subscribe_batched_keys(
+    db: &mut Database,
+    max_batch: usize,
+    max_delay: Duration,
+) -> (lohdb::db::SubscriptionHandle, crossbeam::channel::Receiver<Vec<String>>) {
+    let (tx, rx) = crossbeam::channel::unbounded();
+    let handle = db
+        .subscribe_batched(max_batch, max_delay, move |batch| {
+            tx.send(batch.iter().map(|event| event.key().to_string()).collect()).unwrap();
+        })
+        .unwrap();
+    (handle, rx)
+}
+
+#[test]
+fn test_batched_subscriber_fills_batches_up_to_max_batch() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    // The delay is far longer than the test, so only the size bound applies
+    let (_handle, batches) = subscribe_batched_keys(&mut db, 4, Duration::from_secs(60));
+
+    set_keys(&mut db, 8);
+
+    let timeout = Duration::from_secs(5);
+    assert_eq!(batches.recv_timeout(timeout).unwrap(), vec!["k0", "k1", "k2", "k3"]);
+    assert_eq!(batches.recv_timeout(timeout).unwrap(), vec!["k4", "k5", "k6", "k7"]);
+}
+
+#[test]
+fn test_batched_subscriber_delivers_short_batch_after_max_delay() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    let max_delay = Duration::from_millis(100);
+    let (_handle, batches) = subscribe_batched_keys(&mut db, 100, max_delay);
+
+    let started = std::time::Instant::now();
+    set_keys(&mut db, 3);
+    let batch = batches.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(started.elapsed() >= max_delay);
+    assert_eq!(batch, vec!["k0", "k1", "k2"]);
+
+    // A later change starts a batch of its own
+    db.set("late".to_string(), vec![]).unwrap();
+    assert_eq!(batches.recv_timeout(Duration::from_secs(5)).unwrap(), vec!["late"]);
+}
+
+#[test]
+fn test_batched_subscriber_gets_partial_batch_on_close() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    let (_handle, batches) = subscribe_batched_keys(&mut db, 100, Duration::from_secs(60));
+
+    set_keys(&mut db, 2);
+    db.close().unwrap();
+    assert_eq!(batches.recv_timeout(Duration::from_secs(5)).unwrap(), vec!["k0", "k1"]);
+}
+
+#[test]
+fn test_batched_subscriber_rejects_empty_batches() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    assert!(db.subscribe_batched(0, Duration::from_millis(10), |_| {}).is_err());
+}
+
+#[test]
+fn test_sequenced_subscriber_sees_concurrent_writes_in_commit_order() {
+    const WRITERS: usize = 8;
+    const WRITES: usize = 200;
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    let (_handle, changes) = db.subscribe_sequenced(String::new(), OverflowPolicy::Block).unwrap();
+
+    // Drained as the writers go, so a full buffer holds them up instead of
+    // dropping anything
+    let reader = std::thread::spawn(move || {
+        (0..WRITERS * WRITES).map(|_| changes.recv_timeout(Duration::from_secs(10)).unwrap()).collect::<Vec<_>>()
+    });
+    std::thread::scope(|scope| {
+        for w in 0..WRITERS {
+            let ns = db.namespace(&format!("writer{}", w)).unwrap();
+            scope.spawn(move || {
+                for i in 0..WRITES {
+                    ns.set(format!("key{}", i % 10), i.to_string().into_bytes()).unwrap();
+                }
+            });
+        }
+    });
+
+    let received = reader.join().unwrap();
+    // One record per write, delivered in the order they were logged
+    let seqs: Vec<u64> = received.iter().map(|change| change.seq).collect();
+    assert_eq!(seqs, (1..=(WRITERS * WRITES) as u64).collect::<Vec<_>>());
+    assert!(received.windows(2).all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms));
+
+    // The same sequence the WAL holds
+    let history = db.change_history().unwrap();
+    assert_eq!(history.len(), received.len());
+    for (live, logged) in received.iter().zip(&history) {
+        assert_eq!(live.seq, logged.seq);
+        assert_eq!(live.event.key(), logged.event.key());
+        assert_eq!(live.event.namespace(), logged.event.namespace());
+    }
+}
+
+#[test]
+fn test_sequenced_events_of_one_record_share_its_seq() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    db.set("b".to_string(), b"2".to_vec()).unwrap();
+    let (_handle, changes) = db.subscribe_sequenced("k".to_string(), OverflowPolicy::Block).unwrap();
+
+    db.set("kept".to_string(), b"x".to_vec()).unwrap();
+    db.rename("kept", "k2", false).unwrap();
+    db.set("other".to_string(), b"y".to_vec()).unwrap();
+    db.delete("k2").unwrap();
+
+    let received: Vec<_> = changes.try_iter().collect();
+    let summary: Vec<(u64, &str)> = received.iter().map(|change| (change.seq, change.event.key())).collect();
+    // The rename logs one record and publishes two events; "other" is filtered out
+    assert_eq!(summary, vec![(3, "kept"), (4, "kept"), (4, "k2"), (6, "k2")]);
+}
+
+#[test]
+fn test_changes_after_backfills_a_gap() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+    let (handle, changes) = db.subscribe_sequenced(String::new(), OverflowPolicy::Block).unwrap();
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+    let last_seen = changes.recv().unwrap().seq;
+
+    // Missed while the subscriber was away
+    drop((handle, changes));
+    db.set("b".to_string(), b"2".to_vec()).unwrap();
+    db.delete("a").unwrap();
+
+    let backfill = db.changes_after(last_seen).unwrap();
+    let summary: Vec<(u64, &str)> = backfill.iter().map(|change| (change.seq, change.event.key())).collect();
+    assert_eq!(summary, vec![(2, "b"), (3, "a")]);
+    assert!(matches!(backfill[1].event, ChangeEvent::Delete { .. }));
+
+    // Once checkpointed, the WAL no longer reaches back
+    db.checkpoint().unwrap();
+    assert!(db.changes_after(last_seen).is_err());
+    assert!(db.changes_after(3).unwrap().is_empty());
+}
+
+#[test]
+fn test_delete_only_subscriber_never_sees_sets() {
+    let dir = TempDir::new().unwrap();
+    let mut db = common::open(&dir);
+
+    let (tx, deletes) = crossbeam::channel::unbounded();
+    let _deletes = db.subscribe_filtered(EventFilter::DELETE, move |event| tx.send(event).unwrap()).unwrap();
+    let (tx, removals) = crossbeam::channel::unbounded();
+    let _removals = db
+        .subscribe_prefix_filtered("user:".to_string(), EventFilter::REMOVALS | EventFilter::APPEND, move |event| {
+            tx.send(event).unwrap()
+        })
+        .unwrap();
+
+    db.set("user:1".to_string(), b"alice".to_vec()).unwrap();
+    db.set("user:2".to_string(), b"bob".to_vec()).unwrap();
+    db.append("user:log", b"login").unwrap();
+    db.delete("user:1").unwrap();
+    db.set("order:1".to_string(), b"book".to_vec()).unwrap();
+    db.delete("order:1").unwrap();
+    db.set("user:2".to_string(), b"robert".to_vec()).unwrap();
+
+    let timeout = Duration::from_secs(1);
+    let received: Vec<_> = (0..2).map(|_| deletes.recv_timeout(timeout).unwrap()).collect();
+    assert!(received.iter().all(|event| matches!(event, ChangeEvent::Delete { .. })));
+    assert_eq!(received.iter().map(ChangeEvent::key).collect::<Vec<_>>(), vec!["user:1", "order:1"]);
+
+    assert!(matches!(removals.recv_timeout(timeout).unwrap(), ChangeEvent::Append { key, .. } if key == "user:log"));
+    assert!(matches!(removals.recv_timeout(timeout).unwrap(), ChangeEvent::Delete { key, .. } if key == "user:1"));
+
+    // Filtered out before sending, so nothing else was ever queued
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(deletes.try_recv().is_err());
+    assert!(removals.try_recv().is_err());
+    assert!(db.stats().unwrap().subscriber_stats.iter().all(|stats| stats.queued == 0 && stats.dropped_events == 0));
+}
+
+#[test]
+fn test_event_filter_combines_kinds() {
+    let filter = EventFilter::SET | EventFilter::EXPIRED;
+    let set = ChangeEvent::Set { namespace: None, key: "k".to_string(), value: Vec::new(), version: 1 };
+    let expired = ChangeEvent::Expired { namespace: None, key: "k".to_string() };
+    let delete = ChangeEvent::Delete { namespace: None, key: "k".to_string() };
+
+    assert!(filter.matches(&set) && filter.matches(&expired) && !filter.matches(&delete));
+    assert!(EventFilter::ALL.contains(filter));
+    assert!(!filter.contains(EventFilter::REMOVALS));
+    assert_eq!(EventFilter::default(), EventFilter::ALL);
+}
+
+#[test]
+fn test_block_disconnects_a_stalled_subscriber() {
+    use lohdb::db::EventBus;
+
+    let mut bus = EventBus::with_capacity(1, OverflowPolicy::Block).with_block_timeout(Duration::from_millis(20));
+    let (_stalled_handle, stalled) = bus.subscribe_channel().unwrap();
+    let (_lossy_handle, lossy) = bus.subscribe_channel_with_policy(String::new(), OverflowPolicy::DropOldest).unwrap();
+    let event = |key: &str| ChangeEvent::Delete { namespace: None, key: key.to_string() };
+
+    assert_eq!(bus.publish(event("a")).unwrap(), 0);
+    let started = std::time::Instant::now();
+    assert_eq!(bus.publish(event("b")).unwrap(), 2);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(bus.subscriber_count(), 1);
+
+    // The stalled subscriber keeps what it had buffered, then its channel ends
+    assert_eq!(stalled.recv().unwrap().key(), "a");
+    assert!(stalled.recv().is_err());
+
+    // Later publishes no longer wait on it
+    assert_eq!(bus.publish(event("c")).unwrap(), 1);
+    assert_eq!(lossy.recv().unwrap().key(), "c");
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use lohdb::db::{FileStorageEngine, Operation, StorageEngine, WriteAheadLog};
+use lohdb::{Database, DatabaseConfig, FlushMode};
+use tempfile::TempDir;
+
+#[test]
+fn test_sync_writes_data_file_immediately() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    let mut db = Database::open(config.clone()).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.namespace("ns").unwrap().set("other".to_string(), b"more".to_vec()).unwrap();
+
+    db.sync().unwrap();
+
+    // The data file alone holds the writes, without replaying the WAL
+    let mut engine = FileStorageEngine::new(temp_dir.path().to_string_lossy().to_string());
+    engine.initialize().unwrap();
+    assert_eq!(engine.retrieve("key").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(engine.key_count().unwrap(), 2);
+
+    // A read-only open sees them while the writer is still open
+    let reader = Database::open(DatabaseConfig { read_only: true, ..config }).unwrap();
+    assert_eq!(reader.get("key").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(reader.namespace("ns").unwrap().get("other").unwrap(), Some(b"more".to_vec()));
+
+    // Unlike a checkpoint, the WAL is kept
+    assert_eq!(db.stats().unwrap().wal_sequence, 2);
+    assert!(db.stats().unwrap().wal_file_bytes > 0);
+}
+
+#[test]
+fn test_sync_rejected_when_read_only_and_noop_in_memory() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    drop(Database::open(config.clone()).unwrap());
+
+    let mut reader = Database::open(DatabaseConfig { read_only: true, ..config }).unwrap();
+    assert!(reader.sync().is_err());
+
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    db.sync().unwrap();
+}
+
+/// Keys the data file alone holds, without replaying the WAL
+fn keys_in_data_file(temp_dir: &TempDir) -> Vec<String> {
+    let mut engine = FileStorageEngine::new(temp_dir.path().to_string_lossy().to_string());
+    engine.initialize().unwrap();
+    engine.list_keys().unwrap()
+}
+
+/// Keys set by the records on disk in the WAL
+fn keys_in_wal(temp_dir: &TempDir) -> Vec<String> {
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    wal.iter_entries()
+        .filter_map(|e| match e.unwrap().entry.operation {
+            Operation::Set { key, .. } => Some(key),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_flush_modes_make_different_files_durable() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .wal_buffer_bytes(1024 * 1024)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config.clone()).unwrap();
+
+    // Snapshot: the data file has the key, the WAL still buffers its record
+    db.set("snapshot".to_string(), b"1".to_vec()).unwrap();
+    db.flush_with(FlushMode::Snapshot).unwrap();
+    assert_eq!(keys_in_data_file(&temp_dir), vec!["snapshot".to_string()]);
+    assert!(keys_in_wal(&temp_dir).is_empty());
+    assert_eq!(db.unsynced_count(), 1);
+
+    // Wal: the record reaches the log, the data file is left as it was
+    db.set("wal".to_string(), b"2".to_vec()).unwrap();
+    db.flush_with(FlushMode::Wal).unwrap();
+    assert_eq!(keys_in_wal(&temp_dir), vec!["snapshot".to_string(), "wal".to_string()]);
+    assert_eq!(keys_in_data_file(&temp_dir), vec!["snapshot".to_string()]);
+    assert_eq!(db.unsynced_count(), 0);
+
+    // Both: each file has every write
+    db.set("both".to_string(), b"3".to_vec()).unwrap();
+    db.flush_with(FlushMode::Both).unwrap();
+    assert_eq!(keys_in_wal(&temp_dir).len(), 3);
+    assert_eq!(keys_in_data_file(&temp_dir).len(), 3);
+
+    // A reopen replays the WAL over the data file
+    drop(db);
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("wal").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(db.key_count().unwrap(), 3);
+}
+
+#[test]
+fn test_flush_with_rejected_when_read_only_and_noop_in_memory() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    drop(Database::open(config.clone()).unwrap());
+
+    let mut reader = Database::open(DatabaseConfig { read_only: true, ..config }).unwrap();
+    for mode in [FlushMode::Snapshot, FlushMode::Wal, FlushMode::Both] {
+        assert!(reader.flush_with(mode).is_err());
+    }
+
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    for mode in [FlushMode::Snapshot, FlushMode::Wal, FlushMode::Both] {
+        db.flush_with(mode).unwrap();
+    }
+}
+
+#[test]
+fn test_sync_drains_unsynced_count() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    assert_eq!(db.unsynced_count(), 0);
+
+    for i in 0..10 {
+        db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+    }
+    assert_eq!(db.unsynced_count(), 10);
+    assert_eq!(db.stats().unwrap().unsynced_count, 10);
+
+    db.sync().unwrap();
+    assert_eq!(db.unsynced_count(), 0);
+    assert_eq!(db.stats().unwrap().unsynced_count, 0);
+
+    db.delete("key0").unwrap();
+    assert_eq!(db.unsynced_count(), 1);
+    db.checkpoint().unwrap();
+    assert_eq!(db.unsynced_count(), 0);
+}
+
+#[test]
+fn test_commit_batch_bounds_unsynced_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .wal_commit_batch(4)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+
+    // The fourth write of each batch syncs before returning
+    let mut seen = Vec::new();
+    for i in 0..10 {
+        db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+        seen.push(db.unsynced_count());
+    }
+    assert_eq!(seen, vec![1, 2, 3, 0, 1, 2, 3, 0, 1, 2]);
+
+    db.sync().unwrap();
+    assert_eq!(db.unsynced_count(), 0);
+}
+
+#[test]
+fn test_unsynced_count_with_group_commit_and_in_memory() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .group_commit(true)
+        .build()
+        .unwrap();
+    let mut db = Database::open(config).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(db.unsynced_count(), 0);
+
+    let mut db = Database::open_in_memory().unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(db.unsynced_count(), 0);
+}
+
+fn data_file_keys(temp_dir: &TempDir) -> usize {
+    let mut engine = FileStorageEngine::new(temp_dir.path().to_string_lossy().to_string());
+    engine.initialize().unwrap();
+    engine.key_count().unwrap()
+}
+
+#[test]
+fn test_flush_every_n_writes_checkpoints_on_the_nth_write() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig { flush_every_n_writes: Some(5), ..common::config(&temp_dir) };
+    let mut db = Database::open(config).unwrap();
+
+    for i in 0..4 {
+        db.set(format!("key{}", i), b"value".to_vec()).unwrap();
+    }
+    assert_eq!(data_file_keys(&temp_dir), 0);
+    assert_eq!(db.stats().unwrap().wal_entries, 4);
+
+    // The fifth write returns with all five in the data file
+    db.set("key4".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(data_file_keys(&temp_dir), 5);
+    assert_eq!(db.stats().unwrap().wal_entries, 0);
+
+    // Deletes count too, and the next checkpoint comes five writes later
+    for i in 0..4 {
+        db.delete(&format!("key{}", i)).unwrap();
+    }
+    assert_eq!(data_file_keys(&temp_dir), 5);
+    db.set("key5".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(data_file_keys(&temp_dir), 2);
+}
+
+#[test]
+fn test_flush_every_n_writes_under_concurrent_writers() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DatabaseConfig { flush_every_n_writes: Some(10), ..common::config(&temp_dir) };
+    let db = std::sync::Arc::new(std::sync::Mutex::new(Database::open(config).unwrap()));
+    let (_handle, events) = db.lock().unwrap().subscribe_lifecycle();
+
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                for i in 0..25 {
+                    db.lock().unwrap().set(format!("t{}-{}", t, i), b"v".to_vec()).unwrap();
+                }
+            })
+        })
+        .collect();
+    writers.into_iter().for_each(|writer| writer.join().unwrap());
+
+    // 100 writes, one checkpoint per 10 of them
+    let checkpoints = events
+        .try_iter()
+        .filter(|event| matches!(event, lohdb::LifecycleEvent::Checkpointed { .. }))
+        .count();
+    assert_eq!(checkpoints, 10);
+    assert_eq!(data_file_keys(&temp_dir), 100);
+}
+
+#[test]
+fn test_flush_every_n_writes_must_be_positive() {
+    assert!(DatabaseConfig::builder().flush_every_n_writes(0).build().is_err());
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use lohdb::db::ChangeEvent;
+use lohdb::Database;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use tempfile::TempDir;
+
+#[test]
+fn test_take_returns_and_removes_the_value() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("job:1".to_string(), b"resize".to_vec()).unwrap();
+        db.set("job:2".to_string(), b"encode".to_vec()).unwrap();
+        let (_handle, events) = db.subscribe_channel().unwrap();
+
+        assert_eq!(db.take("job:1").unwrap(), Some(b"resize".to_vec()));
+        assert_eq!(db.take("job:1").unwrap(), None);
+        assert_eq!(db.get("job:1").unwrap(), None);
+
+        // Only the successful take was logged and published
+        assert_eq!(db.stats().unwrap().wal_sequence, 3);
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(&received[0], ChangeEvent::Delete { key, .. } if key == "job:1"));
+    }
+
+    // The delete was logged and survives a restart
+    let mut db = Database::open(config).unwrap();
+    assert_eq!(db.get("job:1").unwrap(), None);
+    assert_eq!(db.take("job:2").unwrap(), Some(b"encode".to_vec()));
+}
+
+#[test]
+fn test_concurrent_takers_get_the_value_once() {
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 20;
+    let temp_dir = TempDir::new().unwrap();
+    let db = Arc::new(common::open(&temp_dir));
+
+    for round in 0..ROUNDS {
+        let ns = db.namespace("queue").unwrap();
+        ns.set("item".to_string(), format!("payload{}", round).into_bytes()).unwrap();
+
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let (db, barrier) = (db.clone(), barrier.clone());
+                thread::spawn(move || {
+                    let ns = db.namespace("queue").unwrap();
+                    barrier.wait();
+                    ns.take("item").unwrap()
+                })
+            })
+            .collect();
+        let taken: Vec<Vec<u8>> = handles.into_iter().filter_map(|h| h.join().unwrap()).collect();
+        assert_eq!(taken, vec![format!("payload{}", round).into_bytes()]);
+    }
+    assert_eq!(db.key_count().unwrap(), 0);
+}
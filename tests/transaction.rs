@@ -0,0 +1,160 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{ChangeEvent, Database, LohError};
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+/// Add `amount` to the number stored at `key` inside `tx`
+fn increment(tx: &mut lohdb::Transaction<'_>, key: &str, amount: u64) {
+    let current = tx.get(key).map_or(0, |v| String::from_utf8(v).unwrap().parse::<u64>().unwrap());
+    tx.set(key.to_string(), (current + amount).to_string().into_bytes()).unwrap();
+}
+
+#[test]
+fn test_conflicting_transactions_exactly_one_commits() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("counter".to_string(), b"10".to_vec()).unwrap();
+
+    let mut first = db.begin().unwrap();
+    let mut second = db.begin().unwrap();
+    increment(&mut first, "counter", 1);
+    increment(&mut second, "counter", 5);
+
+    first.commit().unwrap();
+    assert!(matches!(second.commit(), Err(LohError::Conflict(_))));
+    assert_eq!(db.get("counter").unwrap(), Some(b"11".to_vec()));
+
+    // Retrying from a fresh transaction sees the first commit and succeeds
+    let mut retry = db.begin().unwrap();
+    increment(&mut retry, "counter", 5);
+    retry.commit().unwrap();
+    assert_eq!(db.get("counter").unwrap(), Some(b"16".to_vec()));
+}
+
+#[test]
+fn test_transaction_reads_its_snapshot_and_its_own_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("a".to_string(), b"1".to_vec()).unwrap();
+
+    let mut tx = db.begin().unwrap();
+    // Writes committed after `begin` are invisible to the transaction
+    let mut other = db.begin().unwrap();
+    other.set("b".to_string(), b"2".to_vec()).unwrap();
+    other.commit().unwrap();
+    assert_eq!(tx.get("b"), None);
+
+    tx.set("c".to_string(), b"3".to_vec()).unwrap();
+    tx.delete("a").unwrap();
+    assert_eq!(tx.get("c"), Some(b"3".to_vec()));
+    assert_eq!(tx.get("a"), None);
+    // Nothing is written until commit
+    assert_eq!(db.get("c").unwrap(), None);
+    assert_eq!(db.get("a").unwrap(), Some(b"1".to_vec()));
+}
+
+#[test]
+fn test_scan_conflicts_on_new_keys_in_range() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("job:1".to_string(), b"queued".to_vec()).unwrap();
+
+    let mut tx = db.begin().unwrap();
+    tx.set("job:2".to_string(), b"queued".to_vec()).unwrap();
+    let jobs: Vec<String> = tx.scan("job:").into_iter().map(|(k, _)| k).collect();
+    assert_eq!(jobs, vec!["job:1".to_string(), "job:2".to_string()]);
+    tx.set("jobs:count".to_string(), jobs.len().to_string().into_bytes()).unwrap();
+
+    let mut other = db.begin().unwrap();
+    other.set("job:3".to_string(), b"queued".to_vec()).unwrap();
+    other.commit().unwrap();
+
+    assert!(matches!(tx.commit(), Err(LohError::Conflict(_))));
+    assert_eq!(db.get("jobs:count").unwrap(), None);
+}
+
+#[test]
+fn test_transactions_from_threads_sharing_a_database() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    db.set("counter".to_string(), b"0".to_vec()).unwrap();
+    let db = Arc::new(db);
+
+    // Each thread retries on conflict, so every increment lands exactly once
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let db = db.clone();
+            thread::spawn(move || {
+                for _ in 0..25 {
+                    loop {
+                        let mut tx = db.begin().unwrap();
+                        increment(&mut tx, "counter", 1);
+                        match tx.commit() {
+                            Ok(()) => break,
+                            Err(LohError::Conflict(_)) => continue,
+                            Err(e) => panic!("unexpected error: {}", e),
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in threads {
+        handle.join().unwrap();
+    }
+    assert_eq!(db.get("counter").unwrap(), Some(b"100".to_vec()));
+}
+
+#[test]
+fn test_blind_writes_do_not_conflict() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = common::open(&temp_dir);
+
+    let mut first = db.begin().unwrap();
+    let mut second = db.begin().unwrap();
+    first.set("key".to_string(), b"first".to_vec()).unwrap();
+    second.set("key".to_string(), b"second".to_vec()).unwrap();
+    first.commit().unwrap();
+    second.commit().unwrap();
+    assert_eq!(db.get("key").unwrap(), Some(b"second".to_vec()));
+}
+
+#[test]
+fn test_commit_is_one_wal_record_and_survives_recovery() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("from".to_string(), b"100".to_vec()).unwrap();
+        db.set("to".to_string(), b"0".to_vec()).unwrap();
+
+        let (_subscription, events) = db.subscribe_channel().unwrap();
+
+        let mut tx = db.begin().unwrap();
+        tx.get("from");
+        tx.get("to");
+        tx.set("from".to_string(), b"60".to_vec()).unwrap();
+        tx.set("to".to_string(), b"40".to_vec()).unwrap();
+        tx.delete("missing").unwrap();
+        tx.commit().unwrap();
+
+        let published: Vec<ChangeEvent> = events.try_iter().collect();
+        assert_eq!(published.len(), 2);
+        assert!(published.iter().all(|e| matches!(e, ChangeEvent::Set { .. })));
+    } // Dropped without a checkpoint, so the commit comes back from the WAL
+
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let last = wal.iter_entries().last().unwrap().unwrap().entry;
+    assert_eq!(last.seq, 3);
+    // The delete of a key that never existed is left out
+    assert!(matches!(&last.operation, Operation::Batch { ops } if ops.len() == 2));
+    drop(wal);
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("from").unwrap(), Some(b"60".to_vec()));
+    assert_eq!(db.get("to").unwrap(), Some(b"40".to_vec()));
+    assert_eq!(db.change_history().unwrap().len(), 4);
+}
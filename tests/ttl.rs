@@ -0,0 +1,240 @@
+mod common;
+
+use lohdb::db::{Clock, MockClock};
+use lohdb::{ChangeEvent, Database, DatabaseConfig, LohError};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Where every mock clock starts, in ms since the Unix epoch
+const START_MS: u64 = 1_700_000_000_000;
+
+fn config(temp_dir: &TempDir, sweep_interval_ms: u64, clock: &Arc<MockClock>) -> DatabaseConfig {
+    common::builder(temp_dir)
+        .ttl_sweep_interval_ms(sweep_interval_ms)
+        .clock(clock.clone())
+        .build()
+        .unwrap()
+}
+
+fn mock_clock() -> Arc<MockClock> {
+    Arc::new(MockClock::at_ms(START_MS))
+}
+
+#[test]
+fn test_expired_keys_read_as_absent_until_expire_now_removes_them() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+
+    db.set_with_ttl("session".to_string(), b"abc".to_vec(), Duration::from_millis(50)).unwrap();
+    db.set("user".to_string(), b"alice".to_vec()).unwrap();
+    assert_eq!(db.get("session").unwrap(), Some(b"abc".to_vec()));
+    assert_eq!(db.expire_now().unwrap(), 0);
+
+    clock.advance(Duration::from_millis(80));
+    assert_eq!(db.get("session").unwrap(), None);
+    assert!(db.get_with_metadata("session").unwrap().is_none());
+    // Still stored until swept
+    assert_eq!(db.list_keys().unwrap(), vec!["session".to_string(), "user".to_string()]);
+
+    assert_eq!(db.expire_now().unwrap(), 1);
+    assert_eq!(db.expire_now().unwrap(), 0);
+    assert_eq!(db.list_keys().unwrap(), vec!["user".to_string()]);
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.expired_keys, 1);
+    assert_eq!(stats.wal_sequence, 3);
+
+    let events: Vec<_> = events.try_iter().collect();
+    assert!(matches!(events.last(), Some(ChangeEvent::Expired { key, .. }) if key == "session"));
+    assert!(!events.iter().any(|event| matches!(event, ChangeEvent::Delete { .. })));
+}
+
+#[test]
+fn test_expiry_survives_restart_and_checkpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+
+    {
+        let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+        db.set_with_ttl("logged".to_string(), b"1".to_vec(), Duration::from_millis(100)).unwrap();
+        db.set_with_ttl("forever".to_string(), b"2".to_vec(), Duration::from_millis(100)).unwrap();
+        // A plain set makes the key permanent again
+        db.set("forever".to_string(), b"3".to_vec()).unwrap();
+        db.checkpoint().unwrap();
+        db.set_with_ttl("in_wal".to_string(), b"4".to_vec(), Duration::from_millis(100)).unwrap();
+    }
+
+    clock.advance(Duration::from_millis(150));
+    let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+    assert_eq!(db.get("logged").unwrap(), None);
+    assert_eq!(db.get("in_wal").unwrap(), None);
+    assert_eq!(db.expire_now().unwrap(), 2);
+    assert_eq!(db.list_keys().unwrap(), vec!["forever".to_string()]);
+    drop(db);
+
+    // The sweep's deletes are logged, so they hold after another restart
+    let db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+    assert_eq!(db.list_keys().unwrap(), vec!["forever".to_string()]);
+}
+
+#[test]
+fn test_expire_now_alongside_background_sweep_removes_each_key_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    let mut db = Database::open(config(&temp_dir, 1, &clock)).unwrap();
+    for i in 0..200 {
+        db.set_with_ttl(format!("key{}", i), vec![b'v'; 8], Duration::from_millis(30)).unwrap();
+    }
+    clock.advance(Duration::from_millis(30));
+
+    let mut removed_here = 0;
+    while !db.list_keys().unwrap().is_empty() {
+        removed_here += db.expire_now().unwrap();
+    }
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.expired_keys, 200);
+    assert!(removed_here <= 200);
+    // One Delete record per key, however the work was split
+    assert_eq!(stats.wal_sequence, 400);
+}
+
+#[test]
+fn test_zero_ttl_rejected() {
+    let mut db = Database::open_in_memory().unwrap();
+    assert!(db.set_with_ttl("key".to_string(), vec![], Duration::ZERO).is_err());
+    assert_eq!(db.list_keys().unwrap().len(), 0);
+}
+
+#[test]
+fn test_ttl_and_persist_transitions() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+
+    db.set("plain".to_string(), b"1".to_vec()).unwrap();
+    db.set_with_ttl("session".to_string(), b"2".to_vec(), Duration::from_secs(60)).unwrap();
+    db.set_with_ttl("short".to_string(), b"3".to_vec(), Duration::from_millis(50)).unwrap();
+
+    // Missing keys are errors; keys without an expiry have no TTL
+    assert!(matches!(db.ttl("missing"), Err(LohError::NotFound(_))));
+    assert_eq!(db.ttl("plain").unwrap(), None);
+    assert_eq!(db.ttl("session").unwrap(), Some(Duration::from_secs(60)));
+
+    // Expired but not yet swept reads as missing, and cannot be persisted
+    clock.advance(Duration::from_millis(80));
+    assert!(matches!(db.ttl("short"), Err(LohError::NotFound(_))));
+    assert!(!db.persist("short").unwrap());
+    assert!(!db.persist("plain").unwrap());
+    assert!(!db.persist("missing").unwrap());
+
+    let before = db.stats().unwrap().wal_sequence;
+    assert!(db.persist("session").unwrap());
+    assert_eq!(db.ttl("session").unwrap(), None);
+    assert_eq!(db.get("session").unwrap(), Some(b"2".to_vec()));
+    assert!(!db.persist("session").unwrap());
+    // One record for the effective persist, none for the others
+    assert_eq!(db.stats().unwrap().wal_sequence, before + 1);
+
+    // Persisting changes no value, so subscribers only saw the three sets
+    assert_eq!(events.try_iter().count(), 3);
+
+    // A later set with a TTL makes the key expire again
+    db.set_with_ttl("session".to_string(), b"4".to_vec(), Duration::from_secs(5)).unwrap();
+    assert!(db.ttl("session").unwrap().is_some());
+}
+
+#[test]
+fn test_persist_survives_restart_and_checkpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    {
+        let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+        db.set_with_ttl("replayed".to_string(), b"1".to_vec(), Duration::from_millis(100)).unwrap();
+        db.set_with_ttl("flushed".to_string(), b"2".to_vec(), Duration::from_millis(100)).unwrap();
+        assert!(db.persist("flushed").unwrap());
+        db.checkpoint().unwrap();
+        assert!(db.persist("replayed").unwrap());
+    } // Dropped without a checkpoint, so the second persist comes back from the WAL
+
+    clock.advance(Duration::from_millis(150));
+    let db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+    assert_eq!(db.ttl("replayed").unwrap(), None);
+    assert_eq!(db.ttl("flushed").unwrap(), None);
+    assert_eq!(db.get("replayed").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(db.get("flushed").unwrap(), Some(b"2".to_vec()));
+}
+
+#[test]
+fn test_expiry_is_reported_apart_from_deletes() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+
+    db.set_with_ttl("expiring".to_string(), b"1".to_vec(), Duration::from_millis(30)).unwrap();
+    db.set_with_ttl("overwritten".to_string(), b"2".to_vec(), Duration::from_millis(30)).unwrap();
+    db.set("deleted".to_string(), b"3".to_vec()).unwrap();
+    clock.advance(Duration::from_millis(60));
+
+    // Past its expiry but not yet swept, the fresh value must not be reported
+    db.set("overwritten".to_string(), b"fresh".to_vec()).unwrap();
+    db.delete("deleted").unwrap();
+    assert_eq!(db.expire_now().unwrap(), 1);
+    assert_eq!(db.get("overwritten").unwrap(), Some(b"fresh".to_vec()));
+
+    let removals: Vec<_> = events
+        .try_iter()
+        .filter(|event| !matches!(event, ChangeEvent::Set { .. }))
+        .collect();
+    assert_eq!(removals.len(), 2);
+    assert!(matches!(&removals[0], ChangeEvent::Delete { key, .. } if key == "deleted"));
+    assert!(matches!(&removals[1], ChangeEvent::Expired { namespace: None, key } if key == "expiring"));
+
+    // The log keeps the difference for anyone replaying it
+    let history: Vec<_> = db.change_history().unwrap().into_iter().map(|change| change.event).collect();
+    assert!(matches!(history.last(), Some(ChangeEvent::Expired { key, .. }) if key == "expiring"));
+}
+
+#[test]
+fn test_background_sweep_publishes_expired() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    let mut db = Database::open(config(&temp_dir, 5, &clock)).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+
+    db.set_with_ttl("page".to_string(), b"html".to_vec(), Duration::from_millis(20)).unwrap();
+    clock.advance(Duration::from_millis(20));
+
+    let event = (0..50)
+        .filter_map(|_| events.recv_timeout(Duration::from_millis(100)).ok())
+        .find(|event| !matches!(event, ChangeEvent::Set { .. }))
+        .unwrap();
+    assert!(matches!(event, ChangeEvent::Expired { namespace: None, key } if key == "page"));
+}
+
+#[test]
+fn test_mock_clock_expires_a_key_exactly_on_time() {
+    let temp_dir = TempDir::new().unwrap();
+    let clock = mock_clock();
+    let mut db = Database::open(config(&temp_dir, 0, &clock)).unwrap();
+
+    db.set_with_ttl("session".to_string(), b"abc".to_vec(), Duration::from_secs(1)).unwrap();
+    // Timestamps come from the same clock
+    let last_modified = db.get_with_metadata("session").unwrap().unwrap().last_modified;
+    assert_eq!(last_modified, clock.now());
+
+    clock.advance(Duration::from_millis(999));
+    assert_eq!(db.ttl("session").unwrap(), Some(Duration::from_millis(1)));
+    assert_eq!(db.get("session").unwrap(), Some(b"abc".to_vec()));
+    assert_eq!(db.expire_now().unwrap(), 0);
+
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(db.get("session").unwrap(), None);
+    assert!(matches!(db.ttl("session"), Err(LohError::NotFound(_))));
+    assert_eq!(db.expire_now().unwrap(), 1);
+    assert!(db.list_keys().unwrap().is_empty());
+}
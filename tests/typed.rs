@@ -0,0 +1,83 @@
+mod common;
+
+use lohdb::{Database, LohError, SerializationFormat};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    id: u64,
+    name: String,
+    tags: Vec<String>,
+}
+
+fn user() -> User {
+    User { id: 7, name: "ada".to_string(), tags: vec!["admin".to_string()] }
+}
+
+fn open_db(temp_dir: &TempDir, format: SerializationFormat) -> Database {
+    let config = common::builder(temp_dir)
+        .serialization_format(format)
+        .build()
+        .unwrap();
+    Database::open(config).unwrap()
+}
+
+#[test]
+fn test_typed_values_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open_db(&temp_dir, SerializationFormat::Bincode);
+
+    db.set_typed("user:7", &user()).unwrap();
+    db.set_typed("count", &42u32).unwrap();
+    assert_eq!(db.get_typed::<User>("user:7").unwrap(), Some(user()));
+    assert_eq!(db.get_typed::<u32>("count").unwrap(), Some(42));
+    assert_eq!(db.get_typed::<User>("missing").unwrap(), None);
+
+    // Typed values are ordinary bytes underneath, and survive a reopen
+    assert_eq!(db.get("count").unwrap(), Some(42u32.to_le_bytes().to_vec()));
+    drop(db);
+    let db = open_db(&temp_dir, SerializationFormat::Bincode);
+    assert_eq!(db.get_typed::<User>("user:7").unwrap(), Some(user()));
+    assert_eq!(db.namespace("ns").unwrap().get_typed::<User>("user:7").unwrap(), None);
+}
+
+#[test]
+fn test_typed_values_use_the_configured_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open_db(&temp_dir, SerializationFormat::Json);
+
+    db.set_typed("user:7", &user()).unwrap();
+    assert_eq!(
+        db.get("user:7").unwrap(),
+        Some(br#"{"id":7,"name":"ada","tags":["admin"]}"#.to_vec())
+    );
+    assert_eq!(db.get_typed::<User>("user:7").unwrap(), Some(user()));
+
+    let ns = db.namespace("people").unwrap();
+    ns.set_typed("ada", &user()).unwrap();
+    assert_eq!(ns.get_typed::<User>("ada").unwrap(), Some(user()));
+}
+
+#[test]
+fn test_type_mismatch_is_an_error() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open_db(&temp_dir, SerializationFormat::Bincode);
+    db.set_typed("user:7", &user()).unwrap();
+    db.set("raw".to_string(), b"not bincode".to_vec()).unwrap();
+
+    // Too few bytes for the type, leftover bytes, and arbitrary bytes
+    let err = db.get_typed::<(u64, u64, u64, u64)>("user:7").unwrap_err();
+    assert!(matches!(&err, LohError::TypeMismatch { key, .. } if key == "user:7"), "{:?}", err);
+    assert!(matches!(db.get_typed::<u64>("user:7"), Err(LohError::TypeMismatch { .. })));
+    let err = db.get_typed::<User>("raw").unwrap_err();
+    assert!(err.to_string().starts_with("value of 'raw' is not a valid"), "{}", err);
+    assert!(err.to_string().contains("User"), "{}", err);
+
+    // JSON catches every mismatch
+    let json_dir = TempDir::new().unwrap();
+    let mut db = open_db(&json_dir, SerializationFormat::Json);
+    db.set_typed("n", &42u32).unwrap();
+    assert!(matches!(db.get_typed::<User>("n"), Err(LohError::TypeMismatch { .. })));
+    assert!(matches!(db.get_typed::<String>("n"), Err(LohError::TypeMismatch { .. })));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use lohdb::db::{Operation, WriteAheadLog};
+use lohdb::{Database, DatabaseConfig};
+use std::collections::HashMap;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn attrs(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn test_set_with_meta_keeps_value_and_attributes_apart() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    let meta = attrs(&[("content-type", "image/png"), ("source", "upload")]);
+    db.set_with_meta("blob".to_string(), vec![0x89, 0x50, 0x4e, 0x47], meta.clone()).unwrap();
+
+    assert_eq!(db.get("blob").unwrap(), Some(vec![0x89, 0x50, 0x4e, 0x47]));
+    assert_eq!(db.get_meta("blob").unwrap(), Some(meta));
+
+    // A key written without attributes has an empty map; a missing key has none
+    db.set("plain".to_string(), b"value".to_vec()).unwrap();
+    assert_eq!(db.get_meta("plain").unwrap(), Some(HashMap::new()));
+    assert_eq!(db.get_meta("missing").unwrap(), None);
+}
+
+#[test]
+fn test_later_writes_without_meta_clear_it() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = common::open(&temp_dir);
+    let meta = attrs(&[("content-type", "text/plain")]);
+
+    db.set_with_meta("a".to_string(), b"one".to_vec(), meta.clone()).unwrap();
+    db.set("a".to_string(), b"two".to_vec()).unwrap();
+    assert_eq!(db.get_meta("a").unwrap(), Some(HashMap::new()));
+
+    db.set_with_meta("b".to_string(), b"one".to_vec(), meta.clone()).unwrap();
+    db.set_with_ttl("b".to_string(), b"two".to_vec(), Duration::from_secs(60)).unwrap();
+    assert_eq!(db.get_meta("b").unwrap(), Some(HashMap::new()));
+
+    // Deleting and recreating the key does not bring them back
+    db.set_with_meta("c".to_string(), b"one".to_vec(), meta.clone()).unwrap();
+    db.delete("c").unwrap();
+    assert_eq!(db.get_meta("c").unwrap(), None);
+    db.set("c".to_string(), b"two".to_vec()).unwrap();
+    assert_eq!(db.get_meta("c").unwrap(), Some(HashMap::new()));
+
+    // Setting with new attributes replaces the old ones rather than merging
+    db.set_with_meta("d".to_string(), b"one".to_vec(), meta).unwrap();
+    db.set_with_meta("d".to_string(), b"two".to_vec(), attrs(&[("source", "api")])).unwrap();
+    assert_eq!(db.get_meta("d").unwrap(), Some(attrs(&[("source", "api")])));
+}
+
+#[test]
+fn test_meta_survives_wal_replay() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    let meta = attrs(&[("content-type", "application/json")]);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set_with_meta("doc".to_string(), b"{}".to_vec(), meta.clone()).unwrap();
+        db.namespace("ns").unwrap().set_with_meta("doc".to_string(), b"[]".to_vec(), attrs(&[("source", "ns")])).unwrap();
+        db.set_with_meta("cleared".to_string(), b"x".to_vec(), meta.clone()).unwrap();
+        db.set("cleared".to_string(), b"y".to_vec()).unwrap();
+    } // Dropped without a checkpoint, so everything comes back from the WAL
+
+    let wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let first = wal.iter_entries().next().unwrap().unwrap().entry.operation;
+    assert!(matches!(&first, Operation::SetWithMeta { meta: m, .. } if *m == meta));
+    drop(wal);
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("doc").unwrap(), Some(b"{}".to_vec()));
+    assert_eq!(db.get_meta("doc").unwrap(), Some(meta));
+    assert_eq!(db.namespace("ns").unwrap().get_meta("doc").unwrap(), Some(attrs(&[("source", "ns")])));
+    assert_eq!(db.get_meta("cleared").unwrap(), Some(HashMap::new()));
+}
+
+#[test]
+fn test_meta_survives_checkpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    let meta = attrs(&[("content-type", "text/csv"), ("source", "import")]);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set_with_meta("table".to_string(), b"a,b".to_vec(), meta.clone()).unwrap();
+        db.close().unwrap();
+    } // Closing checkpoints, so the attributes come back from the data file
+
+    let db = Database::open(config).unwrap();
+    assert!(db.change_history().unwrap().is_empty());
+    assert_eq!(db.get_meta("table").unwrap(), Some(meta));
+}
+
+#[test]
+fn test_meta_in_memory() {
+    let config = DatabaseConfig::builder().in_memory(true).build().unwrap();
+    let mut db = Database::open(config).unwrap();
+    db.set_with_meta("k".to_string(), b"v".to_vec(), attrs(&[("tag", "hot")])).unwrap();
+    assert_eq!(db.get_meta("k").unwrap(), Some(attrs(&[("tag", "hot")])));
+}
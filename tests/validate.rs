@@ -0,0 +1,86 @@
+mod common;
+
+use lohdb::{Database, DatabaseConfig, LohError, Operation};
+use tempfile::TempDir;
+
+fn set(key: &str, value: &[u8]) -> Operation {
+    Operation::Set { namespace: None, key: key.to_string(), value: value.to_vec() }
+}
+
+fn limited_db(temp_dir: &TempDir) -> Database {
+    let config = common::builder(temp_dir)
+        .max_value_bytes(16)
+        .build()
+        .unwrap();
+    Database::open(config).unwrap()
+}
+
+#[test]
+fn test_oversized_value_rejected_without_side_effects() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = limited_db(&temp_dir);
+    db.set("existing".to_string(), b"value".to_vec()).unwrap();
+    let (_handle, events) = db.subscribe_channel().unwrap();
+    let before = db.stats().unwrap();
+
+    let batch = vec![
+        set("a", b"small"),
+        Operation::Delete { namespace: None, key: "existing".to_string() },
+        set("b", &[b'x'; 17]),
+        set("c", b"never checked"),
+    ];
+    let err = db.validate_batch(&batch).unwrap_err();
+    assert!(matches!(err, LohError::TooLarge { what: "value", size: 17, limit: 16 }), "{}", err);
+
+    // Nothing was logged, stored or published
+    let after = db.stats().unwrap();
+    assert_eq!(after.wal_sequence, before.wal_sequence);
+    assert_eq!(after.wal_file_bytes, before.wal_file_bytes);
+    assert_eq!(after.sets, before.sets);
+    assert_eq!(db.get("a").unwrap(), None);
+    assert_eq!(db.get("existing").unwrap(), Some(b"value".to_vec()));
+    assert!(events.try_recv().is_err());
+
+    // Without the oversized value the batch passes, and still changes nothing
+    db.validate_batch(&batch[..2]).unwrap();
+    assert_eq!(db.stats().unwrap().wal_sequence, before.wal_sequence);
+}
+
+#[test]
+fn test_key_namespace_and_state_checks() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = limited_db(&temp_dir);
+    db.set("plain".to_string(), b"not chunked".to_vec()).unwrap();
+    db.append("log", b"01234").unwrap();
+
+    let reserved = set("\0sneaky", b"v");
+    assert!(db.validate_batch(&[reserved]).unwrap_err().to_string().contains("NUL"));
+
+    let bad_namespace = Operation::Delete { namespace: Some(String::new()), key: "k".to_string() };
+    assert!(matches!(db.validate_batch(&[bad_namespace]), Err(LohError::InvalidArgument(_))));
+
+    // Appending checks the value already stored
+    let append = |key: &str, chunk: &[u8]| Operation::Append {
+        namespace: None,
+        key: key.to_string(),
+        chunk: chunk.to_vec(),
+        fresh: false,
+    };
+    assert!(db.validate_batch(&[append("plain", b"x")]).unwrap_err().to_string().contains("not a sequence"));
+    assert!(matches!(db.validate_batch(&[append("log", b"abcd")]), Err(LohError::TooLarge { .. })));
+    db.validate_batch(&[append("log", b"a"), append("new", b"abc")]).unwrap();
+    assert_eq!(db.read_chunks("log").unwrap().len(), 1);
+}
+
+#[test]
+fn test_read_only_database_rejects_any_batch() {
+    let temp_dir = TempDir::new().unwrap();
+    drop(limited_db(&temp_dir));
+    let config = DatabaseConfig::builder()
+        .data_dir(temp_dir.path().to_string_lossy().to_string())
+        .read_only(true)
+        .build()
+        .unwrap();
+    let db = Database::open(config).unwrap();
+    assert!(matches!(db.validate_batch(&[set("a", b"v")]), Err(LohError::ReadOnly)));
+}
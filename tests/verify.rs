@@ -0,0 +1,101 @@
+mod common;
+
+use lohdb::{Database, LohError};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn last_segment(temp_dir: &TempDir) -> PathBuf {
+    let mut segments: Vec<_> = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.file_name().unwrap().to_string_lossy().starts_with("wal."))
+        .collect();
+    segments.sort();
+    segments.pop().unwrap()
+}
+
+fn populate(temp_dir: &TempDir) {
+    let mut db = common::open(temp_dir);
+    for key in ["a", "b", "c"] {
+        db.set(key.to_string(), key.as_bytes().to_vec()).unwrap();
+    }
+    db.flush().unwrap();
+}
+
+#[test]
+fn test_verify_clean_database() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+
+    let report = Database::verify(&common::config(&temp_dir)).unwrap();
+    assert!(report.consistent);
+    assert_eq!((report.wal_records, report.wal_first_seq, report.wal_last_seq), (3, 1, 3));
+    assert!(report.data_file_present);
+    assert_eq!(report.data_file_keys, 3);
+    assert!(report.wal_corruption.is_none());
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["consistent"], true);
+    assert_eq!(json["wal_records"], 3);
+}
+
+#[test]
+fn test_torn_tail_is_reported_and_repaired() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    let segment = last_segment(&temp_dir);
+    let good_len = std::fs::metadata(&segment).unwrap().len();
+    let mut bytes = std::fs::read(&segment).unwrap();
+    bytes.extend_from_slice(&[7, 0, 0, 0, 1]);
+    std::fs::write(&segment, &bytes).unwrap();
+
+    // Verifying changes nothing
+    let report = Database::verify(&common::config(&temp_dir)).unwrap();
+    assert!(!report.consistent);
+    assert_eq!(report.wal_records, 3);
+    let corruption = report.wal_corruption.unwrap();
+    assert!(corruption.at_tail);
+    assert_eq!(corruption.offset, good_len);
+    assert!(corruption.error.contains("torn record"), "{}", corruption.error);
+    assert_eq!(std::fs::read(&segment).unwrap(), bytes);
+
+    let report = Database::repair(&common::config(&temp_dir)).unwrap();
+    assert!(report.consistent);
+    assert_eq!(std::fs::metadata(&segment).unwrap().len(), good_len);
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.list_keys().unwrap().len(), 3);
+}
+
+#[test]
+fn test_damage_that_cannot_be_repaired() {
+    // A missing segment in the middle of the log
+    let temp_dir = TempDir::new().unwrap();
+    let segmented = common::builder(&temp_dir)
+        .wal_segment_bytes(1)
+        .build()
+        .unwrap();
+    {
+        let mut db = Database::open(segmented.clone()).unwrap();
+        for key in ["a", "b", "c"] {
+            db.set(key.to_string(), vec![]).unwrap();
+        }
+    }
+    std::fs::remove_file(temp_dir.path().join("wal.000002.log")).unwrap();
+    let report = Database::verify(&segmented).unwrap();
+    assert!(!report.consistent);
+    assert!(!report.wal_corruption.as_ref().unwrap().at_tail);
+    assert!(matches!(Database::repair(&segmented), Err(LohError::WalCorrupt(_))));
+
+    // A data file that fails its checksum
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    let data_file = temp_dir.path().join("data.db");
+    let mut bytes = std::fs::read(&data_file).unwrap();
+    *bytes.last_mut().unwrap() ^= 0xff;
+    std::fs::write(&data_file, bytes).unwrap();
+    let report = Database::verify(&common::config(&temp_dir)).unwrap();
+    assert!(!report.consistent);
+    assert!(report.data_file_error.unwrap().contains("checksum"));
+    assert!(matches!(Database::repair(&common::config(&temp_dir)), Err(LohError::DataCorrupt(_))));
+}
@@ -0,0 +1,621 @@
+mod common;
+
+use lohdb::db::{MemoryBackend, Operation, SegmentFile, WalBackend, WriteAheadLog};
+use lohdb::{Database, LohError};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn set_op(key: &str) -> Operation {
+    Operation::Set { namespace: None, key: key.to_string(), value: key.as_bytes().to_vec() }
+}
+
+/// A record as the pre-versioning log wrote it, spelled out byte by byte:
+/// `len: u32`, then the pre-namespace `Operation` in bincode, a `u32`
+/// variant and each field as a `u64` length and its bytes
+fn baseline_frame(variant: u32, fields: &[&[u8]]) -> Vec<u8> {
+    let mut payload = variant.to_le_bytes().to_vec();
+    for field in fields {
+        payload.extend_from_slice(&(field.len() as u64).to_le_bytes());
+        payload.extend_from_slice(field);
+    }
+    let mut frame = (payload.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+fn baseline_set(key: &str, value: &[u8]) -> Vec<u8> {
+    baseline_frame(0, &[key.as_bytes(), value])
+}
+
+fn baseline_delete(key: &str) -> Vec<u8> {
+    baseline_frame(1, &[key.as_bytes()])
+}
+
+fn replayed_seqs(wal: &mut WriteAheadLog) -> Vec<u64> {
+    let mut seqs = Vec::new();
+    wal.replay(|entry| {
+        seqs.push(entry.seq);
+        Ok(())
+    })
+    .unwrap();
+    seqs
+}
+
+fn segment_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|n| n.starts_with("wal."))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_sequence_numbers_survive_restart() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        assert_eq!(db.stats().unwrap().wal_sequence, 0);
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+        db.set("b".to_string(), b"2".to_vec()).unwrap();
+        db.delete("a").unwrap();
+        assert_eq!(db.stats().unwrap().wal_sequence, 3);
+    }
+
+    let mut db = Database::open(config).unwrap();
+    assert_eq!(db.stats().unwrap().wal_sequence, 3);
+    db.set("c".to_string(), b"3".to_vec()).unwrap();
+    assert_eq!(db.stats().unwrap().wal_sequence, 4);
+    drop(db);
+
+    let mut wal = WriteAheadLog::open_read_only(temp_dir.path()).unwrap();
+    let mut entries = Vec::new();
+    wal.replay(|entry| {
+        entries.push(entry);
+        Ok(())
+    })
+    .unwrap();
+
+    let seqs: Vec<u64> = entries.iter().map(|e| e.seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3, 4]);
+    assert!(entries.iter().all(|e| e.timestamp_ms > 0));
+    assert!(entries.windows(2).all(|w| w[0].timestamp_ms <= w[1].timestamp_ms));
+}
+
+#[test]
+fn test_truncate_keeps_counting() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap();
+        assert_eq!(wal.append(&set_op("a")).unwrap(), 1);
+        assert_eq!(wal.append(&set_op("b")).unwrap(), 2);
+        wal.truncate().unwrap();
+        assert_eq!(wal.first_seq(), 3);
+        assert_eq!(wal.last_seq(), 2);
+    }
+
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap();
+    assert_eq!(wal.last_seq(), 2);
+    assert_eq!(wal.append(&set_op("c")).unwrap(), 3);
+}
+
+#[test]
+fn test_legacy_log_is_upgraded() {
+    let temp_dir = TempDir::new().unwrap();
+    let legacy_path = temp_dir.path().join("wal.log");
+
+    // Pre-versioning format: a single file of bare `len | bincode(Operation)` frames
+    let legacy = [baseline_set("a", b"a"), baseline_delete("b")].concat();
+    std::fs::write(&legacy_path, &legacy).unwrap();
+
+    // Read-only opens replay the old format in place
+    let mut entries = Vec::new();
+    let report = WriteAheadLog::open_read_only(temp_dir.path()).unwrap().replay(|entry| {
+        entries.push((entry.seq, entry.timestamp_ms, entry.operation));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(report.skipped, 0);
+    assert!(matches!(&entries[0], (1, 0, Operation::Set { namespace: None, key, value }) if key == "a" && value == b"a"));
+    assert!(matches!(&entries[1], (2, 0, Operation::Delete { namespace: None, key }) if key == "b"));
+    assert_eq!(std::fs::read(&legacy_path).unwrap(), legacy);
+
+    // Writable opens move it into the first segment and continue the numbering
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap();
+    assert!(!legacy_path.exists());
+    assert!(std::fs::read(temp_dir.path().join("wal.000001.log")).unwrap().starts_with(b"LOHWAL"));
+    assert_eq!(wal.append(&set_op("c")).unwrap(), 3);
+    assert_eq!(replayed_seqs(&mut wal), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_undecodable_legacy_log_fails_the_upgrade() {
+    let temp_dir = TempDir::new().unwrap();
+    let legacy_path = temp_dir.path().join("wal.log");
+    let legacy = [baseline_set("a", b"a"), baseline_frame(7, &[b"unknown"]), baseline_set("b", b"b")].concat();
+    std::fs::write(&legacy_path, &legacy).unwrap();
+
+    let err = WriteAheadLog::open(temp_dir.path()).err().expect("an undecodable record must fail the upgrade");
+    assert!(matches!(err, LohError::WalCorrupt(_)));
+    assert!(err.to_string().contains("record 2"), "{}", err);
+
+    // Nothing was changed on disk
+    assert_eq!(std::fs::read(&legacy_path).unwrap(), legacy);
+    assert_eq!(segment_names(temp_dir.path()), vec!["wal.log".to_string()]);
+}
+
+#[test]
+fn test_baseline_log_keeps_its_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let legacy = [baseline_set("hello", b"world"), baseline_set("gone", b"x"), baseline_delete("gone")].concat();
+    std::fs::write(temp_dir.path().join("wal.log"), legacy).unwrap();
+
+    let db = common::open(&temp_dir);
+    assert_eq!(db.get("hello").unwrap(), Some(b"world".to_vec()));
+    assert_eq!(db.get("gone").unwrap(), None);
+    let replay = db.stats().unwrap().replay;
+    assert_eq!((replay.applied, replay.skipped), (3, 0));
+}
+
+#[test]
+fn test_unknown_format_version_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut header = b"LOHWAL".to_vec();
+    header.push(99);
+    header.extend_from_slice(&1u64.to_le_bytes());
+    std::fs::write(temp_dir.path().join("wal.000001.log"), header).unwrap();
+
+    let err = WriteAheadLog::open(temp_dir.path()).err().expect("unknown version must fail");
+    assert!(err.to_string().contains("format version 99"));
+}
+
+#[test]
+fn test_segments_roll_over_and_replay_in_order() {
+    let temp_dir = TempDir::new().unwrap();
+
+    {
+        let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap().with_segment_bytes(64);
+        for i in 0..10 {
+            wal.append(&set_op(&format!("key{}", i))).unwrap();
+        }
+        assert!(wal.segment_paths().len() > 1);
+    }
+
+    let names = segment_names(temp_dir.path());
+    assert!(names.len() > 1);
+    assert_eq!(names[0], "wal.000001.log");
+
+    // Every segment stays near the bound: one record may overshoot it
+    for name in &names {
+        assert!(std::fs::metadata(temp_dir.path().join(name)).unwrap().len() < 128);
+    }
+
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap().with_segment_bytes(64);
+    assert_eq!(replayed_seqs(&mut wal), (1..=10).collect::<Vec<_>>());
+    assert_eq!(wal.append(&set_op("more")).unwrap(), 11);
+}
+
+#[test]
+fn test_segment_gaps_and_empty_trailing_segment() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+
+    {
+        let mut wal = WriteAheadLog::open(dir).unwrap().with_segment_bytes(1);
+        for key in ["a", "b", "c"] {
+            wal.append(&set_op(key)).unwrap();
+        }
+    }
+    assert_eq!(segment_names(dir), vec!["wal.000001.log", "wal.000002.log", "wal.000003.log"]);
+
+    // A gap in file indices is harmless as long as sequence numbers continue
+    std::fs::rename(dir.join("wal.000003.log"), dir.join("wal.000007.log")).unwrap();
+
+    // A zero-length segment from a crash during rollover is dropped
+    std::fs::write(dir.join("wal.000008.log"), b"").unwrap();
+
+    let mut wal = WriteAheadLog::open(dir).unwrap();
+    assert!(!dir.join("wal.000008.log").exists());
+    assert_eq!(replayed_seqs(&mut wal), vec![1, 2, 3]);
+    assert_eq!(wal.append(&set_op("d")).unwrap(), 4);
+    drop(wal);
+
+    // A missing segment in the middle loses records and is reported
+    std::fs::remove_file(dir.join("wal.000002.log")).unwrap();
+    let mut wal = WriteAheadLog::open(dir).unwrap();
+    let err = wal.replay(|_| Ok(())).unwrap_err();
+    assert!(err.to_string().contains("segment is missing"));
+}
+
+#[test]
+fn test_checkpoint_releases_covered_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .wal_segment_bytes(100)
+        .build()
+        .unwrap();
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        for i in 0..20 {
+            db.set(format!("key{}", i), vec![b'v'; 16]).unwrap();
+        }
+        assert!(segment_names(temp_dir.path()).len() > 2);
+
+        db.checkpoint().unwrap();
+        assert_eq!(segment_names(temp_dir.path()).len(), 1);
+        assert_eq!(db.stats().unwrap().wal_sequence, 20);
+
+        db.set("after".to_string(), b"checkpoint".to_vec()).unwrap();
+    }
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.list_keys().unwrap().len(), 21);
+    assert_eq!(db.get("after").unwrap(), Some(b"checkpoint".to_vec()));
+    assert_eq!(db.stats().unwrap().wal_sequence, 21);
+}
+
+#[test]
+fn test_iter_entries_reports_locations_and_torn_tail() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+
+    {
+        let mut wal = WriteAheadLog::open(dir).unwrap().with_segment_bytes(64);
+        for key in ["a", "b", "c"] {
+            wal.append(&set_op(key)).unwrap();
+        }
+    }
+
+    let wal = WriteAheadLog::open_read_only(dir).unwrap();
+    let entries: Vec<_> = wal.iter_entries().collect::<lohdb::Result<_>>().unwrap();
+    assert_eq!(entries.iter().map(|e| e.entry.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert!(entries.iter().all(|e| e.offset >= 15));
+    assert!(entries[0].segment.ends_with("wal.000001.log"));
+    assert!(matches!(&entries[2].entry.operation, Operation::Set { key, .. } if key == "c"));
+
+    // Half a record header at the end of the last segment
+    let last = wal.segment_paths().pop().unwrap();
+    let mut bytes = std::fs::read(&last).unwrap();
+    bytes.extend_from_slice(&[9, 0, 0]);
+    std::fs::write(&last, bytes).unwrap();
+
+    let wal = WriteAheadLog::open_read_only(dir).unwrap();
+    let results: Vec<_> = wal.iter_entries().collect();
+    assert_eq!(results.len(), 4);
+    assert!(results[..3].iter().all(|r| r.is_ok()));
+    let err = results[3].as_ref().unwrap_err();
+    assert!(err.to_string().contains("torn record: 3 bytes"), "{}", err);
+
+    // Replay still treats the torn tail as the end of the log
+    let mut wal = WriteAheadLog::open_read_only(dir).unwrap();
+    assert_eq!(replayed_seqs(&mut wal), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_write_buffer_holds_records_until_flushed() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    let segment = dir.join("wal.000001.log");
+
+    let mut wal = WriteAheadLog::open(dir).unwrap().with_buffer_bytes(64 * 1024);
+    let empty_len = std::fs::metadata(&segment).unwrap().len();
+    for i in 0..10 {
+        wal.append(&set_op(&format!("key{}", i))).unwrap();
+    }
+    // Nothing reached the file yet, so another reader sees no records
+    assert_eq!(std::fs::metadata(&segment).unwrap().len(), empty_len);
+    assert!(replayed_seqs(&mut WriteAheadLog::open_read_only(dir).unwrap()).is_empty());
+
+    wal.flush().unwrap();
+    assert_eq!(replayed_seqs(&mut WriteAheadLog::open_read_only(dir).unwrap()), (1..=10).collect::<Vec<_>>());
+
+    // Dropping writes out the rest
+    wal.append(&set_op("last")).unwrap();
+    drop(wal);
+    let mut wal = WriteAheadLog::open(dir).unwrap();
+    assert_eq!(replayed_seqs(&mut wal), (1..=11).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_buffered_database_writes_survive_close_and_sync() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::builder(&temp_dir)
+        .wal_buffer_bytes(1024 * 1024)
+        .build()
+        .unwrap();
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+        assert_eq!(db.change_history().unwrap().len(), 1);
+        db.sync().unwrap();
+        db.set("b".to_string(), b"2".to_vec()).unwrap();
+    }
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(db.get("b").unwrap(), Some(b"2".to_vec()));
+}
+
+#[test]
+fn test_buffered_vs_unbuffered_append_rate() {
+    const RECORDS: usize = 20_000;
+    let run = |buffer_bytes: usize| {
+        let temp_dir = TempDir::new().unwrap();
+        let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap().with_buffer_bytes(buffer_bytes);
+        let started = std::time::Instant::now();
+        for i in 0..RECORDS {
+            wal.append(&set_op(&format!("key{}", i))).unwrap();
+        }
+        wal.flush().unwrap();
+        let rate = RECORDS as f64 / started.elapsed().as_secs_f64();
+        (rate, replayed_seqs(&mut wal))
+    };
+
+    // Timing is only reported: debug builds and parallel tests make it too
+    // noisy to assert on (release builds show the buffered log well ahead)
+    let (unbuffered, unbuffered_seqs) = run(0);
+    let (buffered, buffered_seqs) = run(64 * 1024);
+    println!("WAL appends: unbuffered {:.0}/s, 64 KiB buffer {:.0}/s", unbuffered, buffered);
+    assert_eq!(buffered_seqs, unbuffered_seqs);
+    assert_eq!(buffered_seqs.len(), RECORDS);
+}
+
+#[test]
+fn test_size_and_entry_count_track_appends_and_releases() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+
+    let mut wal = WriteAheadLog::open(dir).unwrap().with_segment_bytes(256);
+    assert_eq!(wal.entry_count(), 0);
+    let empty = wal.size_bytes();
+    assert_eq!(empty, wal.disk_usage().unwrap());
+
+    for i in 0..40 {
+        wal.append(&set_op(&format!("key{:02}", i))).unwrap();
+    }
+    assert!(segment_names(dir).len() > 2);
+    assert_eq!(wal.entry_count(), 40);
+    assert_eq!(wal.size_bytes(), wal.disk_usage().unwrap());
+
+    // Releasing drops every rolled-over segment, leaving only the active one's records
+    let before_release = wal.size_bytes();
+    wal.release_through(wal.last_seq()).unwrap();
+    assert!(wal.size_bytes() < before_release);
+    assert_eq!(wal.size_bytes(), wal.disk_usage().unwrap());
+    assert_eq!(wal.entry_count(), wal.last_seq() + 1 - wal.first_seq());
+
+    wal.truncate().unwrap();
+    assert_eq!((wal.entry_count(), wal.size_bytes()), (0, empty));
+    drop(wal);
+
+    // Reopening picks the same numbers up from the files
+    let mut wal = WriteAheadLog::open(dir).unwrap();
+    wal.append(&set_op("after")).unwrap();
+    let size = wal.size_bytes();
+    assert_eq!(wal.entry_count(), 1);
+    drop(wal);
+    let wal = WriteAheadLog::open_read_only(dir).unwrap();
+    assert_eq!((wal.entry_count(), wal.size_bytes()), (1, size));
+}
+
+#[test]
+fn test_size_counts_buffered_records() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap().with_buffer_bytes(64 * 1024);
+    let on_disk = wal.disk_usage().unwrap();
+    wal.append(&set_op("key")).unwrap();
+    assert!(wal.size_bytes() > on_disk);
+    assert_eq!(wal.disk_usage().unwrap(), on_disk);
+    wal.flush().unwrap();
+    assert_eq!(wal.size_bytes(), wal.disk_usage().unwrap());
+}
+
+/// Fails every other write with `kind` until `failures` runs out, and
+/// writes at most a few bytes at a time, so records arrive in pieces
+struct FlakyFile {
+    file: Box<dyn SegmentFile>,
+    kind: io::ErrorKind,
+    failures: Arc<AtomicUsize>,
+    attempts: Arc<AtomicUsize>,
+}
+
+impl Write for FlakyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+        if attempt % 2 == 1 && self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+            return Err(io::Error::new(self.kind, "injected failure"));
+        }
+        self.file.write(&buf[..buf.len().min(7)])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl SegmentFile for FlakyFile {
+    fn sync_data(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn SegmentFile>> {
+        self.file.try_clone()
+    }
+}
+
+fn flaky_wal(dir: &Path, kind: io::ErrorKind, failures: usize, retries: u32) -> (WriteAheadLog, Arc<AtomicUsize>) {
+    let failures = Arc::new(AtomicUsize::new(failures));
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let wal = WriteAheadLog::open(dir)
+        .unwrap()
+        .with_write_retries(retries, Duration::from_millis(1))
+        .with_segment_wrapper(move |file| {
+            Box::new(FlakyFile { file, kind, failures: failures.clone(), attempts: counter.clone() })
+        })
+        .unwrap();
+    (wal, attempts)
+}
+
+#[test]
+fn test_transient_write_failures_are_retried() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut wal, attempts) = flaky_wal(temp_dir.path(), io::ErrorKind::TimedOut, 3, 3);
+
+    // Each failure lands mid-record; the retries pick up where it stopped
+    assert_eq!(wal.append(&set_op("flaky")).unwrap(), 1);
+    assert!(attempts.load(Ordering::SeqCst) > 6);
+    assert_eq!(wal.append(&set_op("steady")).unwrap(), 2);
+    drop(wal);
+
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap();
+    let report = wal.replay(|_| Ok(())).unwrap();
+    assert_eq!((report.applied, report.skipped), (2, 0));
+}
+
+#[test]
+fn test_write_fails_once_retries_are_exhausted() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut wal, _) = flaky_wal(temp_dir.path(), io::ErrorKind::WouldBlock, 3, 2);
+
+    let err = wal.append(&set_op("flaky")).unwrap_err();
+    assert!(err.to_string().contains("injected failure"), "{}", err);
+
+    // The part of the record that was written is cut off again, so the next
+    // append is not read as its continuation
+    assert_eq!(wal.append(&set_op("steady")).unwrap(), 1);
+    drop(wal);
+
+    let mut wal = WriteAheadLog::open(temp_dir.path()).unwrap();
+    let mut keys = Vec::new();
+    let report = wal.replay(|entry| {
+        if let Operation::Set { key, .. } = entry.operation {
+            keys.push(key);
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!((report.applied, report.skipped), (1, 0));
+    assert_eq!(keys, vec!["steady".to_string()]);
+}
+
+#[test]
+fn test_torn_tail_is_cut_before_appending() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = common::config(&temp_dir);
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        db.set("a".to_string(), b"1".to_vec()).unwrap();
+    }
+
+    // A crash partway through a record: a header promising 40 bytes, and two of them
+    let segment = temp_dir.path().join("wal.000001.log");
+    let good_len = std::fs::metadata(&segment).unwrap().len();
+    let mut bytes = std::fs::read(&segment).unwrap();
+    bytes.extend_from_slice(&[40, 0, 0, 0, 2, 0]);
+    std::fs::write(&segment, bytes).unwrap();
+
+    {
+        let mut db = Database::open(config.clone()).unwrap();
+        assert_eq!(std::fs::metadata(&segment).unwrap().len(), good_len);
+        db.set("b".to_string(), b"2".to_vec()).unwrap();
+    }
+
+    let db = Database::open(config).unwrap();
+    assert_eq!(db.get("b").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(db.stats().unwrap().replay.applied, 2);
+}
+
+#[test]
+fn test_permanent_write_failures_are_not_retried() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut wal, attempts) = flaky_wal(temp_dir.path(), io::ErrorKind::StorageFull, 1, 5);
+
+    // The first failure is the second write of the record
+    assert!(wal.append(&set_op("full")).is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_memory_backend_keeps_segments_across_reopen() {
+    let backend = MemoryBackend::new();
+    let dir = Path::new("/wal");
+    let open = || {
+        WriteAheadLog::open_with_backend(dir, Arc::new(backend.clone()))
+            .unwrap()
+            .with_segment_bytes(128)
+    };
+
+    let mut wal = open();
+    for i in 0..10 {
+        wal.append(&set_op(&format!("key{}", i))).unwrap();
+    }
+    wal.sync().unwrap();
+    let segments = wal.segment_paths();
+    assert!(segments.len() > 1);
+    assert!(segments.iter().all(|path| path.starts_with(dir)));
+    assert_eq!(wal.disk_usage().unwrap(), backend.total_bytes());
+    assert_eq!(wal.size_bytes(), backend.total_bytes());
+    drop(wal);
+
+    // Nothing touched the filesystem, and a reopen sees every record
+    assert!(!dir.exists());
+    let mut wal = open();
+    assert_eq!(replayed_seqs(&mut wal), (1..=10).collect::<Vec<_>>());
+    assert_eq!(wal.append(&set_op("after")).unwrap(), 11);
+
+    // Releasing covered segments removes them from the backend
+    wal.roll().unwrap();
+    assert!(wal.release_through(11).unwrap() > 1);
+    assert_eq!(backend.list_segments(dir).unwrap().len(), 1);
+    assert!(replayed_seqs(&mut wal).is_empty());
+    assert_eq!(wal.append(&set_op("next")).unwrap(), 12);
+    assert_eq!(replayed_seqs(&mut wal), vec![12]);
+
+    let located: Vec<u64> = wal.iter_entries().map(|e| e.unwrap().entry.seq).collect();
+    assert_eq!(located, vec![12]);
+}
+
+#[test]
+fn test_oversized_record_is_rejected_before_writing() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+
+    {
+        let mut wal = WriteAheadLog::open(dir).unwrap().with_segment_bytes(1);
+        wal.append(&set_op("before")).unwrap();
+        let segments = segment_names(dir);
+        let bytes = wal.disk_usage().unwrap();
+
+        // Each value fits a record alone, but not together; zeroed
+        // allocations are lazily mapped, so this costs no real memory
+        let half = u32::MAX as usize / 2 + 1;
+        let batch = Operation::Batch {
+            ops: ["a", "b"]
+                .into_iter()
+                .map(|key| Operation::Set { namespace: None, key: key.to_string(), value: vec![0u8; half] })
+                .collect(),
+        };
+        let err = wal.append(&batch).unwrap_err();
+        assert!(matches!(err, LohError::TooLarge { what: "WAL record", .. }), "{}", err);
+
+        // No segment rolled, no bytes written, no sequence number taken
+        assert_eq!(segment_names(dir), segments);
+        assert_eq!(wal.disk_usage().unwrap(), bytes);
+        assert_eq!(wal.append(&set_op("after")).unwrap(), 2);
+    }
+
+    let mut wal = WriteAheadLog::open(dir).unwrap();
+    assert_eq!(replayed_seqs(&mut wal), vec![1, 2]);
+}
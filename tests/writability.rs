@@ -0,0 +1,86 @@
+use lohdb::db::LifecycleEvent;
+use lohdb::{Database, DatabaseConfig, LohError};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn config_for(dir: &std::path::Path, sync_interval_ms: u64) -> DatabaseConfig {
+    DatabaseConfig::builder()
+        .data_dir(dir.to_string_lossy().to_string())
+        .wal_sync_interval_ms(sync_interval_ms)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_open_fails_when_data_dir_cannot_be_created() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("not-a-dir");
+    std::fs::write(&file, b"").unwrap();
+
+    let err = Database::open(config_for(&file.join("db"), 0)).err().expect("open must fail");
+    assert!(matches!(err, LohError::Io(_)));
+    assert!(err.to_string().contains("cannot create data directory"), "{}", err);
+    assert!(err.to_string().contains("not-a-dir"), "{}", err);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_open_fails_on_read_only_data_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("db");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+    // Permission bits do not bind root; nothing to check then
+    if std::fs::write(dir.join("probe"), b"").is_ok() {
+        return;
+    }
+
+    let err = Database::open(config_for(&dir, 0)).err().expect("open must fail");
+    assert!(err.to_string().contains("cannot write to data directory"), "{}", err);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    // Read-only opens never write, so they are still allowed
+    let config = DatabaseConfig::builder()
+        .data_dir(dir.to_string_lossy().to_string())
+        .read_only(true)
+        .build()
+        .unwrap();
+    assert!(Database::open(config).is_ok());
+
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn test_background_flush_failure_is_reported() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(config_for(temp_dir.path(), 20)).unwrap();
+    let (_handle, lifecycle) = db.subscribe_lifecycle();
+
+    // A directory in the way of the data file's temporary copy fails every flush
+    let blocker = temp_dir.path().join("data.db.tmp");
+    std::fs::create_dir(&blocker).unwrap();
+    db.set("key".to_string(), b"value".to_vec()).unwrap();
+
+    let error = loop {
+        match lifecycle.recv_timeout(Duration::from_secs(5)).expect("flush failure event") {
+            LifecycleEvent::FlushFailed { error } => break error,
+            _ => continue,
+        }
+    };
+    assert!(!error.is_empty());
+    assert_eq!(db.stats().unwrap().last_flush_error, Some(error));
+
+    // Writes keep succeeding through the WAL, and the next good flush clears the error
+    std::fs::remove_dir(&blocker).unwrap();
+    db.set("other".to_string(), b"value".to_vec()).unwrap();
+    loop {
+        if let LifecycleEvent::Flushed { .. } = lifecycle.recv_timeout(Duration::from_secs(5)).expect("flush event") {
+            break;
+        }
+    }
+    assert_eq!(db.stats().unwrap().last_flush_error, None);
+    assert_eq!(db.get("key").unwrap(), Some(b"value".to_vec()));
+}